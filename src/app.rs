@@ -0,0 +1,1555 @@
+//! The editor application.
+
+use std::{env, thread};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, RenderFormat, WindowState};
+use crate::dsp::BlockProcessor;
+use cpal::SampleRate;
+use crate::fx::{FXSettings, GlobalFX};
+use midir::{InitError, MidiInput, MidiInputConnection, MidiInputPort};
+use fundsp::hacker32::*;
+use cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}, StreamConfig};
+use crate::module::{Edit, EventData, Module, ModuleCommand, ModuleSync, SectionTrigger, TrackTarget};
+use crate::playback::{Player, PlayerShell, StatusUpdate};
+use rfd::FileDialog;
+use rtrb::RingBuffer;
+use crate::synth::{Key, KeyOrigin, VoiceSettings};
+use macroquad::prelude::*;
+use macroquad::miniquad::window::{
+    get_window_position, set_fullscreen, set_window_position, set_window_size,
+};
+
+use crate::{config, dsp, input, playback, smf, ui, video};
+use crate::input::{Action, Hotkey, MidiEvent, Modifiers};
+use crate::timespan::Timespan;
+use crate::watch::SampleWatch;
+use triple_buffer::triple_buffer;
+use crate::ui::developer::DevState;
+use crate::ui::general::GeneralState;
+use crate::ui::info::Info;
+use crate::ui::instruments::{fix_patch_index, InstrumentsState};
+use crate::ui::lane::LaneTool;
+use crate::ui::perform::PerformState;
+use crate::ui::scopes::ScopesState;
+use crate::ui::settings::SettingsState;
+use crate::ui::tutorial::{Tutorial, DEMO_MODULE};
+use crate::ui::{is_alt_down, is_ctrl_down, is_mod};
+use crate::ui::pattern::PatternEditor;
+use crate::surface::{ControlSurface, SurfaceInput};
+use crate::video::{VideoColors, VideoOutput};
+
+/// Application name, for window title, etc.
+pub const APP_NAME: &str = "Osctet";
+pub(crate) const MODULE_FILETYPE_NAME: &str = "Osctet module";
+pub(crate) const MODULE_EXT: &str = "osctet";
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Returns a path in the same directory as the executable. If no executable
+/// path is available, returns the plain filename as a path.
+pub fn exe_relative_path(filename: &str) -> PathBuf {
+    match env::current_exe() {
+        Ok(mut path) => {
+            path.pop();
+            path.push(filename);
+            path
+        }
+        Err(e) => {
+            eprintln!("Error finding executable path: {e}");
+            filename.into()
+        }
+    }
+}
+
+type MidiConn = MidiInputConnection<Sender<Vec<u8>>>;
+
+/// A connection to a single MIDI input port.
+struct MidiConnection {
+    port_name: String,
+    conn: MidiConn,
+    rx: Receiver<Vec<u8>>,
+    rpn: (u8, u8),
+    bend_range: f32,
+}
+
+/// Handles MIDI connections and state.
+pub struct Midi {
+    // Keep one input around for listing ports. If we need to connect, we'll
+    // create a new input just for that (see Boddlnagg/midir#90).
+    pub(crate) input: Option<MidiInput>,
+    conns: Vec<MidiConnection>,
+    input_id: u16,
+}
+
+impl Midi {
+    fn new() -> Self {
+        let mut m = Self {
+            input: None,
+            conns: Vec::new(),
+            input_id: 0,
+        };
+        m.input = m.new_input().ok();
+        m
+    }
+
+    /// Create a new MIDI input for the application.
+    fn new_input(&mut self) -> Result<MidiInput, InitError> {
+        self.input_id += 1;
+        MidiInput::new(&format!("{} input #{}", APP_NAME, self.input_id))
+    }
+
+    /// Returns the input port with the given name.
+    fn find_port(&self, name: &str) -> Result<MidiInputPort, &'static str> {
+        let input = self.input.as_ref().ok_or("Could not open MIDI")?;
+        input.ports().into_iter()
+            .find(|p| input.port_name(p).is_ok_and(|s| s == name))
+            .ok_or("Selected MIDI device not found")
+    }
+
+    /// Returns true if any input port is connected.
+    pub fn is_connected(&self) -> bool {
+        !self.conns.is_empty()
+    }
+
+    /// Returns true if the named input port is connected.
+    pub fn is_port_connected(&self, name: &str) -> bool {
+        self.conns.iter().any(|c| c.port_name == name)
+    }
+}
+
+pub(crate) const MAIN_TAB_ID: &str = "main";
+pub(crate) const TAB_GENERAL: usize = 0;
+pub(crate) const TAB_PATTERN: usize = 1;
+pub(crate) const TAB_INSTRUMENTS: usize = 2;
+pub(crate) const TAB_SETTINGS: usize = 3;
+const TAB_PERFORM: usize = 4;
+const TAB_SCOPES: usize = 5;
+const TAB_DEVELOPER: usize = 6;
+
+#[cfg(not(debug_assertions))]
+const TABS: [&str; 6] =
+    ["General", "Pattern", "Instruments", "Settings", "Perform", "Scopes"];
+
+#[cfg(debug_assertions)]
+const TABS: [&str; 7] =
+    ["General", "Pattern", "Instruments", "Settings", "Perform", "Scopes", "Developer"];
+
+/// Top-level store of application state.
+struct App {
+    octave: i8,
+    /// Track that the input being handled is routed to, overriding the
+    /// keyjazz track.
+    routed_track: Option<usize>,
+    midi: Midi,
+    surface: ControlSurface,
+    config: Config,
+    fx: GlobalFX,
+    ui: ui::Ui,
+    general_state: GeneralState,
+    pattern_editor: PatternEditor,
+    instruments_state: InstrumentsState,
+    settings_state: SettingsState,
+    perform_state: PerformState,
+    scopes_state: ScopesState,
+    dev_state: DevState,
+    save_path: Option<PathBuf>,
+    update_tx: Sender<StatusUpdate>,
+    update_rx: Receiver<StatusUpdate>,
+    version: String,
+    player: PlayerShell,
+    stereo_width: Shared,
+    module: Module,
+    module_sync: ModuleSync,
+    keyjazz_modulation: f32,
+    /// If true, keyjazz notes sustain after their keys are released.
+    keyjazz_latch: bool,
+    /// Keyjazz keys that are currently held down.
+    keyjazz_held: Vec<Key>,
+    /// MIDI pitch of the last keyjazz note, for the tuner readout.
+    tuner_pitch: Option<f32>,
+    /// Keyjazz notes sustained by latch mode.
+    latched_keys: Vec<(usize, Key)>,
+    /// Learned chord, as pitch offsets from the played note.
+    chord: Vec<f32>,
+    /// If true, keyjazz pitches are being recorded into `chord`.
+    chord_learning: bool,
+    last_autosave_time: Instant,
+    /// If true, the cheat sheet is drawn over the UI.
+    cheat_sheet: bool,
+    tutorial: Option<Tutorial>,
+    /// First stroke of a two-stroke hotkey in progress.
+    chord_prefix: Option<Hotkey>,
+    sample_watch: SampleWatch,
+}
+
+impl App {
+    const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+    fn new(global_fx: GlobalFX, mut config: Config, sample_rate: u32,
+        audio_conf: Option<StreamConfig>, player: PlayerShell, stereo_width: Shared,
+        module: Module, module_sync: ModuleSync
+    ) -> Self {
+        let midi = Midi::new();
+        let mut surface = ControlSurface::new(config.surface_layout);
+        if let Err(e) = surface.connect(config.surface_output.clone()) {
+            eprintln!("error connecting control surface: {e}");
+            config.surface_output = None;
+        }
+        let mut module = module;
+        module.sync = true;
+        let (update_tx, update_rx) = mpsc::channel();
+        let mut ui = ui::Ui::new(config.theme.clone(), config.font_size);
+        if config.font_path.is_some() || config.pattern_font_size.is_some() {
+            ui::settings::load_font(&mut config, &mut ui);
+        }
+        ui.set_tab(MAIN_TAB_ID, config.window.tab.min(TABS.len() - 1));
+        if let (false, Some((x, y))) = (config.window.fullscreen, config.window.position) {
+            set_window_position(x, y);
+        }
+        App {
+            octave: 3,
+            routed_track: None,
+            midi,
+            surface,
+            ui,
+            config,
+            fx: global_fx,
+            pattern_editor: PatternEditor::default(),
+            general_state: Default::default(),
+            instruments_state: InstrumentsState::new(Some(0)),
+            settings_state: SettingsState::new(sample_rate),
+            perform_state: Default::default(),
+            scopes_state: Default::default(),
+            dev_state: DevState::new(audio_conf),
+            save_path: None,
+            update_tx,
+            update_rx,
+            version: format!("v{PKG_VERSION}"),
+            player,
+            stereo_width,
+            module,
+            module_sync,
+            keyjazz_modulation: 0.0,
+            keyjazz_latch: false,
+            keyjazz_held: Vec::new(),
+            tuner_pitch: None,
+            latched_keys: Vec::new(),
+            chord: Vec::new(),
+            chord_learning: false,
+            last_autosave_time: Instant::now(),
+            cheat_sheet: false,
+            tutorial: None,
+            chord_prefix: None,
+            sample_watch: SampleWatch::new(),
+        }
+    }
+
+    // TODO: use most current vel/mod setting when keyjazzing in pattern
+
+    /// Returns the index of the current track to use for keyjazzing.
+    fn keyjazz_track(&self) -> usize {
+        // TODO: switching tracks while keyjazzing could result in stuck notes
+        // TODO: entering note input mode while keyjazzing could result in stuck notes
+        // TODO: switching octave while keyjazzing can result in stuck notes?
+        if let Some(track) = self.routed_track.filter(|&t| t < self.module.tracks.len()) {
+            track
+        } else if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+            self.pattern_editor.cursor_track()
+        } else {
+            0
+        }
+    }
+
+    /// Queue note input for the UI, along with the track it's routed to.
+    fn queue_note(&mut self, key: Key, data: EventData) {
+        let track = self.routed_track.filter(|&t| t < self.module.tracks.len());
+        self.ui.note_queue.push((key, data, track));
+    }
+
+    /// Returns the current patch index to use for keyjazzing.
+    fn keyjazz_patch_index(&self) -> Option<usize> {
+        match self.module.tracks[self.keyjazz_track()].target {
+            TrackTarget::Global | TrackTarget::None | TrackTarget::Audio =>
+                self.instruments_state.patch_index,
+            TrackTarget::Kit => None,
+            TrackTarget::Patch(i) => Some(i),
+        }
+    }
+
+    /// Start a keyjazz note, expanding it into the learned chord if there is
+    /// one.
+    fn keyjazz_note_on(&mut self, key: Key, pitch: f32, pressure: Option<f32>,
+        patch: usize
+    ) {
+        let track = self.keyjazz_track();
+
+        // a new chord replaces the latched one once all keys are up
+        if self.keyjazz_latch && self.keyjazz_held.is_empty() {
+            self.release_latched();
+        }
+        self.keyjazz_held.push(key.clone());
+        self.tuner_pitch = Some(pitch);
+
+        if self.chord_learning {
+            self.chord.push(pitch);
+        }
+
+        let offsets = if self.chord_learning || self.chord.is_empty() {
+            vec![0.0]
+        } else {
+            self.chord.clone()
+        };
+
+        // sync preview with playback when entering notes in the pattern
+        let step = (self.config.quantize_preview
+            && self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN)
+            && self.player.is_playing())
+            .then(|| self.pattern_editor.row_beats());
+
+        for (i, offset) in offsets.into_iter().enumerate() {
+            let key = key.with_chord_tone(i as u8);
+            if self.keyjazz_latch {
+                self.latched_keys.push((track, key.clone()));
+            }
+            if let Some(step) = step {
+                self.player.quantized_note_on(track, key, pitch + offset, pressure,
+                    patch, step);
+            } else {
+                self.player.note_on(track, key, pitch + offset, pressure, patch);
+            }
+        }
+    }
+
+    /// End a keyjazz note, unless it's being held by latch mode.
+    fn keyjazz_note_off(&mut self, key: Key) {
+        self.keyjazz_held.retain(|k| *k != key);
+        if self.keyjazz_latch {
+            return
+        }
+        let track = self.keyjazz_track();
+        for i in 0..self.chord.len().max(1) {
+            self.player.note_off(track, key.with_chord_tone(i as u8));
+        }
+    }
+
+    /// Release all notes sustained by latch mode.
+    fn release_latched(&mut self) {
+        for (track, key) in std::mem::take(&mut self.latched_keys) {
+            self.player.note_off(track, key);
+        }
+    }
+
+    /// Start or finish learning a chord from keyjazz input.
+    fn toggle_chord_learning(&mut self) {
+        if self.chord_learning {
+            // store pitches relative to the lowest note
+            let root = self.chord.iter().copied().fold(f32::INFINITY, f32::min);
+            for pitch in self.chord.iter_mut() {
+                *pitch -= root;
+            }
+            self.chord.sort_by(|a, b| a.total_cmp(b));
+            self.chord.dedup();
+            self.chord.truncate(u8::MAX as usize);
+        } else {
+            self.chord.clear();
+        }
+        self.chord_learning = !self.chord_learning;
+    }
+
+    /// Handle keyboard input.
+    fn handle_keys(&mut self) {
+        let (pressed, released) = (get_keys_pressed(), get_keys_released());
+        let mods = Modifiers::current();
+
+        // translate released keys into note-offs
+        for key in released {
+            let hk = Hotkey::new(mods, key);
+            let note = input::note_from_key(
+                hk, &self.module.tuning, self.octave, &self.config);
+            if note.is_some() {
+                self.routed_track = self.perform_state.split_zone(key).and_then(|z| z.track);
+                let key = Key::new_from_keyboard(input::u8_from_key(key));
+                self.queue_note(key.clone(), EventData::NoteOff);
+                self.keyjazz_note_off(key);
+            }
+        }
+        self.routed_track = None;
+
+        // translate pressed keys into key commands
+        for key in pressed {
+            let hk = Hotkey::new(mods, key);
+            if !is_mod(key) {
+                if let Some(i) = self.general_state.learning_macro.take() {
+                    // escape cancels learning
+                    if key != KeyCode::Escape {
+                        self.module.set_macro_hotkey(i, hk);
+                    }
+                    continue
+                }
+            }
+            // escape only dismisses the cheat sheet
+            if self.cheat_sheet && key == KeyCode::Escape {
+                self.cheat_sheet = false;
+                continue
+            }
+            if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PERFORM)
+                && !self.ui.accepting_keyboard_input() && !is_mod(key)
+                && self.perform_state.trigger(
+                    SectionTrigger::Key(hk.clone()), &mut self.module, &mut self.player) {
+                continue
+            }
+            let hk = if is_mod(key) || self.ui.accepting_keyboard_input() {
+                hk
+            } else {
+                self.config.resolve_hotkey(hk, &mut self.chord_prefix)
+            };
+            if self.chord_prefix.is_some() {
+                self.ui.notify(format!("{hk}, ..."));
+                continue
+            }
+            if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN)
+                && !self.ui.accepting_keyboard_input() {
+                if let Some(i) = self.module.macros.iter()
+                    .position(|m| m.hotkey.as_ref() == Some(&hk)) {
+                    self.pattern_editor.insert_macro(&mut self.module, i);
+                    continue
+                }
+            }
+            if let Some(action) = self.config.hotkey_action(&hk) {
+                match action {
+                    Action::IncrementDivision => self.pattern_editor.inc_division(),
+                    Action::DecrementDivision => self.pattern_editor.dec_division(),
+                    Action::DoubleDivision => self.pattern_editor.double_division(),
+                    Action::HalveDivision => self.pattern_editor.halve_division(),
+                    Action::FocusDivision => self.ui.focus("Division"),
+                    Action::ToggleTriplets => self.pattern_editor.toggle_triplets(),
+                    Action::IncrementOctave =>
+                        self.octave = self.octave.saturating_add(1),
+                    Action::DecrementOctave =>
+                        self.octave = self.octave.saturating_sub(1),
+                    Action::PlayFromStart => self.player.toggle_play_from(Timespan::ZERO),
+                    Action::PlayFromScreen => {
+                        let tick = self.pattern_editor.screen_beat_tick();
+                        self.player.toggle_play_from(tick)
+                    }
+                    Action::PlayFromCursor =>
+                        self.player.toggle_play_from(self.pattern_editor.cursor_tick()),
+                    Action::StopPlayback => self.player.stop(),
+                    Action::NewSong => if self.module.has_unsaved_changes {
+                        self.ui.confirm("Discard unsaved changes?", Action::NewSong);
+                    } else {
+                        self.new_module()
+                    },
+                    Action::OpenSong=> if self.module.has_unsaved_changes {
+                        self.ui.confirm("Discard unsaved changes?", Action::OpenSong);
+                    } else {
+                        self.open_module()
+                    },
+                    Action::SaveSong => self.save_module(),
+                    Action::SaveSongAs => self.save_module_as(),
+                    Action::RenderSong => self.render_and_save(false),
+                    Action::RenderTracks => self.render_and_save(true),
+                    Action::ExportVideo => self.export_video(),
+                    Action::ExportMidi => if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+                        self.export_midi();
+                    },
+                    Action::Undo => if self.module.undo() {
+                        self.player.update_synths(self.module.drain_track_history());
+                        fix_patch_index(&mut self.instruments_state.patch_index,
+                            self.module.patches.len());
+                    } else {
+                        self.ui.report("Nothing to undo");
+                    },
+                    Action::Redo => if self.module.redo() {
+                        self.player.update_synths(self.module.drain_track_history());
+                        fix_patch_index(&mut self.instruments_state.patch_index,
+                            self.module.patches.len());
+                    } else {
+                        self.ui.report("Nothing to redo");
+                    },
+                    Action::ToggleInstrumentPanel =>
+                        self.config.instrument_panel = !self.config.instrument_panel,
+                    Action::ToggleFullscreen => self.toggle_fullscreen(),
+                    Action::ToggleCheatSheet => self.cheat_sheet = !self.cheat_sheet,
+                    Action::NextTab => self.ui.next_tab(MAIN_TAB_ID, TABS.len()),
+                    Action::PrevTab => self.ui.prev_tab(MAIN_TAB_ID, TABS.len()),
+                    Action::Panic => self.player.panic(),
+                    Action::EditMarker => if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+                        self.pattern_editor.edit_marker(&self.module, &mut self.ui);
+                    },
+                    Action::EditCondition => if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+                        self.pattern_editor.edit_condition(&self.module, &mut self.ui);
+                    },
+                    Action::EditSlide => if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+                        self.pattern_editor.edit_slide(&self.module, &mut self.ui);
+                    },
+                    Action::EditEffects => if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+                        self.pattern_editor.edit_effects(&self.module, &mut self.ui);
+                    },
+                    Action::AnalyzeIntervals => if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+                        self.pattern_editor.analyze_intervals(&self.module, &mut self.ui);
+                    },
+                    _ => if self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PATTERN) {
+                        self.pattern_editor.action(*action, &mut self.module, &self.config,
+                            &mut self.player);
+                    },
+                }
+            } else if let Some(action) = self.config.hotkey_action(&hk.without_shift()) {
+                // these actions have some special behavior when used with shift
+                match action {
+                    Action::NextRow | Action::PrevRow
+                        | Action::NextColumn | Action::PrevColumn
+                        | Action::NextBeat | Action::PrevBeat
+                        | Action::NextEvent | Action::PrevEvent
+                        | Action::PatternStart | Action::PatternEnd
+                        | Action::Delete | Action::NoteOff =>
+                            self.pattern_editor.action(
+                                *action, &mut self.module, &self.config, &mut self.player),
+                    _ => (),
+                }
+            }
+
+            // translate pressed keys into note-ons
+            let zone = self.perform_state.split_zone(key).copied();
+            let octave = self.octave.saturating_add(zone.map_or(0, |z| z.octave));
+            let note = input::note_from_key(
+                hk, &self.module.tuning, octave, &self.config);
+            if let Some(note) = note {
+                let key = Key::new_from_keyboard(input::u8_from_key(key));
+                self.routed_track = zone.and_then(|z| z.track);
+                self.queue_note(key.clone(), EventData::Pitch(note));
+                if !(self.ui.accepting_note_input()
+                    || self.pattern_editor.in_digit_column(&self.ui)
+                    || self.pattern_editor.in_global_track(&self.ui)
+                ) {
+                    let index = zone.and_then(|z| z.patch)
+                        .filter(|&i| i < self.module.patches.len())
+                        .or_else(|| self.keyjazz_patch_index());
+                    if let Some((patch, note)) = self.module.map_input(index, note) {
+                        let pitch = self.module.track_pitch(&note, self.keyjazz_track());
+                        self.keyjazz_note_on(key, pitch, None, patch);
+                    }
+                }
+                self.routed_track = None;
+            }
+        }
+    }
+
+    /// Attempt to connect to a MIDI input port.
+    fn midi_connect(&mut self, port_name: &str) -> Result<MidiConnection, Box<dyn Error>> {
+        let port = self.midi.find_port(port_name)?;
+        let mut input = self.midi.new_input()?;
+
+        // ignore SysEx, time, and active sensing
+        input.ignore(midir::Ignore::All);
+
+        let (tx, rx) = channel();
+        let conn = input.connect(
+            &port,
+            APP_NAME,
+            move |_, message, tx| {
+                // ignore the error here, it probably just means that the
+                // user disconnected the port
+                let _ = tx.send(message.to_vec());
+            },
+            tx,
+        )?;
+        Ok(MidiConnection {
+            port_name: port_name.to_owned(),
+            conn,
+            rx,
+            rpn: (0, 0),
+            bend_range: 2.0,
+        })
+    }
+
+    /// Handle incoming MIDI messages.
+    fn handle_midi(&mut self) {
+        for (i, evt) in self.get_midi_events() {
+            // a fixed track for the input overrides channel routing
+            self.routed_track = self.config.midi_inputs.iter()
+                .find(|s| s.port_name == self.midi.conns[i].port_name)
+                .and_then(|s| s.track)
+                .or_else(|| self.config.midi_route(evt.channel()));
+            self.handle_midi_event(i, evt);
+        }
+        self.routed_track = None;
+    }
+
+    /// Collect incoming MIDI events that pass their input's channel filter,
+    /// paired with the index of their connection.
+    fn get_midi_events(&mut self) -> Vec<(usize, MidiEvent)> {
+        let mut v = Vec::new();
+
+        for (i, conn) in self.midi.conns.iter().enumerate() {
+            let settings = self.config.midi_inputs.iter()
+                .find(|s| s.port_name == conn.port_name);
+            while let Ok(chunk) = conn.rx.try_recv() {
+                if let Some(evt) = MidiEvent::parse(&chunk) {
+                    if settings.is_none_or(|s| s.accepts(evt.channel())) {
+                        v.push((i, evt));
+                    }
+                }
+            }
+        }
+
+        v
+    }
+
+    /// Handle an incoming MIDI message from the connection at index `conn`.
+    fn handle_midi_event(&mut self, conn: usize, evt: MidiEvent) {
+        let evt = match self.surface.translate(&evt) {
+            Some(SurfaceInput::Section(i)) => {
+                self.perform_state.queue(i, &self.module, &mut self.player);
+                return
+            }
+            Some(SurfaceInput::ToggleMute(track)) => {
+                if track < self.module.tracks.len() {
+                    self.player.toggle_mute(track);
+                }
+                return
+            }
+            Some(SurfaceInput::Note(evt)) => evt,
+            Some(SurfaceInput::Ignore) => return,
+            None => evt,
+        };
+
+        match evt {
+            MidiEvent::NoteOff { channel, key, .. } => {
+                let key = Key::new_from_midi(channel, key);
+                self.keyjazz_note_off(key.clone());
+                self.queue_note(key, EventData::NoteOff);
+            },
+            MidiEvent::NoteOn { channel, key, velocity } => {
+                if velocity != 0 && self.ui.get_tab(MAIN_TAB_ID) == Some(TAB_PERFORM)
+                    && self.perform_state.trigger(
+                        SectionTrigger::MidiNote(key), &mut self.module, &mut self.player) {
+                    return
+                }
+                let key = Key::new_from_midi(channel, key);
+                if velocity != 0 {
+                    let note = input::note_from_midi(
+                        key.key, &self.module.tuning, &self.config);
+                    self.queue_note(key.clone(), EventData::Pitch(note));
+                    let pressure = self.config.velocity.pressure(velocity);
+                    if self.config.midi_send_velocity {
+                        let v = (pressure * EventData::DIGIT_MAX as f32).round() as u8;
+                        self.queue_note(key.clone(), EventData::Pressure(v));
+                    }
+
+                    let index = self.keyjazz_patch_index();
+                    if let Some((patch, mapped_note)) = self.module.map_input(index, note) {
+                        if !self.ui.accepting_note_input() {
+                            let pitch = self.module.track_pitch(
+                                &mapped_note, self.keyjazz_track());
+                            let pressure = self.config.midi_send_velocity
+                                .then_some(pressure);
+                            self.keyjazz_note_on(key.clone(), pitch, pressure, patch);
+                        }
+                    }
+                } else {
+                    self.keyjazz_note_off(key.clone());
+                    self.queue_note(key, EventData::NoteOff);
+                }
+            },
+            MidiEvent::PolyPressure { channel, key, pressure } => {
+                if self.config.midi_send_pressure == Some(true) {
+                    let key = Key::new_from_midi(channel, key);
+                    self.player.poly_pressure(self.keyjazz_track(), key.clone(),
+                        pressure as f32 / 127.0);
+                    let v = EventData::digit_from_midi(pressure);
+                    self.queue_note(key, EventData::Pressure(v));
+                }
+            },
+            MidiEvent::Controller { channel, controller, value } => {
+                let norm_value = value as f32 / 127.0;
+                match controller {
+                    input::CC_MODULATION | input::CC_MACRO_MIN..=input::CC_MACRO_MAX => {
+                        self.player.modulate(self.keyjazz_track(), channel, norm_value);
+                    },
+                    input::CC_RPN_MSB => self.midi.conns[conn].rpn.0 = value,
+                    input::CC_RPN_LSB => self.midi.conns[conn].rpn.1 = value,
+                    input::CC_DATA_ENTRY_MSB => {
+                        let conn = &mut self.midi.conns[conn];
+                        if conn.rpn == input::RPN_PITCH_BEND_SENSITIVITY {
+                            // set semitones
+                            conn.bend_range = conn.bend_range % 1.0 + norm_value as f32;
+                        }
+                    },
+                    input:: CC_DATA_ENTRY_LSB => {
+                        let conn = &mut self.midi.conns[conn];
+                        if conn.rpn == input::RPN_PITCH_BEND_SENSITIVITY {
+                            // set cents
+                            conn.bend_range = conn.bend_range.floor() + norm_value as f32 / 100.0;
+                        }
+                    },
+                    _ => (),
+                }
+            },
+            MidiEvent::ChannelPressure { channel, pressure } => {
+                if self.config.midi_send_pressure == Some(true) {
+                    self.player.channel_pressure(self.keyjazz_track(),
+                        channel, pressure as f32 / 127.0);
+                    let key = Key::new_from_midi(channel, 0);
+                    let v = EventData::digit_from_midi(pressure);
+                    self.queue_note(key, EventData::Pressure(v));
+                }
+            },
+            MidiEvent::Pitch { channel, bend } => {
+                let semitones = bend * self.midi.conns[conn].bend_range;
+                self.player.pitch_bend(self.keyjazz_track(), channel, semitones);
+                let key = Key::new_from_midi(channel, 0);
+                let data = EventData::Bend((semitones * 100.0).round() as i16);
+                self.queue_note(key, data);
+            },
+        }
+    }
+
+    /// Connect and disconnect MIDI inputs to match the configured list.
+    fn check_midi_reconnect(&mut self) {
+        let inputs = &self.config.midi_inputs;
+        let (conns, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.midi.conns).into_iter()
+            .partition(|c| inputs.iter().any(|s| s.port_name == c.port_name));
+        self.midi.conns = conns;
+        for c in removed {
+            c.conn.close();
+        }
+
+        let names: Vec<_> = self.config.midi_inputs.iter()
+            .map(|s| s.port_name.clone())
+            .filter(|name| !self.midi.is_port_connected(name))
+            .collect();
+        for name in names {
+            match self.midi_connect(&name) {
+                Ok(conn) => self.midi.conns.push(conn),
+                Err(e) => {
+                    self.config.midi_inputs.retain(|s| s.port_name != name);
+                    self.ui.report(format!("MIDI connection to {name} failed: {e}"));
+                },
+            }
+        }
+    }
+
+    /// Do 1 frame. Returns false if it's quitting time.
+    fn frame(&mut self) -> bool {
+        if self.dev_state.only_draw_on_input && !mouse_kb_input() {
+            return true
+        }
+
+        self.player.update();
+        self.player.build_voices(&self.module.patches);
+
+        if is_quit_requested() {
+            if self.module.has_unsaved_changes {
+                self.ui.confirm("Discard unsaved changes?", Action::Quit);
+            } else {
+                self.save_config();
+                return false
+            }
+        }
+
+        if self.config.autosave
+            && self.module.has_unsaved_changes
+            && self.last_autosave_time.elapsed() > Self::AUTOSAVE_INTERVAL {
+            self.autosave();
+        }
+
+        if self.config.watch_samples {
+            let changed = self.sample_watch.poll(&self.module);
+            if !changed.is_empty() {
+                self.reload_samples(&changed);
+            }
+        }
+
+        if self.ui.accepting_keyboard_input() {
+            self.player.clear_notes_with_origin(KeyOrigin::Keyboard);
+        } else {
+            self.handle_keys();
+        }
+
+        if self.ui.accepting_note_input() {
+            self.player.clear_notes_with_origin(KeyOrigin::Midi);
+        }
+
+        // ctrl+scroll. this is here instead of in pattern code because
+        // division can always be changed
+        if is_ctrl_down() && mouse_wheel().1 != 0.0 {
+            let pe = &mut self.pattern_editor;
+            let d = mouse_wheel().1.signum() as i8;
+            pe.set_division(if !is_alt_down() {
+                pe.beat_division.saturating_add_signed(d)
+            } else if d > 0 {
+                pe.beat_division.saturating_mul(2)
+            } else {
+                pe.beat_division / 2
+            });
+        }
+
+        if self.player.is_playing() {
+            let end_tick = self.module.last_event_tick().unwrap_or_default()
+                + Timespan::new(1, 1);
+            if self.player.get_tick() > end_tick {
+                self.player.stop();
+            }
+        }
+
+        self.handle_midi();
+        self.surface.update_leds(&self.module, &self.player);
+
+        self.handle_async_updates();
+        self.check_midi_reconnect();
+        let quit = self.process_ui();
+        self.sync_edits();
+        quit
+    }
+
+    fn sync_edits(&mut self) {
+        let mut fx_changed = false;
+        for edit in self.module.sync_edits() {
+            let patch_indices = match edit {
+                Edit::InsertPatch(i, _) => i..i + 1,
+                Edit::SetPatches { .. } => 0..self.module.patches.len(),
+                _ => 0..0,
+            };
+            fx_changed |= matches!(edit, Edit::SetFX(_));
+            self.module_sync.push(ModuleCommand::Edit(edit));
+            for i in patch_indices {
+                self.module_sync.push(
+                    ModuleCommand::Patch(i, self.module.patches[i].shared_clone()));
+            }
+        }
+        if fx_changed {
+            self.fx.update(&self.module.fx);
+        }
+        if self.module_sync.take_overflow() {
+            self.module_sync.push(ModuleCommand::Load(self.module.shared_clone()));
+            self.player.reinit();
+        }
+        self.module_sync.flush();
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.module.end_gesture();
+        }
+    }
+
+    /// Record the current window geometry and tab in the config.
+    fn store_window_state(&mut self) {
+        let state = &mut self.config.window;
+        state.tab = self.ui.get_tab(MAIN_TAB_ID).unwrap_or_default();
+        if !state.fullscreen {
+            state.width = screen_width().round() as i32;
+            state.height = screen_height().round() as i32;
+            state.position = Some(get_window_position());
+        }
+    }
+
+    /// Switch between fullscreen and windowed mode, restoring the windowed
+    /// size on the way out.
+    fn toggle_fullscreen(&mut self) {
+        self.store_window_state();
+        let state = &mut self.config.window;
+        state.fullscreen = !state.fullscreen;
+        set_fullscreen(state.fullscreen);
+        if !state.fullscreen {
+            set_window_size(state.width as u32, state.height as u32);
+            if let Some((x, y)) = state.position {
+                set_window_position(x, y);
+            }
+        }
+    }
+
+    /// Save config to disk, logging errors.
+    fn save_config(&mut self) {
+        self.store_window_state();
+        if let Err(e) = self.config.save(self.ui.style.theme.clone()) {
+            eprintln!("error saving config: {}", e);
+        }
+    }
+
+    /// Handle incoming async status updates.
+    fn handle_async_updates(&mut self) {
+        while let Ok(update) = self.update_rx.try_recv() {
+            match update {
+                StatusUpdate::Progress(f) =>
+                    self.ui.notify(format!("Rendering: {}%", (f * 100.0).round())),
+                StatusUpdate::Done(mut wav, path) => {
+                    let loudness = self.finish_render(&mut wav);
+                    let peak = amp_db(wav.amplitude());
+
+                    let write_result = match self.config.render_format {
+                        RenderFormat::Wav16 => wav.save_wav16(path),
+                        RenderFormat::Wav32 => wav.save_wav32(path),
+                    };
+                    match write_result {
+                        Ok(_) => self.ui.notify(match loudness {
+                            Some(lufs) => format!(
+                                "Wrote WAV. Loudness: {lufs:.1} LUFS, peak: {peak:.1} dBFS"),
+                            None => String::from("Wrote WAV."),
+                        }),
+                        Err(e) => self.ui.report(format!("Writing WAV failed: {e}")),
+                    }
+                }
+                StatusUpdate::Autosave => self.ui.notify(String::from("Autosaved module.")),
+                StatusUpdate::AutosaveError(e) =>
+                    self.ui.notify(format!("Autosave error: {e}")),
+                StatusUpdate::VideoDone(path) => self.ui.notify(format!("Wrote video to {}.",
+                    path.file_name().unwrap_or_default().to_string_lossy())),
+                StatusUpdate::VideoError(e) =>
+                    self.ui.report(format!("Exporting video failed: {e}")),
+                StatusUpdate::Preview(mut wav) => {
+                    let loudness = self.finish_render(&mut wav);
+                    let peak = amp_db(wav.amplitude());
+                    self.ui.notify(match loudness {
+                        Some(lufs) => format!(
+                            "Playing preview. Loudness: {lufs:.1} LUFS, peak: {peak:.1} dBFS"),
+                        None => String::from("Playing preview."),
+                    });
+                    self.player.play_preview(wav);
+                }
+            }
+        }
+    }
+
+    /// Apply loudness normalization, then quantization and dither from the
+    /// render settings. Returns the loudness of the result, if measurable.
+    fn finish_render(&self, wav: &mut Wave) -> Option<f32> {
+        let mut loudness = dsp::loudness(wav);
+        if let (Some(lufs), true) = (loudness, self.config.normalize_loudness) {
+            let target = self.config.loudness_target;
+            wav.amplify(db_amp(target - lufs));
+            loudness = Some(target);
+        }
+        if let RenderFormat::Wav16 = self.config.render_format {
+            if self.config.dither {
+                dsp::dither16(wav, self.config.noise_shaping);
+            } else {
+                dsp::quantize16(wav);
+            }
+        }
+        loudness
+    }
+
+    /// Process the UI for 1 frame. Returns false if it's quitting time.
+    fn process_ui(&mut self) -> bool {
+        // process actions confirmed via dialog
+        if let Some(action) = self.ui.start_frame(&self.config) {
+            match action {
+                Action::NewSong => self.new_module(),
+                Action::OpenSong => self.open_module(),
+                Action::StartTutorial => self.start_tutorial(),
+                Action::Quit => {
+                    self.save_config();
+                    return false
+                }
+                _ => panic!("unhandled dialog action: {:?}", action),
+            }
+        }
+
+        if self.cheat_sheet {
+            self.cheat_sheet = ui::cheat_sheet::handle_click(&mut self.ui);
+        }
+
+        self.bottom_panel();
+
+        // macro hotkeys are only learned while the macro controls are visible
+        if self.ui.get_tab(MAIN_TAB_ID) != Some(TAB_GENERAL) {
+            self.general_state.learning_macro = None;
+        }
+
+        match self.ui.tab_menu(MAIN_TAB_ID, &TABS, &self.version) {
+            TAB_GENERAL => {
+                let tuning_changed = ui::general::draw(
+                    &mut self.ui, &mut self.module, &mut self.config,
+                    &mut self.player, &mut self.general_state);
+                if std::mem::take(&mut self.general_state.preview_render) {
+                    self.preview_render();
+                }
+                if std::mem::take(&mut self.general_state.start_tutorial) {
+                    if self.module.has_unsaved_changes {
+                        self.ui.confirm("Discard unsaved changes?", Action::StartTutorial);
+                    } else {
+                        self.start_tutorial();
+                    }
+                }
+                if tuning_changed {
+                    self.module_sync.push(
+                        ModuleCommand::Tuning(self.module.tuning.clone()));
+                }
+            }
+            TAB_PATTERN => {
+                if self.config.instrument_panel {
+                    ui::instruments::draw_panel(&mut self.ui, &mut self.module,
+                        &mut self.instruments_state, &mut self.config, &mut self.player,
+                        &mut self.module_sync, self.save_path.as_ref());
+                    if let Some(pos) = self.instruments_state.jump.take() {
+                        self.pattern_editor.jump_to_position(pos);
+                    }
+                }
+                ui::pattern::draw(&mut self.ui, &mut self.module,
+                    &mut self.player, &mut self.pattern_editor, &self.config)
+            }
+            TAB_INSTRUMENTS => {
+                ui::instruments::draw(&mut self.ui, &mut self.module,
+                    &mut self.instruments_state, &mut self.config, &mut self.player,
+                    &mut self.module_sync, self.save_path.as_ref());
+                if let Some(pos) = self.instruments_state.jump.take() {
+                    self.pattern_editor.jump_to_position(pos);
+                    self.ui.set_tab(MAIN_TAB_ID, TAB_PATTERN);
+                }
+            }
+            TAB_SETTINGS => ui::settings::draw(&mut self.ui, &mut self.config,
+                &mut self.settings_state, &mut self.player, &mut self.midi,
+                &mut self.surface),
+            TAB_PERFORM => ui::perform::draw(&mut self.ui, &mut self.module,
+                &mut self.player, &mut self.perform_state),
+            TAB_SCOPES => ui::scopes::draw(&mut self.ui, &self.module, &self.player,
+                &mut self.scopes_state),
+            TAB_DEVELOPER => ui::developer::draw(&mut self.ui, &mut self.dev_state,
+                &self.player),
+            _ => panic!("bad tab value"),
+        }
+
+        if let Some(tutorial) = &mut self.tutorial {
+            tutorial.draw(&mut self.ui, &self.config);
+        }
+        if self.cheat_sheet {
+            ui::cheat_sheet::draw(&mut self.ui, &self.config);
+        }
+
+        let tab_nav = self.ui.get_tab(MAIN_TAB_ID).is_none_or(|i| i != TAB_PATTERN);
+        self.ui.end_frame(tab_nav);
+        true
+    }
+
+    /// Draw the status panel at the bottom of the screen.
+    fn bottom_panel(&mut self) {
+        self.ui.start_bottom_panel();
+
+        if let Some(tutorial) = &mut self.tutorial {
+            if !tutorial.controls(&mut self.ui) {
+                self.tutorial = None;
+            }
+        }
+
+        if let Some(n) = self.ui.edit_box("Octave", 2, self.octave.to_string(),
+            Info::Octave
+        ) {
+            match n.parse::<i8>() {
+                Ok(n) => self.octave = n,
+                Err(e) => self.ui.report(e),
+            }
+        }
+
+        // the step is saved with the module
+        let old_step = self.module.edit_step;
+        let step = &mut self.module.edit_step;
+        if let Some(n) = self.ui.edit_box("Step", 2, step.note.to_string(),
+            Info::EditStep("note column")
+        ) {
+            match n.parse::<u8>() {
+                Ok(n) => step.note = n,
+                Err(e) => self.ui.report(e),
+            }
+        }
+        if let Some(n) = self.ui.edit_box("Value step", 2, step.value.to_string(),
+            Info::EditStep("pressure and modulation columns")
+        ) {
+            match n.parse::<u8>() {
+                Ok(n) => step.value = n,
+                Err(e) => self.ui.report(e),
+            }
+        }
+        if self.module.edit_step != old_step {
+            self.module.has_unsaved_changes = true;
+        }
+
+        self.ui.shared_slider("stereo_width", "Stereo width",
+            &self.stereo_width, -1.0..=1.0, None, 1, true, Info::StereoWidth);
+
+        if self.ui.checkbox("Latch", &mut self.keyjazz_latch, true, Info::KeyjazzLatch)
+            && !self.keyjazz_latch {
+            self.release_latched();
+        }
+
+        let label = if self.chord_learning { "Done" } else { "Learn chord" };
+        if self.ui.button(label, true, Info::LearnChord) {
+            self.toggle_chord_learning();
+        }
+        if !self.chord.is_empty() && !self.chord_learning
+            && self.ui.button("Clear chord", true, Info::ClearChord) {
+            self.chord.clear();
+        }
+
+        match self.ui.get_tab(MAIN_TAB_ID) {
+            Some(TAB_PATTERN) => {
+                if let Some(n) = self.ui.edit_box("Division", 3,
+                    self.pattern_editor.beat_division.to_string(), Info::Division
+                ) {
+                    match n.parse::<u8>() {
+                        Ok(n) => self.pattern_editor.set_division(n),
+                        Err(e) => self.ui.report(e),
+                    }
+                }
+                let preset = ui::pattern::DIVISION_PRESETS.iter()
+                    .find(|(_, div)| *div == self.pattern_editor.beat_division)
+                    .map_or("Custom", |(name, _)| name);
+                if let Some(i) = self.ui.combo_box("division_presets", "", preset,
+                    Info::DivisionPresets,
+                    || ui::pattern::DIVISION_PRESETS.iter()
+                        .map(|(name, div)| format!("{name} ({div})"))
+                        .collect()
+                ) {
+                    self.pattern_editor.set_division(ui::pattern::DIVISION_PRESETS[i].1);
+                }
+
+                self.ui.checkbox("Follow", &mut self.pattern_editor.follow, true,
+                    Info::FollowCheckbox);
+                self.ui.checkbox("Draw", &mut self.pattern_editor.draw_mode, true,
+                    Info::Action(Action::ToggleDrawMode));
+                self.ui.checkbox("Lane", &mut self.pattern_editor.lane.visible, true,
+                    Info::Action(Action::ToggleAutomationLane));
+                if self.pattern_editor.lane.visible {
+                    let tool = &mut self.pattern_editor.lane.tool;
+                    if let Some(i) = self.ui.combo_box("lane_tool", "Tool", tool.name(),
+                        Info::LaneTool,
+                        || LaneTool::VARIANTS.map(|t| t.name().to_owned()).to_vec()
+                    ) {
+                        *tool = LaneTool::VARIANTS[i];
+                    }
+                }
+                self.ui.checkbox("Instruments", &mut self.config.instrument_panel, true,
+                    Info::InstrumentPanel);
+
+                let cursor_time = self.module.time_between(Timespan::ZERO,
+                    self.pattern_editor.cursor_tick());
+                if let Some(s) = self.ui.edit_box("Time", 9, ui::format_time(cursor_time),
+                    Info::SongTime
+                ) {
+                    match ui::parse_time(&s) {
+                        Ok(t) => self.pattern_editor.jump_to(self.module.tick_at_time(t)),
+                        Err(e) => self.ui.report(e),
+                    }
+                }
+                if self.player.is_playing() {
+                    let tick = self.player.get_tick();
+                    let time = self.module.time_between(Timespan::ZERO, tick);
+                    let beat = ((tick.as_f64() + 1.0) * 100.0).round() / 100.0;
+                    self.ui.label(&format!("Playhead: beat {beat}, {}", ui::format_time(time)),
+                        Info::SongTime);
+                }
+
+                if !self.module.markers.is_empty() {
+                    let markers = &self.module.markers;
+                    if let Some(i) = self.ui.combo_box("markers", "Marker", "Jump to",
+                        Info::Markers,
+                        || markers.iter().map(|m| m.text.clone()).collect()
+                    ) {
+                        self.pattern_editor.jump_to(markers[i].tick);
+                    }
+                }
+
+                match self.pattern_editor.selection_summary(&self.module) {
+                    Some(text) => self.ui.label(text, Info::SelectionSummary),
+                    None => {
+                        let text = self.pattern_editor.inspector_text(&self.module);
+                        self.ui.label(&text, Info::Inspector);
+                    }
+                }
+            }
+            _ => {
+                const MAX: f32 = EventData::DIGIT_MAX as f32;
+                if self.ui.formatted_slider("modulation", "Modulation",
+                    &mut self.keyjazz_modulation, 0.0..=MAX, 1, true,
+                    Info::KeyjazzModulation, |x| format!("{:X}", x.round() as u8),
+                    |x| x.round()
+                ) {
+                    self.player.modulate(self.keyjazz_track(), 0,
+                        self.keyjazz_modulation / MAX);
+                }
+                if let Some(pitch) = self.tuner_pitch {
+                    self.ui.label(&self.module.tuning.tuner_text(pitch), Info::Tuner);
+                }
+            }
+        }
+
+        self.ui.end_bottom_panel();
+    }
+
+    /// Start rendering a short excerpt of the render range to audition
+    /// render settings.
+    fn preview_render(&mut self) {
+        let module = Arc::new(self.module.clone());
+        playback::render_preview(module, self.general_state.render_range,
+            self.render_voice_settings(), self.update_tx.clone());
+    }
+
+    /// Returns voice settings for rendering, with the render quality override
+    /// applied. Renders ignore the configured global voice settings, so that a
+    /// module renders the same on any machine; patch overrides still apply.
+    fn render_voice_settings(&self) -> VoiceSettings {
+        VoiceSettings {
+            oversampling: self.config.render_quality,
+            ..Default::default()
+        }
+    }
+
+    /// Browse for and start rendering a WAV file.
+    fn render_and_save(&mut self, tracks: bool) {
+        let range = self.general_state.render_range;
+        if self.module.ends() || range.end.is_some() {
+            let dialog = ui::new_file_dialog(&mut self.player)
+                .add_filter("WAV file", &["wav"])
+                .set_directory(self.config.render_folder.clone()
+                    .unwrap_or(String::from(".")))
+                .set_file_name(self.module.title.clone());
+
+            if let Some(mut path) = dialog.save_file() {
+                path.set_extension("wav");
+                self.config.render_folder = config::dir_as_string(&path);
+                let module = Arc::new(self.module.clone());
+                let tx = self.update_tx.clone();
+                let voices = self.render_voice_settings();
+                if tracks {
+                    playback::render_tracks(module, path, range, voices, tx)
+                } else {
+                    playback::render(module, path, None, range, voices, tx)
+                };
+            }
+        } else {
+            self.ui.report("Module must have End event or render range to export")
+        }
+    }
+
+    /// Browse for and start exporting a video of track oscilloscopes.
+    fn export_video(&mut self) {
+        if !self.module.ends() {
+            self.ui.report("Module must have End event to export");
+            return
+        }
+
+        let dialog = ui::new_file_dialog(&mut self.player)
+            .add_filter("MP4 video (requires ffmpeg)", &["mp4"])
+            .add_filter("PNG sequence", &["png"])
+            .set_directory(self.config.render_folder.clone()
+                .unwrap_or(String::from(".")))
+            .set_file_name(self.module.title.clone());
+
+        if let Some(mut path) = dialog.save_file() {
+            if path.extension().is_none() {
+                path.set_extension("mp4");
+            }
+            self.config.render_folder = config::dir_as_string(&path);
+            let theme = &self.ui.style.theme;
+            let colors = VideoColors {
+                bg: theme.content_bg(),
+                fg: theme.fg(),
+                line: theme.control_bg(),
+            };
+            video::export(Arc::new(self.module.clone()), VideoOutput::from_path(path),
+                self.render_voice_settings(), colors, self.update_tx.clone());
+        }
+    }
+
+    /// Browse for and save a MIDI file of the pattern selection.
+    fn export_midi(&mut self) {
+        let dialog = ui::new_file_dialog(&mut self.player)
+            .add_filter("MIDI file", &["mid"])
+            .set_directory(self.config.render_folder.clone()
+                .unwrap_or(String::from(".")))
+            .set_file_name(self.module.title.clone());
+
+        if let Some(mut path) = dialog.save_file() {
+            path.set_extension("mid");
+            self.config.render_folder = config::dir_as_string(&path);
+            let (tracks, ticks) = self.pattern_editor.export_range(&self.module);
+            match std::fs::write(&path, smf::export(&self.module, tracks, ticks)) {
+                Ok(_) => self.ui.notify(String::from("Wrote MIDI file.")),
+                Err(e) => self.ui.report(format!("Writing MIDI file failed: {e}")),
+            }
+        }
+    }
+
+    /// Handle the "new song" key command.
+    fn new_module(&mut self) {
+        self.load_module(Module::new(Default::default()), None);
+    }
+
+    /// Handle the "save song" key command.
+    fn save_module(&mut self) {
+        self.store_mutes();
+        if let Some(path) = &self.save_path {
+            if let Err(e) = self.module.save(self.pattern_editor.beat_division, path) {
+                self.ui.report(format!("Error saving module: {e}"));
+            } else {
+                self.ui.notify(String::from("Saved module."));
+            }
+        } else {
+            self.save_module_as();
+        }
+    }
+
+    /// Handle the "save song as" key command.
+    fn save_module_as(&mut self) {
+        self.store_mutes();
+        let dialog = self.module_dialog().set_file_name(self.module.title.clone());
+
+        if let Some(mut path) = dialog.save_file() {
+            path.set_extension(MODULE_EXT);
+            self.config.module_folder = config::dir_as_string(&path);
+            if let Err(e) = self.module.save(self.pattern_editor.beat_division, &path) {
+                self.ui.report(format!("Error saving module: {e}"));
+            } else {
+                self.save_path = Some(path);
+                self.ui.notify(String::from("Saved module."));
+            }
+        }
+    }
+
+    /// Autosave in a separate thread.
+    fn autosave(&mut self) {
+        self.last_autosave_time = Instant::now();
+        let path = exe_relative_path(&format!("autosave.{}", MODULE_EXT));
+        self.store_mutes();
+        let mut module = self.module.clone();
+        let tx = self.update_tx.clone();
+        let div = self.pattern_editor.beat_division;
+        thread::spawn(move || {
+            if let Err(e) = module.save(div, &path) {
+                tx.send(StatusUpdate::AutosaveError(e.to_string()))
+            } else {
+                tx.send(StatusUpdate::Autosave)
+            }
+        });
+    }
+
+    /// Reload referenced samples from modified files, as undoable edits.
+    fn reload_samples(&mut self, paths: &[PathBuf]) {
+        let (edits, names) = self.module.reload_samples(paths);
+        for edit in edits {
+            self.module.push_edit(edit);
+        }
+        if !names.is_empty() {
+            self.ui.notify(format!("Reloaded {}.", names.join(", ")));
+        }
+    }
+
+    /// Copy track mute states from the player so that they're saved.
+    fn store_mutes(&mut self) {
+        let mutes = self.player.tracks_muted();
+        for (track, muted) in self.module.tracks.iter_mut().zip(mutes) {
+            track.muted = *muted;
+        }
+    }
+
+    /// Handle the "open song" key command.
+    fn open_module(&mut self) {
+        if let Some(path) = self.module_dialog().pick_file() {
+            self.config.module_folder = config::dir_as_string(&path);
+            match Module::load(&path) {
+                Ok(new_module) => self.load_module(new_module, Some(path)),
+                Err(e) => self.ui.report(format!("Error loading module: {e}")),
+            }
+        }
+    }
+
+    /// Load the demo song and start the guided tour.
+    fn start_tutorial(&mut self) {
+        match Module::from_bytes(DEMO_MODULE, Path::new(".")) {
+            Ok(module) => {
+                self.load_module(module, None);
+                self.tutorial = Some(Tutorial::start(&mut self.ui));
+            }
+            Err(e) => self.ui.report(format!("Error loading demo song: {e}")),
+        }
+    }
+
+    fn module_dialog(&mut self) -> FileDialog {
+        let dir = self.config.module_folder.clone().unwrap_or(String::from("."));
+        ui::new_file_dialog(&mut self.player)
+            .add_filter(MODULE_FILETYPE_NAME, &[MODULE_EXT])
+            .set_directory(dir)
+    }
+
+    /// Replace the current module with `module`, reinitializing state as
+    /// needed.
+    fn load_module(&mut self, new_mod: Module, save_path: Option<PathBuf>) {
+        self.save_path = save_path;
+        self.module_sync.push(ModuleCommand::Load(new_mod.shared_clone()));
+        self.module = new_mod;
+        self.module.sync = true;
+        self.sample_watch = SampleWatch::new();
+        self.general_state.render_range = Default::default();
+        self.general_state.retune_from = None;
+        self.general_state.learning_macro = None;
+        let follow = self.pattern_editor.follow;
+        self.pattern_editor = PatternEditor::default();
+        self.pattern_editor.beat_division = self.module.division;
+        self.pattern_editor.follow = follow;
+        self.instruments_state.patch_index = if self.module.patches.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        self.player.reinit();
+        self.fx.reinit(&self.module.fx);
+    }
+}
+
+/// Returns JACK if available, otherwise ALSA.
+#[cfg(target_os = "linux")]
+fn get_audio_device() -> Option<cpal::Device> {
+    cpal::host_from_id(cpal::HostId::Jack).ok()
+        .and_then(|host| host.default_output_device())
+        .or_else(|| cpal::default_host().default_output_device())
+}
+
+/// Returns the default device.
+#[cfg(not(target_os = "linux"))]
+fn get_audio_device() -> Option<cpal::Device> {
+    cpal::default_host().default_output_device()
+}
+
+/// Returns the best available audio output stream config.
+fn preferred_config(device: &cpal::Device, desired_sr: SampleRate
+) -> Result<StreamConfig, Box<dyn Error>> {
+    device.supported_output_configs()?
+        .filter(|conf| conf.channels() == 2)
+        .max_by_key(|conf| (
+            conf.sample_format().sample_size() > 1,
+            conf.max_sample_rate() >= desired_sr,
+            conf.min_sample_rate() <= desired_sr,
+            conf.sample_format() == cpal::SampleFormat::F32
+        )).map(|conf| {
+            let sr = desired_sr.clamp(conf.min_sample_rate(), conf.max_sample_rate());
+            conf.with_sample_rate(sr).into()
+        }).ok_or("no supported audio config".into())
+}
+
+/// Returns the window state saved in the config, for initial WM settings.
+pub fn saved_window_state() -> WindowState {
+    Config::load().map(|c| c.window).unwrap_or_default()
+}
+
+/// Application entry point.
+pub async fn run(arg: Option<String>) -> Result<(), Box<dyn Error>> {
+    let conf = Config::load().unwrap_or_default();
+    let device = get_audio_device();
+
+    let audio_conf: Result<StreamConfig, Box<dyn Error>> = device.as_ref()
+        .ok_or("no audio output device".into())
+        .and_then(|device| preferred_config(device, SampleRate(conf.desired_sample_rate)));
+    let sample_rate = audio_conf.as_ref()
+        .map(|config| config.sample_rate.0)
+        .unwrap_or(44100);
+    let cloned_conf = audio_conf.as_ref().cloned().ok();
+
+    let mut seq = Sequencer::new(false, 4);
+    seq.set_sample_rate(sample_rate as f64);
+
+    // the sequencer backend is probably not necessary anymore due to mutexing,
+    // but it's still convenient for ownership reasons.
+    let fx_settings: FXSettings = Default::default();
+    let mut global_fx = GlobalFX::new(seq.backend(), &fx_settings);
+    global_fx.net.set_sample_rate(sample_rate as f64);
+    let mut backend = BlockProcessor::new(Box::new(global_fx.net.backend()));
+
+    let module = Module::new(fx_settings);
+    let mut player = Player::new(seq, module.tracks.len(), sample_rate as f32);
+    player.voice_settings = conf.voices;
+    let (discard_producer, discard_consumer) = RingBuffer::new(64);
+    player.jukebox.set_discard(discard_producer);
+    let (voice_producer, voice_consumer) = RingBuffer::new(16);
+    player.set_voice_source(voice_consumer);
+    let (player_cmd_producer, mut player_cmd_consumer) = RingBuffer::new(10);
+    let (module_cmd_producer, mut module_cmd_consumer) = RingBuffer::new(10);
+    let (mut player_state_input, player_state_output) = triple_buffer(&player.state());
+
+    // player updates are aligned to processing blocks
+    const UPDATE_FRAMES: u32 = BlockProcessor::BLOCK_SIZE as u32;
+    let update_interval: f64 = UPDATE_FRAMES as f64 / sample_rate as f64;
+    let mut frames_until_update = UPDATE_FRAMES;
+
+    let mut stream_module = module.shared_clone();
+    let stereo_width = player.stereo_width.clone();
+
+    // audio callback
+    let stream = audio_conf.and_then(|config| {
+        Ok(device.expect("device should be present if config is").build_output_stream(
+            &config, move |data: &mut[f32], _: &cpal::OutputCallbackInfo| {
+                let mut i = 0;
+                let len = data.len();
+                while i < len {
+                    if frames_until_update == 0 {
+                        while let Ok(cmd) = module_cmd_consumer.pop() {
+                            let changes_voices = cmd.changes_voices();
+                            stream_module.handle_command(cmd);
+                            if changes_voices {
+                                player.clear_voice_cache();
+                            }
+                        }
+                        while let Ok(cmd) = player_cmd_consumer.pop() {
+                            player.handle_command(cmd, &stream_module);
+                        }
+                        player.buffer_size = data.len() / 2;
+                        player.frame(&stream_module, update_interval);
+                        frames_until_update = UPDATE_FRAMES;
+                        player_state_input.write(player.state());
+                        backend.process_block();
+                    }
+                    let (l, r) = backend.get_stereo();
+                    let (jl, jr) = player.jukebox.get_stereo();
+                    data[i] = l + jl;
+                    data[i+1] = r + jr;
+                    i += 2;
+                    frames_until_update -= 1;
+                }
+            },
+            |err| eprintln!("stream error: {err}"),
+            None
+        )?)
+    });
+
+    let ps = PlayerShell::new(player_state_output, player_cmd_producer, discard_consumer,
+        voice_producer, stereo_width.clone(), sample_rate as f64);
+    let mut app = App::new(global_fx, conf, sample_rate, cloned_conf, ps, stereo_width,
+        module, ModuleSync::new(module_cmd_producer));
+
+    // ugly duplication, but error typing makes a nice solution difficult
+    match &stream {
+        Ok(stream) => if let Err(e) = stream.play() {
+            app.ui.report(format!("Could not initialize audio: {e}"));
+        }
+        Err(e) => app.ui.report(format!("Could not initialize audio: {e}"))
+    };
+
+    if let Some(arg) = arg {
+        let p = arg.into();
+        match Module::load(&p) {
+            Ok(m) => app.load_module(m, Some(p)),
+            Err(e) => app.ui.report(format!("Error loading module: {e}")),
+        }
+    }
+
+    while app.frame() {
+        next_frame().await
+    }
+
+    Ok(())
+}
+
+/// Returns true if there was mouse or keyboard input.
+fn mouse_kb_input() -> bool {
+    !(get_keys_down().is_empty()
+        && !is_mouse_button_pressed(MouseButton::Left)
+        && !is_mouse_button_released(MouseButton::Left)
+        && !is_mouse_button_down(MouseButton::Left)
+        && !is_mouse_button_pressed(MouseButton::Right)
+        && !is_mouse_button_released(MouseButton::Right)
+        && !is_mouse_button_down(MouseButton::Right)
+        && mouse_wheel() == (0.0, 0.0)
+        && mouse_delta_position() == Vec2::ZERO
+        && !is_quit_requested())
+}
\ No newline at end of file
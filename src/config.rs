@@ -1,9 +1,9 @@
 use std::{collections::HashSet, error::Error, fmt, path::{Path, PathBuf}};
 
-use macroquad::input::KeyCode;
+use macroquad::input::{is_key_down, KeyCode};
 use serde::{Deserialize, Serialize};
 
-use crate::{exe_relative_path, input::{self, Action, Hotkey, Modifiers}, pitch::Note, ui::theme::Theme};
+use crate::{exe_relative_path, input::{self, Action, Hotkey, Modifiers}, pitch::Note, surface::SurfaceLayout, synth::{Oversampling, VoiceSettings}, timespan::Timespan, ui::theme::Theme};
 
 const CONFIG_FILENAME: &str = "config.toml";
 
@@ -19,10 +19,16 @@ fn default_true() -> bool { true }
 
 fn default_false() -> bool { false }
 
+fn default_ttf_size() -> f32 { 16.0 }
+
+fn default_loudness_target() -> f32 { -14.0 }
+
 /// Stores local configuration.
 #[derive(Serialize, Deserialize)]
 pub struct Config {
-    pub default_midi_input: Option<String>,
+    /// Superseded by `midi_inputs`; only read to migrate old configs.
+    #[serde(default, skip_serializing)]
+    default_midi_input: Option<String>,
     pub midi_send_pressure: Option<bool>,
     #[serde(default = "default_true")]
     pub midi_send_velocity: bool,
@@ -33,13 +39,31 @@ pub struct Config {
     pub scale_folder: Option<String>,
     pub sample_folder: Option<String>,
     pub theme_folder: Option<String>,
+    pub settings_folder: Option<String>,
     #[serde(default = "default_keys")]
     keys: Vec<(Hotkey, Action)>,
     #[serde(default = "input::default_note_keys")]
     pub note_keys: Vec<(Hotkey, Note)>,
+    /// Key held to use the secondary layer of the keymap.
+    #[serde(default = "default_layer_key")]
+    pub layer_key: Hotkey,
     /// Index of built-in font data to use.
     #[serde(default = "default_font_size")]
     pub font_size: usize,
+    /// Path to a TrueType or OpenType font. If None, the built-in bitmap font
+    /// is used.
+    #[serde(default)]
+    pub font_path: Option<String>,
+    /// Size to rasterize `font_path` at, in pixels.
+    #[serde(default = "default_ttf_size")]
+    pub ttf_size: f32,
+    /// Index of built-in font data to use for the pattern grid. If None, the
+    /// pattern uses the same font as the rest of the interface.
+    #[serde(default)]
+    pub pattern_font_size: Option<usize>,
+    /// UI scale factor. If None, the scale is based on display DPI.
+    #[serde(default)]
+    pub ui_scale: Option<f32>,
     pub smooth_playhead: bool,
     pub display_info: bool,
     pub desired_sample_rate: u32,
@@ -49,15 +73,97 @@ pub struct Config {
     pub autosave: bool,
     #[serde(default = "default_false")]
     pub trim_samples: bool,
+    /// Detect the pitch of loaded samples that lack a root note.
+    #[serde(default = "default_true")]
+    pub detect_sample_pitch: bool,
+    /// Reload referenced samples when their files are modified.
+    #[serde(default = "default_false")]
+    pub watch_samples: bool,
+    #[serde(default)]
+    pub voices: VoiceSettings,
+    /// The hotkey profile last applied.
+    #[serde(default)]
+    pub key_profile: KeyProfile,
+    #[serde(default)]
+    pub surface_layout: SurfaceLayout,
+    /// MIDI output port for control surface LED feedback.
+    #[serde(default)]
+    pub surface_output: Option<String>,
+    /// Scroll by fractions of a row when following playback.
+    #[serde(default)]
+    pub smooth_scroll: bool,
+    /// Screen position of the playhead when following playback.
+    #[serde(default)]
+    pub playhead_position: PlayheadPosition,
+    /// Keep the cursor on the playhead row when following playback.
+    #[serde(default)]
+    pub lock_cursor: bool,
+    /// Delay keyjazz notes to the next row while playing the pattern.
+    #[serde(default)]
+    pub quantize_preview: bool,
+    /// Apply TPDF dither to 16-bit renders.
+    #[serde(default)]
+    pub dither: bool,
+    /// Shape dither noise toward high frequencies.
+    #[serde(default)]
+    pub noise_shaping: bool,
+    /// Scale renders to `loudness_target`. Loudness is reported either way.
+    #[serde(default)]
+    pub normalize_loudness: bool,
+    /// Target integrated loudness for renders, in LUFS.
+    #[serde(default = "default_loudness_target")]
+    pub loudness_target: f32,
+    /// Show the instrument editor beside the pattern editor.
+    #[serde(default)]
+    pub instrument_panel: bool,
+    /// Window geometry and tab from the last session.
+    #[serde(default)]
+    pub window: WindowState,
+    /// Transform from MIDI note velocity to pressure.
+    #[serde(default)]
+    pub velocity: VelocitySettings,
+    /// MIDI input ports to connect to, with their routing.
+    #[serde(default)]
+    pub midi_inputs: Vec<MidiInputSettings>,
+    /// Pairs of zero-based MIDI channel and the track that channel plays on.
+    /// Unrouted channels play on the keyjazz track.
+    #[serde(default)]
+    pub midi_routes: Vec<(u8, usize)>,
+    /// Crossfade time between playlist songs, in seconds.
+    #[serde(default)]
+    pub playlist_crossfade: f32,
+    /// Labels for rows between beats in the pattern grid.
+    #[serde(default)]
+    pub row_numbers: RowNumbers,
+    /// Highlight every Nth row of the pattern grid. Zero disables.
+    #[serde(default)]
+    pub row_highlight: u8,
+    /// Oversampling used for all patches in renders. If None, patch
+    /// settings are used.
+    #[serde(default)]
+    pub render_quality: Option<Oversampling>,
+    /// Mouse button and wheel behavior.
+    #[serde(default)]
+    pub mouse: MouseSettings,
 }
 
 impl Config {
     /// Load config from disk and initialize.
     pub fn load() -> Result<Self, Box<dyn Error>> {
-        let s = std::fs::read_to_string(config_path())?;
+        Self::read(&config_path())
+    }
+
+    /// Read config from a file, filling in hotkeys for actions it lacks.
+    fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let s = std::fs::read_to_string(path)?;
         let mut c: Self = toml::from_str(&s)?;
+        if let Some(name) = c.default_midi_input.take() {
+            if c.midi_inputs.is_empty() {
+                c.midi_inputs.push(MidiInputSettings::new(name));
+            }
+        }
         let actions: HashSet<Action> = c.keys.iter().map(|x| x.1).collect();
-        for (k, a) in default_keys() {
+        for (k, a) in c.key_profile.keys() {
             if !actions.contains(&a) {
                 c.keys.push((k, a));
             }
@@ -65,6 +171,55 @@ impl Config {
         Ok(c)
     }
 
+    /// Replace all settings except paths and MIDI devices with those from an
+    /// exported file.
+    pub fn import(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let c = Self::read(path)?;
+        *self = Self {
+            module_folder: self.module_folder.take(),
+            patch_folder: self.patch_folder.take(),
+            render_folder: self.render_folder.take(),
+            scale_folder: self.scale_folder.take(),
+            sample_folder: self.sample_folder.take(),
+            theme_folder: self.theme_folder.take(),
+            settings_folder: self.settings_folder.take(),
+            font_path: self.font_path.take(),
+            midi_inputs: std::mem::take(&mut self.midi_inputs),
+            midi_routes: std::mem::take(&mut self.midi_routes),
+            surface_output: self.surface_output.take(),
+            window: self.window,
+            ..c
+        };
+        Ok(())
+    }
+
+    /// Write settings to a file for use on another machine. Paths and MIDI
+    /// devices are omitted.
+    pub fn export(&self, theme: Theme, path: &Path) -> Result<(), Box<dyn Error>> {
+        let c = Self {
+            default_midi_input: None,
+            midi_inputs: Vec::new(),
+            midi_routes: Vec::new(),
+            surface_output: None,
+            theme: Some(theme),
+            module_folder: None,
+            patch_folder: None,
+            render_folder: None,
+            scale_folder: None,
+            sample_folder: None,
+            theme_folder: None,
+            settings_folder: None,
+            font_path: None,
+            keys: self.keys.clone(),
+            note_keys: self.note_keys.clone(),
+            layer_key: self.layer_key.clone(),
+            window: Default::default(),
+            ..*self
+        };
+        std::fs::write(path, toml::to_string_pretty(&c)?)?;
+        Ok(())
+    }
+
     /// Reset all settings except paths to defaults.
     pub fn reset(&mut self) {
         *self = Self {
@@ -74,6 +229,8 @@ impl Config {
             scale_folder: self.scale_folder.take(),
             sample_folder: self.sample_folder.take(),
             theme_folder: self.theme_folder.take(),
+            settings_folder: self.settings_folder.take(),
+            window: self.window,
             ..Default::default()
         };
     }
@@ -87,11 +244,29 @@ impl Config {
         Ok(())
     }
 
+    /// Replace the keymap with a profile's.
+    pub fn set_key_profile(&mut self, profile: KeyProfile) {
+        self.keys = profile.keys();
+        self.key_profile = profile;
+    }
+
+    /// Returns the track that a MIDI channel is routed to, if any.
+    pub fn midi_route(&self, channel: u8) -> Option<usize> {
+        self.midi_routes.iter()
+            .find(|(c, _)| *c == channel)
+            .map(|(_, track)| *track)
+    }
+
     /// Iterate over keymap entries.
     pub fn iter_keymap(&mut self) -> impl Iterator<Item = &mut (Hotkey, Action)> {
         self.keys.iter_mut()
     }
 
+    /// Returns the hotkey-action mapping.
+    pub fn keymap(&self) -> &[(Hotkey, Action)] {
+        &self.keys
+    }
+
     /// Returns the action associated with the given hotkey.
     pub fn hotkey_action(&self, hotkey: &Hotkey) -> Option<&Action> {
         self.keys.iter()
@@ -99,6 +274,29 @@ impl Config {
             .map(|(_, a)| a)
     }
 
+    /// Applies layer and chord state to a key press. If the press completes a
+    /// two-stroke hotkey started by `prefix`, the returned hotkey includes the
+    /// first stroke. If the press starts a two-stroke hotkey, `prefix` is set
+    /// to it.
+    pub fn resolve_hotkey(&self, hotkey: Hotkey, prefix: &mut Option<Hotkey>) -> Hotkey {
+        let hotkey = Hotkey { layer: self.layer_held(), ..hotkey };
+        if let Some(first) = prefix.take() {
+            let chord = hotkey.clone().after(first);
+            if self.hotkey_action(&chord).is_some() {
+                return chord
+            }
+        }
+        if self.keys.iter().any(|(k, _)| k.prefix.as_deref() == Some(&hotkey)) {
+            *prefix = Some(hotkey.clone());
+        }
+        hotkey
+    }
+
+    /// Returns true if the layer key is held.
+    pub fn layer_held(&self) -> bool {
+        self.layer_key.key != KeyCode::Unknown && is_key_down(self.layer_key.key)
+    }
+
     /// Returns true if the action's associated hotkey is down.
     pub fn action_is_down(&self, action: Action) -> bool {
         self.keys.iter().any(|(k, a)| *a == action && k.is_down())
@@ -128,15 +326,45 @@ impl Default for Config {
             scale_folder: None,
             sample_folder: None,
             theme_folder: None,
+            settings_folder: None,
             keys,
             note_keys: input::default_note_keys(),
+            layer_key: default_layer_key(),
             font_size: default_font_size(),
+            font_path: None,
+            ttf_size: default_ttf_size(),
+            pattern_font_size: None,
+            ui_scale: None,
             smooth_playhead: false,
             display_info: true,
             desired_sample_rate: 48000,
             render_format: RenderFormat::Wav16,
             autosave: default_true(),
             trim_samples: default_false(),
+            detect_sample_pitch: default_true(),
+            watch_samples: default_false(),
+            voices: Default::default(),
+            key_profile: Default::default(),
+            surface_layout: Default::default(),
+            surface_output: None,
+            smooth_scroll: false,
+            playhead_position: Default::default(),
+            lock_cursor: false,
+            quantize_preview: false,
+            dither: false,
+            noise_shaping: false,
+            normalize_loudness: false,
+            loudness_target: default_loudness_target(),
+            instrument_panel: false,
+            window: Default::default(),
+            velocity: Default::default(),
+            midi_inputs: Vec::new(),
+            midi_routes: Vec::new(),
+            playlist_crossfade: 0.0,
+            row_numbers: Default::default(),
+            row_highlight: 0,
+            render_quality: None,
+            mouse: Default::default(),
         }
     }
 }
@@ -148,8 +376,17 @@ pub fn dir_as_string(p: &Path) -> Option<String> {
 
 /// Returns the default hotkey-action mapping.
 fn default_keys() -> Vec<(Hotkey, Action)> {
+    KeyProfile::Default.keys()
+}
+
+fn default_layer_key() -> Hotkey {
+    Hotkey::new(Modifiers::None, KeyCode::Menu)
+}
+
+/// Returns the default mapping, without platform-specific changes.
+fn base_keys() -> Vec<(Hotkey, Action)> {
     // this is a function instead of a constant so we can use `Hotkey::new`
-    let mut keys = vec![
+    vec![
         // global
         (Hotkey::new(Modifiers::Ctrl, KeyCode::N), Action::NewSong),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::O), Action::OpenSong),
@@ -157,10 +394,14 @@ fn default_keys() -> Vec<(Hotkey, Action)> {
         (Hotkey::new(Modifiers::CtrlShift, KeyCode::S), Action::SaveSongAs),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::E), Action::RenderSong),
         (Hotkey::new(Modifiers::CtrlShift, KeyCode::E), Action::RenderTracks),
+        (Hotkey::new(Modifiers::CtrlAltShift, KeyCode::E), Action::ExportVideo),
         (Hotkey::new(Modifiers::CtrlShift, KeyCode::Tab), Action::PrevTab),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::Tab), Action::NextTab),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::Z), Action::Undo),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::Y), Action::Redo),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::I), Action::ToggleInstrumentPanel),
+        (Hotkey::new(Modifiers::Alt, KeyCode::Enter), Action::ToggleFullscreen),
+        (Hotkey::new(Modifiers::Shift, KeyCode::F1), Action::ToggleCheatSheet),
 
         // status
         (Hotkey::new(Modifiers::Ctrl, KeyCode::Minus), Action::DecrementDivision),
@@ -170,6 +411,7 @@ fn default_keys() -> Vec<(Hotkey, Action)> {
         (Hotkey::new(Modifiers::Shift, KeyCode::Key9), Action::DecrementOctave),
         (Hotkey::new(Modifiers::Shift, KeyCode::Key0), Action::IncrementOctave),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::D), Action::FocusDivision),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::T), Action::ToggleTriplets),
 
         // pattern nav
         (Hotkey::new(Modifiers::None, KeyCode::Up), Action::PrevRow),
@@ -193,6 +435,7 @@ fn default_keys() -> Vec<(Hotkey, Action)> {
         (Hotkey::new(Modifiers::None, KeyCode::T), Action::TapTempo),
         (Hotkey::new(Modifiers::None, KeyCode::L), Action::Loop),
         (Hotkey::new(Modifiers::None, KeyCode::E), Action::End),
+        (Hotkey::new(Modifiers::None, KeyCode::K), Action::Skip),
         (Hotkey::new(Modifiers::None, KeyCode::GraveAccent), Action::Interpolate),
 
         // pitch & notation
@@ -214,6 +457,8 @@ fn default_keys() -> Vec<(Hotkey, Action)> {
         (Hotkey::new(Modifiers::CtrlShift, KeyCode::V), Action::MixPaste),
         (Hotkey::new(Modifiers::CtrlAlt, KeyCode::V), Action::InsertPaste),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::H), Action::StretchPaste),
+        (Hotkey::new(Modifiers::Alt, KeyCode::V), Action::PasteSpecial),
+        (Hotkey::new(Modifiers::CtrlShift, KeyCode::H), Action::FillPaste),
 
         // playback
         (Hotkey::new(Modifiers::None, KeyCode::Enter), Action::PlayFromScreen),
@@ -222,6 +467,8 @@ fn default_keys() -> Vec<(Hotkey, Action)> {
         (Hotkey::new(Modifiers::None, KeyCode::ScrollLock), Action::ToggleFollow),
         (Hotkey::new(Modifiers::None, KeyCode::F9), Action::MuteTrack),
         (Hotkey::new(Modifiers::None, KeyCode::F10), Action::SoloTrack),
+        (Hotkey::new(Modifiers::Shift, KeyCode::F10), Action::AddSoloTrack),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::F10), Action::MomentarySolo),
         (Hotkey::new(Modifiers::None, KeyCode::F11), Action::UnmuteAllTracks),
         (Hotkey::new(Modifiers::None, KeyCode::F12), Action::Panic),
 
@@ -230,17 +477,107 @@ fn default_keys() -> Vec<(Hotkey, Action)> {
         (Hotkey::new(Modifiers::None, KeyCode::Insert), Action::InsertRows),
         (Hotkey::new(Modifiers::None, KeyCode::Backspace), Action::DeleteRows),
         (Hotkey::new(Modifiers::Ctrl, KeyCode::P), Action::PlaceEvenly),
+        (Hotkey::new(Modifiers::Alt, KeyCode::Down), Action::OffsetLater),
+        (Hotkey::new(Modifiers::Alt, KeyCode::Up), Action::OffsetEarlier),
         (Hotkey::new(Modifiers::CtrlAlt, KeyCode::Left), Action::ShiftTrackLeft),
         (Hotkey::new(Modifiers::CtrlAlt, KeyCode::Right), Action::ShiftTrackRight),
-    ];
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::M), Action::EditMarker),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::G), Action::GroupTracks),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::Slash), Action::EditCondition),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::Period), Action::EditSlide),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::Comma), Action::EditEffects),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::K), Action::ToggleTrackLock),
+        (Hotkey::new(Modifiers::CtrlShift, KeyCode::K), Action::ToggleColumnLock),
+        (Hotkey::new(Modifiers::CtrlAlt, KeyCode::E), Action::ExportMidi),
+        (Hotkey::new(Modifiers::CtrlAlt, KeyCode::C), Action::CopyText),
+        (Hotkey::new(Modifiers::CtrlAlt, KeyCode::M), Action::CopyMml),
+        (Hotkey::new(Modifiers::CtrlAlt, KeyCode::I), Action::CopyInspector),
+        (Hotkey::new(Modifiers::CtrlAlt, KeyCode::A), Action::AnalyzeIntervals),
+        (Hotkey::new(Modifiers::Ctrl, KeyCode::Backslash), Action::ToggleSplitView),
+        (Hotkey::new(Modifiers::Alt, KeyCode::Backslash), Action::SwitchPane),
+        (Hotkey::new(Modifiers::CtrlShift, KeyCode::T), Action::ToggleTripletGrid),
+        (Hotkey::new(Modifiers::CtrlShift, KeyCode::D), Action::ToggleDrawMode),
+        (Hotkey::new(Modifiers::CtrlShift, KeyCode::L), Action::ToggleAutomationLane),
+        (Hotkey::new(Modifiers::Alt, KeyCode::Q), Action::Quantize),
+        (Hotkey::new(Modifiers::CtrlAlt, KeyCode::K), Action::SaveMacro),
+    ]
+}
 
-    if cfg!(target_os = "macos") {
-        for (k, _) in &mut keys {
-            k.mods.swap_super_and_ctrl()
+/// Built-in hotkey mapping.
+#[derive(Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyProfile {
+    #[default]
+    Default,
+    FamiTracker,
+    OpenMPT,
+}
+
+impl KeyProfile {
+    pub const VARIANTS: [Self; 3] = [Self::Default, Self::FamiTracker, Self::OpenMPT];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Default => "Default",
+            Self::FamiTracker => "FamiTracker-like",
+            Self::OpenMPT => "OpenMPT-like",
+        }
+    }
+
+    /// Returns the hotkeys that differ from the default mapping. These
+    /// shouldn't collide with any unchanged default hotkey.
+    fn overrides(&self) -> Vec<(Hotkey, Action)> {
+        match self {
+            Self::Default => Vec::new(),
+            Self::FamiTracker => vec![
+                (Hotkey::new(Modifiers::None, KeyCode::F5), Action::PlayFromStart),
+                (Hotkey::new(Modifiers::None, KeyCode::F6), Action::PlayFromScreen),
+                (Hotkey::new(Modifiers::None, KeyCode::F7), Action::PlayFromCursor),
+                (Hotkey::new(Modifiers::None, KeyCode::F8), Action::StopPlayback),
+                (Hotkey::new(Modifiers::Ctrl, KeyCode::F1), Action::DecrementValues),
+                (Hotkey::new(Modifiers::Ctrl, KeyCode::F2), Action::IncrementValues),
+                (Hotkey::new(Modifiers::Ctrl, KeyCode::F3), Action::NudgeOctaveDown),
+                (Hotkey::new(Modifiers::Ctrl, KeyCode::F4), Action::NudgeOctaveUp),
+                (Hotkey::new(Modifiers::None, KeyCode::KpDivide), Action::DecrementOctave),
+                (Hotkey::new(Modifiers::None, KeyCode::KpMultiply), Action::IncrementOctave),
+            ],
+            Self::OpenMPT => vec![
+                (Hotkey::new(Modifiers::None, KeyCode::F5), Action::PlayFromStart),
+                (Hotkey::new(Modifiers::None, KeyCode::F6), Action::PlayFromScreen),
+                (Hotkey::new(Modifiers::None, KeyCode::F7), Action::PlayFromCursor),
+                (Hotkey::new(Modifiers::None, KeyCode::F8), Action::StopPlayback),
+                (Hotkey::new(Modifiers::Ctrl, KeyCode::A), Action::DecrementValues),
+                (Hotkey::new(Modifiers::Ctrl, KeyCode::Q), Action::IncrementValues),
+                (Hotkey::new(Modifiers::CtrlShift, KeyCode::A), Action::NudgeOctaveDown),
+                (Hotkey::new(Modifiers::CtrlShift, KeyCode::Q), Action::NudgeOctaveUp),
+                (Hotkey::new(Modifiers::Ctrl, KeyCode::Key5), Action::SelectAllChannels),
+                (Hotkey::new(Modifiers::None, KeyCode::KpDivide), Action::DecrementOctave),
+                (Hotkey::new(Modifiers::None, KeyCode::KpMultiply), Action::IncrementOctave),
+            ],
         }
     }
 
-    keys
+    /// Returns the full hotkey-action mapping for this profile.
+    pub fn keys(&self) -> Vec<(Hotkey, Action)> {
+        let mut keys = base_keys();
+
+        for (hotkey, action) in self.overrides() {
+            match keys.iter_mut().find(|(_, a)| *a == action) {
+                Some(entry) => entry.0 = hotkey,
+                None => keys.push((hotkey, action)),
+            }
+        }
+
+        if cfg!(target_os = "macos") {
+            for (k, _) in &mut keys {
+                k.mods.swap_super_and_ctrl();
+                if let Some(prefix) = &mut k.prefix {
+                    prefix.mods.swap_super_and_ctrl();
+                }
+            }
+        }
+
+        keys
+    }
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Copy)]
@@ -261,4 +598,315 @@ impl fmt::Display for RenderFormat {
             Self::Wav32 => "32-bit",
         })
     }
-}
\ No newline at end of file
+}
+
+/// Window state saved between sessions.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct WindowState {
+    /// Size of the windowed-mode window, in logical pixels.
+    pub width: i32,
+    pub height: i32,
+    /// Position of the windowed-mode window. If None, the window manager
+    /// places the window.
+    pub position: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    /// Index of the active main tab.
+    pub tab: usize,
+}
+
+impl Default for WindowState {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            position: None,
+            fullscreen: false,
+            tab: 0,
+        }
+    }
+}
+
+/// Response curve for MIDI note velocity.
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    Exponential,
+    Fixed,
+}
+
+impl VelocityCurve {
+    pub const VARIANTS: [Self; 3] = [Self::Linear, Self::Exponential, Self::Fixed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Exponential => "Exponential",
+            Self::Fixed => "Fixed",
+        }
+    }
+}
+
+/// Transform from MIDI note velocity to pressure.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct VelocitySettings {
+    pub curve: VelocityCurve,
+    /// Exponent of the exponential curve. Values above 1 tame hot
+    /// keyboards, and values below 1 help stiff ones.
+    pub exponent: f32,
+    /// Output of the fixed curve.
+    pub fixed: f32,
+    /// Multiplier applied to the curve output.
+    pub scale: f32,
+    /// Offset added to the scaled curve output.
+    pub offset: f32,
+}
+
+impl VelocitySettings {
+    /// Returns the pressure in 0..=1 for a MIDI velocity.
+    pub fn pressure(&self, velocity: u8) -> f32 {
+        let x = velocity as f32 / 127.0;
+        let y = match self.curve {
+            VelocityCurve::Linear => x,
+            VelocityCurve::Exponential => x.powf(self.exponent),
+            VelocityCurve::Fixed => self.fixed,
+        };
+        (y * self.scale + self.offset).clamp(0.0, 1.0)
+    }
+}
+
+impl Default for VelocitySettings {
+    fn default() -> Self {
+        Self {
+            curve: VelocityCurve::Linear,
+            exponent: 2.0,
+            fixed: 1.0,
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+/// Connection and routing settings for a MIDI input port.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MidiInputSettings {
+    pub port_name: String,
+    /// Zero-based MIDI channel to accept. If None, all channels are accepted.
+    pub channel: Option<u8>,
+    /// Track to play notes on. If None, the keyjazz track is used.
+    pub track: Option<usize>,
+}
+
+impl MidiInputSettings {
+    pub fn new(port_name: String) -> Self {
+        Self {
+            port_name,
+            channel: None,
+            track: None,
+        }
+    }
+
+    /// Returns true if events on `channel` pass the channel filter.
+    pub fn accepts(&self, channel: u8) -> bool {
+        self.channel.is_none_or(|c| c == channel)
+    }
+}
+
+/// Where the playhead sits in the pattern viewport during follow.
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+pub enum PlayheadPosition {
+    #[default]
+    Center,
+    TopThird,
+}
+
+impl PlayheadPosition {
+    pub const VARIANTS: [Self; 2] = [Self::Center, Self::TopThird];
+
+    /// Returns the fraction of the viewport above the playhead.
+    pub fn fraction(&self) -> Timespan {
+        match self {
+            Self::Center => Timespan::new(1, 2),
+            Self::TopThird => Timespan::new(1, 3),
+        }
+    }
+}
+
+impl fmt::Display for PlayheadPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::Center => "Center",
+            Self::TopThird => "Top third",
+        })
+    }
+}
+
+/// How rows between beats are labeled in the pattern grid.
+#[derive(Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RowNumbers {
+    #[default]
+    Off,
+    Decimal,
+    Hex,
+    /// The row's position in the beat as a reduced fraction.
+    Fraction,
+    /// The row's tick from the start of the song as a reduced fraction.
+    Absolute,
+}
+
+impl RowNumbers {
+    pub const VARIANTS: [Self; 5] =
+        [Self::Off, Self::Decimal, Self::Hex, Self::Fraction, Self::Absolute];
+
+    /// Returns the label for a row within a zero-based beat, if it has one.
+    /// Row zero is the beat row, which is labeled with the beat number
+    /// instead.
+    pub fn label(&self, beat: u32, row: u8, division: u8) -> Option<String> {
+        if row == 0 {
+            return None
+        }
+        match self {
+            Self::Off => None,
+            Self::Decimal => Some(format!(".{row}")),
+            Self::Hex => Some(format!(".{row:X}")),
+            Self::Fraction => {
+                let t = Timespan::new(row as i32, division);
+                Some(format!("{}/{}", t.num(), t.den()))
+            }
+            Self::Absolute => {
+                let n = (beat * division as u32 + row as u32) as i32;
+                let t = Timespan::new(n, division);
+                Some(format!("{}/{}", t.num(), t.den()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for RowNumbers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::Off => "Off",
+            Self::Decimal => "Decimal",
+            Self::Hex => "Hex",
+            Self::Fraction => "Fraction",
+            Self::Absolute => "Absolute",
+        })
+    }
+}
+
+/// What a double click in the pattern editor does, besides placing the
+/// cursor.
+#[derive(Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum DoubleClickAction {
+    #[default]
+    None,
+    SelectAllRows,
+    PlayFromRow,
+}
+
+impl DoubleClickAction {
+    pub const VARIANTS: [Self; 3] = [Self::None, Self::SelectAllRows, Self::PlayFromRow];
+}
+
+impl fmt::Display for DoubleClickAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::None => "None",
+            Self::SelectAllRows => "Select all rows",
+            Self::PlayFromRow => "Play from row",
+        })
+    }
+}
+
+/// Mouse button and wheel behavior.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct MouseSettings {
+    /// Drag with the middle button to pan the pattern.
+    pub middle_drag_pan: bool,
+    /// Turning the wheel over a slider changes its value instead of
+    /// scrolling.
+    pub wheel_adjusts_sliders: bool,
+    pub double_click: DoubleClickAction,
+    /// Lines scrolled per wheel step.
+    pub scroll_lines: f32,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            middle_drag_pan: true,
+            wheel_adjusts_sliders: false,
+            double_click: DoubleClickAction::None,
+            scroll_lines: 3.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_labels() {
+        assert_eq!(RowNumbers::Decimal.label(0, 0, 4), None);
+        assert_eq!(RowNumbers::Decimal.label(0, 11, 12).as_deref(), Some(".11"));
+        assert_eq!(RowNumbers::Hex.label(0, 11, 12).as_deref(), Some(".B"));
+        assert_eq!(RowNumbers::Fraction.label(3, 2, 8).as_deref(), Some("1/4"));
+        assert_eq!(RowNumbers::Absolute.label(2, 2, 8).as_deref(), Some("9/4"));
+        assert_eq!(RowNumbers::Absolute.label(1, 0, 4), None);
+        assert_eq!(RowNumbers::Off.label(0, 1, 4), None);
+    }
+
+    #[test]
+    fn test_key_profiles_unique() {
+        for profile in KeyProfile::VARIANTS {
+            let keys = profile.keys();
+            let hotkeys: HashSet<_> = keys.iter().map(|(k, _)| k).collect();
+            let actions: HashSet<_> = keys.iter().map(|(_, a)| a).collect();
+            assert_eq!(hotkeys.len(), keys.len(), "{}", profile.name());
+            assert_eq!(actions.len(), keys.len(), "{}", profile.name());
+        }
+    }
+
+    #[test]
+    fn test_resolve_hotkey() {
+        let mut cfg = Config::default();
+        // there's no window to read key state from
+        cfg.layer_key = Hotkey::new(Modifiers::None, KeyCode::Unknown);
+        let first = Hotkey::new(Modifiers::Ctrl, KeyCode::J);
+        let second = Hotkey::new(Modifiers::None, KeyCode::E);
+        cfg.keys.push((second.clone().after(first.clone()), Action::PatternEnd));
+
+        let mut prefix = None;
+        assert!(cfg.resolve_hotkey(first.clone(), &mut prefix) == first);
+        assert!(prefix.as_ref() == Some(&first));
+        let chord = cfg.resolve_hotkey(second.clone(), &mut prefix);
+        assert_eq!(cfg.hotkey_action(&chord), Some(&Action::PatternEnd));
+        assert!(prefix.is_none());
+
+        // without the prefix, the second stroke is an ordinary hotkey
+        assert!(cfg.resolve_hotkey(second.clone(), &mut prefix) == second);
+    }
+
+    #[test]
+    fn test_velocity_pressure() {
+        let mut v = VelocitySettings::default();
+        assert_eq!(v.pressure(127), 1.0);
+        v.curve = VelocityCurve::Exponential;
+        assert_eq!(v.pressure(0), 0.0);
+        assert!(v.pressure(64) < 0.3);
+        v.curve = VelocityCurve::Fixed;
+        v.fixed = 0.5;
+        v.offset = 0.25;
+        assert_eq!(v.pressure(1), 0.75);
+    }
+
+    #[test]
+    fn test_midi_input_channel_filter() {
+        let mut input = MidiInputSettings::new(String::from("Keys"));
+        assert!(input.accepts(0) && input.accepts(15));
+        input.channel = Some(9);
+        assert!(input.accepts(9));
+        assert!(!input.accepts(0));
+    }
+}
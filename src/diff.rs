@@ -0,0 +1,266 @@
+//! Module comparison and merging.
+
+use serde::Serialize;
+
+use crate::module::{Edit, Event, LocatedEvent, Module, Track, TrackTarget};
+
+/// A difference between two modules.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Difference {
+    Title,
+    Author,
+    Tuning,
+    FX,
+    Kit,
+    Markers,
+    /// Patch contents differ, or the patch only exists in the other module.
+    Patch(usize),
+    /// Track target, offsets, or channel count differ, or the track only
+    /// exists in the other module.
+    Track(usize),
+    /// Pattern events differ in a channel.
+    Channel(usize, usize),
+}
+
+impl Difference {
+    /// Returns a description of the difference for display.
+    pub fn describe(&self, module: &Module, other: &Module) -> String {
+        match *self {
+            Self::Title => format!("Title: \"{}\" -> \"{}\"", module.title, other.title),
+            Self::Author => format!("Author: \"{}\" -> \"{}\"", module.author, other.author),
+            Self::Tuning => String::from("Tuning"),
+            Self::FX => String::from("Global FX"),
+            Self::Kit => String::from("Kit"),
+            Self::Markers => String::from("Markers"),
+            Self::Patch(i) => match module.patches.get(i) {
+                Some(patch) => format!("Patch {i}: {}", patch.name),
+                None => format!("New patch: {}", other.patches[i].name),
+            },
+            Self::Track(i) => if i < module.tracks.len() {
+                format!("Track {i} settings")
+            } else {
+                format!("New track {i}")
+            },
+            Self::Channel(track, channel) => {
+                let (mine, theirs) = channel_diff(module, other, track, channel);
+                let first = mine.iter().chain(theirs.iter())
+                    .map(|e| e.tick)
+                    .min()
+                    .map(|t| t.as_f64())
+                    .unwrap_or_default();
+                format!("Track {track}, channel {}: {} events, from beat {}",
+                    channel + 1, mine.len() + theirs.len(), first.floor() + 1.0)
+            }
+        }
+    }
+}
+
+/// Returns true if two values serialize identically.
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    match (rmp_serde::to_vec(a), rmp_serde::to_vec(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn same_event(a: &Event, b: &Event) -> bool {
+    a.tick == b.tick && a.data == b.data
+}
+
+/// Returns the differences between `module` and `other`, from the
+/// perspective of merging `other` into `module`.
+pub fn diff(module: &Module, other: &Module) -> Vec<Difference> {
+    let mut diffs = Vec::new();
+
+    if module.title != other.title {
+        diffs.push(Difference::Title);
+    }
+    if module.author != other.author {
+        diffs.push(Difference::Author);
+    }
+    if module.tuning != other.tuning {
+        diffs.push(Difference::Tuning);
+    }
+    if !same(&module.fx, &other.fx) {
+        diffs.push(Difference::FX);
+    }
+    if !same(&module.kit, &other.kit) {
+        diffs.push(Difference::Kit);
+    }
+    if !same(&module.markers, &other.markers) {
+        diffs.push(Difference::Markers);
+    }
+
+    for (i, patch) in other.patches.iter().enumerate() {
+        if module.patches.get(i).is_none_or(|p| !same(p, patch)) {
+            diffs.push(Difference::Patch(i));
+        }
+    }
+
+    for (i, track) in other.tracks.iter().enumerate() {
+        let Some(mine) = module.tracks.get(i) else {
+            diffs.push(Difference::Track(i));
+            continue
+        };
+        if !same(&mine.target, &map_target(module, other, track.target))
+            || mine.transpose != track.transpose
+            || mine.detune != track.detune
+            || mine.channels.len() != track.channels.len() {
+            diffs.push(Difference::Track(i));
+        }
+        for channel in 0..mine.channels.len().min(track.channels.len()) {
+            let (a, b) = channel_diff(module, other, i, channel);
+            if !a.is_empty() || !b.is_empty() {
+                diffs.push(Difference::Channel(i, channel));
+            }
+        }
+    }
+
+    diffs
+}
+
+/// Returns events only in `module`'s channel and events only in `other`'s
+/// channel.
+fn channel_diff<'a>(module: &'a Module, other: &'a Module, track: usize, channel: usize
+) -> (Vec<&'a Event>, Vec<&'a Event>) {
+    let events = |m: &'a Module| m.tracks.get(track)
+        .and_then(|t| t.channels.get(channel))
+        .map(|c| c.events.as_slice())
+        .unwrap_or_default();
+    let (a, b) = (events(module), events(other));
+    (
+        a.iter().filter(|x| !b.iter().any(|y| same_event(x, y))).collect(),
+        b.iter().filter(|x| !a.iter().any(|y| same_event(x, y))).collect(),
+    )
+}
+
+/// Maps a track target from `other` to the equivalent target in `module`.
+/// Patch targets point to the first identical patch in `module`, or to the
+/// same index if it exists, or to nothing.
+fn map_target(module: &Module, other: &Module, target: TrackTarget) -> TrackTarget {
+    let TrackTarget::Patch(i) = target else {
+        return target
+    };
+    other.patches.get(i)
+        .and_then(|patch| module.patches.iter().position(|p| same(p, patch)))
+        .or((i < module.patches.len()).then_some(i))
+        .map(TrackTarget::Patch)
+        .unwrap_or(TrackTarget::None)
+}
+
+/// Merge a difference from `other` into `module` as an undoable edit.
+pub fn accept(module: &mut Module, other: &Module, diff: Difference) {
+    let set_patches = |patches, kit| Edit::SetPatches {
+        patches,
+        kit,
+        targets: module.tracks.iter().map(|t| t.target).collect(),
+    };
+    match diff {
+        Difference::Title => module.push_edit(Edit::SetTitle(other.title.clone())),
+        Difference::Author => module.push_edit(Edit::SetAuthor(other.author.clone())),
+        Difference::Tuning => module.push_edit(Edit::SetTuning(other.tuning.clone())),
+        Difference::FX => module.push_edit(Edit::SetFX(other.fx.clone())),
+        Difference::Kit => {
+            let edit = set_patches(module.patches.clone(), other.kit.clone());
+            module.push_edit(edit);
+        }
+        Difference::Markers => module.push_edit(Edit::SetMarkers(other.markers.clone())),
+        Difference::Patch(i) => if i < module.patches.len() {
+            let mut patches = module.patches.clone();
+            patches[i] = other.patches[i].clone();
+            let edit = set_patches(patches, module.kit.clone());
+            module.push_edit(edit);
+        } else {
+            module.push_edit(Edit::InsertPatch(module.patches.len(),
+                other.patches[i].clone()));
+        }
+        Difference::Track(i) => {
+            let theirs = &other.tracks[i];
+            let target = map_target(module, other, theirs.target);
+            let Some(mine) = module.tracks.get(i) else {
+                let track = Track { target, ..theirs.clone() };
+                module.push_edit(Edit::InsertTrack(module.tracks.len(), track));
+                return
+            };
+            let mut track = mine.clone();
+            track.target = target;
+            track.transpose = theirs.transpose;
+            track.detune = theirs.detune;
+            track.channels.truncate(theirs.channels.len());
+            let len = track.channels.len();
+            track.channels.extend(theirs.channels[len..].iter().cloned());
+            module.push_edit(Edit::ReplaceTrack(i, track));
+        }
+        Difference::Channel(track, channel) => {
+            let (mine, theirs) = channel_diff(module, other, track, channel);
+            let locate = |event: &Event| LocatedEvent {
+                track,
+                channel,
+                event: event.clone(),
+            };
+            let remove = mine.into_iter().map(|e| locate(e).position()).collect();
+            let add = theirs.into_iter().map(locate).collect();
+            module.push_edit(Edit::PatternData { remove, add });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fx::FXSettings, module::{Channel, EventData}, timespan::Timespan};
+
+    #[test]
+    fn test_diff_and_accept() {
+        let module = Module::new(FXSettings::default());
+        let mut other = module.clone();
+        other.title = String::from("Other");
+        other.tracks[2].channels[0].events.push(Event {
+            tick: Timespan::new(1, 1),
+            data: EventData::NoteOff,
+        });
+
+        let mut merged = module.clone();
+        let diffs = diff(&merged, &other);
+        assert_eq!(diffs, vec![Difference::Title, Difference::Channel(2, 0)]);
+
+        for d in diffs {
+            accept(&mut merged, &other, d);
+        }
+        assert!(diff(&merged, &other).is_empty());
+
+        // accepted differences can be undone
+        while merged.undo() {}
+        assert_eq!(merged.title, module.title);
+        assert!(merged.tracks[2].channels[0].events.is_empty());
+    }
+
+    #[test]
+    fn test_accept_track() {
+        let module = Module::new(FXSettings::default());
+        let mut other = module.clone();
+        other.patches.push(other.patches[0].clone());
+        other.patches[1].name = String::from("Other");
+        other.tracks[1].target = TrackTarget::Patch(1);
+        other.tracks[1].transpose = 2;
+        other.tracks[1].channels.push(Channel::default());
+
+        // the target points past the module's patches
+        let mut merged = module.clone();
+        accept(&mut merged, &other, Difference::Track(1));
+        assert!(matches!(merged.tracks[1].target, TrackTarget::None));
+        assert_eq!(merged.tracks[1].transpose, 2);
+        assert_eq!(merged.tracks[1].channels.len(), 2);
+
+        // one undo reverts the whole track
+        assert!(merged.undo());
+        assert!(!merged.undo());
+        assert!(same(&merged.tracks[1], &module.tracks[1]));
+
+        // the target follows the patch once it's merged
+        accept(&mut merged, &other, Difference::Patch(1));
+        accept(&mut merged, &other, Difference::Track(1));
+        assert!(matches!(merged.tracks[1].target, TrackTarget::Patch(1)));
+        assert!(!diff(&merged, &other).contains(&Difference::Track(1)));
+    }
+}
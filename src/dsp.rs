@@ -1,6 +1,6 @@
 //! Custom FunDSP audio nodes.
 
-use std::marker::PhantomData;
+use std::{f64::consts::PI, marker::PhantomData, sync::{atomic::{AtomicU32, AtomicUsize, Ordering}, Arc}};
 
 use fundsp::prelude::*;
 
@@ -40,7 +40,7 @@ pub fn adsr_scalable(
 }
 
 /// ADS envelope. Helper for ADSR.
-fn ads(attack: f32, decay: f32, sustain: f32, time: f32, sqrt_attack: bool) -> f32 {
+pub fn ads(attack: f32, decay: f32, sustain: f32, time: f32, sqrt_attack: bool) -> f32 {
     if time < attack {
         let level = lerp(0.0, 1.0, time / attack);
         if sqrt_attack {
@@ -251,4 +251,561 @@ impl AudioNode for Smooth {
         output.set(0, input.at(0));
         output
     }
-}
\ No newline at end of file
+}
+
+/// One-sample difference. Tilts the spectrum up by 6 dB/octave.
+pub fn differentiate() -> An<Differentiator> {
+    An(Differentiator { prev: 0.0 })
+}
+
+#[derive(Clone)]
+pub struct Differentiator {
+    prev: f32,
+}
+
+impl AudioNode for Differentiator {
+    const ID: u64 = 204;
+    type Inputs = U1;
+    type Outputs = U1;
+
+    fn reset(&mut self) {
+        self.prev = 0.0;
+    }
+
+    #[inline]
+    fn tick(&mut self, input: &Frame<f32, Self::Inputs>) -> Frame<f32, Self::Outputs> {
+        let y = input[0] - self.prev;
+        self.prev = input[0];
+        [y].into()
+    }
+
+    fn route(&mut self, _input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        SignalFrame::new(self.outputs())
+    }
+}
+
+/// Clocked 15-bit LFSR noise in the style of retro sound chips. Input is the
+/// clock rate in Hz.
+pub fn lfsr_noise() -> An<LfsrNoise> {
+    let mut node = LfsrNoise {
+        register: 1,
+        phase: 0.0,
+        sample_duration: 0.0,
+    };
+    node.set_sample_rate(DEFAULT_SR);
+    An(node)
+}
+
+#[derive(Clone)]
+pub struct LfsrNoise {
+    register: u16,
+    phase: f32,
+    sample_duration: f32,
+}
+
+impl LfsrNoise {
+    /// Maximum register shifts per sample.
+    const MAX_SHIFTS: f32 = 16.0;
+}
+
+impl AudioNode for LfsrNoise {
+    const ID: u64 = 205;
+    type Inputs = U1;
+    type Outputs = U1;
+
+    fn reset(&mut self) {
+        self.register = 1;
+        self.phase = 0.0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_duration = 1.0 / sample_rate as f32;
+    }
+
+    #[inline]
+    fn tick(&mut self, input: &Frame<f32, Self::Inputs>) -> Frame<f32, Self::Outputs> {
+        self.phase = (self.phase + input[0] * self.sample_duration).min(Self::MAX_SHIFTS);
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+            let bit = (self.register ^ (self.register >> 1)) & 1;
+            self.register = (self.register >> 1) | (bit << 14);
+        }
+
+        [if self.register & 1 == 0 { 1.0 } else { -1.0 }].into()
+    }
+
+    fn route(&mut self, _input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        SignalFrame::new(self.outputs())
+    }
+}
+
+/// Quantize a wave to 16-bit levels with TPDF dither. If `noise_shaping` is
+/// true, quantization error is fed back so that the noise floor is pushed
+/// toward high frequencies.
+pub fn dither16(wave: &mut Wave, noise_shaping: bool) {
+    const SCALE: f32 = i16::MAX as f32;
+
+    // xorshift, since the noise doesn't need to be good, just uncorrelated
+    let mut state = 0x2545_f491_u32;
+    let mut rand = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    };
+
+    for channel in 0..wave.channels() {
+        let mut error = 0.0;
+        for i in 0..wave.len() {
+            let mut x = wave.at(channel, i) * SCALE;
+            if noise_shaping {
+                x -= error;
+            }
+            let q = (x + rand() - rand()).round().clamp(-SCALE, SCALE);
+            error = q - x;
+            wave.set(channel, i, q / SCALE);
+        }
+    }
+}
+
+/// Round and clamp a wave to 16-bit levels, without dither.
+pub fn quantize16(wave: &mut Wave) {
+    const SCALE: f32 = i16::MAX as f32;
+
+    for channel in 0..wave.channels() {
+        for i in 0..wave.len() {
+            let q = (wave.at(channel, i) * SCALE).round().clamp(-SCALE, SCALE);
+            wave.set(channel, i, q / SCALE);
+        }
+    }
+}
+
+/// Direct form I biquad, for offline processing.
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    fn new(b: [f32; 3], a: [f32; 2]) -> Self {
+        Self { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    fn tick(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+
+    /// Returns the two-stage K-weighting filter from ITU-R BS.1770.
+    fn k_weighting(sample_rate: f64) -> [Self; 2] {
+        // high shelf modeling the acoustic effect of the head
+        let k = (PI * 1681.9745 / sample_rate).tan() as f32;
+        let q = 0.70717525;
+        let vh = 10.0f32.powf(3.9998438 / 20.0);
+        let vb = vh.powf(0.49966677);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Self::new([
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ], [
+            2.0 * (k * k - 1.0) / a0,
+            (1.0 - k / q + k * k) / a0,
+        ]);
+
+        // RLB high-pass
+        let k = (PI * 38.135471 / sample_rate).tan() as f32;
+        let q = 0.50032704;
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Self::new([1.0, -2.0, 1.0], [
+            2.0 * (k * k - 1.0) / a0,
+            (1.0 - k / q + k * k) / a0,
+        ]);
+
+        [shelf, highpass]
+    }
+}
+
+/// Returns the integrated loudness of a wave in LUFS, as specified by ITU-R
+/// BS.1770, or None if the wave is too short or quiet to measure.
+pub fn loudness(wave: &Wave) -> Option<f32> {
+    const ABSOLUTE_GATE: f64 = -70.0;
+    const RELATIVE_GATE: f64 = -10.0;
+
+    let block_len = (wave.sample_rate() * 0.4) as usize;
+    let hop = block_len / 4;
+    if block_len == 0 || wave.len() < block_len {
+        return None
+    }
+
+    // K-weighted squares, summed across channels
+    let mut power = vec![0.0f64; wave.len()];
+    for channel in 0..wave.channels() {
+        let [mut shelf, mut highpass] = Biquad::k_weighting(wave.sample_rate());
+        for (i, p) in power.iter_mut().enumerate() {
+            let x = highpass.tick(shelf.tick(wave.at(channel, i))) as f64;
+            *p += x * x;
+        }
+    }
+
+    // mean power of overlapping 400 ms blocks
+    let mut sum = power[..block_len].iter().sum::<f64>();
+    let mut blocks = vec![sum / block_len as f64];
+    let mut start = 0;
+    while start + hop + block_len <= power.len() {
+        sum -= power[start..start + hop].iter().sum::<f64>();
+        sum += power[start + block_len..start + block_len + hop].iter().sum::<f64>();
+        start += hop;
+        blocks.push(sum / block_len as f64);
+    }
+
+    let lufs = |p: f64| -0.691 + 10.0 * p.log10();
+    let gated_mean = |gate: f64| {
+        let gated: Vec<_> = blocks.iter().filter(|p| lufs(**p) > gate).collect();
+        (!gated.is_empty()).then(|| gated.iter().copied().sum::<f64>() / gated.len() as f64)
+    };
+
+    let relative_gate = lufs(gated_mean(ABSOLUTE_GATE)?) + RELATIVE_GATE;
+    gated_mean(relative_gate.max(ABSOLUTE_GATE)).map(|p| lufs(p) as f32)
+}
+
+/// Returns frame indices of transients in a mono signal, by looking for sharp
+/// rises in short-term energy. The first frame is always included.
+pub fn detect_onsets(signal: &[f32], sample_rate: f64) -> Vec<usize> {
+    const HOP_SECONDS: f64 = 0.005;
+    const MIN_GAP_SECONDS: f64 = 0.05;
+    /// Energy increase between hops that counts as a transient.
+    const RISE_DB: f32 = 9.0;
+    /// Hops quieter than this are never transients.
+    const FLOOR_DB: f32 = -40.0;
+
+    let hop = ((sample_rate * HOP_SECONDS) as usize).max(1);
+    let min_gap = (MIN_GAP_SECONDS / HOP_SECONDS) as usize;
+    let energy: Vec<_> = signal.chunks(hop).map(|chunk| {
+        let power = chunk.iter().map(|x| x * x).sum::<f32>() / chunk.len() as f32;
+        10.0 * (power + 1e-10).log10()
+    }).collect();
+
+    let mut onsets = vec![0];
+    let mut last = 0;
+    for i in 1..energy.len() {
+        if energy[i] > FLOOR_DB && energy[i] - energy[i - 1] >= RISE_DB
+            && i - last >= min_gap {
+            onsets.push(i * hop);
+            last = i;
+        }
+    }
+    onsets
+}
+
+/// Estimates the fundamental frequency of a mono signal in Hz using the YIN
+/// algorithm. Returns None if no clear period is found.
+pub fn detect_pitch(signal: &[f32], sample_rate: f64) -> Option<f64> {
+    const MIN_FREQ: f64 = 30.0;
+    const MAX_FREQ: f64 = 2000.0;
+    const THRESHOLD: f32 = 0.15;
+    // skip the attack, where the period is least stable
+    const ONSET_SECONDS: f64 = 0.05;
+
+    let min_lag = (sample_rate / MAX_FREQ) as usize;
+    let max_lag = ((sample_rate / MIN_FREQ) as usize).min(signal.len() / 2);
+    if max_lag <= min_lag + 1 {
+        return None
+    }
+    let start = ((sample_rate * ONSET_SECONDS) as usize)
+        .min(signal.len() - max_lag * 2);
+    let window = &signal[start..start + max_lag * 2];
+
+    // cumulative mean normalized difference function
+    let mut cmnd = vec![1.0; max_lag + 1];
+    let mut running_sum = 0.0;
+    for lag in 1..=max_lag {
+        let diff: f32 = (0..max_lag)
+            .map(|i| (window[i] - window[i + lag]).powi(2))
+            .sum();
+        running_sum += diff;
+        cmnd[lag] = if running_sum > 0.0 {
+            diff * lag as f32 / running_sum
+        } else {
+            1.0
+        };
+    }
+
+    // first dip below the threshold, followed down to its local minimum
+    let mut lag = (min_lag.max(2)..max_lag).find(|&i| cmnd[i] < THRESHOLD)?;
+    while lag + 1 < max_lag && cmnd[lag + 1] < cmnd[lag] {
+        lag += 1;
+    }
+
+    // parabolic interpolation for sub-sample accuracy
+    let (a, b, c) = (cmnd[lag - 1], cmnd[lag], cmnd[lag + 1]);
+    let denom = a - 2.0 * b + c;
+    let offset = if denom.abs() > f32::EPSILON {
+        0.5 * (a - c) / denom
+    } else {
+        0.0
+    };
+
+    Some(sample_rate / (lag as f64 + offset as f64))
+}
+
+/// Plays a wave as a stream of overlapping grains, so that playback speed
+/// and pitch can be set independently. Input is the rate at which grains are
+/// read, in frames per sample; `speed` is how fast the read head moves through
+/// the wave, relative to the original.
+pub fn granular_wave(wave: Arc<Wave>, loop_point: Option<usize>, speed: f32,
+    grain_size: f32
+) -> An<GranularWave> {
+    let mut node = GranularWave {
+        wave,
+        loop_point,
+        speed: speed as f64,
+        grain_size: grain_size as f64,
+        grain_len: 1,
+        advance: 0.0,
+        head: 0.0,
+        grains: [Grain::default(); 2],
+    };
+    node.set_sample_rate(DEFAULT_SR);
+    An(node)
+}
+
+#[derive(Clone, Copy, Default)]
+struct Grain {
+    /// Read position in the wave, in frames.
+    position: f64,
+    /// Samples since the grain started.
+    age: usize,
+}
+
+#[derive(Clone)]
+pub struct GranularWave {
+    wave: Arc<Wave>,
+    loop_point: Option<usize>,
+    speed: f64,
+    grain_size: f64,
+    /// Grain length in samples.
+    grain_len: usize,
+    /// Read head advance per sample, in frames.
+    advance: f64,
+    /// Read head position, in frames. Grains start here.
+    head: f64,
+    grains: [Grain; 2],
+}
+
+impl GranularWave {
+    /// Returns the interpolated frame at `position`, or zero if it's past
+    /// the end of the wave.
+    fn at(&self, position: f64) -> f32 {
+        let len = self.wave.len();
+        let i = position as usize;
+        if i + 1 >= len {
+            return if i < len { self.wave.at(0, i) } else { 0.0 }
+        }
+        let t = (position - i as f64) as f32;
+        self.wave.at(0, i) * (1.0 - t) + self.wave.at(0, i + 1) * t
+    }
+}
+
+impl AudioNode for GranularWave {
+    const ID: u64 = 206;
+    type Inputs = U1;
+    type Outputs = U1;
+
+    fn reset(&mut self) {
+        self.head = 0.0;
+        // stagger grains by half a length so their windows sum to unity
+        self.grains = [
+            Grain { position: 0.0, age: 0 },
+            Grain { position: 0.0, age: self.grain_len / 2 },
+        ];
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.grain_len = ((self.grain_size * sample_rate) as usize).max(2);
+        self.advance = self.speed * self.wave.sample_rate() / sample_rate;
+        self.reset();
+    }
+
+    #[inline]
+    fn tick(&mut self, input: &Frame<f32, Self::Inputs>) -> Frame<f32, Self::Outputs> {
+        let mut output = 0.0;
+
+        for i in 0..self.grains.len() {
+            let grain = self.grains[i];
+            let phase = grain.age as f64 / self.grain_len as f64;
+            let window = (PI * phase).sin().powi(2) as f32;
+            output += self.at(grain.position) * window;
+
+            self.grains[i] = if grain.age + 1 >= self.grain_len {
+                Grain { position: self.head, age: 0 }
+            } else {
+                Grain { position: grain.position + input[0] as f64, age: grain.age + 1 }
+            };
+        }
+
+        self.head += self.advance;
+        let len = self.wave.len() as f64;
+        if self.head >= len {
+            if let Some(pt) = self.loop_point {
+                self.head = pt as f64 + (self.head - len) % (len - pt as f64).max(1.0);
+            }
+        }
+
+        [output].into()
+    }
+
+    fn route(&mut self, _input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        SignalFrame::new(self.outputs())
+    }
+}
+
+/// Ring buffer of recent track output for oscilloscopes. Voice taps add into
+/// it on the audio thread, one block at a time, and the UI thread reads it.
+/// Reads may tear, which is fine for display.
+pub struct ScopeBuffer {
+    samples: Vec<AtomicU32>,
+    /// Ring index of the first sample of the block being written.
+    pos: AtomicUsize,
+}
+
+impl ScopeBuffer {
+    /// Length of the ring, in samples.
+    pub const LEN: usize = 4096;
+
+    pub fn new() -> Self {
+        Self {
+            samples: (0..Self::LEN).map(|_| AtomicU32::new(0)).collect(),
+            pos: AtomicUsize::new(0),
+        }
+    }
+
+    /// Start a new block, clearing it for taps to add to. Call this before
+    /// each processing block.
+    pub fn advance(&self) {
+        let pos = (self.pos.load(Ordering::Relaxed) + BlockProcessor::BLOCK_SIZE)
+            % Self::LEN;
+        for i in pos..pos + BlockProcessor::BLOCK_SIZE {
+            self.samples[i % Self::LEN].store(0, Ordering::Relaxed);
+        }
+        self.pos.store(pos, Ordering::Relaxed);
+    }
+
+    fn add(&self, pos: usize, offset: usize, x: f32) {
+        let sample = &self.samples[(pos + offset) % Self::LEN];
+        let sum = f32::from_bits(sample.load(Ordering::Relaxed)) + x;
+        sample.store(sum.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns the completed blocks in the ring, oldest first.
+    pub fn read(&self) -> Vec<f32> {
+        let start = self.pos.load(Ordering::Relaxed) + BlockProcessor::BLOCK_SIZE;
+        (start..start + Self::LEN - BlockProcessor::BLOCK_SIZE)
+            .map(|i| f32::from_bits(self.samples[i % Self::LEN].load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+impl Default for ScopeBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sink that adds its input to a scope buffer.
+pub fn scope_tap(buffer: &Arc<ScopeBuffer>) -> An<ScopeTap> {
+    An(ScopeTap {
+        buffer: buffer.clone(),
+        block_pos: None,
+        offset: 0,
+    })
+}
+
+#[derive(Clone)]
+pub struct ScopeTap {
+    buffer: Arc<ScopeBuffer>,
+    /// Buffer position of the block being written.
+    block_pos: Option<usize>,
+    /// Offset of the next sample in the block.
+    offset: usize,
+}
+
+impl AudioNode for ScopeTap {
+    const ID: u64 = 207;
+    type Inputs = U1;
+    type Outputs = U0;
+
+    fn reset(&mut self) {
+        self.block_pos = None;
+    }
+
+    fn set_sample_rate(&mut self, _sample_rate: f64) {}
+
+    #[inline]
+    fn tick(&mut self, input: &Frame<f32, Self::Inputs>) -> Frame<f32, Self::Outputs> {
+        let pos = self.buffer.pos.load(Ordering::Relaxed);
+        if self.block_pos != Some(pos) {
+            self.block_pos = Some(pos);
+            self.offset = 0;
+        }
+        if self.offset < BlockProcessor::BLOCK_SIZE {
+            self.buffer.add(pos, self.offset, input[0]);
+            self.offset += 1;
+        }
+        Frame::default()
+    }
+
+    fn route(&mut self, _input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        SignalFrame::new(self.outputs())
+    }
+}
+
+/// Processes a stereo unit in blocks of `MAX_BUFFER_SIZE` frames, which lets
+/// FunDSP use its SIMD code paths instead of ticking each node once per
+/// sample. Output can still be pulled one frame at a time.
+pub struct BlockProcessor {
+    unit: Box<dyn AudioUnit>,
+    buffer: BufferArray<U2>,
+    index: usize,
+}
+
+impl BlockProcessor {
+    pub const BLOCK_SIZE: usize = MAX_BUFFER_SIZE;
+
+    pub fn new(unit: Box<dyn AudioUnit>) -> Self {
+        Self {
+            unit,
+            buffer: BufferArray::new(),
+            index: Self::BLOCK_SIZE,
+        }
+    }
+
+    /// Process a new block, discarding any unread frames of the previous one.
+    pub fn process_block(&mut self) {
+        self.unit.process(Self::BLOCK_SIZE, &BufferRef::empty(),
+            &mut self.buffer.buffer_mut());
+        self.index = 0;
+    }
+
+    /// Returns the left and right channels of the current block.
+    pub fn block(&self) -> (&[f32], &[f32]) {
+        (self.buffer.channel_f32(0), self.buffer.channel_f32(1))
+    }
+
+    /// Returns the next frame, processing a new block if the current one is
+    /// exhausted.
+    #[inline]
+    pub fn get_stereo(&mut self) -> (f32, f32) {
+        if self.index >= Self::BLOCK_SIZE {
+            self.process_block();
+        }
+        let i = self.index;
+        self.index += 1;
+        (self.buffer.at_f32(0, i), self.buffer.at_f32(1, i))
+    }
+}
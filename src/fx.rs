@@ -1,5 +1,7 @@
 //! Global FX.
 
+use std::f32::consts::FRAC_1_SQRT_2;
+
 use fundsp::hacker32::*;
 use realseq::SequencerBackend;
 use serde::{Deserialize, Serialize};
@@ -7,17 +9,22 @@ use serde::{Deserialize, Serialize};
 use crate::dsp::compressor;
 
 // Serializable FX settings, to be stored in save files.
-#[derive(Clone, Default, Serialize, Deserialize)]
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct FXSettings {
     pub spatial: SpatialFx,
     pub comp: Compression,
+    #[serde(default)]
+    pub subsonic: Subsonic,
 }
 
 /// Handles updates of global FX.
 pub struct GlobalFX {
     pub net: Net,
     spatial_id: NodeId,
+    subsonic_id: NodeId,
     comp_id: NodeId,
+    /// Settings that the net was last built from.
+    settings: FXSettings,
 }
 
 impl GlobalFX {
@@ -25,46 +32,92 @@ impl GlobalFX {
 
     pub fn new(backend: SequencerBackend, settings: &FXSettings) -> Self {
         let (spatial, spatial_id) = Net::wrap_id(settings.spatial.make_node());
+        let (subsonic, subsonic_id) = Net::wrap_id(settings.subsonic.make_node());
         let (comp, comp_id) = Net::wrap_id(settings.comp.make_node());
 
         Self {
             net: Net::wrap(Box::new(backend))
                 >> (multipass::<U2>()
                     + (multipass::<U2>() >> spatial))
-                >> (dcblock() | dcblock())
+                >> subsonic
                 >> comp,
             spatial_id,
+            subsonic_id,
             comp_id,
+            settings: settings.clone(),
         }
     }
 
     /// Reinitialize all FX.
     pub fn reinit(&mut self, settings: &FXSettings) {
-        self.net.crossfade(self.spatial_id, Fade::Smooth, Self::FADE_TIME,
-            settings.spatial.make_node());
-        self.net.crossfade(self.comp_id, Fade::Smooth, Self::FADE_TIME,
-            settings.comp.make_node());
+        self.crossfade(self.spatial_id, settings.spatial.make_node());
+        self.crossfade(self.subsonic_id, settings.subsonic.make_node());
+        self.crossfade(self.comp_id, settings.comp.make_node());
         self.net.commit();
+        self.settings = settings.clone();
     }
 
-    /// Update spatial FX.
-    pub fn commit_spatial(&mut self, spatial: &SpatialFx) {
-        self.crossfade(self.spatial_id, spatial.make_node());
-    }
-
-    /// Update compression FX.
-    pub fn commit_comp(&mut self, comp: &Compression) {
-        self.crossfade(self.comp_id, comp.make_node());
+    /// Update the FX stages whose settings changed, leaving the others (and
+    /// their tails) alone.
+    pub fn update(&mut self, settings: &FXSettings) {
+        if *settings == self.settings {
+            return
+        }
+        if settings.spatial != self.settings.spatial {
+            self.crossfade(self.spatial_id, settings.spatial.make_node());
+        }
+        if settings.subsonic != self.settings.subsonic {
+            self.crossfade(self.subsonic_id, settings.subsonic.make_node());
+        }
+        if settings.comp != self.settings.comp {
+            self.crossfade(self.comp_id, settings.comp.make_node());
+        }
+        self.net.commit();
+        self.settings = settings.clone();
     }
 
     fn crossfade(&mut self, id: NodeId, unit: Box<dyn AudioUnit>) {
         self.net.crossfade(id, Fade::Smooth, Self::FADE_TIME, unit);
-        self.net.commit();
+    }
+}
+
+/// High-pass filter for removing DC offset and subsonic rumble.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Subsonic {
+    pub enabled: bool,
+    /// Cutoff frequency in Hz.
+    pub cutoff: f32,
+}
+
+impl Subsonic {
+    pub const MIN_CUTOFF: f32 = 10.0;
+    pub const MAX_CUTOFF: f32 = 30.0;
+
+    /// Returns a mono filter node, or None if the filter is disabled.
+    pub fn make_mono_node(&self) -> Option<An<impl AudioNode<Inputs = U1, Outputs = U1>>> {
+        self.enabled.then(|| dcblock() >> highpass_hz(self.cutoff, FRAC_1_SQRT_2))
+    }
+
+    /// Returns a stereo filter node. DC is always blocked.
+    fn make_node(&self) -> Box<dyn AudioUnit> {
+        match self.make_mono_node() {
+            Some(node) => Box::new(node.clone() | node),
+            None => Box::new(dcblock() | dcblock()),
+        }
+    }
+}
+
+impl Default for Subsonic {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cutoff: 20.0,
+        }
     }
 }
 
 /// Compression FX settings.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Compression {
     pub gain: f32,
     pub threshold: f32,
@@ -97,7 +150,7 @@ impl Default for Compression {
 }
 
 /// Spatial FX settings (delay/reverb).
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum SpatialFx {
     None,
     Reverb {
@@ -0,0 +1,26 @@
+//! UI-free interface for embedding the player in other programs.
+//!
+//! Build with `default-features = false, features = ["headless"]` to leave
+//! out the editor and its windowing, audio device, and MIDI dependencies.
+//! Renders are stereo at 44.1 kHz; a `Streamer` runs at any sample rate.
+
+use std::{error::Error, path::PathBuf};
+
+pub use fundsp::wave::Wave;
+
+pub use crate::module::Module;
+pub use crate::playback::{render_patch_to_buffer, render_to_wave, Streamer};
+pub use crate::synth::{Patch, VoiceSettings};
+pub use crate::timespan::Timespan;
+
+/// Loads a module from an .osctet file.
+pub fn load_module(path: impl Into<PathBuf>) -> Result<Module, Box<dyn Error>> {
+    Module::load(&path.into())
+}
+
+/// Renders a whole module with default voice settings. Modules that loop are
+/// faded out after their first loop. Modules missing End stop some seconds
+/// after their last event.
+pub fn render_module(module: &Module) -> Wave {
+    render_to_wave(module, None, VoiceSettings::default(), |_| ())
+}
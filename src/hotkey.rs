@@ -0,0 +1,346 @@
+//! Key combinations, shared by hotkeys and performance triggers.
+
+use std::fmt;
+
+#[cfg(feature = "gui")]
+use macroquad::input::{is_key_down, KeyCode};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "gui"))]
+pub use KeyCodeDef as KeyCode;
+
+/// Redefinition of macroquad's KeyCode for serde. Without the GUI, this
+/// stands in for KeyCode itself.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", serde(remote = "KeyCode"))]
+#[cfg_attr(not(feature = "gui"), derive(PartialEq, Eq, Hash, Clone, Copy, Debug))]
+pub enum KeyCodeDef {
+    Space = 0x0020,
+    Apostrophe = 0x0027,
+    Comma = 0x002c,
+    Minus = 0x002d,
+    Period = 0x002e,
+    Slash = 0x002f,
+    Key0 = 0x0030,
+    Key1 = 0x0031,
+    Key2 = 0x0032,
+    Key3 = 0x0033,
+    Key4 = 0x0034,
+    Key5 = 0x0035,
+    Key6 = 0x0036,
+    Key7 = 0x0037,
+    Key8 = 0x0038,
+    Key9 = 0x0039,
+    Semicolon = 0x003b,
+    Equal = 0x003d,
+    A = 0x0041,
+    B = 0x0042,
+    C = 0x0043,
+    D = 0x0044,
+    E = 0x0045,
+    F = 0x0046,
+    G = 0x0047,
+    H = 0x0048,
+    I = 0x0049,
+    J = 0x004a,
+    K = 0x004b,
+    L = 0x004c,
+    M = 0x004d,
+    N = 0x004e,
+    O = 0x004f,
+    P = 0x0050,
+    Q = 0x0051,
+    R = 0x0052,
+    S = 0x0053,
+    T = 0x0054,
+    U = 0x0055,
+    V = 0x0056,
+    W = 0x0057,
+    X = 0x0058,
+    Y = 0x0059,
+    Z = 0x005a,
+    LeftBracket = 0x005b,
+    Backslash = 0x005c,
+    RightBracket = 0x005d,
+    GraveAccent = 0x0060,
+    World1 = 0x0100,
+    World2 = 0x0101,
+    Escape = 0xff1b,
+    Enter = 0xff0d,
+    Tab = 0xff09,
+    Backspace = 0xff08,
+    Insert = 0xff63,
+    Delete = 0xffff,
+    Right = 0xff53,
+    Left = 0xff51,
+    Down = 0xff54,
+    Up = 0xff52,
+    PageUp = 0xff55,
+    PageDown = 0xff56,
+    Home = 0xff50,
+    End = 0xff57,
+    CapsLock = 0xffe5,
+    ScrollLock = 0xff14,
+    NumLock = 0xff7f,
+    PrintScreen = 0xfd1d,
+    Pause = 0xff13,
+    F1 = 0xffbe,
+    F2 = 0xffbf,
+    F3 = 0xffc0,
+    F4 = 0xffc1,
+    F5 = 0xffc2,
+    F6 = 0xffc3,
+    F7 = 0xffc4,
+    F8 = 0xffc5,
+    F9 = 0xffc6,
+    F10 = 0xffc7,
+    F11 = 0xffc8,
+    F12 = 0xffc9,
+    F13 = 0xffca,
+    F14 = 0xffcb,
+    F15 = 0xffcc,
+    F16 = 0xffcd,
+    F17 = 0xffce,
+    F18 = 0xffcf,
+    F19 = 0xffd0,
+    F20 = 0xffd1,
+    F21 = 0xffd2,
+    F22 = 0xffd3,
+    F23 = 0xffd4,
+    F24 = 0xffd5,
+    F25 = 0xffd6,
+    Kp0 = 0xffb0,
+    Kp1 = 0xffb1,
+    Kp2 = 0xffb2,
+    Kp3 = 0xffb3,
+    Kp4 = 0xffb4,
+    Kp5 = 0xffb5,
+    Kp6 = 0xffb6,
+    Kp7 = 0xffb7,
+    Kp8 = 0xffb8,
+    Kp9 = 0xffb9,
+    KpDecimal = 0xffae,
+    KpDivide = 0xffaf,
+    KpMultiply = 0xffaa,
+    KpSubtract = 0xffad,
+    KpAdd = 0xffab,
+    KpEnter = 0xff8d,
+    KpEqual = 0xffbd,
+    LeftShift = 0xffe1,
+    LeftControl = 0xffe3,
+    LeftAlt = 0xffe9,
+    LeftSuper = 0xffeb,
+    RightShift = 0xffe2,
+    RightControl = 0xffe4,
+    RightAlt = 0xffea,
+    RightSuper = 0xffec,
+    Menu = 0xff67,
+    // Android back button
+    Back = 0xff04,
+    Unknown = 0x01ff,
+}
+
+/// Translates a keycode to a string representation for hotkey UI.
+fn key_to_string(key: KeyCode) -> String {
+    let s = match key {
+        KeyCode::Apostrophe => "'",
+        KeyCode::Comma => ",",
+        KeyCode::Minus => "-",
+        KeyCode::Period => ".",
+        KeyCode::Slash => "/",
+        KeyCode::Key0 => "0",
+        KeyCode::Key1 => "1",
+        KeyCode::Key2 => "2",
+        KeyCode::Key3 => "3",
+        KeyCode::Key4 => "4",
+        KeyCode::Key5 => "5",
+        KeyCode::Key6 => "6",
+        KeyCode::Key7 => "7",
+        KeyCode::Key8 => "8",
+        KeyCode::Key9 => "9",
+        KeyCode::Semicolon => ";",
+        KeyCode::Equal => "=",
+        KeyCode::LeftBracket => "[",
+        KeyCode::Backslash => "\\",
+        KeyCode::RightBracket => "]",
+        KeyCode::GraveAccent => "`",
+        _ => &format!("{:?}", key),
+    };
+    s.to_owned()
+}
+
+/// Combination of modifier keys. This is a silly way to store this information,
+/// but it serializes to TOML a lot nicer than a struct of three booleans.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Modifiers {
+    None,
+    Super,
+    Ctrl,
+    Alt,
+    Shift,
+    SuperCtrl,
+    SuperAlt,
+    SuperShift,
+    CtrlAlt,
+    CtrlShift,
+    AltShift,
+    SuperCtrlAlt,
+    SuperCtrlShift,
+    SuperAltShift,
+    CtrlAltShift,
+    SuperCtrlAltShift,
+}
+
+impl Modifiers {
+    /// Returns the currently held modifiers.
+    #[cfg(feature = "gui")]
+    pub fn current() -> Self {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let alt = is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let sup = is_key_down(KeyCode::LeftSuper) || is_key_down(KeyCode::RightSuper);
+        Self::from_boolean([sup, ctrl, alt, shift])
+    }
+
+    fn from_boolean(states: [bool; 4]) -> Self {
+        match states {
+            [false, false, false, false] => Self::None,
+            [false, true, false, false] => Self::Ctrl,
+            [false, false, true, false] => Self::Alt,
+            [false, false, false, true] => Self::Shift,
+            [false, true, true, false] => Self::CtrlAlt,
+            [false, true, false, true] => Self::CtrlShift,
+            [false, false, true, true] => Self::AltShift,
+            [false, true, true, true] => Self::CtrlAltShift,
+            [true, false, false, false] => Self::Super,
+            [true, true, false, false] => Self::SuperCtrl,
+            [true, false, true, false] => Self::SuperAlt,
+            [true, false, false, true] => Self::SuperShift,
+            [true, true, true, false] => Self::SuperCtrlAlt,
+            [true, true, false, true] => Self::SuperCtrlShift,
+            [true, false, true, true] => Self::SuperAltShift,
+            [true, true, true, true] => Self::SuperCtrlAltShift,
+        }
+    }
+
+    fn to_boolean(&self) -> [bool; 4] {
+        match self {
+            Self::None => [false, false, false, false],
+            Self::Ctrl => [false, true, false, false],
+            Self::Alt => [false, false, true, false],
+            Self::Shift => [false, false, false, true],
+            Self::CtrlAlt => [false, true, true, false],
+            Self::CtrlShift => [false, true, false, true],
+            Self::AltShift => [false, false, true, true],
+            Self::CtrlAltShift => [false, true, true, true],
+            Self::Super => [true, false, false, false],
+            Self::SuperCtrl => [true, true, false, false],
+            Self::SuperAlt => [true, false, true, false],
+            Self::SuperShift => [true, false, false, true],
+            Self::SuperCtrlAlt => [true, true, true, false],
+            Self::SuperCtrlShift => [true, true, false, true],
+            Self::SuperAltShift => [true, false, true, true],
+            Self::SuperCtrlAltShift => [true, true, true, true],
+        }
+    }
+
+    /// Returns a version of this modifier with shift omitted. This is used
+    /// for actions that have special alternative behavior when shift is held.
+    pub fn without_shift(&self) -> Self {
+        let mut states = self.to_boolean();
+        states[3] = false;
+        Self::from_boolean(states)
+    }
+
+    /// Swaps this modifier's Ctrl and Super states.
+    pub fn swap_super_and_ctrl(&mut self) {
+        let mut states = self.to_boolean();
+        states.swap(0, 1);
+        *self = Self::from_boolean(states)
+    }
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let states = self.to_boolean();
+        let mut tokens = Vec::new();
+        if states[0] {
+            if cfg!(target_os = "macos") {
+                tokens.push("Cmd")
+            } else {
+                tokens.push("Super")
+            }
+        }
+        if states[1] {
+            tokens.push("Ctrl")
+        }
+        if states[2] {
+            if cfg!(target_os = "macos") {
+                tokens.push("Option")
+            } else {
+                tokens.push("Alt")
+            }
+        }
+        if states[3] {
+            tokens.push("Shift")
+        }
+        write!(f, "{}", if tokens.is_empty() {
+            "(none)".into()
+        } else {
+            tokens.join("+")
+        })
+    }
+}
+
+/// Key combination.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+pub struct Hotkey {
+    pub mods: Modifiers,
+    #[cfg_attr(feature = "gui", serde(with = "KeyCodeDef"))]
+    pub key: KeyCode,
+    /// Key combination pressed before this one, for two-stroke hotkeys.
+    #[serde(default)]
+    pub prefix: Option<Box<Hotkey>>,
+    /// If true, the hotkey only applies while the layer key is held.
+    #[serde(default)]
+    pub layer: bool,
+}
+
+impl Hotkey {
+    pub fn new(mods: Modifiers, key: KeyCode) -> Self {
+        Self { mods, key, prefix: None, layer: false }
+    }
+
+    /// Returns a two-stroke version of the hotkey, pressed after `prefix`.
+    pub fn after(self, prefix: Hotkey) -> Self {
+        Self { prefix: Some(Box::new(prefix)), ..self }
+    }
+
+    /// Checks whether the hotkey is currently held.
+    #[cfg(feature = "gui")]
+    pub fn is_down(&self) -> bool {
+        is_key_down(self.key) && self.mods == Modifiers::current()
+    }
+
+    /// Returns a verison of the hotkey without shift. This is used for
+    /// actions that have special alternative behavior when shift is held.
+    pub fn without_shift(&self) -> Self {
+        Self { mods: self.mods.without_shift(), ..self.clone() }
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(prefix) = &self.prefix {
+            write!(f, "{prefix}, ")?;
+        }
+        if self.layer {
+            write!(f, "Layer+")?;
+        }
+        if self.mods == Modifiers::None {
+            write!(f, "{}", key_to_string(self.key))
+        } else {
+            write!(f, "{}+{}", self.mods, key_to_string(self.key))
+        }
+    }
+}
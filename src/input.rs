@@ -1,12 +1,12 @@
 //! Code for processing keyboard and MIDI input.
 
-use std::fmt;
-
-use macroquad::input::{is_key_down, KeyCode};
+use macroquad::input::KeyCode;
 use serde::{Deserialize, Serialize};
 
 use crate::{config::Config, pitch::{Nominal, Note, Tuning}};
 
+pub use crate::hotkey::{Hotkey, Modifiers};
+
 pub const CC_MODULATION: u8 = 1;
 pub const CC_MACRO_MIN: u8 = 41;
 pub const CC_MACRO_MAX: u8 = 48;
@@ -22,6 +22,16 @@ pub fn u8_from_key(k: KeyCode) -> u8 {
     format!("{:?}", k).bytes().last().unwrap_or_default()
 }
 
+/// Returns true if a key is in the bottom two letter rows of the keyboard,
+/// which form the lower zone of a keyboard split.
+pub fn is_lower_zone_key(k: KeyCode) -> bool {
+    matches!(k, KeyCode::A | KeyCode::S | KeyCode::D | KeyCode::F | KeyCode::G
+        | KeyCode::H | KeyCode::J | KeyCode::K | KeyCode::L | KeyCode::Semicolon
+        | KeyCode::Apostrophe | KeyCode::Z | KeyCode::X | KeyCode::C | KeyCode::V
+        | KeyCode::B | KeyCode::N | KeyCode::M | KeyCode::Comma | KeyCode::Period
+        | KeyCode::Slash)
+}
+
 /// Returns true if sharps & flats are useful for a given tuning. Sharps are
 /// considered useless if they're identical to unison or the whole tone.
 fn use_sharps(t: &Tuning) -> bool {
@@ -53,10 +63,7 @@ pub fn note_from_key(key: Hotkey, t: &Tuning, equave: i8, cfg: &Config) -> Optio
 
 /// Returns the default key-to-note mapping.
 pub fn default_note_keys() -> Vec<(Hotkey, Note)> {
-    let f1 = |key| Hotkey {
-        key,
-        mods: Modifiers::None,
-    };
+    let f1 = |key| Hotkey::new(Modifiers::None, key);
     let f2 = |nominal, accidentals: i8, offset: i8| Note {
         arrows: 0,
         nominal,
@@ -231,6 +238,16 @@ impl MidiEvent {
     /// The zero value of a pitch bend message.
     const PITCH_CENTER: i16 = 0x2000;
 
+    /// Returns the zero-based channel of the event.
+    pub fn channel(&self) -> u8 {
+        match *self {
+            Self::NoteOff { channel, .. } | Self::NoteOn { channel, .. }
+                | Self::PolyPressure { channel, .. } | Self::Controller { channel, .. }
+                | Self::ChannelPressure { channel, .. } | Self::Pitch { channel, .. }
+                => channel,
+        }
+    }
+
     /// Parses a byte sequence into an event struct.
     pub fn parse(data: &[u8]) -> Option<Self> {
         // all the messages we're interested in are at least 2 bytes
@@ -256,321 +273,6 @@ impl MidiEvent {
     }
 }
 
-/// Redefinition of macroquad's KeyCode for serde.
-#[derive(Serialize, Deserialize)]
-#[serde(remote = "KeyCode")]
-enum KeyCodeDef {
-    Space = 0x0020,
-    Apostrophe = 0x0027,
-    Comma = 0x002c,
-    Minus = 0x002d,
-    Period = 0x002e,
-    Slash = 0x002f,
-    Key0 = 0x0030,
-    Key1 = 0x0031,
-    Key2 = 0x0032,
-    Key3 = 0x0033,
-    Key4 = 0x0034,
-    Key5 = 0x0035,
-    Key6 = 0x0036,
-    Key7 = 0x0037,
-    Key8 = 0x0038,
-    Key9 = 0x0039,
-    Semicolon = 0x003b,
-    Equal = 0x003d,
-    A = 0x0041,
-    B = 0x0042,
-    C = 0x0043,
-    D = 0x0044,
-    E = 0x0045,
-    F = 0x0046,
-    G = 0x0047,
-    H = 0x0048,
-    I = 0x0049,
-    J = 0x004a,
-    K = 0x004b,
-    L = 0x004c,
-    M = 0x004d,
-    N = 0x004e,
-    O = 0x004f,
-    P = 0x0050,
-    Q = 0x0051,
-    R = 0x0052,
-    S = 0x0053,
-    T = 0x0054,
-    U = 0x0055,
-    V = 0x0056,
-    W = 0x0057,
-    X = 0x0058,
-    Y = 0x0059,
-    Z = 0x005a,
-    LeftBracket = 0x005b,
-    Backslash = 0x005c,
-    RightBracket = 0x005d,
-    GraveAccent = 0x0060,
-    World1 = 0x0100,
-    World2 = 0x0101,
-    Escape = 0xff1b,
-    Enter = 0xff0d,
-    Tab = 0xff09,
-    Backspace = 0xff08,
-    Insert = 0xff63,
-    Delete = 0xffff,
-    Right = 0xff53,
-    Left = 0xff51,
-    Down = 0xff54,
-    Up = 0xff52,
-    PageUp = 0xff55,
-    PageDown = 0xff56,
-    Home = 0xff50,
-    End = 0xff57,
-    CapsLock = 0xffe5,
-    ScrollLock = 0xff14,
-    NumLock = 0xff7f,
-    PrintScreen = 0xfd1d,
-    Pause = 0xff13,
-    F1 = 0xffbe,
-    F2 = 0xffbf,
-    F3 = 0xffc0,
-    F4 = 0xffc1,
-    F5 = 0xffc2,
-    F6 = 0xffc3,
-    F7 = 0xffc4,
-    F8 = 0xffc5,
-    F9 = 0xffc6,
-    F10 = 0xffc7,
-    F11 = 0xffc8,
-    F12 = 0xffc9,
-    F13 = 0xffca,
-    F14 = 0xffcb,
-    F15 = 0xffcc,
-    F16 = 0xffcd,
-    F17 = 0xffce,
-    F18 = 0xffcf,
-    F19 = 0xffd0,
-    F20 = 0xffd1,
-    F21 = 0xffd2,
-    F22 = 0xffd3,
-    F23 = 0xffd4,
-    F24 = 0xffd5,
-    F25 = 0xffd6,
-    Kp0 = 0xffb0,
-    Kp1 = 0xffb1,
-    Kp2 = 0xffb2,
-    Kp3 = 0xffb3,
-    Kp4 = 0xffb4,
-    Kp5 = 0xffb5,
-    Kp6 = 0xffb6,
-    Kp7 = 0xffb7,
-    Kp8 = 0xffb8,
-    Kp9 = 0xffb9,
-    KpDecimal = 0xffae,
-    KpDivide = 0xffaf,
-    KpMultiply = 0xffaa,
-    KpSubtract = 0xffad,
-    KpAdd = 0xffab,
-    KpEnter = 0xff8d,
-    KpEqual = 0xffbd,
-    LeftShift = 0xffe1,
-    LeftControl = 0xffe3,
-    LeftAlt = 0xffe9,
-    LeftSuper = 0xffeb,
-    RightShift = 0xffe2,
-    RightControl = 0xffe4,
-    RightAlt = 0xffea,
-    RightSuper = 0xffec,
-    Menu = 0xff67,
-    // Android back button
-    Back = 0xff04,
-    Unknown = 0x01ff,
-}
-
-/// Translates a keycode to a string representation for hotkey UI.
-fn key_to_string(key: KeyCode) -> String {
-    let s = match key {
-        KeyCode::Apostrophe => "'",
-        KeyCode::Comma => ",",
-        KeyCode::Minus => "-",
-        KeyCode::Period => ".",
-        KeyCode::Slash => "/",
-        KeyCode::Key0 => "0",
-        KeyCode::Key1 => "1",
-        KeyCode::Key2 => "2",
-        KeyCode::Key3 => "3",
-        KeyCode::Key4 => "4",
-        KeyCode::Key5 => "5",
-        KeyCode::Key6 => "6",
-        KeyCode::Key7 => "7",
-        KeyCode::Key8 => "8",
-        KeyCode::Key9 => "9",
-        KeyCode::Semicolon => ";",
-        KeyCode::Equal => "=",
-        KeyCode::LeftBracket => "[",
-        KeyCode::Backslash => "\\",
-        KeyCode::RightBracket => "]",
-        KeyCode::GraveAccent => "`",
-        _ => &format!("{:?}", key),
-    };
-    s.to_owned()
-}
-
-/// Combination of modifier keys. This is a silly way to store this information,
-/// but it serializes to TOML a lot nicer than a struct of three booleans.
-#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
-pub enum Modifiers {
-    None,
-    Super,
-    Ctrl,
-    Alt,
-    Shift,
-    SuperCtrl,
-    SuperAlt,
-    SuperShift,
-    CtrlAlt,
-    CtrlShift,
-    AltShift,
-    SuperCtrlAlt,
-    SuperCtrlShift,
-    SuperAltShift,
-    CtrlAltShift,
-    SuperCtrlAltShift,
-}
-
-impl Modifiers {
-    /// Returns the currently held modifiers.
-    pub fn current() -> Self {
-        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
-        let alt = is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
-        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
-        let sup = is_key_down(KeyCode::LeftSuper) || is_key_down(KeyCode::RightSuper);
-        Self::from_boolean([sup, ctrl, alt, shift])
-    }
-
-    fn from_boolean(states: [bool; 4]) -> Self {
-        match states {
-            [false, false, false, false] => Self::None,
-            [false, true, false, false] => Self::Ctrl,
-            [false, false, true, false] => Self::Alt,
-            [false, false, false, true] => Self::Shift,
-            [false, true, true, false] => Self::CtrlAlt,
-            [false, true, false, true] => Self::CtrlShift,
-            [false, false, true, true] => Self::AltShift,
-            [false, true, true, true] => Self::CtrlAltShift,
-            [true, false, false, false] => Self::Super,
-            [true, true, false, false] => Self::SuperCtrl,
-            [true, false, true, false] => Self::SuperAlt,
-            [true, false, false, true] => Self::SuperShift,
-            [true, true, true, false] => Self::SuperCtrlAlt,
-            [true, true, false, true] => Self::SuperCtrlShift,
-            [true, false, true, true] => Self::SuperAltShift,
-            [true, true, true, true] => Self::SuperCtrlAltShift,
-        }
-    }
-
-    fn to_boolean(&self) -> [bool; 4] {
-        match self {
-            Self::None => [false, false, false, false],
-            Self::Ctrl => [false, true, false, false],
-            Self::Alt => [false, false, true, false],
-            Self::Shift => [false, false, false, true],
-            Self::CtrlAlt => [false, true, true, false],
-            Self::CtrlShift => [false, true, false, true],
-            Self::AltShift => [false, false, true, true],
-            Self::CtrlAltShift => [false, true, true, true],
-            Self::Super => [true, false, false, false],
-            Self::SuperCtrl => [true, true, false, false],
-            Self::SuperAlt => [true, false, true, false],
-            Self::SuperShift => [true, false, false, true],
-            Self::SuperCtrlAlt => [true, true, true, false],
-            Self::SuperCtrlShift => [true, true, false, true],
-            Self::SuperAltShift => [true, false, true, true],
-            Self::SuperCtrlAltShift => [true, true, true, true],
-        }
-    }
-
-    /// Returns a version of this modifier with shift omitted. This is used
-    /// for actions that have special alternative behavior when shift is held.
-    pub fn without_shift(&self) -> Self {
-        let mut states = self.to_boolean();
-        states[3] = false;
-        Self::from_boolean(states)
-    }
-
-    /// Swaps this modifier's Ctrl and Super states.
-    pub fn swap_super_and_ctrl(&mut self) {
-        let mut states = self.to_boolean();
-        states.swap(0, 1);
-        *self = Self::from_boolean(states)
-    }
-}
-
-impl fmt::Display for Modifiers {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let states = self.to_boolean();
-        let mut tokens = Vec::new();
-        if states[0] {
-            if cfg!(target_os = "macos") {
-                tokens.push("Cmd")
-            } else {
-                tokens.push("Super")
-            }
-        }
-        if states[1] {
-            tokens.push("Ctrl")
-        }
-        if states[2] {
-            if cfg!(target_os = "macos") {
-                tokens.push("Option")
-            } else {
-                tokens.push("Alt")
-            }
-        }
-        if states[3] {
-            tokens.push("Shift")
-        }
-        write!(f, "{}", if tokens.is_empty() {
-            "(none)".into()
-        } else {
-            tokens.join("+")
-        })
-    }
-}
-
-/// Key combination.
-#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
-pub struct Hotkey {
-    pub mods: Modifiers,
-    #[serde(with = "KeyCodeDef")]
-    pub key: KeyCode,
-}
-
-impl Hotkey {
-    pub fn new(mods: Modifiers, key: KeyCode) -> Self {
-        Self { mods, key }
-    }
-
-    /// Checks whether the hotkey is currently held.
-    pub fn is_down(&self) -> bool {
-        is_key_down(self.key) && self.mods == Modifiers::current()
-    }
-
-    /// Returns a verison of the hotkey without shift. This is used for
-    /// actions that have special alternative behavior when shift is held.
-    pub fn without_shift(&self) -> Self {
-        Self { mods: self.mods.without_shift(), key: self.key }
-    }
-}
-
-impl fmt::Display for Hotkey {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.mods == Modifiers::None {
-            write!(f, "{}", key_to_string(self.key))
-        } else {
-            write!(f, "{}+{}", self.mods, key_to_string(self.key))
-        }
-    }
-}
-
 /// Mappable key commands. Can also be used in situations like confirmation
 /// dialogs where commands need to be deferred pending further input.
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -645,9 +347,86 @@ pub enum Action {
     Quit,
     ShiftTrackLeft,
     ShiftTrackRight,
+    EditMarker,
+    GroupTracks,
+    AddSoloTrack,
+    MomentarySolo,
+    EditCondition,
+    EditSlide,
+    EditEffects,
+    OffsetLater,
+    OffsetEarlier,
+    PasteSpecial,
+    FillPaste,
+    ToggleTrackLock,
+    ToggleColumnLock,
+    ExportMidi,
+    CopyText,
+    CopyMml,
+    ToggleInstrumentPanel,
+    ToggleSplitView,
+    SwitchPane,
+    ToggleFullscreen,
+    CopyInspector,
+    ExportVideo,
+    ToggleTriplets,
+    ToggleTripletGrid,
+    Skip,
+    AnalyzeIntervals,
+    ToggleCheatSheet,
+    StartTutorial,
+    ToggleDrawMode,
+    ToggleAutomationLane,
+    Quantize,
+    SaveMacro,
 }
 
 impl Action {
+    /// Categories returned by `category`, in display order.
+    pub const CATEGORIES: [&'static str; 8] = ["Global", "Status", "Navigation", "Events",
+        "Pitch & notation", "Clipboard", "Playback", "Pattern"];
+
+    /// Returns the category the action is listed under in the cheat sheet.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::NewSong | Self::OpenSong | Self::SaveSong | Self::SaveSongAs
+                | Self::RenderSong | Self::RenderTracks | Self::ExportVideo
+                | Self::NextTab | Self::PrevTab | Self::Undo | Self::Redo
+                | Self::ToggleInstrumentPanel | Self::ToggleFullscreen | Self::Quit
+                | Self::ToggleCheatSheet | Self::StartTutorial => "Global",
+            Self::IncrementDivision | Self::DecrementDivision | Self::DoubleDivision
+                | Self::HalveDivision | Self::FocusDivision | Self::IncrementOctave
+                | Self::DecrementOctave | Self::ToggleTriplets => "Status",
+            Self::NextRow | Self::PrevRow | Self::NextColumn | Self::PrevColumn
+                | Self::NextChannel | Self::PrevChannel | Self::NextBeat | Self::PrevBeat
+                | Self::NextEvent | Self::PrevEvent | Self::PatternStart
+                | Self::PatternEnd | Self::SelectAllChannels
+                | Self::SelectAllRows => "Navigation",
+            Self::UseLastNote | Self::NoteOff | Self::TapTempo | Self::RationalTempo
+                | Self::Loop | Self::End | Self::Skip | Self::Interpolate => "Events",
+            Self::DecrementValues | Self::IncrementValues | Self::NudgeOctaveDown
+                | Self::NudgeOctaveUp | Self::NudgeArrowDown | Self::NudgeArrowUp
+                | Self::NudgeFlat | Self::NudgeSharp | Self::NudgeEnharmonic
+                | Self::CycleNotation => "Pitch & notation",
+            Self::Cut | Self::Copy | Self::Paste | Self::MixPaste | Self::InsertPaste
+                | Self::StretchPaste | Self::PasteSpecial | Self::FillPaste
+                | Self::CopyText | Self::CopyMml | Self::CopyInspector => "Clipboard",
+            Self::PlayFromStart | Self::PlayFromScreen | Self::PlayFromCursor
+                | Self::StopPlayback | Self::ToggleFollow | Self::MuteTrack
+                | Self::SoloTrack | Self::AddSoloTrack | Self::MomentarySolo
+                | Self::UnmuteAllTracks | Self::Panic => "Playback",
+            Self::Delete | Self::InsertRows | Self::DeleteRows | Self::PlaceEvenly
+                | Self::OffsetLater | Self::OffsetEarlier | Self::ShiftTrackLeft
+                | Self::ShiftTrackRight | Self::EditMarker | Self::GroupTracks
+                | Self::EditCondition | Self::EditSlide | Self::EditEffects
+                | Self::ToggleTrackLock | Self::ToggleColumnLock | Self::ExportMidi
+                | Self::AnalyzeIntervals | Self::ToggleSplitView | Self::SwitchPane
+                | Self::ToggleTripletGrid | Self::ToggleDrawMode
+                | Self::ToggleAutomationLane | Self::Quantize
+                | Self::SaveMacro => "Pattern",
+        }
+    }
+
     /// Returns the UI string for the action.
     pub fn name(&self) -> &'static str {
         match self {
@@ -721,6 +500,38 @@ impl Action {
             Self::Quit => "Quit",
             Self::ShiftTrackLeft => "Shift track left",
             Self::ShiftTrackRight => "Shift track right",
+            Self::EditMarker => "Edit marker",
+            Self::GroupTracks => "Group tracks",
+            Self::AddSoloTrack => "Add solo track",
+            Self::MomentarySolo => "Momentary solo",
+            Self::EditCondition => "Edit note condition",
+            Self::EditSlide => "Edit note slide",
+            Self::EditEffects => "Edit vibrato/tremolo",
+            Self::OffsetLater => "Offset events later",
+            Self::OffsetEarlier => "Offset events earlier",
+            Self::PasteSpecial => "Paste special",
+            Self::FillPaste => "Fill paste",
+            Self::ToggleTrackLock => "Toggle track lock",
+            Self::ToggleColumnLock => "Toggle column lock",
+            Self::ExportMidi => "Export MIDI clip",
+            Self::ExportVideo => "Export video",
+            Self::ToggleTriplets => "Toggle triplets",
+            Self::ToggleTripletGrid => "Toggle triplet grid",
+            Self::Skip => "Mark skip",
+            Self::AnalyzeIntervals => "Analyze intervals",
+            Self::ToggleCheatSheet => "Toggle cheat sheet",
+            Self::StartTutorial => "Start tutorial",
+            Self::ToggleDrawMode => "Toggle draw mode",
+            Self::ToggleAutomationLane => "Toggle automation lane",
+            Self::Quantize => "Quantize",
+            Self::SaveMacro => "Save selection as macro",
+            Self::CopyText => "Copy as text",
+            Self::CopyMml => "Copy as MML",
+            Self::ToggleInstrumentPanel => "Toggle instrument panel",
+            Self::ToggleSplitView => "Toggle split view",
+            Self::SwitchPane => "Switch pane",
+            Self::ToggleFullscreen => "Toggle fullscreen",
+            Self::CopyInspector => "Copy cursor description",
         }
     }
 }
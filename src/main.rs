@@ -5,17 +5,20 @@ use std::{backtrace::Backtrace, env, error::Error, fs, panic};
 
 use macroquad::{input::prevent_quit, miniquad::conf::Icon, prelude::Conf, texture::Image};
 
-use osctet::{exe_relative_path, run, APP_NAME};
+use osctet::{exe_relative_path, run, saved_window_state, APP_NAME};
 
 /// Filename to write panic messages to.
 const PANIC_FILE: &str = "error.txt";
 
 /// Returns initial WM settings.
 fn window_conf() -> Conf {
+    let state = saved_window_state();
     Conf {
         window_title: APP_NAME.to_owned(),
-        window_width: 1280,
-        window_height: 720,
+        window_width: state.width,
+        window_height: state.height,
+        fullscreen: state.fullscreen,
+        high_dpi: true,
         icon: Some(Icon {
             small: decode_icon(include_bytes!("../icon/icon_16.png"))
                 .try_into().unwrap(),
@@ -1,13 +1,13 @@
 //! Definitions for most stored module data.
 
-use std::{collections::HashSet, error::Error, fs::File, io::{BufReader, Read, Write}, path::PathBuf};
+use std::{collections::{HashSet, VecDeque}, error::Error, fmt, fs::{self, File}, io::{Read, Write}, ops::Range, path::{Path, PathBuf}};
 
-use flate2::{bufread::GzDecoder, write::GzEncoder};
+use flate2::bufread::GzDecoder;
 use rmp_serde::{config::BytesMode, Serializer};
-use rtrb::Producer;
+use rtrb::{Producer, PushError};
 use serde::{Deserialize, Serialize};
 
-use crate::{fx::FXSettings, pitch::{Note, Tuning}, playback::{tick_interval, DEFAULT_TEMPO}, synth::Patch, timespan::Timespan};
+use crate::{fx::FXSettings, hotkey::Hotkey, pitch::{Note, Tuning}, playback::{tick_interval, DEFAULT_TEMPO}, synth::{pcm::PcmData, Parameter, Patch}, timespan::Timespan};
 
 pub const GLOBAL_COLUMN: u8 = 0;
 pub const NOTE_COLUMN: u8 = 0;
@@ -27,6 +27,24 @@ pub struct Module {
     /// This field is just for save/load. See `PatternEditor` for actual usage.
     #[serde(default = "default_division")]
     pub division: u8,
+    /// Save version, used to convert data from older files.
+    #[serde(default)]
+    pub version: u8,
+    /// Text annotations, sorted by tick.
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    /// Sorted by first track, and never overlapping.
+    #[serde(default)]
+    pub groups: Vec<TrackGroup>,
+    /// Rows the pattern cursor advances after entering data.
+    #[serde(default)]
+    pub edit_step: EditStep,
+    /// Templates for quantizing, extracted from pattern data.
+    #[serde(default)]
+    pub grooves: Vec<Groove>,
+    /// Event bundles inserted at the pattern cursor by hotkey.
+    #[serde(default)]
+    pub macros: Vec<EventMacro>,
 
     #[serde(skip)]
     undo_stack: Vec<Edit>,
@@ -40,6 +58,12 @@ pub struct Module {
     sync_stack: Vec<Edit>,
     #[serde(skip)]
     pub sync: bool,
+    /// Number of edits performed, including undos and redos.
+    #[serde(skip)]
+    edit_count: u64,
+    /// True if the last undo item came from a gesture that hasn't ended.
+    #[serde(skip)]
+    gesture: bool,
 }
 
 /// Default beat division for serde.
@@ -67,19 +91,159 @@ impl Module {
             division: default_division(),
             sync_stack: Vec::new(),
             sync: false,
+            version: Self::VERSION,
+            markers: Vec::new(),
+            groups: Vec::new(),
+            edit_step: Default::default(),
+            grooves: Vec::new(),
+            macros: Vec::new(),
+            edit_count: 0,
+            gesture: false,
         }
     }
 
+    /// Current save version.
+    const VERSION: u8 = 1;
+
+    /// zstd compression level for saved modules.
+    const COMPRESSION_LEVEL: i32 = 9;
+
     /// Load a module from `path`.
     pub fn load(path: &PathBuf) -> Result<Self, Box<dyn Error>> {
-        let file = File::open(path)?;
-        let mut input = Vec::new();
-        GzDecoder::new(BufReader::new(file)).read_to_end(&mut input)?;
+        Self::from_bytes(&fs::read(path)?, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Load a module from the contents of a module file. Referenced sample
+    /// files are looked up relative to `dir`.
+    pub fn from_bytes(bytes: &[u8], dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let input = decompress(bytes)?;
         let mut module = rmp_serde::from_slice::<Self>(&input)?;
+        if module.version > Self::VERSION {
+            return Err("module was saved by a newer version of Osctet".into())
+        }
+        module.migrate();
+        for data in module.pcm_data_mut() {
+            if let Err(e) = data.load_reference(dir) {
+                eprintln!("{e}");
+            }
+        }
         module.init_patches();
+        module.init_clips();
         Ok(module)
     }
 
+    /// Initialize deserialized audio clips.
+    fn init_clips(&mut self) {
+        for clip in self.tracks.iter_mut().flat_map(|t| t.clips.iter_mut()) {
+            if let Err(e) = clip.data.init() {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    /// Returns the PCM data of all patches and audio clips.
+    fn pcm_data_mut(&mut self) -> impl Iterator<Item = &mut PcmData> {
+        self.patches.iter_mut().flat_map(|p| p.pcm_data_mut())
+            .chain(self.tracks.iter_mut()
+                .flat_map(|t| t.clips.iter_mut().map(|c| &mut c.data)))
+    }
+
+    /// Copy referenced samples into a "samples" folder next to the module
+    /// file at `path`. Returns the number of files copied.
+    pub fn collect_samples(&mut self, path: &Path) -> Result<usize, Box<dyn Error>> {
+        let dir = path.parent().unwrap_or(Path::new(".")).join("samples");
+        let mut n = 0;
+        for data in self.pcm_data_mut() {
+            if data.collect(&dir)? {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    /// Assigns a hotkey to a macro, removing it from any other macro.
+    pub fn set_macro_hotkey(&mut self, index: usize, hotkey: Hotkey) {
+        let mut macros = self.macros.clone();
+        for m in &mut macros {
+            if m.hotkey.as_ref() == Some(&hotkey) {
+                m.hotkey = None;
+            }
+        }
+        if let Some(m) = macros.get_mut(index) {
+            m.hotkey = Some(hotkey);
+            self.push_edit(Edit::SetMacros(macros));
+        }
+    }
+
+    /// Returns the file paths of referenced samples.
+    pub fn referenced_sample_paths(&self) -> Vec<&PathBuf> {
+        self.patches.iter().flat_map(|p| p.pcm_data())
+            .chain(self.tracks.iter().flat_map(|t| t.clips.iter().map(|c| &c.data)))
+            .filter(|data| data.referenced)
+            .filter_map(|data| data.path.as_ref())
+            .collect()
+    }
+
+    /// Returns edits that re-read referenced samples from the files at
+    /// `paths`, and the file names of the reloaded samples. Samples that fail
+    /// to load are left as they are.
+    pub fn reload_samples(&self, paths: &[PathBuf]) -> (Vec<Edit>, Vec<String>) {
+        let watched = |data: &PcmData| {
+            data.referenced && data.path.as_ref().is_some_and(|p| paths.contains(p))
+        };
+        let mut names = Vec::new();
+        let mut reload = |data: &mut PcmData| {
+            if !watched(data) {
+                return false
+            }
+            let Ok(new) = data.reload() else { return false };
+            if !names.contains(&new.filename) {
+                names.push(new.filename.clone());
+            }
+            *data = new;
+            true
+        };
+
+        let mut edits = Vec::new();
+        if self.patches.iter().flat_map(|p| p.pcm_data()).any(watched) {
+            let mut patches = self.patches.clone();
+            let mut changed = false;
+            for data in patches.iter_mut().flat_map(|p| p.pcm_data_mut()) {
+                changed |= reload(data);
+            }
+            if changed {
+                edits.push(Edit::SetPatches {
+                    patches,
+                    kit: self.kit.clone(),
+                    targets: self.tracks.iter().map(|t| t.target).collect(),
+                });
+            }
+        }
+
+        for (i, track) in self.tracks.iter().enumerate() {
+            if !track.clips.iter().any(|c| watched(&c.data)) {
+                continue
+            }
+            let mut clips = track.clips.clone();
+            let mut changed = false;
+            for clip in &mut clips {
+                changed |= reload(&mut clip.data);
+            }
+            if changed {
+                edits.push(Edit::SetClips(i, clips));
+            }
+        }
+
+        (edits, names)
+    }
+
+    /// Convert data from older save versions.
+    fn migrate(&mut self) {
+        // version 0 is unversioned and needs no conversion. add a step here
+        // whenever the meaning of stored data changes.
+        self.version = Self::VERSION;
+    }
+
     /// Initialize deserialized patches.
     fn init_patches(&mut self) {
         for patch in &mut self.patches {
@@ -91,12 +255,26 @@ impl Module {
     /// editor stores the working beat division, not the module.
     pub fn save(&mut self, division: u8, path: &PathBuf) -> Result<(), Box<dyn Error>> {
         self.division = division;
+
+        // referenced samples are saved without their data
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let taken: Vec<_> = self.pcm_data_mut()
+            .map(|data| data.take_referenced_data(dir))
+            .collect();
         let mut contents = Vec::new();
         let mut ser = Serializer::new(&mut contents)
             .with_bytes(BytesMode::ForceIterables);
-        self.serialize(&mut ser)?;
+        let result = self.serialize(&mut ser);
+        for (data, taken) in self.pcm_data_mut().zip(taken) {
+            if let Some(taken) = taken {
+                data.restore_data(taken);
+            }
+        }
+        result?;
         let file = File::create(path)?;
-        GzEncoder::new(file, Default::default()).write_all(&contents)?;
+        let mut encoder = zstd::Encoder::new(file, Self::COMPRESSION_LEVEL)?;
+        encoder.write_all(&contents)?;
+        encoder.finish()?;
         self.has_unsaved_changes = false;
         Ok(())
     }
@@ -170,6 +348,53 @@ impl Module {
         events
     }
 
+    /// Push an edit that renotates note events to the nearest pitches in the
+    /// current tuning, keeping them close to how they sounded in `from`, with
+    /// track offsets applied. Kit tracks are left alone. Returns the number of notes changed.
+    pub fn retune_notes(&mut self, from: &Tuning) -> usize {
+        let mut notes = Vec::new();
+        for (track_i, track) in self.tracks.iter().enumerate() {
+            if !matches!(track.target, TrackTarget::Patch(_)) {
+                continue
+            }
+            for (channel_i, channel) in track.channels.iter().enumerate() {
+                for evt in &channel.events {
+                    if let EventData::Pitch(note) = evt.data {
+                        notes.push((track_i, channel_i, evt.tick, note));
+                    }
+                }
+            }
+        }
+        let Some(lo) = notes.iter().map(|n| n.3.equave).min() else { return 0 };
+        let hi = notes.iter().map(|n| n.3.equave).max().unwrap_or(lo);
+
+        let candidates = self.tuning.notated_pitches(lo.saturating_sub(1)..=hi.saturating_add(1));
+        let (mut remove, mut add) = (Vec::new(), Vec::new());
+        for (track, channel, tick, note) in notes {
+            let offsets = &self.tracks[track];
+            let pitch = from.shifted_midi_pitch(&note, offsets.transpose as i32)
+                + offsets.detune as f32 / 100.0;
+            let nearest = candidates.iter()
+                .map(|(n, _)| (*n, self.track_pitch(n, track)))
+                .min_by(|a, b| (a.1 - pitch).abs().total_cmp(&(b.1 - pitch).abs()));
+            if let Some((new_note, _)) = nearest.filter(|(n, _)| *n != note) {
+                let event = LocatedEvent {
+                    track,
+                    channel,
+                    event: Event { tick, data: EventData::Pitch(new_note) },
+                };
+                remove.push(event.position());
+                add.push(event);
+            }
+        }
+
+        let n = add.len();
+        if n > 0 {
+            self.push_edit(Edit::PatternData { remove, add });
+        }
+        n
+    }
+
     /// Returns the pattern event at `pos`.
     pub fn event_at(&mut self, pos: &Position) -> Option<&mut Event> {
         if let Some(track) = self.tracks.get_mut(pos.track) {
@@ -206,13 +431,98 @@ impl Module {
     pub fn map_note(&self, note: Note, track: usize) -> Option<(usize, Note)> {
         self.tracks.get(track).and_then(|track| {
             match track.target {
-                TrackTarget::None | TrackTarget::Global => None,
+                TrackTarget::None | TrackTarget::Global | TrackTarget::Audio => None,
                 TrackTarget::Kit => self.get_kit_patch(note),
                 TrackTarget::Patch(i) => Some((i, note)),
             }
         })
     }
 
+    /// Returns the positions of notes that trigger the patch at `index`,
+    /// including notes mapped through the kit.
+    pub fn patch_usage(&self, index: usize) -> Vec<Position> {
+        let mut positions = Vec::new();
+        for (track_i, track) in self.tracks.iter().enumerate() {
+            for (channel_i, channel) in track.channels.iter().enumerate() {
+                for event in &channel.events {
+                    if let EventData::Pitch(note) = event.data {
+                        if self.map_note(note, track_i).is_some_and(|(i, _)| i == index) {
+                            positions.push(Position {
+                                tick: event.tick,
+                                track: track_i,
+                                channel: channel_i,
+                                column: NOTE_COLUMN,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        positions
+    }
+
+    /// Returns true if any track or kit entry refers to the patch at `index`.
+    fn patch_assigned(&self, index: usize) -> bool {
+        self.kit.iter().any(|entry| entry.patch_index == index)
+            || self.tracks.iter().any(|track|
+                matches!(track.target, TrackTarget::Patch(i) if i == index))
+    }
+
+    /// Remove patches that aren't assigned to any track or kit entry.
+    /// Returns the number of patches removed.
+    pub fn remove_unused_patches(&mut self) -> usize {
+        let redirect = (0..self.patches.len())
+            .map(|i| self.patch_assigned(i).then_some(i))
+            .collect();
+        self.remap_patches(redirect)
+    }
+
+    /// Merge patches with identical settings, keeping the first of each set
+    /// of duplicates. Returns the number of patches removed.
+    pub fn merge_duplicate_patches(&mut self) -> usize {
+        // compare serialized settings, ignoring names
+        let keys: Vec<_> = self.patches.iter().map(|patch| {
+            let mut patch = patch.clone();
+            patch.name.clear();
+            rmp_serde::to_vec(&patch).ok()
+        }).collect();
+        let redirect = keys.iter().enumerate().map(|(i, key)| {
+            Some(key.as_ref().and_then(|key| keys.iter().position(|k| k.as_ref() == Some(key)))
+                .unwrap_or(i))
+        }).collect();
+        self.remap_patches(redirect)
+    }
+
+    /// Push an edit that replaces each patch with the patch at the index in
+    /// `redirect`, or removes it if None. Patches that redirect to themselves
+    /// are kept. Returns the number of patches removed.
+    fn remap_patches(&mut self, redirect: Vec<Option<usize>>) -> usize {
+        let kept: Vec<_> = (0..self.patches.len())
+            .filter(|&i| redirect[i] == Some(i))
+            .collect();
+        let removed = self.patches.len() - kept.len();
+        if removed == 0 {
+            return 0
+        }
+
+        let new_index = |i: usize| redirect[i].and_then(|j| kept.iter().position(|&k| k == j));
+        let patches = kept.iter().map(|&i| self.patches[i].clone()).collect();
+        let kit = self.kit.iter().filter_map(|entry| {
+            new_index(entry.patch_index).map(|patch_index| KitEntry {
+                patch_index,
+                ..entry.clone()
+            })
+        }).collect();
+        let targets = self.tracks.iter().map(|track| match track.target {
+            TrackTarget::Patch(i) => new_index(i).map(TrackTarget::Patch)
+                .unwrap_or(TrackTarget::None),
+            target => target,
+        }).collect();
+
+        self.push_edit(Edit::SetPatches { patches, kit, targets });
+        removed
+    }
+
     /// Push an edit appending a new track.
     pub fn add_track(&mut self) {
         let index = self.tracks.len();
@@ -220,6 +530,91 @@ impl Module {
         self.push_edit(Edit::InsertTrack(index, track));
     }
 
+    /// Returns the marker at `tick`, if any.
+    pub fn marker_at(&self, tick: Timespan) -> Option<&Marker> {
+        self.markers.iter().find(|m| m.tick == tick)
+    }
+
+    /// Returns the MIDI pitch of a note on a track, including the track's
+    /// transpose and detune.
+    pub fn track_pitch(&self, note: &Note, track: usize) -> f32 {
+        let track = &self.tracks[track];
+        self.tuning.shifted_midi_pitch(note, track.transpose as i32)
+            + track.detune as f32 / 100.0
+    }
+
+    /// Returns the group containing a track, if any.
+    pub fn track_group(&self, track: usize) -> Option<&TrackGroup> {
+        self.groups.iter().find(|g| g.tracks().contains(&track))
+    }
+
+    /// Check whether a track is hidden by a collapsed group.
+    pub fn track_hidden(&self, track: usize) -> bool {
+        self.track_group(track).is_some_and(|g| g.collapsed)
+    }
+
+    /// Returns the gain applied to a track by its group.
+    pub fn track_gain(&self, track: usize) -> f32 {
+        self.track_group(track).map_or(1.0, |g| g.volume.0.value())
+    }
+
+    /// Push an edit grouping a range of tracks. Existing groups that overlap
+    /// the range are removed. The control track can't be grouped.
+    pub fn group_tracks(&mut self, tracks: Range<usize>) {
+        let tracks = tracks.start.max(1)..tracks.end.min(self.tracks.len());
+        if tracks.is_empty() {
+            return
+        }
+
+        let mut groups: Vec<_> = self.groups.iter()
+            .filter(|g| g.tracks().end <= tracks.start || g.start >= tracks.end)
+            .cloned()
+            .collect();
+        let i = groups.partition_point(|g| g.start < tracks.start);
+        groups.insert(i, TrackGroup::new(format!("Group {}", self.groups.len() + 1), tracks));
+        self.push_edit(Edit::SetGroups(groups));
+    }
+
+    /// Update group ranges for an inserted track.
+    fn insert_group_track(&mut self, index: usize) {
+        for group in &mut self.groups {
+            if index <= group.start {
+                group.start += 1;
+            } else if index < group.tracks().end {
+                group.len += 1;
+            }
+        }
+    }
+
+    /// Update group ranges for a removed track. Empty groups are removed.
+    fn remove_group_track(&mut self, index: usize) {
+        for group in &mut self.groups {
+            if index < group.start {
+                group.start -= 1;
+            } else if index < group.tracks().end {
+                group.len -= 1;
+            }
+        }
+        self.groups.retain(|g| g.len > 0);
+    }
+
+    /// Update group ranges for a track moved from `src` to `dst`. A track
+    /// moved within its group stays in it.
+    fn shift_group_track(&mut self, src: usize, dst: usize) {
+        if self.groups.iter().any(|g| g.tracks().contains(&src) && g.tracks().contains(&dst)) {
+            return
+        }
+        self.remove_group_track(src);
+        self.insert_group_track(dst);
+    }
+
+    /// Push an edit appending a new audio track.
+    pub fn add_audio_track(&mut self) {
+        let index = self.tracks.len();
+        let track = Track::new(TrackTarget::Audio);
+        self.push_edit(Edit::InsertTrack(index, track));
+    }
+
     /// Push an edit inserting an event.
     pub fn insert_event(&mut self, track: usize, channel: usize, event: Event) {
         self.push_edit(Edit::PatternData {
@@ -255,14 +650,77 @@ impl Module {
             start: start.tick,
             distance,
             insert: Vec::new(),
+            markers: Vec::new(),
         });
     }
 
-    /// Performs an edit operation and updates undo/redo stacks.
+    /// Returns true if edits to a column are blocked.
+    pub fn column_locked(&self, track: usize, column: u8) -> bool {
+        self.tracks.get(track).is_some_and(|t| t.locks
+            .get(EventData::spatial_from_logical(column) as usize)
+            .is_some_and(|x| *x))
+    }
+
+    /// Removes changes to locked columns from a pattern edit. Returns None if
+    /// nothing is left to change. Edits that add events to a locked column
+    /// are rejected whole, so that moves into a locked column don't delete
+    /// their sources.
+    fn filter_locked(&self, edit: Edit) -> Option<Edit> {
+        match edit {
+            Edit::PatternData { remove, add } => {
+                if add.iter().any(|e| self.column_locked(e.track, e.event.data.logical_column())) {
+                    return None
+                }
+                let remove: Vec<_> = remove.into_iter()
+                    .filter(|p| !self.column_locked(p.track, p.column))
+                    .collect();
+                (!remove.is_empty() || !add.is_empty())
+                    .then_some(Edit::PatternData { remove, add })
+            }
+            Edit::ReplaceEvents(events) => {
+                let events: Vec<_> = events.into_iter()
+                    .filter(|e| !self.column_locked(e.track, e.event.data.logical_column()))
+                    .collect();
+                (!events.is_empty()).then_some(Edit::ReplaceEvents(events))
+            }
+            Edit::ShiftEvents { channels, start, distance, insert, markers } => {
+                // shifting moves every column, so skip partially locked tracks
+                let channels: Vec<_> = channels.into_iter()
+                    .filter(|c| self.tracks[c.track as usize].locks.iter().all(|x| !x))
+                    .collect();
+                (!channels.is_empty()).then_some(
+                    Edit::ShiftEvents { channels, start, distance, insert, markers })
+            }
+            edit => Some(edit),
+        }
+    }
+
+    /// Performs an edit operation and updates undo/redo stacks. Pattern
+    /// changes in locked columns are dropped.
     pub fn push_edit(&mut self, edit: Edit) {
+        let Some(edit) = self.filter_locked(edit) else {
+            return
+        };
         let edit = self.flip_edit(edit);
         self.undo_stack.push(edit);
         self.redo_stack.clear();
+        self.gesture = false;
+    }
+
+    /// Performs an edit as part of a continuous gesture, such as a slider
+    /// drag. Edits in the same gesture are undone in one step.
+    pub fn push_gesture_edit(&mut self, edit: Edit) {
+        let inverse = self.flip_edit(edit);
+        if !self.gesture {
+            self.undo_stack.push(inverse);
+            self.gesture = true;
+        }
+        self.redo_stack.clear();
+    }
+
+    /// End the current gesture, if any.
+    pub fn end_gesture(&mut self) {
+        self.gesture = false;
     }
 
     /// Performs an edit operation and returns its inverse.
@@ -271,31 +729,58 @@ impl Module {
             self.sync_stack.push(edit.clone());
         }
         self.has_unsaved_changes = true;
+        self.edit_count += 1;
         match edit {
             Edit::InsertTrack(index, track) => {
                 self.tracks.insert(index, track);
+                self.insert_group_track(index);
                 self.track_history.push(TrackEdit::Insert(index));
                 Edit::RemoveTrack(index)
             }
             Edit::RemoveTrack(index) => {
                 let track = self.tracks.remove(index);
+                self.remove_group_track(index);
                 self.track_history.push(TrackEdit::Remove(index));
                 Edit::InsertTrack(index, track)
             }
-            Edit::ShiftTrack(index, offset) => {
+            Edit::DuplicateTrack { index, events } => {
+                let mut track = self.tracks[index].clone();
+                if !events {
+                    track.clips.clear();
+                    for channel in &mut track.channels {
+                        channel.events.clear();
+                    }
+                }
+                self.tracks.insert(index + 1, track);
+                self.insert_group_track(index + 1);
+                self.track_history.push(TrackEdit::Insert(index + 1));
+                Edit::RemoveTrack(index + 1)
+            }
+            Edit::ShiftTrack { index, offset, groups } => {
                 // this could be implemented with insert + remove, but that
                 // means multiple undo items and more memory usage
                 let dst = index.saturating_add_signed(offset);
                 let track = self.tracks.remove(index);
                 self.tracks.insert(dst, track);
+                // a track can leave a group without a way back, so the
+                // inverse restores groups as they were
+                let old_groups = self.groups.clone();
+                match groups {
+                    Some(groups) => self.groups = groups,
+                    None => self.shift_group_track(index, dst),
+                }
                 self.track_history.push(TrackEdit::Remove(index));
                 self.track_history.push(TrackEdit::Insert(dst));
-                Edit::ShiftTrack(dst, -offset)
+                Edit::ShiftTrack { index: dst, offset: -offset, groups: Some(old_groups) }
             }
             Edit::RemapTrack(index, target) => {
                 let target = std::mem::replace(&mut self.tracks[index].target, target);
                 Edit::RemapTrack(index, target)
             }
+            Edit::ReplaceTrack(index, track) => {
+                let track = std::mem::replace(&mut self.tracks[index], track);
+                Edit::ReplaceTrack(index, track)
+            }
             Edit::AddChannel(index, channel) => {
                 let track = &mut self.tracks[index];
                 track.channels.push(channel);
@@ -335,7 +820,32 @@ impl Module {
                 let patch = self.remove_patch(index);
                 Edit::InsertPatch(index, patch)
             }
-            Edit::ShiftEvents { channels, start, distance, insert } => {
+            Edit::ShiftPatch(index, offset) => {
+                let dst = index.saturating_add_signed(offset);
+                let patch = self.patches.remove(index);
+                self.patches.insert(dst, patch);
+
+                let remap = |i: usize| if i == index {
+                    dst
+                } else if index < i && i <= dst {
+                    i - 1
+                } else if dst <= i && i < index {
+                    i + 1
+                } else {
+                    i
+                };
+                for entry in self.kit.iter_mut() {
+                    entry.patch_index = remap(entry.patch_index);
+                }
+                for track in self.tracks.iter_mut() {
+                    if let TrackTarget::Patch(i) = track.target {
+                        track.target = TrackTarget::Patch(remap(i));
+                    }
+                }
+
+                Edit::ShiftPatch(dst, -offset)
+            }
+            Edit::ShiftEvents { channels, start, distance, insert, markers } => {
                 // shift/delete events starting at selection
                 let mut deleted = Vec::new();
                 for coords in &channels {
@@ -360,11 +870,19 @@ impl Module {
                     self.tracks[track].channels[channel].sort_events();
                 }
 
+                // markers belong to the control track
+                let deleted_markers = if channels.iter().any(|c| c.track == 0) {
+                    self.shift_markers(start, distance, markers)
+                } else {
+                    Vec::new()
+                };
+
                 Edit::ShiftEvents {
                     channels,
                     start,
                     distance: -distance,
                     insert: deleted,
+                    markers: deleted_markers,
                 }
             },
             Edit::ReplaceEvents(events) => {
@@ -372,7 +890,81 @@ impl Module {
                     self.replace_event(event)
                 }).collect())
             },
+            Edit::SetClips(index, clips) => {
+                let clips = std::mem::replace(&mut self.tracks[index].clips, clips);
+                Edit::SetClips(index, clips)
+            },
+            Edit::SetTitle(title) => Edit::SetTitle(std::mem::replace(&mut self.title, title)),
+            Edit::SetAuthor(author) =>
+                Edit::SetAuthor(std::mem::replace(&mut self.author, author)),
+            Edit::SetTuning(tuning) =>
+                Edit::SetTuning(std::mem::replace(&mut self.tuning, tuning)),
+            Edit::SetMarkers(markers) =>
+                Edit::SetMarkers(std::mem::replace(&mut self.markers, markers)),
+            Edit::SetGroups(groups) => Edit::SetGroups(std::mem::replace(&mut self.groups, groups)),
+            Edit::SetGrooves(grooves) =>
+                Edit::SetGrooves(std::mem::replace(&mut self.grooves, grooves)),
+            Edit::SetMacros(macros) =>
+                Edit::SetMacros(std::mem::replace(&mut self.macros, macros)),
+            Edit::SetGroupVolume(index, volume) => {
+                let param = &self.groups[index].volume.0;
+                let old = Edit::SetGroupVolume(index, param.value());
+                param.set(volume);
+                old
+            }
+            Edit::SetFX(fx) => Edit::SetFX(std::mem::replace(&mut self.fx, fx)),
+            Edit::SetTrackOffsets(index, transpose, detune) => {
+                let track = &mut self.tracks[index];
+                let old = Edit::SetTrackOffsets(index, track.transpose, track.detune);
+                track.transpose = transpose;
+                track.detune = detune;
+                old
+            }
+            Edit::SetPatches { patches, kit, targets } => {
+                let old_targets = self.tracks.iter_mut()
+                    .zip(targets)
+                    .map(|(track, target)| std::mem::replace(&mut track.target, target))
+                    .collect();
+                Edit::SetPatches {
+                    patches: std::mem::replace(&mut self.patches, patches),
+                    kit: std::mem::replace(&mut self.kit, kit),
+                    targets: old_targets,
+                }
+            }
+            Edit::SetMarker(tick, marker) => {
+                let old = self.markers.iter().position(|m| m.tick == tick)
+                    .map(|i| self.markers.remove(i));
+                if let Some(marker) = marker {
+                    let i = self.markers.partition_point(|m| m.tick < tick);
+                    self.markers.insert(i, marker);
+                }
+                Edit::SetMarker(tick, old)
+            },
+        }
+    }
+
+    /// Shift markers the same way `Channel::shift_events` shifts events, then
+    /// insert `insert`. Returns the deleted markers.
+    fn shift_markers(&mut self, start: Timespan, distance: Timespan, insert: Vec<Marker>
+    ) -> Vec<Marker> {
+        let deleted = if distance < Timespan::ZERO {
+            let (keep, pass) = std::mem::take(&mut self.markers).into_iter()
+                .partition(|m| m.tick < start || m.tick >= start - distance);
+            self.markers = keep;
+            pass
+        } else {
+            Vec::new()
+        };
+
+        for marker in self.markers.iter_mut() {
+            if marker.tick >= start {
+                marker.tick = (marker.tick + distance).max(Timespan::ZERO);
+            }
         }
+        self.markers.extend(insert);
+        self.markers.sort_by_key(|m| m.tick);
+
+        deleted
     }
 
     /// Replace an event in-place, returning the old value.
@@ -391,6 +983,7 @@ impl Module {
 
     /// Returns true if there was something to undo.
     pub fn undo(&mut self) -> bool {
+        self.gesture = false;
         if let Some(edit) = self.undo_stack.pop() {
             let edit = self.flip_edit(edit);
             self.redo_stack.push(edit);
@@ -402,6 +995,7 @@ impl Module {
 
     /// Returns true if there was something to redo.
     pub fn redo(&mut self) -> bool {
+        self.gesture = false;
         if let Some(edit) = self.redo_stack.pop() {
             let edit = self.flip_edit(edit);
             self.undo_stack.push(edit);
@@ -411,18 +1005,64 @@ impl Module {
         }
     }
 
+    /// Returns the number of edits performed. Useful for detecting changes.
+    pub fn edit_count(&self) -> u64 {
+        self.edit_count
+    }
+
     /// Returns track insertions & removals made since the last call.
     pub fn drain_track_history(&mut self) -> Vec<TrackEdit> {
         self.track_history.drain(..).collect()
     }
 
-    /// Returns the last loop event before beat count `before_time`.
-    pub fn find_loop_start(&self, before_time: f64) -> Option<Timespan> {
-        self.tracks[0].channels.iter().flat_map(|c| {
-            c.events.iter()
-                .filter(|e| e.data == EventData::Loop && e.tick.as_f64() < before_time)
-                .map(|e| e.tick)
-        }).max()
+    /// Returns the tick of the loop that an End event at `end` jumps back to,
+    /// along with the loop's play count if it has one. A counted loop is only
+    /// closed by the first End after it; later Ends stop playback.
+    pub fn loop_for_end(&self, end: Timespan) -> Option<(Timespan, Option<u8>)> {
+        let events = self.ctrl_events();
+        let (start, count) = events.iter().rev()
+            .filter(|e| e.tick < end)
+            .find_map(|e| match e.data {
+                EventData::Loop => Some((e.tick, None)),
+                EventData::LoopCount(n) => Some((e.tick, Some(n))),
+                _ => None,
+            })?;
+        if count.is_some() && events.iter()
+            .any(|e| e.data == EventData::End && e.tick > start && e.tick < end) {
+            return None
+        }
+        Some((start, count))
+    }
+
+    /// Returns the tick where a section skipped at `tick` ends: the next
+    /// marker or Section event. Returns None if there is neither.
+    pub fn skip_target(&self, tick: Timespan) -> Option<Timespan> {
+        let markers = self.markers.iter().map(|m| m.tick);
+        let sections = self.ctrl_events().into_iter()
+            .filter(|e| e.data == EventData::Section)
+            .map(|e| e.tick);
+        markers.chain(sections).filter(|t| *t > tick).min()
+    }
+
+    /// Returns the time in seconds jumped over by Skip events from `start`
+    /// to `end`, or to the end of the module if `end` is None. Skips inside
+    /// skipped sections are ignored.
+    pub fn skipped_time(&self, start: Timespan, end: Option<Timespan>) -> f64 {
+        let mut resume = start;
+        let mut time = 0.0;
+        for evt in self.ctrl_events() {
+            if evt.data != EventData::Skip || evt.tick < resume
+                || end.is_some_and(|end| evt.tick >= end) {
+                continue
+            }
+            let Some(target) = self.skip_target(evt.tick) else {
+                break
+            };
+            let target = end.map_or(target, |end| target.min(end));
+            time += self.time_between(evt.tick, target);
+            resume = target;
+        }
+        time
     }
 
     /// Returns true if the module has an End event.
@@ -441,12 +1081,15 @@ impl Module {
         events
     }
 
-    /// Returns true if the module loops.
+    /// Returns true if the module loops indefinitely.
     pub fn loops(&self) -> bool {
+        let mut counted = false;
         for event in self.ctrl_events() {
             match event.data {
+                EventData::End if counted => counted = false,
                 EventData::End => return false,
                 EventData::Loop => return true,
+                EventData::LoopCount(_) => counted = true,
                 _ => (),
             }
         }
@@ -496,11 +1139,76 @@ impl Module {
         result
     }
 
-    /// Returns the total playtime of the module in seconds.
+    /// Returns the time in seconds from `start` to `end`, following tempo
+    /// changes. Loops and End events are ignored.
+    pub fn time_between(&self, start: Timespan, end: Timespan) -> f64 {
+        let mut tick = start;
+        let mut time = 0.0;
+        let mut tempo = self.tempo_at(start);
+
+        for evt in self.ctrl_events() {
+            if evt.tick <= start {
+                continue
+            } else if evt.tick >= end {
+                break
+            }
+            match evt.data {
+                EventData::Tempo(t) => {
+                    time += tick_interval(evt.tick - tick, tempo);
+                    tick = evt.tick;
+                    tempo = t;
+                }
+                EventData::RationalTempo(n, d) => {
+                    time += tick_interval(evt.tick - tick, tempo);
+                    tick = evt.tick;
+                    tempo *= n as f32 / d as f32;
+                }
+                _ => (),
+            }
+        }
+
+        time + tick_interval(end - tick, tempo)
+    }
+
+    /// Returns the tick reached `time` seconds from the start, following
+    /// tempo changes. Loops and End events are ignored.
+    pub fn tick_at_time(&self, time: f64) -> Timespan {
+        let mut tick = Timespan::ZERO;
+        let mut elapsed = 0.0;
+        let mut tempo = self.tempo_at(Timespan::ZERO);
+
+        for evt in self.ctrl_events() {
+            if evt.tick <= Timespan::ZERO {
+                continue
+            }
+            let next_tempo = match evt.data {
+                EventData::Tempo(t) => t,
+                EventData::RationalTempo(n, d) => tempo * n as f32 / d as f32,
+                _ => continue,
+            };
+            let interval = tick_interval(evt.tick - tick, tempo);
+            if elapsed + interval > time {
+                break
+            }
+            elapsed += interval;
+            tick = evt.tick;
+            tempo = next_tempo;
+        }
+
+        // far-off times would overflow the tick
+        const MAX_BEATS: f64 = 1_000_000.0;
+        let beats = ((time - elapsed) * tempo as f64 / 60.0).min(MAX_BEATS);
+        tick + Timespan::approximate(beats)
+    }
+
+    /// Returns the total playtime of the module in seconds, including the
+    /// repeats of counted loops.
     pub fn playtime(&self) -> f64 {
         let mut tick = Timespan::ZERO;
         let mut time = 0.0;
         let mut tempo = DEFAULT_TEMPO;
+        // start time and play count of the open counted loop
+        let mut counted_loop = None;
 
         for evt in self.ctrl_events() {
             match evt.data {
@@ -514,8 +1222,19 @@ impl Module {
                     tick = evt.tick;
                     tempo *= n as f32 / d as f32;
                 }
+                EventData::LoopCount(n) => {
+                    time += tick_interval(evt.tick - tick, tempo);
+                    tick = evt.tick;
+                    counted_loop = Some((time, n));
+                }
                 EventData::End => {
-                    return time + tick_interval(evt.tick - tick, tempo)
+                    time += tick_interval(evt.tick - tick, tempo);
+                    tick = evt.tick;
+                    match counted_loop.take() {
+                        Some((start, n)) =>
+                            time += (time - start) * n.saturating_sub(1) as f64,
+                        None => return time,
+                    }
                 }
                 _ => (),
             }
@@ -530,7 +1249,6 @@ impl Module {
 
     pub fn handle_command(&mut self, cmd: ModuleCommand) {
         match cmd {
-            ModuleCommand::FX(fx) => self.fx = fx,
             ModuleCommand::Kit(kit) => self.kit = kit,
             ModuleCommand::Load(module) => *self = module,
             ModuleCommand::Tuning(tuning) => self.tuning = tuning,
@@ -551,18 +1269,55 @@ impl Module {
     }
 }
 
+/// Decompress saved module data. Older versions used gzip instead of zstd.
+fn decompress(input: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+    let mut output = Vec::new();
+    if input.starts_with(&GZIP_MAGIC) {
+        GzDecoder::new(input).read_to_end(&mut output)?;
+    } else {
+        zstd::Decoder::new(input)?.read_to_end(&mut output)?;
+    }
+    Ok(output)
+}
+
 /// Kit mapping.
 #[derive(Clone, Default, Serialize, Deserialize)]
 pub struct KitEntry {
     pub input_note: Note,
     pub patch_index: usize,
     pub patch_note: Note,
+    /// Short name shown instead of the input note in kit tracks.
+    #[serde(default)]
+    pub name: String,
+}
+
+impl KitEntry {
+    /// Maximum number of characters in a kit entry name.
+    pub const MAX_NAME_CHARS: usize = 4;
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Track {
     pub target: TrackTarget,
     pub channels: Vec<Channel>,
+    /// Only used by audio tracks.
+    #[serde(default)]
+    pub clips: Vec<AudioClip>,
+    /// Mute state as of the last save. The player is authoritative while
+    /// the module is open.
+    #[serde(default)]
+    pub muted: bool,
+    /// Pitch offset in scale steps.
+    #[serde(default)]
+    pub transpose: i16,
+    /// Pitch offset in cents.
+    #[serde(default)]
+    pub detune: i16,
+    /// Edit protection for each spatial column.
+    #[serde(default)]
+    pub locks: [bool; 3],
 }
 
 impl Track {
@@ -570,6 +1325,11 @@ impl Track {
         Self {
             target,
             channels: vec![Channel::default()],
+            clips: Vec::new(),
+            muted: false,
+            transpose: 0,
+            detune: 0,
+            locks: [false; 3],
         }
     }
 }
@@ -581,6 +1341,170 @@ pub enum TrackTarget {
     Global,
     Kit,
     Patch(usize),
+    Audio,
+}
+
+/// Rows the pattern cursor advances after data entry, by column type.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EditStep {
+    pub note: u8,
+    /// Step for pressure and modulation columns.
+    pub value: u8,
+}
+
+impl EditStep {
+    /// Returns the step for a pattern column.
+    pub fn for_column(&self, column: u8) -> u8 {
+        if column == NOTE_COLUMN { self.note } else { self.value }
+    }
+}
+
+/// Text annotation on a pattern row. Has no effect on playback, but also
+/// marks the start of a section in performance mode.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Marker {
+    pub tick: Timespan,
+    pub text: String,
+    /// Input that queues this marker's section in performance mode.
+    #[serde(default)]
+    pub trigger: Option<SectionTrigger>,
+}
+
+/// Micro-timing and accent pattern, one step per row. Steps repeat every
+/// `offsets.len()` rows, counted from the start of the song.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Groove {
+    pub name: String,
+    /// Offset of each step from its row, as a fraction of a row.
+    pub offsets: Vec<f32>,
+    /// Pressure of the note on each step, if any.
+    pub accents: Vec<Option<u8>>,
+}
+
+impl Groove {
+    /// Returns the offset of grid row `n`, as a fraction of a row.
+    pub fn offset(&self, n: i32) -> f32 {
+        self.offsets.get(n.rem_euclid(self.offsets.len().max(1) as i32) as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the accent of grid row `n`, if any.
+    pub fn accent(&self, n: i32) -> Option<u8> {
+        self.accents.get(n.rem_euclid(self.accents.len().max(1) as i32) as usize)
+            .copied()
+            .flatten()
+    }
+}
+
+/// Named bundle of events, inserted at the pattern cursor by hotkey. Event
+/// ticks are relative to the insertion point.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EventMacro {
+    pub name: String,
+    pub hotkey: Option<Hotkey>,
+    /// Events for the control track.
+    pub control: Vec<Event>,
+    /// Events for the cursor channel.
+    pub channel: Vec<Event>,
+}
+
+/// Keyboard or MIDI input mapped to a performance mode section.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum SectionTrigger {
+    Key(Hotkey),
+    MidiNote(u8),
+}
+
+impl fmt::Display for SectionTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(hotkey) => write!(f, "{hotkey}"),
+            Self::MidiNote(n) => write!(f, "MIDI note {n}"),
+        }
+    }
+}
+
+/// A sample placed on an audio track. Unlike `Parameter`, cloning a clip
+/// shares its parameters, so that changes reach the audio thread's copy.
+#[derive(Serialize, Deserialize)]
+pub struct AudioClip {
+    pub tick: Timespan,
+    pub data: PcmData,
+    /// Linear gain.
+    pub gain: Parameter,
+    /// Fade-in time in seconds.
+    pub fade_in: Parameter,
+    /// Fade-out time in seconds.
+    pub fade_out: Parameter,
+}
+
+impl AudioClip {
+    pub fn new(tick: Timespan, data: PcmData) -> Self {
+        Self {
+            tick,
+            data,
+            gain: Parameter::from(1.0),
+            fade_in: Parameter::from(0.0),
+            fade_out: Parameter::from(0.0),
+        }
+    }
+}
+
+impl Clone for AudioClip {
+    fn clone(&self) -> Self {
+        Self {
+            tick: self.tick,
+            data: self.data.clone(),
+            gain: self.gain.shared_clone(),
+            fade_in: self.fade_in.shared_clone(),
+            fade_out: self.fade_out.shared_clone(),
+        }
+    }
+}
+
+/// Set of adjacent tracks with shared mute, solo, and volume controls. Like
+/// `AudioClip`, cloning a group shares its parameters.
+#[derive(Serialize, Deserialize)]
+pub struct TrackGroup {
+    pub name: String,
+    /// Index of the first member track.
+    pub start: usize,
+    /// Number of member tracks.
+    pub len: usize,
+    /// If true, member tracks are hidden in the pattern editor.
+    pub collapsed: bool,
+    /// Linear gain applied to member tracks.
+    pub volume: Parameter,
+}
+
+impl TrackGroup {
+    pub fn new(name: String, tracks: Range<usize>) -> Self {
+        Self {
+            name,
+            start: tracks.start,
+            len: tracks.len(),
+            collapsed: false,
+            volume: Parameter::from(1.0),
+        }
+    }
+
+    /// Returns the indices of member tracks.
+    pub fn tracks(&self) -> Range<usize> {
+        self.start..self.start + self.len
+    }
+}
+
+impl Clone for TrackGroup {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            start: self.start,
+            len: self.len,
+            collapsed: self.collapsed,
+            volume: self.volume.shared_clone(),
+        }
+    }
 }
 
 /// Contains an event sequence. Is a struct for legacy reasons.
@@ -646,6 +1570,31 @@ impl Channel {
         glide
     }
 
+    /// Returns the condition on notes at `tick`, if any.
+    pub fn condition_at(&self, tick: Timespan) -> Option<NoteCondition> {
+        self.events.iter().find_map(|e| match e.data {
+            EventData::Condition(c) if e.tick == tick => Some(c),
+            _ => None,
+        })
+    }
+
+    /// Returns the slide length on notes at `tick`, if any.
+    pub fn slide_at(&self, tick: Timespan) -> Option<u8> {
+        self.events.iter().find_map(|e| match e.data {
+            EventData::Slide(n) if e.tick == tick => Some(n),
+            _ => None,
+        })
+    }
+
+    /// Returns the vibrato and tremolo events at `tick`.
+    pub fn effects_at(&self, tick: Timespan) -> Vec<&EventData> {
+        self.events.iter()
+            .filter(|e| e.tick == tick
+                && matches!(e.data, EventData::Vibrato(..) | EventData::Tremolo(..)))
+            .map(|e| &e.data)
+            .collect()
+    }
+
     /// Returns the last note event before `tick`.
     pub fn prev_note(&self, tick: Timespan) -> Option<&Event> {
         self.events.iter()
@@ -682,6 +1631,21 @@ pub enum EventData {
     Bend(i16),
     /// Section marker. No effect on playback.
     Section,
+    /// Condition on whether the note at the same tick plays.
+    Condition(NoteCondition),
+    /// Slide into the note at the same tick from the previous note, over
+    /// the given number of eighth beats.
+    Slide(u8),
+    /// Vibrato rate and depth digits. Lasts until the next note.
+    Vibrato(u8, u8),
+    /// Tremolo rate and depth digits. Lasts until the next note.
+    Tremolo(u8, u8),
+    /// Loop start whose section plays this many times in total before
+    /// playback continues past the End that closes it.
+    LoopCount(u8),
+    /// Start of a section that renders leave out, up to the next marker or
+    /// Section event. No effect on playback in the editor.
+    Skip,
 }
 
 impl EventData {
@@ -691,6 +1655,21 @@ impl EventData {
     /// Binary or'ed with "spatial column" value.
     pub const INTERP_COL_FLAG: u8 = 0x80;
 
+    /// Binary or'ed with "spatial column" value for note conditions.
+    pub const COND_COL_FLAG: u8 = 0x40;
+
+    /// Binary or'ed with "spatial column" value for note slides.
+    pub const SLIDE_COL_FLAG: u8 = 0x20;
+
+    /// Binary or'ed with "spatial column" value for vibrato.
+    pub const VIB_COL_FLAG: u8 = 0x10;
+
+    /// Binary or'ed with "spatial column" value for tremolo.
+    pub const TREM_COL_FLAG: u8 = 0x08;
+
+    /// Length of one slide step, in beats.
+    pub const SLIDE_STEP: f64 = 0.125;
+
     /// Convert a 7-bit MIDI value to a digit value.
     pub fn digit_from_midi(midi_value: u8) -> u8 {
         (midi_value as f32 * Self::DIGIT_MAX as f32 / 127.0).round() as u8
@@ -698,7 +1677,13 @@ impl EventData {
 
     /// Returns the column where the event should be drawn.
     pub fn spatial_column(&self) -> u8 {
-        self.logical_column() & !Self::INTERP_COL_FLAG
+        Self::spatial_from_logical(self.logical_column())
+    }
+
+    /// Strips flags from a logical column value.
+    pub fn spatial_from_logical(column: u8) -> u8 {
+        column & !(Self::INTERP_COL_FLAG | Self::COND_COL_FLAG
+            | Self::SLIDE_COL_FLAG | Self::VIB_COL_FLAG | Self::TREM_COL_FLAG)
     }
 
     /// Returns a logical column value. Used to distinguish
@@ -709,6 +1694,10 @@ impl EventData {
             Self::Modulation(_) => MOD_COLUMN,
             Self::StartGlide(col) | Self::EndGlide(col) | Self::TickGlide(col)
                 => col | Self::INTERP_COL_FLAG,
+            Self::Condition(_) => NOTE_COLUMN | Self::COND_COL_FLAG,
+            Self::Slide(_) => NOTE_COLUMN | Self::SLIDE_COL_FLAG,
+            Self::Vibrato(..) => MOD_COLUMN | Self::VIB_COL_FLAG,
+            Self::Tremolo(..) => MOD_COLUMN | Self::TREM_COL_FLAG,
             _ => NOTE_COLUMN,
         }
     }
@@ -717,9 +1706,11 @@ impl EventData {
     pub fn goes_in_track(&self, track: usize) -> bool {
         match self {
             Self::Bend(_) | Self::Pressure(_) | Self::Modulation(_)
-                | Self::NoteOff | Self::Pitch(_) => track != 0,
+                | Self::NoteOff | Self::Pitch(_) | Self::Condition(_)
+                | Self::Slide(_) | Self::Vibrato(..) | Self::Tremolo(..) => track != 0,
             Self::Tempo(_) | Self::RationalTempo(_, _)
-                | Self::End | Self::Loop | Self::Section => track == 0,
+                | Self::End | Self::Loop | Self::LoopCount(_)
+                | Self::Skip | Self::Section => track == 0,
             Self::StartGlide(col) | Self::EndGlide(col) | Self::TickGlide(col)
                 => track != 0 || *col == GLOBAL_COLUMN,
             Self::InterpolatedModulation(_) | Self::InterpolatedPitch(_)
@@ -728,8 +1719,49 @@ impl EventData {
     }
 }
 
+/// Condition on whether a note plays, checked each time it's triggered.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum NoteCondition {
+    /// Percent chance to play.
+    Chance(u8),
+    /// Play on loop pass N (1-based) out of every M passes.
+    Every(u8, u8),
+}
+
+impl NoteCondition {
+    /// Parse text like "50%" or "2:4".
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Some(s) = s.strip_suffix('%') {
+            let n = s.parse().ok()?;
+            (1..100).contains(&n).then_some(Self::Chance(n))
+        } else {
+            let (n, m) = s.split_once(':')?;
+            let (n, m) = (n.parse().ok()?, m.parse().ok()?);
+            (n > 0 && n <= m).then_some(Self::Every(n, m))
+        }
+    }
+
+    /// Check whether a note with this condition should play. `random`
+    /// should be in the range 0..1.
+    pub fn passes(&self, loop_pass: u32, random: f32) -> bool {
+        match *self {
+            Self::Chance(n) => random * 100.0 < n as f32,
+            Self::Every(n, m) => loop_pass % m as u32 == n as u32 - 1,
+        }
+    }
+}
+
+impl fmt::Display for NoteCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Chance(n) => write!(f, "{n}%"),
+            Self::Every(n, m) => write!(f, "{n}:{m}"),
+        }
+    }
+}
+
 /// Pattern position.
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub tick: Timespan,
     pub track: usize,
@@ -783,8 +1815,23 @@ impl Position {
 pub enum Edit {
     InsertTrack(usize, Track),
     RemoveTrack(usize),
-    ShiftTrack(usize, isize),
+    /// Insert a copy of a track after it. Without events, the copy keeps
+    /// the target, channel count, offsets, and locks, but not pattern data
+    /// or audio clips.
+    DuplicateTrack {
+        index: usize,
+        events: bool,
+    },
+    /// Move a track by an offset. Group ranges follow the track, unless
+    /// `groups` is some, in which case they replace the module's groups.
+    ShiftTrack {
+        index: usize,
+        offset: isize,
+        groups: Option<Vec<TrackGroup>>,
+    },
     RemapTrack(usize, TrackTarget),
+    /// Replace a track's settings and channels in place.
+    ReplaceTrack(usize, Track),
     AddChannel(usize, Channel),
     RemoveChannel(usize),
     PatternData {
@@ -793,13 +1840,38 @@ pub enum Edit {
     },
     InsertPatch(usize, Patch),
     RemovePatch(usize),
+    /// Move a patch by an offset, keeping references to patches intact.
+    ShiftPatch(usize, isize),
     ShiftEvents {
         channels: Vec<ChannelCoords>,
         start: Timespan,
         distance: Timespan,
         insert: Vec<LocatedEvent>,
+        /// Markers to insert after shifting.
+        markers: Vec<Marker>,
     },
     ReplaceEvents(Vec<LocatedEvent>),
+    SetClips(usize, Vec<AudioClip>),
+    SetTitle(String),
+    SetAuthor(String),
+    SetTuning(Tuning),
+    /// Set or remove the marker at a tick.
+    SetMarker(Timespan, Option<Marker>),
+    SetMarkers(Vec<Marker>),
+    SetGroups(Vec<TrackGroup>),
+    SetGrooves(Vec<Groove>),
+    SetMacros(Vec<EventMacro>),
+    /// Set the linear gain of a track group.
+    SetGroupVolume(usize, f32),
+    SetFX(FXSettings),
+    /// Set the transpose and detune of a track.
+    SetTrackOffsets(usize, i16, i16),
+    /// Replace all patches, kit entries, and track targets at once.
+    SetPatches {
+        patches: Vec<Patch>,
+        kit: Vec<KitEntry>,
+        targets: Vec<TrackTarget>,
+    },
 }
 
 /// Position of a channel.
@@ -851,25 +1923,90 @@ impl LocatedEvent {
 pub enum ModuleCommand {
     Load(Module),
     Tuning(Tuning),
-    FX(FXSettings),
     Kit(Vec<KitEntry>),
     Edit(Edit),
     Patch(usize, Patch),
 }
 
-/// Wrapper for module sync handling.
+impl ModuleCommand {
+    /// Returns true if the command changes patches or track targets, which
+    /// makes prebuilt voices stale.
+    pub fn changes_voices(&self) -> bool {
+        match self {
+            Self::Load(_) | Self::Kit(_) | Self::Patch(..) => true,
+            Self::Tuning(_) => false,
+            Self::Edit(edit) => matches!(edit, Edit::InsertTrack(..)
+                | Edit::RemoveTrack(_)
+                | Edit::DuplicateTrack { .. }
+                | Edit::ShiftTrack { .. }
+                | Edit::RemapTrack(..)
+                | Edit::ReplaceTrack(..)
+                | Edit::InsertPatch(..)
+                | Edit::RemovePatch(_)
+                | Edit::ShiftPatch(..)
+                | Edit::SetPatches { .. }),
+        }
+    }
+}
+
+/// Wrapper for module sync handling. Commands that don't fit in the ring
+/// buffer are held and sent in order once there's room.
 pub struct ModuleSync {
     producer: Producer<ModuleCommand>,
+    pending: VecDeque<ModuleCommand>,
+    /// Set when held commands were discarded, so the whole module needs to
+    /// be sent again.
+    overflowed: bool,
 }
 
 impl ModuleSync {
+    /// Maximum number of held commands.
+    const MAX_PENDING: usize = 256;
+
     pub fn new(producer: Producer<ModuleCommand>) -> Self {
-        Self { producer }
+        Self {
+            producer,
+            pending: VecDeque::new(),
+            overflowed: false,
+        }
     }
 
     pub fn push(&mut self, cmd: ModuleCommand) {
-        if let Err(e) = self.producer.push(cmd) {
-            eprintln!("error pushing module command: {e}")
+        if self.producer.is_abandoned() {
+            self.pending.clear();
+            return
+        }
+        match (&cmd, self.pending.back()) {
+            // a loaded module supersedes everything held
+            (ModuleCommand::Load(_), _) => self.pending.clear(),
+            // a newer copy of a patch supersedes a held copy of the same patch
+            (ModuleCommand::Patch(i, _), Some(ModuleCommand::Patch(j, _))) if i == j => {
+                self.pending.pop_back();
+            }
+            _ => (),
+        }
+        if self.pending.len() >= Self::MAX_PENDING {
+            self.pending.clear();
+            self.overflowed = true;
+            return
+        }
+        self.pending.push_back(cmd);
+        self.flush();
+    }
+
+    /// Returns true if held commands were discarded since the last call. The
+    /// module should then be sent again with a Load command.
+    pub fn take_overflow(&mut self) -> bool {
+        std::mem::take(&mut self.overflowed)
+    }
+
+    /// Send held commands to the audio thread, as many as fit.
+    pub fn flush(&mut self) {
+        while let Some(cmd) = self.pending.pop_front() {
+            if let Err(PushError::Full(cmd)) = self.producer.push(cmd) {
+                self.pending.push_front(cmd);
+                break
+            }
         }
     }
 }
@@ -878,6 +2015,30 @@ impl ModuleSync {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_module_sync_backlog() {
+        let (producer, mut consumer) = rtrb::RingBuffer::new(1);
+        let mut sync = ModuleSync::new(producer);
+        sync.push(ModuleCommand::Kit(Vec::new()));
+        sync.push(ModuleCommand::Patch(0, Patch::new(String::from("a"))));
+        sync.push(ModuleCommand::Patch(0, Patch::new(String::from("b"))));
+        assert!(matches!(consumer.pop(), Ok(ModuleCommand::Kit(_))));
+        sync.flush();
+        assert!(matches!(consumer.pop(), Ok(ModuleCommand::Patch(0, p)) if p.name == "b"));
+        assert!(consumer.pop().is_err());
+
+        for _ in 0..=ModuleSync::MAX_PENDING + 1 {
+            sync.push(ModuleCommand::Kit(Vec::new()));
+        }
+        assert!(sync.pending.len() <= ModuleSync::MAX_PENDING);
+        assert!(sync.take_overflow());
+        assert!(!sync.take_overflow());
+
+        drop(consumer);
+        sync.push(ModuleCommand::Kit(Vec::new()));
+        assert!(sync.pending.is_empty());
+    }
+
     #[test]
     fn test_digit_from_midi() {
         assert_eq!(EventData::digit_from_midi(0x00), 0x0);
@@ -885,4 +2046,346 @@ mod tests {
         assert_eq!(EventData::digit_from_midi(0x3f), 0x7);
         assert_eq!(EventData::digit_from_midi(0x40), 0x8);
     }
+
+    #[test]
+    fn test_load_gzip_module() {
+        let module = Module::load(&PathBuf::from("./testdata/lfo.osctet")).unwrap();
+        assert_eq!(module.version, Module::VERSION);
+    }
+
+    #[test]
+    fn test_decompress_zstd() {
+        let data = b"osctet".repeat(100);
+        let compressed = zstd::encode_all(&data[..], Module::COMPRESSION_LEVEL).unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_duplicate_track() {
+        let mut module = Module::new(Default::default());
+        module.tracks[2].locks = [true, false, false];
+        module.tracks[2].channels.push(Channel::default());
+        module.tracks[2].channels[1].events.push(Event {
+            tick: Timespan::ZERO,
+            data: EventData::NoteOff,
+        });
+
+        module.push_edit(Edit::DuplicateTrack { index: 2, events: false });
+        assert_eq!(module.tracks.len(), 4);
+        assert_eq!(module.tracks[3].locks, [true, false, false]);
+        assert_eq!(module.tracks[3].channels.len(), 2);
+        assert!(module.tracks[3].channels[1].events.is_empty());
+
+        assert!(module.undo());
+        module.push_edit(Edit::DuplicateTrack { index: 2, events: true });
+        assert_eq!(module.tracks[3].channels[1].events.len(), 1);
+    }
+
+    #[test]
+    fn test_counted_loop() {
+        let mut module = Module::new(Default::default());
+        let ctrl = &mut module.tracks[0].channels[0].events;
+        for (beat, data) in [(1, EventData::LoopCount(3)), (2, EventData::End),
+            (4, EventData::End)] {
+            ctrl.push(Event { tick: Timespan::new(beat, 1), data });
+        }
+
+        assert!(!module.loops());
+        assert_eq!(module.loop_for_end(Timespan::new(2, 1)),
+            Some((Timespan::new(1, 1), Some(3))));
+        assert_eq!(module.loop_for_end(Timespan::new(4, 1)), None);
+        // 4 beats, plus 2 repeats of 1 beat, at 120 BPM
+        assert_eq!(module.playtime(), 3.0);
+    }
+
+    #[test]
+    fn test_tick_at_time() {
+        let mut module = Module::new(Default::default());
+        module.tracks[0].channels[0].events.push(Event {
+            tick: Timespan::new(4, 1),
+            data: EventData::Tempo(60.0),
+        });
+
+        // 4 beats at 120 BPM, then 1 beat at 60 BPM
+        assert_eq!(module.tick_at_time(1.0), Timespan::new(2, 1));
+        assert_eq!(module.tick_at_time(3.0), Timespan::new(5, 1));
+        assert_eq!(module.time_between(Timespan::ZERO, Timespan::new(5, 1)), 3.0);
+        assert!(module.tick_at_time(1e300) > Timespan::new(5, 1));
+    }
+
+    #[test]
+    fn test_skip_target() {
+        let mut module = Module::new(Default::default());
+        module.markers.push(Marker {
+            tick: Timespan::new(8, 1),
+            text: String::from("B"),
+            trigger: None,
+        });
+        module.tracks[0].channels[0].events.push(Event {
+            tick: Timespan::new(4, 1),
+            data: EventData::Section,
+        });
+
+        assert_eq!(module.skip_target(Timespan::new(2, 1)), Some(Timespan::new(4, 1)));
+        assert_eq!(module.skip_target(Timespan::new(4, 1)), Some(Timespan::new(8, 1)));
+        assert_eq!(module.skip_target(Timespan::new(8, 1)), None);
+
+        module.tracks[0].channels[0].events.push(Event {
+            tick: Timespan::new(2, 1),
+            data: EventData::Skip,
+        });
+        // 2 beats at 120 BPM
+        assert_eq!(module.skipped_time(Timespan::ZERO, None), 1.0);
+        assert_eq!(module.skipped_time(Timespan::ZERO, Some(Timespan::new(3, 1))), 0.5);
+        assert_eq!(module.skipped_time(Timespan::new(3, 1), None), 0.0);
+    }
+
+    #[test]
+    fn test_retune_notes() {
+        let mut module = Module::new(Default::default());
+        let e4 = Note::new(0, crate::pitch::Nominal::E, 0, 4);
+        module.tracks[2].channels[0].events.push(Event {
+            tick: Timespan::ZERO,
+            data: EventData::Pitch(e4),
+        });
+
+        let old = std::mem::replace(&mut module.tuning, Tuning::divide(2.0, 5, 1).unwrap());
+        assert_eq!(module.retune_notes(&old), 1);
+        let EventData::Pitch(note) = module.tracks[2].channels[0].events[0].data else {
+            panic!("expected note event")
+        };
+        assert!((module.tuning.midi_pitch(&note) - 64.8).abs() < 0.01);
+
+        assert!(module.undo());
+        assert_eq!(module.tracks[2].channels[0].events[0].data, EventData::Pitch(e4));
+
+        // transposed tracks keep their sounding pitch
+        module.tracks[2].transpose = 1;
+        assert_eq!(module.retune_notes(&old), 1);
+        let EventData::Pitch(note) = module.tracks[2].channels[0].events[0].data else {
+            panic!("expected note event")
+        };
+        assert!((module.track_pitch(&note, 2) - 64.8).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_group_track_edits() {
+        let mut module = Module::new(Default::default());
+        for _ in 0..4 {
+            module.add_track();
+        }
+        module.group_tracks(2..4);
+        assert_eq!(module.groups[0].tracks(), 2..4);
+
+        module.push_edit(Edit::InsertTrack(3, Track::new(TrackTarget::None)));
+        assert_eq!(module.groups[0].tracks(), 2..5);
+        module.push_edit(Edit::RemoveTrack(1));
+        assert_eq!(module.groups[0].tracks(), 1..4);
+
+        module.group_tracks(0..2);
+        assert_eq!(module.groups.len(), 1);
+        assert_eq!(module.groups[0].tracks(), 1..2);
+        assert!(module.undo());
+        assert_eq!(module.groups[0].tracks(), 1..4);
+    }
+
+    #[test]
+    fn test_shift_track_groups() {
+        let mut module = Module::new(Default::default());
+        for _ in 0..3 {
+            module.add_track();
+        }
+        module.tracks[3].channels.push(Channel::default());
+        module.group_tracks(2..4);
+        let shift = |index, offset| Edit::ShiftTrack { index, offset, groups: None };
+
+        module.push_edit(shift(3, -1));
+        assert_eq!(module.groups[0].tracks(), 2..4);
+        assert_eq!(module.tracks[2].channels.len(), 2);
+        module.push_edit(shift(2, 3));
+        assert_eq!(module.groups[0].tracks(), 2..3);
+        module.push_edit(shift(1, 1));
+        assert_eq!(module.groups[0].tracks(), 1..2);
+
+        assert!(module.undo());
+        assert!(module.undo());
+        assert_eq!(module.groups[0].tracks(), 2..4);
+        assert_eq!(module.tracks[2].channels.len(), 2);
+        assert!(module.redo());
+        assert_eq!(module.groups[0].tracks(), 2..3);
+        assert_eq!(module.tracks[5].channels.len(), 2);
+    }
+
+    #[test]
+    fn test_note_condition() {
+        assert_eq!(NoteCondition::parse("50%"), Some(NoteCondition::Chance(50)));
+        assert_eq!(NoteCondition::parse("2:4"), Some(NoteCondition::Every(2, 4)));
+        assert_eq!(NoteCondition::parse("100%"), None);
+        assert_eq!(NoteCondition::parse("5:4"), None);
+        assert_eq!(NoteCondition::parse("2:4").unwrap().to_string(), "2:4");
+
+        let cond = NoteCondition::Every(2, 3);
+        let passes: Vec<_> = (0..6).map(|i| cond.passes(i, 0.0)).collect();
+        assert_eq!(passes, [false, true, false, false, true, false]);
+    }
+
+    #[test]
+    fn test_patch_usage() {
+        let mut module = Module::new(Default::default());
+        module.patches.push(Patch::new(String::from("Drum")));
+        module.kit.push(KitEntry {
+            patch_index: 1,
+            ..Default::default()
+        });
+        let mapped = KitEntry::default().input_note;
+        let unmapped = Note::new(0, crate::pitch::Nominal::D, 0, 4);
+        for (track, tick, note) in [(1, 0, mapped), (1, 1, unmapped), (2, 2, mapped)] {
+            module.insert_event(track, 0, Event {
+                tick: Timespan::new(tick, 1),
+                data: EventData::Pitch(note),
+            });
+        }
+
+        let usage = module.patch_usage(1);
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].track, usage[0].tick), (1, Timespan::ZERO));
+        assert_eq!(module.patch_usage(0).len(), 1);
+    }
+
+    #[test]
+    fn test_patch_cleanup() {
+        let mut module = Module::new(Default::default());
+        module.patches.push(module.patches[0].duplicate());
+        module.patches.push(Patch::new(String::from("Unused")));
+        module.patches[2].gain.0.set(0.25);
+        module.tracks[1].target = TrackTarget::Patch(1);
+        module.kit.push(KitEntry {
+            patch_index: 1,
+            ..Default::default()
+        });
+
+        assert_eq!(module.merge_duplicate_patches(), 1);
+        assert_eq!(module.patches.len(), 2);
+        assert!(matches!(module.tracks[1].target, TrackTarget::Patch(0)));
+        assert_eq!(module.kit[0].patch_index, 0);
+
+        assert_eq!(module.remove_unused_patches(), 1);
+        assert_eq!(module.patches.len(), 1);
+
+        assert!(module.undo());
+        assert!(module.undo());
+        assert_eq!(module.patches.len(), 3);
+        assert!(matches!(module.tracks[1].target, TrackTarget::Patch(1)));
+    }
+
+    #[test]
+    fn test_shift_patch() {
+        let mut module = Module::new(Default::default());
+        module.patches.push(Patch::new(String::from("B")));
+        module.patches.push(Patch::new(String::from("C")));
+        module.tracks[1].target = TrackTarget::Patch(2);
+        module.kit.push(KitEntry {
+            patch_index: 0,
+            ..Default::default()
+        });
+
+        module.push_edit(Edit::ShiftPatch(2, -2));
+        assert_eq!(module.patches[0].name, "C");
+        assert_eq!(module.patches[2].name, "B");
+        assert!(matches!(module.tracks[1].target, TrackTarget::Patch(0)));
+        assert_eq!(module.kit[0].patch_index, 1);
+
+        assert!(module.undo());
+        assert_eq!(module.patches[2].name, "C");
+        assert!(matches!(module.tracks[1].target, TrackTarget::Patch(2)));
+        assert_eq!(module.kit[0].patch_index, 0);
+    }
+
+    #[test]
+    fn test_gesture_edit() {
+        let mut module = Module::new(Default::default());
+        module.groups.push(TrackGroup::new(String::from("G"), 2..3));
+        for volume in [0.8, 0.6, 0.4] {
+            module.push_gesture_edit(Edit::SetGroupVolume(0, volume));
+        }
+        module.end_gesture();
+        module.push_gesture_edit(Edit::SetGroupVolume(0, 0.2));
+
+        assert!(module.undo());
+        assert_eq!(module.groups[0].volume.0.value(), 0.4);
+        assert!(module.undo());
+        assert_eq!(module.groups[0].volume.0.value(), 1.0);
+        assert!(!module.undo());
+    }
+
+    #[test]
+    fn test_column_lock() {
+        let mut module = Module::new(Default::default());
+        module.tracks[2].locks[VEL_COLUMN as usize] = true;
+        module.insert_event(2, 0, Event { tick: Timespan::ZERO, data: EventData::Pressure(3) });
+        module.insert_event(2, 0, Event { tick: Timespan::ZERO, data: EventData::Modulation(3) });
+        assert_eq!(module.tracks[2].channels[0].events.len(), 1);
+        assert!(module.column_locked(2, VEL_COLUMN));
+        assert!(!module.column_locked(2, MOD_COLUMN | EventData::VIB_COL_FLAG));
+    }
+
+    #[test]
+    fn test_move_into_locked_column() {
+        let mut module = Module::new(Default::default());
+        module.tracks[2].locks[NOTE_COLUMN as usize] = true;
+        let event = Event { tick: Timespan::ZERO, data: EventData::NoteOff };
+        module.insert_event(1, 0, event.clone());
+
+        let src = LocatedEvent { track: 1, channel: 0, event: event.clone() };
+        let dst = LocatedEvent { track: 2, channel: 0, event };
+        module.push_edit(Edit::PatternData {
+            remove: vec![src.position()],
+            add: vec![dst],
+        });
+        assert_eq!(module.tracks[1].channels[0].events.len(), 1);
+        assert!(module.tracks[2].channels[0].events.is_empty());
+    }
+
+    #[test]
+    fn test_shift_markers() {
+        let mut module = Module::new(Default::default());
+        for (tick, text) in [(1, "A"), (2, "B"), (4, "C")] {
+            module.markers.push(Marker {
+                tick: Timespan::new(tick, 1),
+                text: String::from(text),
+                trigger: None,
+            });
+        }
+        let ticks = |module: &Module| module.markers.iter()
+            .map(|m| m.tick)
+            .collect::<Vec<_>>();
+        let start = Position::new(Timespan::new(2, 1), 0, 0, NOTE_COLUMN);
+
+        module.shift_channel_events(start, start, Timespan::new(-1, 1));
+        assert_eq!(ticks(&module), [Timespan::new(1, 1), Timespan::new(3, 1)]);
+        module.undo();
+        assert_eq!(ticks(&module), [1, 2, 4].map(|t| Timespan::new(t, 1)));
+        assert_eq!(module.markers[1].text, "B");
+
+        // shifting other tracks leaves markers alone
+        let start = Position { track: 1, ..start };
+        module.shift_channel_events(start, start, Timespan::new(1, 1));
+        assert_eq!(ticks(&module), [1, 2, 4].map(|t| Timespan::new(t, 1)));
+    }
+
+    #[test]
+    fn test_marker_undo() {
+        let mut module = Module::new(Default::default());
+        let tick = Timespan::new(2, 1);
+        module.markers.push(Marker {
+            tick,
+            text: String::from("A"),
+            trigger: Some(SectionTrigger::MidiNote(60)),
+        });
+
+        module.push_edit(Edit::SetMarker(tick, None));
+        assert!(module.markers.is_empty());
+        module.undo();
+        assert!(module.markers[0].trigger == Some(SectionTrigger::MidiNote(60)));
+    }
 }
\ No newline at end of file
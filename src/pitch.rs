@@ -1,11 +1,13 @@
 //! Tuning and notation utilities.
 
 use std::error::Error;
+use std::ops::RangeInclusive;
 use std::{fmt, fs};
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "gui")]
 use crate::ui::text;
 
 /// Fixed reference point regardless of tuning.
@@ -19,11 +21,41 @@ const DEFAULT_ROOT: Note = Note {
     equave: 4,
 };
 
+/// Simple just intervals within an octave, for interval readouts.
+const JI_RATIOS: [(u32, u32); 17] = [(1, 1), (16, 15), (9, 8), (7, 6), (6, 5), (5, 4),
+    (9, 7), (4, 3), (11, 8), (7, 5), (3, 2), (8, 5), (5, 3), (7, 4), (9, 5), (15, 8),
+    (2, 1)];
+
+/// Maximum deviation in cents for a pitch to match a just interval.
+const JI_TOLERANCE: f32 = 3.0;
+
+/// 12-ET pitch classes as nominals and sharps, starting on C.
+const ET_CLASSES: [(Nominal, i8); 12] = [
+    (Nominal::C, 0), (Nominal::C, 1), (Nominal::D, 0), (Nominal::D, 1),
+    (Nominal::E, 0), (Nominal::F, 0), (Nominal::F, 1), (Nominal::G, 0),
+    (Nominal::G, 1), (Nominal::A, 0), (Nominal::A, 1), (Nominal::B, 0),
+];
+
 /// Converts a freq ratio to cents.
 fn cents(ratio: f32) -> f32 {
     1200.0 * ratio.log2() / 2.0_f32.log2()
 }
 
+/// Returns the simple just ratio nearest to a non-negative interval in cents,
+/// and the interval's deviation from it in cents.
+pub fn nearest_just_ratio(interval: f32) -> ((u32, u32), f32) {
+    let octaves = (interval / 1200.0).floor().max(0.0);
+    let reduced = interval - octaves * 1200.0;
+    let deviation = |&(n, d): &(u32, u32)| reduced - cents(n as f32 / d as f32);
+    let (n, d) = JI_RATIOS.into_iter()
+        .min_by(|a, b| deviation(a).abs().total_cmp(&deviation(b).abs()))
+        .expect("ratio list is not empty");
+    let error = deviation(&(n, d));
+    let n = n << octaves as u32;
+    let gcd = (1..=d).rev().find(|i| n % i == 0 && d % i == 0).unwrap_or(1);
+    ((n / gcd, d / gcd), error)
+}
+
 /// Converts cents to a freq ratio.
 fn find_ratio(cents: f32) -> f32 {
     2.0_f32.powf(2.0_f32.log2() * cents / 1200.0)
@@ -98,6 +130,9 @@ pub struct Tuning {
     /// Cents values of scale notes. The last value is also the scale period.
     pub scale: Vec<f32>,
     pub arrow_steps: u8,
+    /// Custom characters used to display and parse notation.
+    #[serde(default)]
+    pub names: NoteNames,
 }
 
 impl Tuning {
@@ -113,6 +148,7 @@ impl Tuning {
             root: DEFAULT_ROOT,
             scale: (1..=steps).map(|i| i as f32 * step).collect(),
             arrow_steps,
+            names: Default::default(),
         })
     }
 
@@ -138,13 +174,19 @@ impl Tuning {
             root,
             scale: scale?,
             arrow_steps: 1,
+            names: Default::default(),
         })
     }
 
     /// Translates notation to a concrete pitch.
     pub fn midi_pitch(&self, note: &Note) -> f32 {
+        self.shifted_midi_pitch(note, 0)
+    }
+
+    /// Returns the MIDI pitch of a note shifted by a number of scale steps.
+    pub fn shifted_midi_pitch(&self, note: &Note, steps: i32) -> f32 {
         let root_steps = self.raw_steps(&self.root);
-        let steps = self.raw_steps(note) - root_steps;
+        let steps = self.raw_steps(note) - root_steps + steps;
         let root_pitch = self.pitch_from_steps(
             root_steps, self.root.equave, REFERENCE_MIDI_PITCH, 4);
         self.pitch_from_steps(steps, note.equave, root_pitch, self.root.equave)
@@ -267,6 +309,40 @@ impl Tuning {
 
         v
     }
+
+    /// Returns the notated pitches of each scale step in a range of equaves,
+    /// with their MIDI pitches. Steps without notation are left out.
+    pub fn notated_pitches(&self, equaves: RangeInclusive<i8>) -> Vec<(Note, f32)> {
+        equaves.flat_map(|equave| (0..self.scale.len())
+            .filter_map(move |i| self.notation(i, equave).first().copied()))
+            .map(|note| (note, self.midi_pitch(&note)))
+            .collect()
+    }
+
+    /// Returns a readout of a MIDI pitch's frequency, its deviation from the
+    /// nearest 12-ET note, and its just ratio above the root if it's close to
+    /// a simple one.
+    pub fn tuner_text(&self, pitch: f32) -> String {
+        let freq = 440.0 * 2.0_f32.powf((pitch - REFERENCE_MIDI_PITCH) / 12.0);
+        let nearest = pitch.round() as i32;
+        let deviation = (pitch - nearest as f32) * 100.0;
+        let (nominal, sharps) = ET_CLASSES[nearest.rem_euclid(12) as usize];
+        let note = Note::new(0, nominal, sharps, (nearest.div_euclid(12) - 1) as i8);
+        let name = self.names.note_string(&note, "");
+        let mut text = format!("{freq:.2} Hz, {name} {deviation:+.1}c");
+        if let Some((n, d)) = self.just_ratio(pitch) {
+            text.push_str(&format!(", {n}/{d}"));
+        }
+        text
+    }
+
+    /// Returns the simple just interval between the root and a MIDI pitch,
+    /// octave-reduced, if there is one within tolerance.
+    fn just_ratio(&self, pitch: f32) -> Option<(u32, u32)> {
+        let interval = ((pitch - self.midi_pitch(&self.root)) * 100.0).rem_euclid(1200.0);
+        let (ratio, error) = nearest_just_ratio(interval);
+        (error.abs() <= JI_TOLERANCE).then_some(ratio)
+    }
 }
 
 /// Parses a Scala file interval into cents.
@@ -286,6 +362,85 @@ fn parse_interval(s: &str) -> Option<f32> {
     })
 }
 
+/// Custom characters for nominals and accidentals. Unset characters use the
+/// default notation.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NoteNames {
+    /// Characters for the nominals, in the order of `NoteNames::ORDER`.
+    pub nominals: [Option<char>; 7],
+    pub sharp: Option<char>,
+    pub flat: Option<char>,
+    pub up: Option<char>,
+    pub down: Option<char>,
+}
+
+impl NoteNames {
+    /// Order of nominals in `nominals`.
+    pub const ORDER: [Nominal; 7] = [Nominal::C, Nominal::D, Nominal::E, Nominal::F,
+        Nominal::G, Nominal::A, Nominal::B];
+
+    /// Returns the character used for a nominal.
+    pub fn nominal_char(&self, nominal: Nominal) -> char {
+        Self::ORDER.iter().position(|n| *n == nominal)
+            .and_then(|i| self.nominals[i])
+            .unwrap_or(nominal.char())
+    }
+
+    /// Returns the nominal for a character. Custom characters take precedence
+    /// over default ones.
+    pub fn parse_nominal(&self, c: char) -> Option<Nominal> {
+        Self::ORDER.iter().zip(self.nominals)
+            .find(|(_, name)| *name == Some(c))
+            .map(|(nominal, _)| *nominal)
+            .or_else(|| Nominal::VARIANTS.into_iter().find(|n| n.char() == c))
+    }
+
+    /// Returns text for a note, using `natural` for the accidental of a
+    /// natural note.
+    pub fn note_string(&self, note: &Note, natural: &str) -> String {
+        let arrows = if note.arrows > 0 {
+            self.up.unwrap_or('^').to_string().repeat(note.arrows as usize)
+        } else {
+            self.down.unwrap_or('v').to_string().repeat(note.arrows.unsigned_abs() as usize)
+        };
+        let accidentals = if note.sharps > 0 {
+            self.sharp.unwrap_or('#').to_string().repeat(note.sharps as usize)
+        } else if note.sharps < 0 {
+            self.flat.unwrap_or('b').to_string().repeat(note.sharps.unsigned_abs() as usize)
+        } else {
+            String::from(natural)
+        };
+        format!("{arrows}{}{accidentals}{}", self.nominal_char(note.nominal), note.equave)
+    }
+
+    /// Returns the character used for a note's arrows, and the arrow count if
+    /// it should be shown as a superscript.
+    #[cfg(feature = "gui")]
+    pub fn arrow_glyph(&self, note: &Note) -> (char, Option<u8>) {
+        glyph(note.arrows, self.up, self.down, note.arrow_char())
+    }
+
+    /// Returns the character used for a note's sharps/flats, and the count if
+    /// it should be shown as a superscript.
+    #[cfg(feature = "gui")]
+    pub fn accidental_glyph(&self, note: &Note) -> (char, Option<u8>) {
+        glyph(note.sharps, self.sharp, self.flat, note.accidental_char())
+    }
+}
+
+/// Chooses between a custom and default character for a signed count of
+/// accidentals. Custom characters have no double forms, so their count is
+/// shown from two up.
+#[cfg(feature = "gui")]
+fn glyph(n: i8, raise: Option<char>, lower: Option<char>, default: char
+) -> (char, Option<u8>) {
+    let count = n.unsigned_abs();
+    match if n > 0 { raise } else if n < 0 { lower } else { None } {
+        Some(c) => (c, (count > 1).then_some(count)),
+        None => (default, (count > 2).then_some(count)),
+    }
+}
+
 /// Abstract notational representation of pitch.
 #[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Note {
@@ -301,6 +456,7 @@ impl Note {
     }
 
     /// Returns the character code used for this note's arrows.
+    #[cfg(feature = "gui")]
     pub fn arrow_char(&self) -> char {
         char::from_u32(match self.arrows {
             ..=-3 => text::SUB_DOWN,
@@ -314,6 +470,7 @@ impl Note {
     }
 
     /// Returns the character code used for this note's sharps/flats.
+    #[cfg(feature = "gui")]
     pub fn accidental_char(&self) -> char {
         char::from_u32(match self.sharps {
             ..=-3 => text::SUB_FLAT,
@@ -419,6 +576,7 @@ mod tests {
             root: DEFAULT_ROOT,
             scale: vec![240.0, 480.0, 720.0, 960.0, 1200.0],
             arrow_steps: 1,
+            names: Default::default(),
         });
         Tuning::divide(1.0, 5, 1).unwrap_err();
         Tuning::divide(0.5, 5, 1).unwrap_err();
@@ -436,6 +594,8 @@ mod tests {
         assert_eq!(t.midi_pitch(&Note { sharps: 1, ..A4 }), 70.0);
         t.root = Note::new(0, Nominal::D, 0, 0);
         assert_eq!(t.midi_pitch(&A4), 69.0);
+        assert_eq!(t.shifted_midi_pitch(&A4, 2), 71.0);
+        assert_eq!(t.shifted_midi_pitch(&A4, -13), 56.0);
     }
 
     #[test]
@@ -517,4 +677,26 @@ mod tests {
         assert_eq!(t.octave_offet(&Note::new(0, Nominal::A, 5, 4)), 1);
         assert_eq!(t.octave_offet(&Note::new(-1, Nominal::B, 0, 4)), 0);
     }
+
+    #[test]
+    fn test_tuner_text() {
+        let t = Tuning::divide(2.0, 12, 1).unwrap();
+        assert_eq!(t.tuner_text(69.0), "440.00 Hz, A4 +0.0c");
+        assert_eq!(t.tuner_text(67.0), "392.00 Hz, G4 +0.0c, 3/2");
+        assert_eq!(t.tuner_text(63.86), "326.97 Hz, E4 -14.0c, 5/4");
+
+        let mut t = t;
+        t.names.sharp = Some('\u{266f}');
+        t.names.nominals[6] = Some('H');
+        assert_eq!(t.tuner_text(70.0), "466.16 Hz, A\u{266f}4 +0.0c");
+        assert_eq!(t.tuner_text(71.0), "493.88 Hz, H4 +0.0c");
+    }
+
+    #[test]
+    fn test_nearest_just_ratio() {
+        assert_eq!(nearest_just_ratio(700.0).0, (3, 2));
+        assert_eq!(nearest_just_ratio(1590.0).0, (5, 2));
+        assert_eq!(nearest_just_ratio(1195.0).0, (2, 1));
+        assert!((nearest_just_ratio(1200.0).1).abs() < 0.01);
+    }
 }
\ No newline at end of file
@@ -1,16 +1,25 @@
-use std::{path::PathBuf, sync::{mpsc::{self, Sender}, Arc, Mutex}, thread};
+use std::{collections::VecDeque, ops::Range, path::PathBuf, sync::{mpsc::{self, Sender}, Arc, Mutex}, thread};
 
 use fundsp::hacker32::*;
-use rtrb::Producer;
+use rtrb::{Consumer, Producer};
 use triple_buffer::Output;
 
-use crate::{fx::GlobalFX, module::{Event, EventData, LocatedEvent, Module, TrackEdit, GLOBAL_COLUMN, MOD_COLUMN, NOTE_COLUMN, VEL_COLUMN}, synth::{Key, KeyOrigin, Patch, Synth, DEFAULT_PRESSURE}, timespan::Timespan};
+use crate::{dsp::{BlockProcessor, ScopeBuffer}, fx::GlobalFX, module::{AudioClip, Event, EventData, LocatedEvent, Module, TrackEdit, TrackTarget, GLOBAL_COLUMN, MOD_COLUMN, NOTE_COLUMN, VEL_COLUMN}, synth::{pcm, Key, KeyOrigin, Patch, PrebuiltVoice, Synth, VoiceCache, VoiceRequest, VoiceSettings, DEFAULT_PRESSURE, SMOOTH_TIME}, timespan::Timespan};
 
 pub const DEFAULT_TEMPO: f32 = 120.0;
 
 /// For rendering.
 const LOOP_FADEOUT_TIME: f64 = 10.0;
 
+/// Length of render previews, in seconds.
+pub const PREVIEW_TIME: f64 = 10.0;
+
+/// Vibrato and tremolo rate per digit step, in Hz.
+const EFFECT_RATE_STEP: f32 = 0.75;
+
+/// Vibrato depth per digit step, in semitones.
+const VIBRATO_DEPTH_STEP: f32 = 0.125;
+
 /// Information for the UI thread sent from the audio thread.
 #[derive(Clone)]
 pub struct PlayerState {
@@ -18,6 +27,16 @@ pub struct PlayerState {
     pub beat: f64,
     pub buffer_size: usize,
     pub tracks_muted: Vec<bool>,
+    /// Start beat of the looping section in performance mode.
+    pub perf_section: Option<f64>,
+    /// Start beat of the queued section in performance mode.
+    pub perf_queued: Option<f64>,
+    /// Index of the playing playlist song.
+    pub playlist_position: Option<usize>,
+    /// Recent output of each track.
+    pub scopes: Vec<Arc<ScopeBuffer>>,
+    /// Voices for the UI thread to build ahead of time.
+    pub voice_requests: Vec<VoiceRequest>,
 }
 
 impl PlayerState {
@@ -41,6 +60,15 @@ pub enum PlayerCommand {
     ToggleMute(usize),
     ToggleSolo(usize),
     UnmuteAll,
+    ToggleGroupMute(Range<usize>),
+    SoloTracks(Range<usize>),
+    AddSolo(usize),
+    SetMutes(Vec<bool>),
+    QueueSection {
+        section: Range<f64>,
+        bar: f64,
+    },
+    EndPerformance,
     NoteOn {
         track: usize,
         key: Key,
@@ -48,6 +76,14 @@ pub enum PlayerCommand {
         pressure: Option<f32>,
         patch: usize,
     },
+    QuantizedNoteOn {
+        track: usize,
+        key: Key,
+        pitch: f32,
+        pressure: Option<f32>,
+        patch: usize,
+        step: f64,
+    },
     ResetMemory,
     PolyPressure {
         track: usize,
@@ -68,30 +104,75 @@ pub enum PlayerCommand {
         track: usize,
         channel: u8,
         semitones: f32,
-    }
+    },
+    SetVoiceSettings(VoiceSettings),
+    PlayPlaylist {
+        songs: VecDeque<Streamer>,
+        crossfade: f64,
+    },
+    StopPlaylist,
+    PlayPreview(Wave),
+}
+
+/// Objects that the audio thread is done with, sent back to the UI thread so
+/// that they aren't deallocated on the audio thread.
+pub enum Discard {
+    Song(Streamer),
+    Songs(VecDeque<Streamer>),
+    Preview(Wave),
+    Voice(PrebuiltVoice),
+    VoiceCache(VoiceCache),
 }
 
 /// Imitation of the Player API for the UI thread.
 pub struct PlayerShell {
     state_output: Output<PlayerState>,
     cmd_producer: Producer<PlayerCommand>,
+    discard_consumer: Consumer<Discard>,
+    voice_producer: Producer<PrebuiltVoice>,
+    stereo_width: Shared,
+    /// Count of voices built for seeded patches.
+    seeded_voices: u64,
     state: PlayerState,
+    sample_rate: f64,
 }
 
 impl PlayerShell {
-    pub fn new(state_output: Output<PlayerState>, cmd_producer: Producer<PlayerCommand>
+    pub fn new(state_output: Output<PlayerState>, cmd_producer: Producer<PlayerCommand>,
+        discard_consumer: Consumer<Discard>, voice_producer: Producer<PrebuiltVoice>,
+        stereo_width: Shared, sample_rate: f64
     ) -> Self {
         let mut state_output = state_output;
         Self {
             state: state_output.read().clone(),
             state_output,
             cmd_producer,
+            discard_consumer,
+            voice_producer,
+            stereo_width,
+            seeded_voices: 0,
+            sample_rate,
         }
     }
 
-    /// Update cached state.
+    /// Build the voices requested by the audio thread and send them over.
+    pub fn build_voices(&mut self, patches: &[Patch]) {
+        for request in &self.state.voice_requests {
+            if self.voice_producer.is_full() {
+                break
+            }
+            if let Some(voice) = request.build(patches, &self.stereo_width,
+                &mut self.seeded_voices) {
+                let _ = self.voice_producer.push(voice);
+            }
+        }
+    }
+
+    /// Update cached state, and deallocate objects discarded by the audio
+    /// thread.
     pub fn update(&mut self) {
         self.state = self.state_output.read().clone();
+        while self.discard_consumer.pop().is_ok() {}
     }
 
     fn cmd(&mut self, cmd: PlayerCommand) {
@@ -151,6 +232,46 @@ impl PlayerShell {
         self.cmd(PlayerCommand::UnmuteAll)
     }
 
+    pub fn toggle_group_mute(&mut self, tracks: Range<usize>) {
+        self.cmd(PlayerCommand::ToggleGroupMute(tracks))
+    }
+
+    pub fn solo_tracks(&mut self, tracks: Range<usize>) {
+        self.cmd(PlayerCommand::SoloTracks(tracks))
+    }
+
+    pub fn add_solo(&mut self, track: usize) {
+        self.cmd(PlayerCommand::AddSolo(track))
+    }
+
+    pub fn set_mutes(&mut self, mutes: Vec<bool>) {
+        self.cmd(PlayerCommand::SetMutes(mutes))
+    }
+
+    /// Switch to looping a section at the next bar boundary, or immediately
+    /// if stopped. `bar` is in beats.
+    pub fn queue_section(&mut self, section: Range<f64>, bar: f64) {
+        self.cmd(PlayerCommand::QueueSection { section, bar })
+    }
+
+    /// Stop looping the current section.
+    pub fn end_performance(&mut self) {
+        self.cmd(PlayerCommand::EndPerformance)
+    }
+
+    pub fn perf_section(&self) -> Option<f64> {
+        self.state.perf_section
+    }
+
+    pub fn perf_queued(&self) -> Option<f64> {
+        self.state.perf_queued
+    }
+
+    /// Returns the mute state of every track.
+    pub fn tracks_muted(&self) -> &[bool] {
+        &self.state.tracks_muted
+    }
+
     pub fn track_muted(&mut self, track: usize) -> bool {
         self.state.tracks_muted.get(track).cloned().unwrap_or_default()
     }
@@ -161,6 +282,16 @@ impl PlayerShell {
         self.cmd(PlayerCommand::NoteOn { track, key, pitch, pressure, patch })
     }
 
+    /// Start a note at the next multiple of `step` beats if playing, or
+    /// immediately if not.
+    pub fn quantized_note_on(&mut self, track: usize, key: Key, pitch: f32,
+        pressure: Option<f32>, patch: usize, step: f64
+    ) {
+        self.cmd(PlayerCommand::QuantizedNoteOn {
+            track, key, pitch, pressure, patch, step
+        })
+    }
+
     pub fn reset_memory(&mut self) {
         self.cmd(PlayerCommand::ResetMemory)
     }
@@ -184,6 +315,39 @@ impl PlayerShell {
     pub fn pitch_bend(&mut self, track: usize, channel: u8, semitones: f32) {
         self.cmd(PlayerCommand::PitchBend { track, channel, semitones })
     }
+
+    pub fn set_voice_settings(&mut self, settings: VoiceSettings) {
+        self.cmd(PlayerCommand::SetVoiceSettings(settings))
+    }
+
+    /// Stop pattern playback and play `modules` back to back, crossfading
+    /// over `crossfade` seconds.
+    pub fn play_playlist(&mut self, modules: Vec<Module>, crossfade: f64) {
+        // build the players here rather than on the audio thread
+        let songs = modules.into_iter()
+            .map(|module| Streamer::new(module, self.sample_rate))
+            .collect();
+        self.cmd(PlayerCommand::PlayPlaylist { songs, crossfade })
+    }
+
+    pub fn stop_playlist(&mut self) {
+        self.cmd(PlayerCommand::StopPlaylist)
+    }
+
+    /// Stop playback and play a rendered wave.
+    pub fn play_preview(&mut self, wave: Wave) {
+        self.cmd(PlayerCommand::PlayPreview(wave))
+    }
+
+    /// Returns the index of the playing playlist song.
+    pub fn playlist_position(&self) -> Option<usize> {
+        self.state.playlist_position
+    }
+
+    /// Returns the recent output of each track.
+    pub fn scopes(&self) -> &[Arc<ScopeBuffer>] {
+        &self.state.scopes
+    }
 }
 
 /// Handles module playback. In methods that take a `track` argument, 0 can
@@ -195,10 +359,68 @@ pub struct Player {
     beat: f64,
     tempo: f32,
     looped: bool,
+    /// Number of times playback has looped since starting.
+    loop_pass: u32,
+    /// Number of times the current counted loop has repeated.
+    repeat_pass: u32,
+    /// Jump over sections marked with Skip events.
+    render_skips: bool,
     metronome: bool,
     sample_rate: f32,
     pub stereo_width: Shared,
     pub buffer_size: usize,
+    /// Global voice allocation settings.
+    pub voice_settings: VoiceSettings,
+    /// Track indices and sequencer events of playing audio clips.
+    clip_events: Vec<(usize, EventId)>,
+    /// Voices built on the UI thread. If None, voices are built here.
+    voice_source: Option<Consumer<PrebuiltVoice>>,
+    /// Incremented when tracks or patches change, so that voices requested
+    /// before the change can be recognized.
+    voice_generation: u64,
+    /// Beat range of the looping section in performance mode.
+    perf_section: Option<Range<f64>>,
+    /// Section to switch to at the next bar boundary.
+    perf_queue: Option<Range<f64>>,
+    /// Bar length in beats, for performance mode.
+    perf_bar: f64,
+    /// Pitch slides in progress.
+    slides: Vec<Slide>,
+    /// Notes waiting for the next quantization boundary.
+    pending_notes: Vec<PendingNote>,
+    /// Playlist output, mixed in after global FX.
+    pub jukebox: Jukebox,
+}
+
+/// A note-on delayed by input quantization.
+struct PendingNote {
+    beat: f64,
+    track: usize,
+    key: Key,
+    pitch: f32,
+    pressure: Option<f32>,
+    patch: usize,
+    /// True if the key was released before the note started.
+    released: bool,
+}
+
+/// A pitch slide from a note slide event.
+struct Slide {
+    track: usize,
+    key: Key,
+    from: f32,
+    to: f32,
+    /// Beat range of the slide.
+    span: Range<f64>,
+}
+
+impl Slide {
+    /// Returns the pitch at `beat`.
+    fn pitch(&self, beat: f64) -> f32 {
+        let t = ((beat - self.span.start) / (self.span.end - self.span.start))
+            .clamp(0.0, 1.0) as f32;
+        self.from + (self.to - self.from) * t
+    }
 }
 
 impl Player {
@@ -210,10 +432,23 @@ impl Player {
             beat: 0.0,
             tempo: DEFAULT_TEMPO,
             looped: false,
+            loop_pass: 0,
+            repeat_pass: 0,
+            render_skips: false,
             metronome: false,
             sample_rate,
             stereo_width: shared(1.0),
             buffer_size: 0,
+            voice_settings: Default::default(),
+            clip_events: Vec::new(),
+            voice_source: None,
+            voice_generation: 0,
+            perf_section: None,
+            perf_queue: None,
+            perf_bar: 4.0,
+            slides: Vec::new(),
+            pending_notes: Vec::new(),
+            jukebox: Jukebox::new(sample_rate as f64),
         }
     }
 
@@ -223,7 +458,30 @@ impl Player {
             beat: self.beat,
             buffer_size: self.buffer_size,
             tracks_muted: self.synths.iter().map(|x| x.muted).collect(),
+            perf_section: self.perf_section.as_ref().map(|r| r.start),
+            perf_queued: self.perf_queue.as_ref().map(|r| r.start),
+            playlist_position: self.jukebox.position(),
+            scopes: self.synths.iter().map(|x| x.scope.clone()).collect(),
+            voice_requests: self.voice_requests(),
+        }
+    }
+
+    /// Returns requests for voices to build ahead of time, if voices are
+    /// built on the UI thread.
+    fn voice_requests(&self) -> Vec<VoiceRequest> {
+        let mut requests = Vec::new();
+        if self.voice_source.is_some() {
+            for (i, synth) in self.synths.iter().enumerate() {
+                synth.request_voices(i, self.voice_generation, &self.voice_settings,
+                    &mut requests);
+            }
         }
+        requests
+    }
+
+    /// Sets where voices built on the UI thread come from.
+    pub fn set_voice_source(&mut self, consumer: Consumer<PrebuiltVoice>) {
+        self.voice_source = Some(consumer);
     }
 
     pub fn handle_command(&mut self, cmd: PlayerCommand, module: &Module) {
@@ -231,10 +489,14 @@ impl Player {
             PlayerCommand::PlayFrom(beat) => if self.playing {
                 self.stop();
             } else {
+                self.jukebox.stop();
                 self.play_from(beat, module);
             },
-            PlayerCommand::Stop => self.stop(),
-            PlayerCommand::Reinitialize => self.reinit(module.tracks.len()),
+            PlayerCommand::Stop => {
+                self.stop();
+                self.jukebox.stop_preview();
+            }
+            PlayerCommand::Reinitialize => self.reinit(module),
             PlayerCommand::Panic => self.panic(),
             PlayerCommand::ClearNotesWithOrigin(origin) =>
                 self.clear_notes_with_origin(origin),
@@ -243,11 +505,20 @@ impl Player {
             PlayerCommand::ToggleMute(track) => self.toggle_mute(module, track),
             PlayerCommand::ToggleSolo(track) => self.toggle_solo(module, track),
             PlayerCommand::UnmuteAll => self.unmute_all(module),
+            PlayerCommand::ToggleGroupMute(tracks) => self.toggle_group_mute(module, tracks),
+            PlayerCommand::SoloTracks(tracks) => self.solo_tracks(module, tracks),
+            PlayerCommand::AddSolo(track) => self.add_solo(module, track),
+            PlayerCommand::SetMutes(mutes) => self.set_mutes(module, &mutes),
+            PlayerCommand::QueueSection { section, bar } =>
+                self.queue_section(section, bar, module),
+            PlayerCommand::EndPerformance => {
+                self.perf_section = None;
+                self.perf_queue = None;
+            }
             PlayerCommand::NoteOn { track, key, pitch, pressure, patch } =>
-                match module.patches.get(patch) {
-                    Some(patch) => self.note_on(track, key, pitch, pressure, patch),
-                    None => eprintln!("patch index out of bounds"),
-                },
+                self.note_on(track, key, pitch, pressure, patch, module),
+            PlayerCommand::QuantizedNoteOn { track, key, pitch, pressure, patch, step } =>
+                self.quantized_note_on(track, key, pitch, pressure, patch, step, module),
             PlayerCommand::ResetMemory => self.reset_memory(),
             PlayerCommand::ChannelPressure { track, channel, pressure } =>
                 self.channel_pressure(track, channel, pressure),
@@ -257,15 +528,29 @@ impl Player {
                 self.pitch_bend(track, channel, semitones),
             PlayerCommand::PolyPressure { track, key, pressure } =>
                 self.poly_pressure(track, key, pressure),
+            PlayerCommand::SetVoiceSettings(settings) => self.voice_settings = settings,
+            PlayerCommand::PlayPlaylist { songs, crossfade } => {
+                self.stop();
+                self.jukebox.play(songs, crossfade);
+            }
+            PlayerCommand::StopPlaylist => self.jukebox.stop(),
+            PlayerCommand::PlayPreview(wave) => {
+                self.stop();
+                self.jukebox.play_preview(wave);
+            }
         }
     }
 
-    /// Reinitialize state.
-    pub fn reinit(&mut self, num_tracks: usize) {
+    /// Reinitialize state, restoring track mutes from the module.
+    pub fn reinit(&mut self, module: &Module) {
         for synth in &mut self.synths {
             synth.clear_all_notes(&mut self.seq);
         }
-        self.synths = (0..num_tracks).map(|_| Synth::new(self.sample_rate)).collect();
+        self.synths = module.tracks.iter().map(|track| {
+            let mut synth = Synth::new(self.sample_rate);
+            synth.muted = track.muted;
+            synth
+        }).collect();
         self.playing = false;
         self.beat = 0.0;
         self.tempo = DEFAULT_TEMPO;
@@ -286,19 +571,147 @@ impl Player {
         self.playing = false;
         self.metronome = false;
         self.clear_notes_with_origin(KeyOrigin::Pattern);
+        self.stop_clips();
+        self.perf_section = None;
+        self.perf_queue = None;
+        self.slides.clear();
     }
 
     pub fn play(&mut self) {
         self.playing = true;
         self.looped = false;
+        self.loop_pass = 0;
+        self.repeat_pass = 0;
     }
 
     pub fn play_from(&mut self, tick: Timespan, module: &Module) {
         self.simulate_events(tick, module);
         self.beat = tick.as_f64();
+        self.resume_clips(tick, module);
         self.play();
     }
 
+    /// Move the playhead without stopping playback.
+    fn jump_to(&mut self, tick: Timespan, module: &Module) {
+        self.beat = tick.as_f64();
+        self.reinit_memory(tick, module);
+        self.stop_clips();
+        self.resume_clips(tick, module);
+        self.slides.clear();
+        self.start_pending_notes(module, true);
+    }
+
+    /// Queue a section to loop in performance mode.
+    fn queue_section(&mut self, section: Range<f64>, bar: f64, module: &Module) {
+        self.perf_bar = bar;
+        if self.playing {
+            self.perf_queue = Some(section);
+        } else {
+            self.play_from(Timespan::approximate(section.start), module);
+            self.perf_section = Some(section);
+        }
+    }
+
+    /// Returns the beat at which performance mode changes the playhead during
+    /// this frame, and the section to loop after the change.
+    fn perf_transition(&mut self, prev_time: f64) -> Option<(f64, Range<f64>)> {
+        let section = self.perf_section.clone()
+            .unwrap_or(f64::NEG_INFINITY..f64::INFINITY);
+
+        if self.perf_queue.is_some() {
+            // bars are counted from the start of the current section
+            let origin = if section.start.is_finite() { section.start } else { 0.0 };
+            let bar = origin
+                + ((prev_time - origin) / self.perf_bar).floor() * self.perf_bar
+                + self.perf_bar;
+            let boundary = bar.min(section.end);
+            if boundary <= self.beat {
+                return self.perf_queue.take().map(|next| (boundary, next))
+            }
+        }
+
+        (self.beat >= section.end).then_some((section.end, section))
+    }
+
+    /// Start audio clips that begin in the beat range [`start`, `end`).
+    fn start_clips(&mut self, module: &Module, start: f64, end: f64) {
+        for (track_i, track) in module.tracks.iter().enumerate() {
+            if self.clips_muted(track_i, track.target) {
+                continue
+            }
+            for clip in &track.clips {
+                if (start..end).contains(&clip.tick.as_f64()) {
+                    self.play_clip(track_i, clip, 0.0);
+                }
+            }
+        }
+    }
+
+    /// Start audio clips that began before `tick`, partway through.
+    fn resume_clips(&mut self, tick: Timespan, module: &Module) {
+        for track_i in 0..module.tracks.len() {
+            self.resume_track_clips(tick, module, track_i);
+        }
+    }
+
+    /// Start audio clips in one track that began before `tick`, partway
+    /// through.
+    fn resume_track_clips(&mut self, tick: Timespan, module: &Module, track_i: usize) {
+        let Some(track) = module.tracks.get(track_i) else { return };
+        if self.clips_muted(track_i, track.target) {
+            return
+        }
+        for clip in &track.clips {
+            if clip.tick < tick {
+                self.play_clip(track_i, clip, module.time_between(clip.tick, tick));
+            }
+        }
+    }
+
+    fn clips_muted(&self, track: usize, target: TrackTarget) -> bool {
+        !matches!(target, TrackTarget::Audio)
+            || self.synths.get(track).is_none_or(|s| s.muted)
+    }
+
+    /// Play an audio clip, starting `offset` seconds in.
+    fn play_clip(&mut self, track: usize, clip: &AudioClip, offset: f64) {
+        let duration = clip.data.duration() - offset;
+        if duration <= 0.0 {
+            return
+        }
+
+        let rate = clip.data.wave.sample_rate();
+        let start = (offset * rate) as usize;
+        let net = (dc(rate as f32 / self.sample_rate)
+            >> resample(unit::<U0, U1>(clip.data.make_clip_unit(start))))
+            * var(&clip.gain.0) * var(&self.synths[track].gain)
+            >> split::<U2>()
+            >> (multipass::<U2>() | zero() | zero());
+        let fade_in = (clip.fade_in.0.value() as f64 - offset).max(SMOOTH_TIME as f64);
+        let fade_out = (clip.fade_out.0.value() as f64).min(duration);
+
+        self.clip_events.push((track, self.seq.push_relative(
+            0.0, duration, Fade::Smooth, fade_in, fade_out, Box::new(net))));
+    }
+
+    /// Cut all playing audio clips.
+    fn stop_clips(&mut self) {
+        for (_, id) in self.clip_events.drain(..) {
+            self.seq.edit_relative(id, 0.0, SMOOTH_TIME as f64);
+        }
+    }
+
+    /// Cut the playing audio clips of one track.
+    fn stop_track_clips(&mut self, track: usize) {
+        let seq = &mut self.seq;
+        self.clip_events.retain(|&(i, id)| {
+            if i == track {
+                seq.edit_relative(id, 0.0, SMOOTH_TIME as f64);
+            }
+            i != track
+        });
+    }
+
     pub fn toggle_play_from(&mut self, tick: Timespan, module: &Module) {
         if self.playing {
             self.stop()
@@ -315,27 +728,129 @@ impl Player {
 
     /// Update synths for track edits.
     pub fn update_synths(&mut self, edits: Vec<TrackEdit>) {
+        self.voice_generation += 1;
         for edit in edits {
             match edit {
-                TrackEdit::Insert(i) =>
-                    self.synths.insert(i, Synth::new(self.sample_rate)),
+                TrackEdit::Insert(i) => {
+                    self.synths.insert(i, Synth::new(self.sample_rate));
+                    for (track, _) in &mut self.clip_events {
+                        if *track >= i {
+                            *track += 1;
+                        }
+                    }
+                }
                 TrackEdit::Remove(i) => {
                     self.synths[i].clear_all_notes(&mut self.seq);
                     self.synths.remove(i);
+                    self.stop_track_clips(i);
+                    for (track, _) in &mut self.clip_events {
+                        if *track > i {
+                            *track -= 1;
+                        }
+                    }
                 }
             }
         }
     }
 
     pub fn note_on(&mut self, track: usize, key: Key,
-        pitch: f32, pressure: Option<f32>, patch: &Patch
+        pitch: f32, pressure: Option<f32>, patch_index: usize, module: &Module
     ) {
+        let Some(patch) = module.patches.get(patch_index) else {
+            eprintln!("patch index out of bounds");
+            return
+        };
         if let Some(synth) = self.synths.get_mut(track) {
-            synth.note_on(key, pitch, pressure, patch, &mut self.seq, &self.stereo_width);
+            synth.note_on(key, pitch, pressure, patch_index, patch, &mut self.seq,
+                &self.stereo_width, &self.voice_settings);
+        }
+    }
+
+    /// Discard prebuilt voices. Call this when module patches or track
+    /// targets change.
+    pub fn clear_voice_cache(&mut self) {
+        for synth in &mut self.synths {
+            let cache = synth.clear_voice_cache();
+            if !cache.is_empty() {
+                self.jukebox.discard(Discard::VoiceCache(cache));
+            }
+        }
+        self.voice_generation += 1;
+    }
+
+    /// Take voices built on the UI thread if there's a source of them,
+    /// otherwise build at most one voice ahead of time, so that note-ons
+    /// don't need to.
+    fn refill_voice_cache(&mut self, module: &Module) {
+        if let Some(consumer) = &mut self.voice_source {
+            while let Ok(voice) = consumer.pop() {
+                let unused = match self.synths.get_mut(voice.track) {
+                    Some(synth) if voice.generation == self.voice_generation =>
+                        synth.add_prebuilt_voice(voice),
+                    _ => Some(voice),
+                };
+                if let Some(voice) = unused {
+                    self.jukebox.discard(Discard::Voice(voice));
+                }
+            }
+            return
+        }
+
+        for synth in &mut self.synths {
+            if synth.refill_voice_cache(&module.patches, &self.stereo_width,
+                &self.voice_settings) {
+                break
+            }
+        }
+    }
+
+    /// Start a note at the next multiple of `step` beats if playing, or
+    /// immediately if not.
+    #[allow(clippy::too_many_arguments)]
+    pub fn quantized_note_on(&mut self, track: usize, key: Key, pitch: f32,
+        pressure: Option<f32>, patch: usize, step: f64, module: &Module
+    ) {
+        if !self.playing || step <= 0.0 {
+            self.note_on(track, key, pitch, pressure, patch, module);
+            return
+        }
+
+        self.pending_notes.push(PendingNote {
+            beat: (self.beat / step).ceil() * step,
+            track,
+            key,
+            pitch,
+            pressure,
+            patch,
+            released: false,
+        });
+    }
+
+    /// Start pending notes that have reached their boundary, or all of them if
+    /// `all` is true.
+    fn start_pending_notes(&mut self, module: &Module, all: bool) {
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_notes)
+            .into_iter()
+            .partition(|n| all || n.beat <= self.beat);
+        self.pending_notes = waiting;
+
+        for note in ready {
+            self.note_on(note.track, note.key.clone(), note.pitch, note.pressure,
+                note.patch, module);
+            if note.released {
+                self.note_off(note.track, note.key);
+            }
         }
     }
 
     pub fn note_off(&mut self, track: usize, key: Key) {
+        // let a tapped note sound once its boundary arrives
+        if let Some(note) = self.pending_notes.iter_mut()
+            .find(|n| n.track == track && n.key == key && !n.released) {
+            note.released = true;
+            return
+        }
+
         if let Some(synth) = self.synths.get_mut(track) {
             synth.note_off(key, &mut self.seq);
         }
@@ -354,6 +869,22 @@ impl Player {
         }
     }
 
+    /// Handle a vibrato event.
+    fn vibrato(&mut self, track: usize, channel: u8, rate: u8, depth: u8) {
+        if let Some(synth) = self.synths.get_mut(track) {
+            synth.vibrato(channel, rate as f32 * EFFECT_RATE_STEP,
+                depth as f32 * VIBRATO_DEPTH_STEP);
+        }
+    }
+
+    /// Handle a tremolo event.
+    fn tremolo(&mut self, track: usize, channel: u8, rate: u8, depth: u8) {
+        if let Some(synth) = self.synths.get_mut(track) {
+            synth.tremolo(channel, rate as f32 * EFFECT_RATE_STEP,
+                depth as f32 / EventData::DIGIT_MAX as f32);
+        }
+    }
+
     pub fn channel_pressure(&mut self, track: usize, channel: u8, pressure: f32) {
         if let Some(synth) = self.synths.get_mut(track) {
             synth.channel_pressure(channel, pressure);
@@ -391,12 +922,31 @@ impl Player {
 
     /// Handle a frame of length `dt`.
     pub fn frame(&mut self, module: &Module, dt: f64) {
+        for (i, synth) in self.synths.iter().enumerate() {
+            synth.gain.set(module.track_gain(i));
+            synth.scope.advance();
+        }
+        self.refill_voice_cache(module);
+
         if !self.playing {
+            self.start_pending_notes(module, true);
             return
         }
 
-        let prev_time = self.beat;
+        let mut prev_time = self.beat;
         self.beat += interval_beats(dt, self.tempo);
+
+        if let Some((boundary, section)) = self.perf_transition(prev_time) {
+            if self.perf_section.as_ref().is_none_or(|s| s.start != section.start) {
+                self.clear_notes_with_origin(KeyOrigin::Pattern);
+            }
+            let overshoot = self.beat - boundary;
+            prev_time = section.start;
+            self.jump_to(Timespan::approximate(section.start), module);
+            self.beat += overshoot;
+            self.perf_section = Some(section);
+        }
+
         let current_timespan = Timespan::approximate(self.beat);
 
         let mut events = Vec::new();
@@ -422,6 +972,8 @@ impl Player {
                         }
 
                         match event.data {
+                            // don't disturb modulation interpolation
+                            EventData::Vibrato(..) | EventData::Tremolo(..) => continue,
                             EventData::StartGlide(i) => if glide[i as usize] {
                                 continue
                             } else {
@@ -447,7 +999,7 @@ impl Player {
                     if glide[i] {
                         if let Some(data) = interpolate_events(
                             prev_data[i], next_event[i], start_tick[i],
-                            self.beat as f32, module
+                            self.beat as f32, module, track_i
                         ) {
                             events.push(LocatedEvent {
                                 track: track_i,
@@ -476,6 +1028,8 @@ impl Player {
             }
         }
 
+        self.start_clips(module, prev_time, self.beat);
+
         for event in events {
             self.handle_event(&event.event, module, event.track, event.channel);
             if let EventData::End = event.event.data {
@@ -483,6 +1037,9 @@ impl Player {
             }
         }
 
+        self.update_slides();
+        self.start_pending_notes(module, false);
+
         if self.metronome && self.beat.ceil() != prev_time.ceil() {
             self.seq.push_relative(0.0, 0.01, Fade::Smooth, 0.01, 0.01,
                 Box::new(square_hz(440.0 * 8.0) >> split::<U4>()));
@@ -530,9 +1087,12 @@ impl Player {
                     EventData::NoteOff => active_note = None,
                     EventData::Tempo(t) => self.tempo = t,
                     EventData::RationalTempo(n, d) => self.tempo *= n as f32 / d as f32,
-                    EventData::End | EventData::Loop | EventData::StartGlide(_)
+                    EventData::End | EventData::Loop | EventData::LoopCount(_)
+                        | EventData::Skip | EventData::StartGlide(_)
                         | EventData::EndGlide(_) | EventData::TickGlide(_)
-                        | EventData::Section => (),
+                        | EventData::Section | EventData::Condition(_)
+                        | EventData::Slide(_) | EventData::Vibrato(..)
+                        | EventData::Tremolo(..) => (),
                     EventData::InterpolatedPitch(_)
                         | EventData::InterpolatedPressure(_)
                         | EventData::InterpolatedModulation(_)
@@ -551,9 +1111,10 @@ impl Player {
                     origin: KeyOrigin::Pattern,
                     channel: channel_i as u8,
                     key: 0,
+                    chord_tone: 0,
                 };
-                let pitch = module.tuning.midi_pitch(&note);
-                self.note_on(track_i, key, pitch, None, &module.patches[patch]);
+                let pitch = module.track_pitch(&note, track_i);
+                self.note_on(track_i, key, pitch, None, patch, module);
                 self.pitch_bend(track_i, channel_i as u8, bend_offset as f32 / 100.0);
             }
         }
@@ -609,19 +1170,65 @@ impl Player {
 
         if synth.muted {
             synth.clear_all_notes(&mut self.seq);
+            self.stop_track_clips(track_i);
         } else if self.playing {
-            self.simulate_track_events(Timespan::approximate(self.beat), module, track_i);
+            let tick = Timespan::approximate(self.beat);
+            self.simulate_track_events(tick, module, track_i);
+            self.resume_track_clips(tick, module, track_i);
         }
     }
 
     /// Solo/unsolo a track.
     pub fn toggle_solo(&mut self, module: &Module, track_i: usize) {
+        self.solo_tracks(module, track_i..track_i + 1);
+    }
+
+    /// Mute all tracks in a range, or unmute them if all are already muted.
+    pub fn toggle_group_mute(&mut self, module: &Module, tracks: Range<usize>) {
+        let muted = self.synths.get(tracks.clone())
+            .is_some_and(|s| s.iter().all(|x| x.muted));
+
+        let toggle_indices: Vec<_> = tracks
+            .filter(|&i| self.synths.get(i).is_some_and(|x| x.muted == muted))
+            .collect();
+
+        for i in toggle_indices {
+            self.toggle_mute(module, i);
+        }
+    }
+
+    /// Solo/unsolo a range of tracks.
+    pub fn solo_tracks(&mut self, module: &Module, tracks: Range<usize>) {
         let soloed = self.synths.iter().enumerate()
-            .all(|(i, x)| i == 0 || x.muted == (i != track_i));
+            .all(|(i, x)| i == 0 || x.muted != tracks.contains(&i));
 
         let toggle_indices: Vec<_> = self.synths.iter().enumerate()
-            .filter(|(i, x)| (*i == track_i && x.muted)
-                || (*i != track_i && x.muted == soloed))
+            .filter(|(i, x)| (tracks.contains(i) && x.muted)
+                || (!tracks.contains(i) && x.muted == soloed))
+            .map(|(i, _)| i)
+            .collect();
+
+        for i in toggle_indices {
+            self.toggle_mute(module, i);
+        }
+    }
+
+    /// Add a track to the soloed tracks, or remove it if it's already
+    /// soloed. Works like a normal solo if no tracks are muted.
+    pub fn add_solo(&mut self, module: &Module, track_i: usize) {
+        if !self.synths.iter().any(|x| x.muted) {
+            self.toggle_solo(module, track_i);
+        } else if self.synths.iter().enumerate().all(|(i, x)| i == 0 || i == track_i || x.muted) {
+            self.unmute_all(module);
+        } else {
+            self.toggle_mute(module, track_i);
+        }
+    }
+
+    /// Set the mute state of each track.
+    pub fn set_mutes(&mut self, module: &Module, mutes: &[bool]) {
+        let toggle_indices: Vec<_> = self.synths.iter().zip(mutes).enumerate()
+            .filter(|(_, (x, muted))| x.muted != **muted)
             .map(|(i, _)| i)
             .collect();
 
@@ -647,6 +1254,17 @@ impl Player {
         self.synths[i].muted
     }
 
+    /// Bend notes that are sliding, and drop finished slides.
+    fn update_slides(&mut self) {
+        let slides = std::mem::take(&mut self.slides);
+        for slide in &slides {
+            self.bend_to(slide.track, slide.key.clone(), slide.pitch(self.beat));
+        }
+        self.slides = slides.into_iter()
+            .filter(|s| s.span.end > self.beat)
+            .collect();
+    }
+
     /// Process a pattern event.
     fn handle_event(&mut self, event: &Event, module: &Module,
         track: usize, channel: usize
@@ -655,17 +1273,34 @@ impl Player {
             origin: KeyOrigin::Pattern,
             channel: channel as u8,
             key: 0,
+            chord_tone: 0,
         };
 
         match event.data {
             EventData::Pitch(note) => {
+                let channel = &module.tracks[track].channels[channel];
+                if channel.condition_at(event.tick)
+                    .is_some_and(|c| !c.passes(self.loop_pass, rand::random())) {
+                    return
+                }
                 if let Some((patch, note)) = module.map_note(note, track) {
-                    let pitch = module.tuning.midi_pitch(&note);
-                    let channel = &module.tracks[track].channels[channel];
+                    let pitch = module.track_pitch(&note, track);
+                    let from = channel.slide_at(event.tick)
+                        .and_then(|n| Some((n, self.synths.get(track)?.key_pitch(&key)?)));
                     if channel.is_interpolated(NOTE_COLUMN, event.tick) {
                         self.bend_to(track, key, pitch);
+                    } else if let Some((n, from)) = from {
+                        let start = event.tick.as_f64();
+                        self.slides.retain(|s| s.track != track || s.key != key);
+                        self.slides.push(Slide {
+                            track,
+                            key,
+                            from,
+                            to: pitch,
+                            span: start..start + n as f64 * EventData::SLIDE_STEP,
+                        });
                     } else {
-                        self.note_on(track, key, pitch, None, &module.patches[patch]);
+                        self.note_on(track, key, pitch, None, patch, module);
                     }
                 }
             }
@@ -683,15 +1318,33 @@ impl Player {
                     self.tempo *= n as f32 / d as f32;
                 }
             }
-            EventData::End => if let Some(tick) = module.find_loop_start(self.beat) {
-                self.beat = tick.as_f64();
-                self.reinit_memory(tick, module);
-                self.looped = true;
-            } else {
-                self.stop();
+            EventData::End => match module.loop_for_end(event.tick) {
+                Some((tick, Some(count))) => if self.repeat_pass + 1 < count as u32 {
+                    self.repeat_pass += 1;
+                    self.jump_to(tick, module);
+                } else {
+                    self.repeat_pass = 0;
+                },
+                Some((tick, None)) => {
+                    self.jump_to(tick, module);
+                    self.looped = true;
+                    self.loop_pass += 1;
+                }
+                None => self.stop(),
             },
-            EventData::Loop | EventData::StartGlide(_) | EventData::EndGlide(_)
-                | EventData::TickGlide(_) | EventData::Section => (),
+            EventData::Skip => if self.render_skips {
+                match module.skip_target(event.tick) {
+                    Some(tick) => self.jump_to(tick, module),
+                    None => self.stop(),
+                }
+            },
+            EventData::Loop | EventData::LoopCount(_) | EventData::StartGlide(_) | EventData::EndGlide(_)
+                | EventData::TickGlide(_) | EventData::Section
+                | EventData::Condition(_) | EventData::Slide(_) => (),
+            EventData::Vibrato(rate, depth) =>
+                self.vibrato(track, channel as u8, rate, depth),
+            EventData::Tremolo(rate, depth) =>
+                self.tremolo(track, channel as u8, rate, depth),
             EventData::InterpolatedPitch(pitch) => self.bend_to(track, key, pitch),
             EventData::InterpolatedPressure(v) =>
                 self.channel_pressure(track, channel as u8, v),
@@ -718,68 +1371,184 @@ pub enum StatusUpdate {
     Done(Wave, PathBuf),
     Autosave,
     AutosaveError(String),
+    VideoDone(PathBuf),
+    VideoError(String),
+    Preview(Wave),
 }
 
-/// Renders module to PCM. Loops forever if module is missing End!
-/// If `track` is some, solo that track for rendering.
+/// Span of a module to render.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct RenderRange {
+    pub start: Timespan,
+    /// If None, rendering continues to the End event.
+    pub end: Option<Timespan>,
+}
+
+/// Renders module to PCM on a new thread. If `track` is some, solo that
+/// track for rendering.
 pub fn render(module: Arc<Module>, path: PathBuf, track: Option<usize>,
-    tx: Sender<StatusUpdate>
+    range: RenderRange, voice_settings: VoiceSettings, tx: Sender<StatusUpdate>
 ) {
     thread::spawn(move || {
-        const SAMPLE_RATE: f64 = 44100.0;
-        const BLOCK_SIZE: i32 = 64;
-
-        let mut wave = Wave::new(2, SAMPLE_RATE);
-        let mut seq = Sequencer::new(false, 4);
-        seq.set_sample_rate(SAMPLE_RATE);
-        let mut fx = GlobalFX::new(seq.backend(), &module.fx);
-        let fadeout_gain = shared(1.0);
-        fx.net = fx.net * (var(&fadeout_gain) | var(&fadeout_gain));
-        fx.net.set_sample_rate(SAMPLE_RATE);
-        let mut player = Player::new(seq, module.tracks.len(), SAMPLE_RATE as f32);
-        if let Some(track) = track {
-            player.toggle_solo(&module, track);
-        }
-        let mut backend = BlockRateAdapter::new(Box::new(fx.net.backend()));
-        let dt = BLOCK_SIZE as f64 / SAMPLE_RATE;
-        let mut playtime = 0.0;
-        let mut time_since_loop = 0.0;
-        let render_time = if module.loops() {
-            module.playtime() + LOOP_FADEOUT_TIME
-        } else {
-            module.playtime()
-        };
-        let mut prev_progress = 0.0;
-
-        player.play();
-        while player.playing && time_since_loop < LOOP_FADEOUT_TIME {
-            player.frame(&module, dt);
-            playtime += dt;
-            for _ in 0..BLOCK_SIZE {
-                wave.push(backend.get_stereo());
-            }
-            if player.looped {
-                fadeout_gain.set(1.0 - (time_since_loop / LOOP_FADEOUT_TIME) as f32);
-                time_since_loop += dt;
+        let wave = render_range_to_wave(&module, track, range, None, voice_settings,
+            |progress| {
+            if let Err(e) = tx.send(StatusUpdate::Progress(progress)) {
+                eprintln!("{e}");
             }
+        });
 
-            let progress = playtime / render_time;
-            if progress - prev_progress >= 0.01 {
-                prev_progress = progress;
+        if let Err(e) = tx.send(StatusUpdate::Done(wave, path)) {
+            eprintln!("{e}");
+        }
+    });
+}
+
+/// Renders the first `PREVIEW_TIME` seconds of a range on a new thread, for
+/// auditioning render settings.
+pub fn render_preview(module: Arc<Module>, range: RenderRange,
+    voice_settings: VoiceSettings, tx: Sender<StatusUpdate>
+) {
+    thread::spawn(move || {
+        let wave = render_range_to_wave(&module, None, range, Some(PREVIEW_TIME),
+            voice_settings, |progress| {
                 if let Err(e) = tx.send(StatusUpdate::Progress(progress)) {
                     eprintln!("{e}");
                 }
-            }
-        }
+            });
 
-        if let Err(e) = tx.send(StatusUpdate::Done(wave, path)) {
+        if let Err(e) = tx.send(StatusUpdate::Preview(wave)) {
             eprintln!("{e}");
         }
     });
 }
 
+/// Renders module to PCM on the calling thread. `progress` is called with
+/// the completed fraction at most once per percent.
+pub fn render_to_wave(module: &Module, track: Option<usize>,
+    voice_settings: VoiceSettings, progress: impl FnMut(f64)
+) -> Wave {
+    render_range_to_wave(module, track, RenderRange::default(), None, voice_settings,
+        progress)
+}
+
+/// Renders part of a module to PCM on the calling thread, leaving out
+/// sections marked with Skip events. If `max_time` is some, rendering stops
+/// after that many seconds. Rendering never runs longer than the module's
+/// playtime plus the loop fadeout, in case the module is missing End, a Skip
+/// jumps past End, or the range starts after End.
+pub fn render_range_to_wave(module: &Module, track: Option<usize>, range: RenderRange,
+    max_time: Option<f64>, voice_settings: VoiceSettings, mut progress: impl FnMut(f64)
+) -> Wave {
+    const SAMPLE_RATE: f64 = 44100.0;
+    const BLOCK_SIZE: usize = BlockProcessor::BLOCK_SIZE;
+
+    let _blocking = pcm::stream::set_blocking(true);
+
+    let mut wave = Wave::new(2, SAMPLE_RATE);
+    let mut seq = Sequencer::new(false, 4);
+    seq.set_sample_rate(SAMPLE_RATE);
+    let mut fx = GlobalFX::new(seq.backend(), &module.fx);
+    let fadeout_gain = shared(1.0);
+    fx.net = fx.net * (var(&fadeout_gain) | var(&fadeout_gain));
+    fx.net.set_sample_rate(SAMPLE_RATE);
+    let mut player = Player::new(seq, module.tracks.len(), SAMPLE_RATE as f32);
+    player.voice_settings = voice_settings;
+    player.render_skips = true;
+    if let Some(track) = track {
+        player.toggle_solo(module, track);
+    }
+    let mut backend = BlockProcessor::new(Box::new(fx.net.backend()));
+    let dt = BLOCK_SIZE as f64 / SAMPLE_RATE;
+    let mut playtime = 0.0;
+    let mut time_since_loop = 0.0;
+    let render_time = match range.end {
+        Some(end) => module.time_between(range.start, end),
+        None => module.playtime() - module.time_between(Timespan::ZERO, range.start)
+            + if module.loops() { LOOP_FADEOUT_TIME } else { 0.0 },
+    } - module.skipped_time(range.start, range.end);
+    let render_time = render_time.min(max_time.unwrap_or(f64::INFINITY)).max(dt);
+    let time_limit = module.playtime().max(render_time) + LOOP_FADEOUT_TIME;
+    let mut prev_progress = 0.0;
+
+    if range.start == Timespan::ZERO {
+        player.play();
+    } else {
+        player.play_from(range.start, module);
+    }
+    while player.playing && time_since_loop < LOOP_FADEOUT_TIME
+        && range.end.is_none_or(|end| player.beat < end.as_f64())
+        && max_time.is_none_or(|t| playtime < t)
+        && playtime < time_limit {
+        player.frame(module, dt);
+        playtime += dt;
+        backend.process_block();
+        let (l, r) = backend.block();
+        for i in 0..BLOCK_SIZE {
+            wave.push((l[i], r[i]));
+        }
+        if player.looped {
+            fadeout_gain.set(1.0 - (time_since_loop / LOOP_FADEOUT_TIME) as f32);
+            time_since_loop += dt;
+        }
+
+        let fraction = (playtime / render_time).min(1.0);
+        if fraction - prev_progress >= 0.01 {
+            prev_progress = fraction;
+            progress(fraction);
+        }
+    }
+
+    wave
+}
+
+/// Renders a single note of a patch to a stereo wave, without global FX.
+/// The note is held for `duration` seconds at MIDI pitch `note`, rounded up
+/// to a whole block, then released and rendered until its envelopes finish.
+pub fn render_patch_to_buffer(patch: &Patch, note: f32, duration: f64) -> Wave {
+    const SAMPLE_RATE: f64 = 44100.0;
+    const BLOCK_SIZE: usize = BlockProcessor::BLOCK_SIZE;
+
+    let _blocking = pcm::stream::set_blocking(true);
+
+    let mut seq = Sequencer::new(false, 4);
+    seq.set_sample_rate(SAMPLE_RATE);
+    // discard the FX send outputs
+    let mut net = Net::wrap(Box::new(seq.backend()))
+        >> Net::wrap(Box::new(multipass::<U2>() | multisink::<U2>()));
+    net.set_sample_rate(SAMPLE_RATE);
+    let mut backend = BlockProcessor::new(Box::new(net));
+    let mut synth = Synth::new(SAMPLE_RATE as f32);
+    let key = Key::new_from_keyboard(0);
+    synth.note_on(key.clone(), note, None, 0, patch, &mut seq, &shared(1.0),
+        &VoiceSettings::default());
+
+    let release = patch.envs.iter().map(|env| env.release).fold(0.0, f32::max);
+    let render_time = duration + (release + SMOOTH_TIME) as f64;
+    let dt = BLOCK_SIZE as f64 / SAMPLE_RATE;
+    let mut wave = Wave::new(2, SAMPLE_RATE);
+    let mut time = 0.0;
+    let mut released = false;
+
+    while time < render_time {
+        if !released && time >= duration {
+            synth.note_off(key.clone(), &mut seq);
+            released = true;
+        }
+        backend.process_block();
+        let (l, r) = backend.block();
+        for i in 0..BLOCK_SIZE {
+            wave.push((l[i], r[i]));
+        }
+        time += dt;
+    }
+
+    wave
+}
+
 /// Renders each track to its own WAV file.
-pub fn render_tracks(module: Arc<Module>, path: PathBuf, final_tx: Sender<StatusUpdate>) {
+pub fn render_tracks(module: Arc<Module>, path: PathBuf, range: RenderRange,
+    voice_settings: VoiceSettings, final_tx: Sender<StatusUpdate>
+) {
     let track_range = 1..module.tracks.len();
     let progress = Arc::new(Mutex::new(
         track_range.clone().map(|_| 0.0).collect::<Vec<_>>()
@@ -792,7 +1561,7 @@ pub fn render_tracks(module: Arc<Module>, path: PathBuf, final_tx: Sender<Status
             .with_file_name(format!("{}_{}",
                 path.file_stem().and_then(|s| s.to_str()).unwrap_or_default(), i))
             .with_extension("wav");
-        render(module.clone(), path, Some(i), tx);
+        render(module.clone(), path, Some(i), range, voice_settings, tx);
         let progress = progress.clone();
 
         thread::spawn(move || {
@@ -818,6 +1587,232 @@ pub fn render_tracks(module: Arc<Module>, path: PathBuf, final_tx: Sender<Status
     }
 }
 
+/// Renders a module on demand into caller-provided buffers, for embedding in
+/// games and other programs. Playback jumps back to the Loop event at End,
+/// the same as in the editor, so looping modules stream without gaps. Modules
+/// without a loop stop at End, after which release tails and silence are
+/// rendered.
+pub struct Streamer {
+    module: Module,
+    player: Player,
+    backend: BlockProcessor,
+    /// Kept alive for the backend.
+    _fx: GlobalFX,
+    update_interval: f64,
+    frames_until_update: usize,
+}
+
+impl Streamer {
+    /// Creates a streamer at the start of `module`.
+    pub fn new(module: Module, sample_rate: f64) -> Self {
+        let mut seq = Sequencer::new(false, 4);
+        seq.set_sample_rate(sample_rate);
+        let mut fx = GlobalFX::new(seq.backend(), &module.fx);
+        fx.net.set_sample_rate(sample_rate);
+        let backend = BlockProcessor::new(Box::new(fx.net.backend()));
+        let mut player = Player::new(seq, module.tracks.len(), sample_rate as f32);
+        player.reinit(&module);
+        player.play_from(Timespan::ZERO, &module);
+
+        Self {
+            module,
+            player,
+            backend,
+            _fx: fx,
+            update_interval: BlockProcessor::BLOCK_SIZE as f64 / sample_rate,
+            frames_until_update: 0,
+        }
+    }
+
+    /// Returns the next stereo frame.
+    fn next_frame(&mut self) -> (f32, f32) {
+        // player updates are aligned to processing blocks
+        if self.frames_until_update == 0 {
+            self.player.frame(&self.module, self.update_interval);
+            self.backend.process_block();
+            self.frames_until_update = BlockProcessor::BLOCK_SIZE;
+        }
+        self.frames_until_update -= 1;
+        self.backend.get_stereo()
+    }
+
+    /// Fills separate left and right channel buffers. Only the length of the
+    /// shorter buffer is filled.
+    pub fn fill(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            (*l, *r) = self.next_frame();
+        }
+    }
+
+    /// Fills a buffer of interleaved stereo frames.
+    pub fn fill_interleaved(&mut self, data: &mut [f32]) {
+        for frame in data.chunks_exact_mut(2) {
+            (frame[0], frame[1]) = self.next_frame();
+        }
+    }
+
+    /// Moves the playhead to `tick`, as if the module had been played up to
+    /// that point. Notes sounding from the pattern are cut.
+    pub fn seek(&mut self, tick: Timespan) {
+        self.player.stop();
+        self.player.play_from(tick, &self.module);
+    }
+
+    /// Returns the closest `Timespan` to the playhead.
+    pub fn tick(&self) -> Timespan {
+        self.player.get_tick()
+    }
+
+    /// Returns the number of times playback has looped since starting or
+    /// seeking.
+    pub fn loop_count(&self) -> u32 {
+        self.player.loop_pass
+    }
+
+    /// Returns true if playback has reached End without looping.
+    pub fn is_finished(&self) -> bool {
+        !self.player.is_playing()
+    }
+}
+
+/// Plays a queue of modules back to back, for the playlist. Each module plays
+/// until it reaches End or first loops. Also plays render previews.
+pub struct Jukebox {
+    songs: VecDeque<Streamer>,
+    /// Rendered wave and playback position in its samples.
+    preview: Option<(Wave, f64)>,
+    /// Crossfade time between songs, in seconds. Zero for a hard cut.
+    crossfade: f64,
+    /// Time into the crossfade out of the front song, if fading.
+    fade_time: Option<f64>,
+    /// Index of the front song in the original queue.
+    position: usize,
+    sample_rate: f64,
+    /// Where finished songs are sent to be deallocated. If None, they're
+    /// dropped in place.
+    discard: Option<Producer<Discard>>,
+}
+
+impl Jukebox {
+    fn new(sample_rate: f64) -> Self {
+        Self {
+            songs: VecDeque::new(),
+            preview: None,
+            crossfade: 0.0,
+            fade_time: None,
+            position: 0,
+            sample_rate,
+            discard: None,
+        }
+    }
+
+    /// Sets where finished songs are sent to be deallocated.
+    pub fn set_discard(&mut self, producer: Producer<Discard>) {
+        self.discard = Some(producer);
+    }
+
+    /// Sends an object away to be deallocated, or drops it if that isn't
+    /// possible.
+    fn discard(&mut self, item: Discard) {
+        if let Some(producer) = &mut self.discard {
+            // if the queue is full, the item is dropped here after all
+            let _ = producer.push(item);
+        }
+    }
+
+    fn play(&mut self, songs: VecDeque<Streamer>, crossfade: f64) {
+        let old = std::mem::replace(&mut self.songs, songs);
+        self.discard(Discard::Songs(old));
+        self.crossfade = crossfade.max(0.0);
+        self.fade_time = None;
+        self.position = 0;
+    }
+
+    fn stop(&mut self) {
+        let songs = std::mem::take(&mut self.songs);
+        self.discard(Discard::Songs(songs));
+        self.stop_preview();
+        self.fade_time = None;
+    }
+
+    fn stop_preview(&mut self) {
+        if let Some((wave, _)) = self.preview.take() {
+            self.discard(Discard::Preview(wave));
+        }
+    }
+
+    fn play_preview(&mut self, wave: Wave) {
+        self.stop();
+        self.preview = Some((wave, 0.0));
+    }
+
+    /// Returns the next frame of the preview, resampled to the output rate.
+    fn preview_frame(&mut self) -> Option<(f32, f32)> {
+        let (wave, pos) = self.preview.as_mut()?;
+        let i = *pos as usize;
+        if i + 1 >= wave.len() {
+            self.stop_preview();
+            return None
+        }
+        let t = pos.fract() as f32;
+        let lerp = |ch: usize| wave.at(ch, i) * (1.0 - t) + wave.at(ch, i + 1) * t;
+        let frame = (lerp(0), lerp(1));
+        *pos += wave.sample_rate() / self.sample_rate;
+        Some(frame)
+    }
+
+    /// Returns the index of the playing song.
+    fn position(&self) -> Option<usize> {
+        (!self.songs.is_empty()).then_some(self.position)
+    }
+
+    fn next_song(&mut self) {
+        if let Some(song) = self.songs.pop_front() {
+            self.discard(Discard::Song(song));
+        }
+        self.position += 1;
+        self.fade_time = None;
+    }
+
+    /// Returns the next stereo frame, or silence if nothing is queued.
+    pub fn get_stereo(&mut self) -> (f32, f32) {
+        if let Some(frame) = self.preview_frame() {
+            return frame
+        }
+        let Some(song) = self.songs.front_mut() else {
+            return (0.0, 0.0)
+        };
+        let (mut l, mut r) = song.next_frame();
+
+        if self.fade_time.is_none() && (song.is_finished() || song.loop_count() > 0) {
+            if self.crossfade > 0.0 {
+                self.fade_time = Some(0.0);
+            } else {
+                self.next_song();
+            }
+        }
+
+        if let Some(t) = self.fade_time {
+            let gain = (t / self.crossfade) as f32;
+            l *= 1.0 - gain;
+            r *= 1.0 - gain;
+            if let Some(next) = self.songs.get_mut(1) {
+                let (next_l, next_r) = next.next_frame();
+                l += next_l * gain;
+                r += next_r * gain;
+            }
+            let t = t + 1.0 / self.sample_rate;
+            if t >= self.crossfade {
+                self.next_song();
+            } else {
+                self.fade_time = Some(t);
+            }
+        }
+
+        (l, r)
+    }
+}
+
 /// Calculates the total rational tempo change between 2 points.
 fn tempo_ratio_between(start: Timespan, end: Timespan, module: &Module) -> f32 {
     let mut m = 1.0f32;
@@ -840,15 +1835,15 @@ fn tempo_ratio_between(start: Timespan, end: Timespan, module: &Module) -> f32 {
 
 /// Calculates interpolated event data.
 fn interpolate_events(prev: Option<&EventData>, next: Option<&Event>,
-    start: Timespan, time: f32, module: &Module
+    start: Timespan, time: f32, module: &Module, track: usize
 ) -> Option<EventData> {
     if let Some(next) = next {
         let t = (time - start.as_f32()) / (next.tick.as_f32() - start.as_f32());
 
         match next.data {
             EventData::Pitch(b) => if let Some(EventData::Pitch(a)) = prev {
-                let a = module.tuning.midi_pitch(a);
-                let b = module.tuning.midi_pitch(&b);
+                let a = module.track_pitch(a, track);
+                let b = module.track_pitch(&b, track);
                 Some(EventData::InterpolatedPitch(lerp(a, b, t)))
             } else {
                 None
@@ -895,4 +1890,125 @@ fn interpolate_events(prev: Option<&EventData>, next: Option<&Event>,
     } else {
         None
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use crate::{fx::FXSettings, synth::Waveform};
+
+    use super::*;
+
+    /// Largest per-sample difference allowed from a golden render.
+    const GOLDEN_TOLERANCE: f32 = 1e-4;
+
+    /// Compare a render to its golden file in testdata/golden. Goldens are
+    /// written instead if they're missing or `OSCTET_BLESS` is set.
+    fn check_golden(name: &str, wave: &Wave) {
+        let path = Path::new("testdata/golden").join(format!("{name}.f32"));
+        let samples: Vec<f32> = (0..wave.len())
+            .flat_map(|i| [wave.at(0, i), wave.at(1, i)])
+            .collect();
+
+        if env::var_os("OSCTET_BLESS").is_some() || !path.exists() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let bytes: Vec<u8> = samples.iter().flat_map(|x| x.to_le_bytes()).collect();
+            fs::write(&path, bytes).unwrap();
+            eprintln!("{name}: wrote {}", path.display());
+            return
+        }
+
+        let golden: Vec<f32> = fs::read(&path)
+            .unwrap_or_else(|e| panic!("{name}: {e}"))
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(golden.len(), samples.len(), "{name}: length differs");
+        let max_diff = golden.iter().zip(&samples)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(max_diff <= GOLDEN_TOLERANCE, "{name}: max difference {max_diff}");
+    }
+
+    fn test_patch(name: &str, waveform: Waveform) -> Patch {
+        let mut patch = Patch::new(name.to_owned());
+        patch.oscs[0].waveform = waveform;
+        patch.seed = Some(1);
+        patch
+    }
+
+    #[test]
+    fn test_golden_renders() {
+        for patch in [
+            test_patch("sine", Waveform::Sine),
+            test_patch("saw", Waveform::Sawtooth),
+            test_patch("noise", Waveform::Noise),
+        ] {
+            let wave = render_patch_to_buffer(&patch, 60.0, 0.25);
+            check_golden(&patch.name, &wave);
+        }
+    }
+
+    #[test]
+    fn test_streamer_loop_and_seek() {
+        let mut module = Module::new(FXSettings::default());
+        let end = Event { tick: Timespan::new(1, 1), data: EventData::End };
+        module.tracks[0].channels[0].events.push(end);
+        let mut buf = vec![0.0; 44100 * 2];
+
+        let mut streamer = Streamer::new(module.clone(), 44100.0);
+        streamer.fill_interleaved(&mut buf);
+        assert!(streamer.is_finished());
+
+        module.tracks[0].channels[0].events.push(Event {
+            tick: Timespan::ZERO,
+            data: EventData::Loop,
+        });
+        let mut streamer = Streamer::new(module, 44100.0);
+        streamer.fill_interleaved(&mut buf);
+        assert!(!streamer.is_finished());
+        assert!(streamer.loop_count() > 0);
+
+        streamer.seek(Timespan::new(1, 2));
+        assert_eq!(streamer.tick(), Timespan::new(1, 2));
+        assert_eq!(streamer.loop_count(), 0);
+    }
+
+    #[test]
+    fn test_jukebox_advances() {
+        let mut module = Module::new(FXSettings::default());
+        let end = Event { tick: Timespan::new(1, 1), data: EventData::End };
+        module.tracks[0].channels[0].events.push(end);
+        let mut jukebox = Jukebox::new(44100.0);
+        let songs = [module.clone(), module].map(|m| Streamer::new(m, 44100.0));
+        jukebox.play(VecDeque::from(songs), 0.0);
+        assert_eq!(jukebox.position(), Some(0));
+
+        // each module ends after half a second
+        for _ in 0..33075 {
+            jukebox.get_stereo();
+        }
+        assert_eq!(jukebox.position(), Some(1));
+        for _ in 0..22050 {
+            jukebox.get_stereo();
+        }
+        assert_eq!(jukebox.position(), None);
+    }
+
+    #[test]
+    fn test_render_without_end_stops() {
+        let module = Module::new(FXSettings::default());
+        let wave = render_to_wave(&module, None, VoiceSettings::default(), |_| ());
+        assert!(wave.duration() <= LOOP_FADEOUT_TIME + 1.0);
+    }
+
+    #[test]
+    fn test_seeded_render_repeats() {
+        let patch = test_patch("noise", Waveform::Noise);
+        let a = render_patch_to_buffer(&patch, 60.0, 0.1);
+        let b = render_patch_to_buffer(&patch, 60.0, 0.1);
+        assert_eq!(a.len(), b.len());
+        assert!((0..a.len()).all(|i| a.at(0, i) == b.at(0, i) && a.at(1, i) == b.at(1, i)));
+    }
+}
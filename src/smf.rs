@@ -0,0 +1,131 @@
+//! Standard MIDI file export.
+
+use std::ops::Range;
+
+use crate::{module::{EventData, Module, VEL_COLUMN}, timespan::Timespan};
+
+/// MIDI ticks per beat.
+const PPQ: u16 = 960;
+
+/// Velocity of notes that aren't preceded by a pressure event.
+const DEFAULT_VELOCITY: u8 = 100;
+
+/// A timestamped MIDI event.
+struct SmfEvent {
+    tick: u32,
+    data: Vec<u8>,
+}
+
+/// Returns a type 0 MIDI file containing the notes in `tracks` during
+/// `ticks`. Notes are rounded to the nearest MIDI key.
+pub fn export(module: &Module, tracks: Range<usize>, ticks: Range<Timespan>) -> Vec<u8> {
+    let to_smf = |tick: Timespan| {
+        ((tick - ticks.start).as_f64().max(0.0) * PPQ as f64).round() as u32
+    };
+
+    let tempo = module.tempo_at(ticks.start);
+    let usec_per_beat = (60_000_000.0 / tempo).round() as u32;
+    let mut events = vec![SmfEvent {
+        tick: 0,
+        data: [&[0xff, 0x51, 0x03], &usec_per_beat.to_be_bytes()[1..]].concat(),
+    }];
+
+    for track_i in tracks.filter(|&i| i > 0) {
+        let Some(track) = module.tracks.get(track_i) else { break };
+        for channel in &track.channels {
+            // pressure comes first so that it applies to a note on the same row
+            let mut channel_events: Vec<_> = channel.events.iter()
+                .filter(|e| ticks.contains(&e.tick))
+                .collect();
+            channel_events.sort_by_key(|e| (e.tick, e.data.spatial_column() != VEL_COLUMN));
+
+            let mut velocity = DEFAULT_VELOCITY;
+            let mut sounding: Option<u8> = None;
+
+            for event in channel_events {
+                let tick = to_smf(event.tick);
+                match event.data {
+                    EventData::Pitch(note) => {
+                        if let Some(key) = sounding.take() {
+                            events.push(note_off(tick, key));
+                        }
+                        let key = module.track_pitch(&note, track_i).round()
+                            .clamp(0.0, 127.0) as u8;
+                        events.push(SmfEvent { tick, data: vec![0x90, key, velocity] });
+                        sounding = Some(key);
+                    }
+                    EventData::NoteOff => if let Some(key) = sounding.take() {
+                        events.push(note_off(tick, key));
+                    }
+                    EventData::Pressure(v) => {
+                        velocity = ((v as f32 / EventData::DIGIT_MAX as f32 * 127.0).round()
+                            as u8).max(1);
+                    }
+                    _ => (),
+                }
+            }
+
+            if let Some(key) = sounding {
+                events.push(note_off(to_smf(ticks.end), key));
+            }
+        }
+    }
+
+    // note-offs before note-ons, so repeated keys don't cut each other
+    events.sort_by_key(|e| (e.tick, e.data[0] == 0x90));
+
+    let mut track_data = Vec::new();
+    let mut prev_tick = 0;
+    for event in events {
+        write_vlq(&mut track_data, event.tick - prev_tick);
+        track_data.extend(event.data);
+        prev_tick = event.tick;
+    }
+    track_data.extend([0x00, 0xff, 0x2f, 0x00]);
+
+    let mut file = Vec::new();
+    file.extend(b"MThd");
+    file.extend(6u32.to_be_bytes());
+    file.extend(0u16.to_be_bytes()); // format
+    file.extend(1u16.to_be_bytes()); // number of tracks
+    file.extend(PPQ.to_be_bytes());
+    file.extend(b"MTrk");
+    file.extend((track_data.len() as u32).to_be_bytes());
+    file.extend(track_data);
+    file
+}
+
+fn note_off(tick: u32, key: u8) -> SmfEvent {
+    SmfEvent { tick, data: vec![0x80, key, 0] }
+}
+
+/// Append a variable-length quantity to `buf`.
+fn write_vlq(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_vlq() {
+        for (value, expected) in [
+            (0, vec![0x00]),
+            (0x7f, vec![0x7f]),
+            (0x80, vec![0x81, 0x00]),
+            (0x3fff, vec![0xff, 0x7f]),
+            (0x200000, vec![0x81, 0x80, 0x80, 0x00]),
+        ] {
+            let mut buf = Vec::new();
+            write_vlq(&mut buf, value);
+            assert_eq!(buf, expected);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Grid controller support. Pads on the top row queue performance sections,
+//! pads on the next row toggle track mutes, and the remaining pads play notes.
+//! LED colors are sent back over a MIDI output.
+
+use std::{error::Error, fmt};
+
+use midir::{MidiOutput, MidiOutputConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::{input::MidiEvent, module::Module, playback::PlayerShell, APP_NAME};
+
+/// Width and height of the pad grid.
+const GRID_SIZE: u8 = 8;
+
+/// Row of pads that queue sections.
+const SECTION_ROW: u8 = GRID_SIZE - 1;
+
+/// Row of pads that toggle track mutes.
+const MUTE_ROW: u8 = GRID_SIZE - 2;
+
+/// MIDI key played by the bottom left note pad.
+const NOTE_BASE_KEY: u8 = 36;
+
+/// Semitones between rows of note pads.
+const NOTE_ROW_INTERVAL: u8 = 5;
+
+/// Note mapping and LED palette of a grid controller.
+#[derive(Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum SurfaceLayout {
+    #[default]
+    None,
+    /// Launchpad in programmer mode.
+    Launchpad,
+    ApcMini,
+}
+
+impl SurfaceLayout {
+    pub const VARIANTS: [Self; 3] = [Self::None, Self::Launchpad, Self::ApcMini];
+
+    /// Returns the row and column of a pad, with row 0 at the bottom.
+    fn pad(&self, key: u8) -> Option<(u8, u8)> {
+        match self {
+            Self::None => None,
+            Self::Launchpad => {
+                let (row, col) = (key / 10, key % 10);
+                ((1..=GRID_SIZE).contains(&row) && (1..=GRID_SIZE).contains(&col))
+                    .then(|| (row - 1, col - 1))
+            }
+            Self::ApcMini => (key < GRID_SIZE * GRID_SIZE)
+                .then(|| (key / GRID_SIZE, key % GRID_SIZE)),
+        }
+    }
+
+    /// Returns the MIDI key of a pad.
+    fn key(&self, row: u8, col: u8) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Launchpad => (row + 1) * 10 + col + 1,
+            Self::ApcMini => row * GRID_SIZE + col,
+        }
+    }
+
+    /// Returns the note velocity that sets a pad to a color.
+    fn velocity(&self, led: Led) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Launchpad => match led {
+                Led::Off => 0,
+                Led::Green => 21,
+                Led::Yellow => 13,
+                Led::Red => 5,
+            },
+            Self::ApcMini => match led {
+                Led::Off => 0,
+                Led::Green => 1,
+                Led::Yellow => 5,
+                Led::Red => 3,
+            },
+        }
+    }
+}
+
+impl fmt::Display for SurfaceLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::None => "(none)",
+            Self::Launchpad => "Launchpad",
+            Self::ApcMini => "APC mini",
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Led {
+    Off,
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Result of a pad press or release.
+pub enum SurfaceInput {
+    /// Queue the section starting at a marker index.
+    Section(usize),
+    ToggleMute(usize),
+    /// Note event with the key remapped from the pad grid.
+    Note(MidiEvent),
+    /// The event came from a control pad but requires no action.
+    Ignore,
+}
+
+/// Handles control surface mapping and LED feedback.
+pub struct ControlSurface {
+    layout: SurfaceLayout,
+    // Keep one output around for listing ports, as with MIDI input.
+    output: Option<MidiOutput>,
+    port_name: Option<String>,
+    conn: Option<MidiOutputConnection>,
+    output_id: u16,
+    /// Last color sent to each pad, or None if unknown.
+    leds: Vec<Option<Led>>,
+}
+
+impl ControlSurface {
+    pub fn new(layout: SurfaceLayout) -> Self {
+        let mut s = Self {
+            layout,
+            output: None,
+            port_name: None,
+            conn: None,
+            output_id: 0,
+            leds: vec![None; (GRID_SIZE * GRID_SIZE) as usize],
+        };
+        s.output = s.new_output().ok();
+        s
+    }
+
+    fn new_output(&mut self) -> Result<MidiOutput, midir::InitError> {
+        self.output_id += 1;
+        MidiOutput::new(&format!("{} output #{}", APP_NAME, self.output_id))
+    }
+
+    pub fn layout(&self) -> SurfaceLayout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: SurfaceLayout) {
+        self.layout = layout;
+        self.leds.fill(None);
+    }
+
+    /// Returns true if MIDI output is available.
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    pub fn port_name(&self) -> Option<&str> {
+        self.port_name.as_deref()
+    }
+
+    /// Return the names of MIDI output options.
+    pub fn output_names(&self) -> Vec<String> {
+        let mut v = vec![String::from("(none)")];
+        if let Some(output) = &self.output {
+            v.extend(output.ports().into_iter()
+                .map(|p| output.port_name(&p).unwrap_or(String::from("(unknown)"))));
+        }
+        v
+    }
+
+    /// Connect to an output port for LED feedback, or disconnect if `name`
+    /// is None.
+    pub fn connect(&mut self, name: Option<String>) -> Result<(), Box<dyn Error>> {
+        if let Some(c) = self.conn.take() {
+            c.close();
+        }
+        self.port_name = None;
+        self.leds.fill(None);
+
+        if let Some(name) = name {
+            let port = {
+                let output = self.output.as_ref().ok_or("Could not open MIDI")?;
+                output.ports().into_iter()
+                    .find(|p| output.port_name(p).is_ok_and(|s| s == name))
+                    .ok_or("Selected MIDI device not found")?
+            };
+            let output = self.new_output()?;
+            self.conn = Some(output.connect(&port, APP_NAME)?);
+            self.port_name = Some(name);
+        }
+
+        Ok(())
+    }
+
+    /// Map an incoming MIDI event. Returns None if the event isn't from a
+    /// pad.
+    pub fn translate(&self, evt: &MidiEvent) -> Option<SurfaceInput> {
+        let (channel, key, velocity) = match *evt {
+            MidiEvent::NoteOn { channel, key, velocity } => (channel, key, velocity),
+            MidiEvent::NoteOff { channel, key } => (channel, key, 0),
+            _ => return None,
+        };
+        let (row, col) = self.layout.pad(key)?;
+
+        Some(match row {
+            SECTION_ROW if velocity != 0 => SurfaceInput::Section(col as usize),
+            // track 0 is the global track
+            MUTE_ROW if velocity != 0 => SurfaceInput::ToggleMute(col as usize + 1),
+            SECTION_ROW | MUTE_ROW => SurfaceInput::Ignore,
+            _ => {
+                let key = NOTE_BASE_KEY + row * NOTE_ROW_INTERVAL + col;
+                SurfaceInput::Note(if velocity != 0 {
+                    MidiEvent::NoteOn { channel, key, velocity }
+                } else {
+                    MidiEvent::NoteOff { channel, key }
+                })
+            }
+        })
+    }
+
+    /// Send LED colors for any pads whose state has changed.
+    pub fn update_leds(&mut self, module: &Module, player: &PlayerShell) {
+        let Some(conn) = &mut self.conn else { return };
+        if self.layout == SurfaceLayout::None {
+            return
+        }
+
+        for col in 0..GRID_SIZE {
+            let section_led = module.markers.get(col as usize).map_or(Led::Off, |m| {
+                let start = Some(m.tick.as_f64());
+                if player.perf_queued() == start {
+                    Led::Yellow
+                } else if player.perf_section() == start {
+                    Led::Green
+                } else {
+                    Led::Red
+                }
+            });
+            let track = col as usize + 1;
+            let mute_led = if track >= module.tracks.len() {
+                Led::Off
+            } else if player.tracks_muted().get(track) == Some(&true) {
+                Led::Red
+            } else {
+                Led::Green
+            };
+
+            for (row, led) in [(SECTION_ROW, section_led), (MUTE_ROW, mute_led)] {
+                let i = (row * GRID_SIZE + col) as usize;
+                if self.leds[i] != Some(led) {
+                    let msg = [0x90, self.layout.key(row, col), self.layout.velocity(led)];
+                    if let Err(e) = conn.send(&msg) {
+                        eprintln!("error sending LED state: {e}");
+                        return
+                    }
+                    self.leds[i] = Some(led);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_mapping() {
+        for layout in SurfaceLayout::VARIANTS {
+            if layout == SurfaceLayout::None {
+                continue
+            }
+            for row in 0..GRID_SIZE {
+                for col in 0..GRID_SIZE {
+                    assert!(layout.pad(layout.key(row, col)) == Some((row, col)));
+                }
+            }
+        }
+    }
+}
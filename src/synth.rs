@@ -4,7 +4,7 @@ pub(crate) mod pcm;
 pub(crate) mod lfo;
 
 use core::f64;
-use std::{collections::{HashMap, VecDeque}, error::Error, fmt::Display, fs, path::Path};
+use std::{collections::{HashMap, VecDeque}, error::Error, fmt::Display, fs, path::Path, sync::Arc};
 
 use lfo::LFO;
 use pcm::PcmData;
@@ -13,7 +13,8 @@ use fundsp::hacker32::*;
 use rmp_serde::{config::BytesMode, Serializer};
 use serde::{Deserialize, Serialize};
 
-use crate::{dsp::*, ui::MAX_PATCH_NAME_CHARS};
+use crate::dsp::*;
+use crate::fx::Subsonic;
 
 /// The MIDI pitch of the default note (C4). Used to adjust frequency controls
 /// of loaded samples.
@@ -26,9 +27,15 @@ pub const REF_FREQ: f32 = 261.6256;
 /// Frequency ratio of one semitone in 12-ET.
 const SEMITONE_RATIO: f32 = 1.059463;
 
-/// Maximum voices that can be playing at one time in a channel, including
-/// voices in the release phase.
-const VOICES_PER_CHANNEL: usize = 3;
+/// Default maximum voices that can be playing at one time in a channel,
+/// including voices in the release phase.
+pub const DEFAULT_VOICE_LIMIT: u8 = 8;
+
+/// Upper bound for voice limit settings.
+pub const MAX_VOICE_LIMIT: u8 = 32;
+
+/// Maximum length of a patch name, in characters.
+pub const MAX_PATCH_NAME_CHARS: usize = 20;
 
 /// Maximum scale when modulating envelopes. The minimum is just the inverse.
 pub const MAX_ENV_SCALE: f32 = 16.0;
@@ -36,6 +43,31 @@ pub const MAX_ENV_SCALE: f32 = 16.0;
 pub const MIN_FREQ_RATIO: f32 = 0.25;
 pub const MAX_FREQ_RATIO: f32 = 16.0;
 
+/// Clock rate range of retro noise, in Hz.
+const MIN_RETRO_CLOCK: f32 = 100.0;
+const MAX_RETRO_CLOCK: f32 = 24_000.0;
+
+/// Pitch of drum models relative to the note.
+const KICK_RATIO: f32 = 0.25;
+const SNARE_RATIO: f32 = 0.75;
+
+/// Inharmonic square wave ratios for the hat model, after the TR-808.
+const HAT_RATIOS: [f32; 6] = [0.785, 1.165, 1.414, 2.0, 2.066, 3.06];
+
+/// Maximum pitch sweep of the kick model, in octaves.
+pub const MAX_KICK_SWEEP: f32 = 4.0;
+
+/// Time constant of the kick model's pitch sweep, in seconds.
+const KICK_SWEEP_TIME: f32 = 0.03;
+
+/// Highpass cutoff range of the snare and hat models, in Hz.
+const MIN_DRUM_CUTOFF: f32 = 500.0;
+const MAX_DRUM_CUTOFF: f32 = 12_000.0;
+
+/// Common frequency ratios for FM.
+pub const RATIO_PRESETS: [f32; 12] =
+    [0.5, 1.0, 1.41, 2.0, 3.0, 3.5, 4.0, 5.0, 7.0, 9.0, 11.0, 14.0];
+
 // (Hz)
 pub const MIN_FILTER_CUTOFF: f32 = 20.0;
 pub const MAX_FILTER_CUTOFF: f32 = 22_000.0;
@@ -61,6 +93,10 @@ pub const SMOOTH_TIME: f32 = 0.01;
 /// Arbitrary constant for scaling FM depth.
 const FM_DEPTH_MULTIPLIER: f32 = 20.0;
 
+/// Envelope follower attack and release times, in seconds.
+const FOLLOWER_ATTACK: f32 = 0.005;
+const FOLLOWER_RELEASE: f32 = 0.05;
+
 /// Wraps a Shared value for serialization.
 /// Cloning creates a new Shared value.
 #[derive(Serialize, Deserialize)]
@@ -68,7 +104,7 @@ const FM_DEPTH_MULTIPLIER: f32 = 20.0;
 pub struct Parameter(pub Shared);
 
 impl Parameter {
-    fn shared_clone(&self) -> Self {
+    pub(crate) fn shared_clone(&self) -> Self {
         Self(self.0.clone())
     }
 }
@@ -111,6 +147,8 @@ pub struct Key {
     pub origin: KeyOrigin,
     pub channel: u8,
     pub key: u8,
+    /// Index of the note within a keyjazz chord, or zero.
+    pub chord_tone: u8,
 }
 
 impl Key {
@@ -119,6 +157,7 @@ impl Key {
             origin: KeyOrigin::Keyboard,
             channel: 0,
             key,
+            chord_tone: 0,
         }
     }
 
@@ -127,6 +166,15 @@ impl Key {
             origin: KeyOrigin::Midi,
             channel,
             key,
+            chord_tone: 0,
+        }
+    }
+
+    /// Returns a key for another note of a chord triggered by this key.
+    pub fn with_chord_tone(&self, chord_tone: u8) -> Self {
+        Self {
+            chord_tone,
+            ..self.clone()
         }
     }
 }
@@ -152,6 +200,96 @@ impl PlayMode {
     }
 }
 
+/// How to choose which voice to cut when the voice limit is reached.
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum StealPolicy {
+    Oldest,
+    Quietest,
+    SamePitch,
+}
+
+impl StealPolicy {
+    pub const VARIANTS: [StealPolicy; 3] = [Self::Oldest, Self::Quietest, Self::SamePitch];
+
+    /// Returns the UI string for this steal policy.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Oldest => "Oldest",
+            Self::Quietest => "Quietest",
+            Self::SamePitch => "Same pitch",
+        }
+    }
+}
+
+/// Voice allocation settings. The global settings can be overridden per
+/// patch.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct VoiceSettings {
+    /// Maximum voices per channel, including releasing voices.
+    pub limit: u8,
+    pub steal_policy: StealPolicy,
+    /// Overrides patch oversampling if set. Only used for renders.
+    #[serde(skip)]
+    pub oversampling: Option<Oversampling>,
+}
+
+impl VoiceSettings {
+    /// Returns the effective settings for `patch`.
+    fn for_patch(&self, patch: &Patch) -> Self {
+        Self {
+            limit: patch.voice_limit.unwrap_or(self.limit).max(1),
+            steal_policy: patch.steal_policy.unwrap_or(self.steal_policy),
+            oversampling: Some(self.oversampling.unwrap_or(patch.oversampling)),
+        }
+    }
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_VOICE_LIMIT,
+            steal_policy: StealPolicy::Oldest,
+            oversampling: None,
+        }
+    }
+}
+
+/// Factor that generators and filters run faster than the sample rate by,
+/// to reduce aliasing.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Oversampling {
+    #[default]
+    X1,
+    X2,
+    X4,
+}
+
+impl Oversampling {
+    pub const VARIANTS: [Self; 3] = [Self::X1, Self::X2, Self::X4];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::X1 => "1x",
+            Self::X2 => "2x",
+            Self::X4 => "4x",
+        }
+    }
+
+    /// Wraps a node to process at the oversampled rate.
+    fn apply<X>(&self, node: An<X>) -> Net
+    where
+        X: AudioNode + 'static,
+        X::Inputs: Size<f32>,
+        X::Outputs: Size<f32>,
+    {
+        match self {
+            Self::X1 => Net::wrap(Box::new(node)),
+            Self::X2 => Net::wrap(Box::new(oversample(node))),
+            Self::X4 => Net::wrap(Box::new(oversample(oversample(node)))),
+        }
+    }
+}
+
 /// Generator/LFO wave source.
 #[derive(Clone, Serialize, Deserialize)]
 pub enum Waveform {
@@ -162,17 +300,23 @@ pub enum Waveform {
     Hold,
     Noise,
     Pcm(Option<PcmData>),
+    Kick,
+    Snare,
+    Hat,
 }
 
 impl Waveform {
     /// Variants that generators can be set to.
-    pub const VARIANTS: [Waveform; 7] = [
+    pub const VARIANTS: [Waveform; 10] = [
         Self::Sawtooth,
         Self::Pulse,
         Self::Triangle,
         Self::Sine,
         Self::Hold,
         Self::Noise,
+        Self::Kick,
+        Self::Snare,
+        Self::Hat,
         Self::Pcm(None),
     ];
 
@@ -196,12 +340,20 @@ impl Waveform {
             Self::Hold => "S&H",
             Self::Noise => "Noise",
             Self::Pcm(_) => "PCM",
+            Self::Kick => "Kick",
+            Self::Snare => "Snare",
+            Self::Hat => "Hat",
         }
     }
 
     /// Returns true if this waveform makes use of the `tone` control.
     pub fn uses_tone(&self) -> bool {
-        matches!(self, Self::Pulse | Self::Noise)
+        matches!(self, Self::Pulse | Self::Noise) || self.is_drum()
+    }
+
+    /// Returns true if this is a percussion model.
+    pub fn is_drum(&self) -> bool {
+        matches!(self, Self::Kick | Self::Snare | Self::Hat)
     }
 
     /// Returns true if this waveform makes uses of frequency controls.
@@ -211,18 +363,22 @@ impl Waveform {
 
     /// Check whether this waveform is affected by the "tone" control.
     fn has_tone_control(&self) -> bool {
-        matches!(*self, Waveform::Pulse | Waveform::Noise)
+        matches!(*self, Waveform::Pulse | Waveform::Noise) || self.is_drum()
     }
 
     /// Check whether this waveform can use oversampling.
     pub fn uses_oversampling(&self) -> bool {
         !matches!(*self, Waveform::Hold | Waveform::Noise | Waveform::Pcm(_))
+            && !self.is_drum()
     }
 }
 
 /// Default pressure at song start. Equivalent to 0xA/0xF.
 pub const DEFAULT_PRESSURE: f32 = 2.0/3.0;
 
+/// Number of prebuilt voices to keep on hand per patch.
+const VOICE_CACHE_SIZE: usize = 2;
+
 /// A Synth orchestrates the playing of voices.
 pub struct Synth {
     /// Voices that are "on".
@@ -239,8 +395,26 @@ pub struct Synth {
     prev_freq: Option<f32>,
     /// Sample rate to pass when creating DSP.
     sample_rate: f32,
+    /// Incremented for each new voice, to determine voice age.
+    voice_serial: u64,
+    /// Incremented for each voice built from a seeded patch.
+    seeded_voices: u64,
+    /// Prebuilt voices, tagged with patch index.
+    voice_cache: Vec<(usize, VoiceTemplate)>,
+    /// Indices of patches that have been played on this synth.
+    cached_patches: Vec<usize>,
     /// If true, note-ons are ignored.
     pub muted: bool,
+    /// Linear gain applied to all voices.
+    pub gain: Shared,
+    /// Recent output of all voices, before panning.
+    pub scope: Arc<ScopeBuffer>,
+}
+
+/// Reference to a voice in a `Synth`.
+enum VoiceRef {
+    Active(Key),
+    Released(usize),
 }
 
 impl Synth {
@@ -253,7 +427,13 @@ impl Synth {
             pressure_memory: vec![DEFAULT_PRESSURE],
             prev_freq: None,
             sample_rate,
+            voice_serial: 0,
+            seeded_voices: 0,
+            voice_cache: Vec::new(),
+            cached_patches: Vec::new(),
             muted: false,
+            gain: shared(1.0),
+            scope: Arc::new(ScopeBuffer::new()),
         }
     }
 
@@ -281,9 +461,79 @@ impl Synth {
         }
     }
 
+    /// Remove prebuilt voices, returning them so that they can be deallocated
+    /// elsewhere. Call this when patches change.
+    pub fn clear_voice_cache(&mut self) -> VoiceCache {
+        VoiceCache(std::mem::take(&mut self.voice_cache))
+    }
+
+    /// Adds requests for the voices that played patches are short on to
+    /// `requests`, for building off the audio thread. `generation` identifies
+    /// the state of the player's tracks and patches.
+    pub fn request_voices(&self, track: usize, generation: u64,
+        voice_settings: &VoiceSettings, requests: &mut Vec<VoiceRequest>
+    ) {
+        for &patch in &self.cached_patches {
+            let n = self.voice_cache.iter().filter(|(j, _)| *j == patch).count();
+            if n < VOICE_CACHE_SIZE {
+                requests.push(VoiceRequest {
+                    track,
+                    patch,
+                    generation,
+                    sample_rate: self.sample_rate,
+                    gain: self.gain.clone(),
+                    scope: self.scope.clone(),
+                    voice_settings: *voice_settings,
+                });
+            }
+        }
+    }
+
+    /// Add a voice built from a request. Returns the voice back if the cache
+    /// is already full.
+    pub fn add_prebuilt_voice(&mut self, voice: PrebuiltVoice) -> Option<PrebuiltVoice> {
+        let n = self.voice_cache.iter().filter(|(j, _)| *j == voice.patch).count();
+        if n >= VOICE_CACHE_SIZE {
+            return Some(voice)
+        }
+        self.voice_cache.push((voice.patch, voice.template));
+        None
+    }
+
+    /// Build one voice for a played patch that's short on prebuilt voices.
+    /// Returns false if no voice needed to be built.
+    pub fn refill_voice_cache(&mut self, patches: &[Patch], pan_polarity: &Shared,
+        voice_settings: &VoiceSettings,
+    ) -> bool {
+        for &i in &self.cached_patches {
+            let n = self.voice_cache.iter().filter(|(j, _)| *j == i).count();
+            if n < VOICE_CACHE_SIZE {
+                if let Some(patch) = patches.get(i) {
+                    let seed = voice_seed(patch, &mut self.seeded_voices);
+                    let template = VoiceTemplate::new(patch, self.sample_rate,
+                        pan_polarity, &self.gain, &self.scope, seed,
+                        voice_settings.for_patch(patch).oversampling.unwrap_or_default());
+                    self.voice_cache.push((i, template));
+                    return true
+                }
+            }
+        }
+        false
+    }
+
+    /// Take a prebuilt voice for a patch, if one is available.
+    fn take_voice_template(&mut self, patch_index: usize) -> Option<VoiceTemplate> {
+        if !self.cached_patches.contains(&patch_index) {
+            self.cached_patches.push(patch_index);
+        }
+        let i = self.voice_cache.iter().position(|(j, _)| *j == patch_index)?;
+        Some(self.voice_cache.swap_remove(i).1)
+    }
+
     /// Start a note. If pressure is None, use memory.
     pub fn note_on(&mut self, key: Key, pitch: f32, pressure: Option<f32>,
-        patch: &Patch, seq: &mut Sequencer, pan_polarity: &Shared,
+        patch_index: usize, patch: &Patch, seq: &mut Sequencer, pan_polarity: &Shared,
+        voice_settings: &VoiceSettings,
     ) {
         if self.muted {
             return
@@ -344,11 +594,21 @@ impl Synth {
             } else {
                 self.pressure_memory[channel]
             };
-            let voice = Voice::new(pitch, bend, pressure, self.mod_memory[channel],
-                self.prev_freq, patch, seq, self.sample_rate, pan_polarity);
+            let voice_settings = voice_settings.for_patch(patch);
+            self.steal_voices(channel, pitch, &voice_settings, seq);
+            self.voice_serial += 1;
+            let template = match self.take_voice_template(patch_index) {
+                Some(template) => template,
+                None => {
+                    let seed = voice_seed(patch, &mut self.seeded_voices);
+                    VoiceTemplate::new(patch, self.sample_rate, pan_polarity, &self.gain,
+                        &self.scope, seed, voice_settings.oversampling.unwrap_or_default())
+                }
+            };
+            let voice = Voice::new(template, pitch, bend, pressure,
+                self.mod_memory[channel], self.prev_freq, patch, seq, self.voice_serial);
 
             self.insert_voice(key, voice);
-            self.check_truncate_voices(channel, seq);
             self.prev_freq = Some(midi_hz(pitch));
         }
     }
@@ -360,15 +620,64 @@ impl Synth {
         }
     }
 
-    /// Cut the oldest released voice if max_voices is exceeded.
-    fn check_truncate_voices(&mut self, channel: usize, seq: &mut Sequencer) {
-        if self.released_voices[channel].len() >= VOICES_PER_CHANNEL {
-            let voice = self.released_voices[channel].pop_front()
-                .expect("released voice count confirmed to be nonzero");
-            voice.cut(seq);
+    /// Cut voices in `channel` until there is room for a new voice at
+    /// `pitch`. Released voices are stolen before active ones.
+    fn steal_voices(&mut self, channel: usize, pitch: f32, settings: &VoiceSettings,
+        seq: &mut Sequencer
+    ) {
+        let limit = settings.limit as usize;
+
+        while self.channel_voice_count(channel) >= limit {
+            let Some(voice_ref) = self.choose_victim(channel, pitch, settings.steal_policy)
+                else { break };
+
+            let voice = match voice_ref {
+                VoiceRef::Active(key) => self.active_voices.remove(&key),
+                VoiceRef::Released(i) => self.released_voices[channel].remove(i),
+            };
+
+            if let Some(voice) = voice {
+                voice.cut(seq);
+            }
         }
     }
 
+    /// Returns the number of active and released voices in a channel.
+    fn channel_voice_count(&self, channel: usize) -> usize {
+        self.active_voices.keys().filter(|k| k.channel as usize == channel).count()
+            + self.released_voices[channel].len()
+    }
+
+    /// Returns the voice in `channel` that `policy` would steal first.
+    fn choose_victim(&self, channel: usize, pitch: f32, policy: StealPolicy
+    ) -> Option<VoiceRef> {
+        let released = self.released_voices[channel].iter().enumerate()
+            .map(|(i, v)| (VoiceRef::Released(i), v));
+        let active = self.active_voices.iter()
+            .filter(|(k, _)| k.channel as usize == channel)
+            .map(|(k, v)| (VoiceRef::Active(k.clone()), v));
+        let released_first = |a: &(VoiceRef, &Voice), b: &(VoiceRef, &Voice)|
+            matches!(a.0, VoiceRef::Active(_)).cmp(&matches!(b.0, VoiceRef::Active(_)));
+        let mut candidates: Vec<_> = released.chain(active).collect();
+
+        if policy == StealPolicy::SamePitch {
+            if let Some(i) = candidates.iter()
+                .position(|(_, v)| (v.base_pitch - pitch).abs() < 0.01) {
+                return Some(candidates.swap_remove(i).0)
+            }
+        }
+
+        candidates.into_iter().min_by(|a, b| {
+            released_first(a, b).then_with(|| match policy {
+                StealPolicy::Oldest | StealPolicy::SamePitch =>
+                    a.1.serial.cmp(&b.1.serial),
+                StealPolicy::Quietest => a.1.vars.pressure.value()
+                    .total_cmp(&b.1.vars.pressure.value())
+                    .then(a.1.serial.cmp(&b.1.serial)),
+            })
+        }).map(|(r, _)| r)
+    }
+
     /// Handle a note off event.
     pub fn note_off(&mut self, key: Key, seq: &mut Sequencer) {
         if let Some(voice) = self.active_voices.remove(&key) {
@@ -422,6 +731,11 @@ impl Synth {
         }
     }
 
+    /// Returns the MIDI pitch of `key` note, if it's playing.
+    pub fn key_pitch(&self, key: &Key) -> Option<f32> {
+        self.active_voices.get(key).map(|v| v.base_pitch)
+    }
+
     /// Set `key` note's MIDI pitch.
     pub fn bend_to(&mut self, key: Key, pitch: f32) {
         if let Some(voice) = self.active_voices.get_mut(&key) {
@@ -458,6 +772,26 @@ impl Synth {
         }
     }
 
+    /// Set vibrato on `channel`'s playing notes.
+    pub fn vibrato(&mut self, channel: u8, rate: f32, depth: f32) {
+        for (key, voice) in self.active_voices.iter() {
+            if key.channel == channel {
+                voice.vars.vibrato_rate.set(rate);
+                voice.vars.vibrato_depth.set(depth);
+            }
+        }
+    }
+
+    /// Set tremolo on `channel`'s playing notes.
+    pub fn tremolo(&mut self, channel: u8, rate: f32, depth: f32) {
+        for (key, voice) in self.active_voices.iter() {
+            if key.channel == channel {
+                voice.vars.tremolo_rate.set(rate);
+                voice.vars.tremolo_depth.set(depth);
+            }
+        }
+    }
+
     /// Set pressure that new notes will use.
     pub fn set_vel_memory(&mut self, channel: u8, pressure: f32) {
         self.expand_memory(channel as usize);
@@ -488,6 +822,26 @@ pub struct Patch {
     pub distortion: Parameter,
     #[serde(default)]
     pub version: u8,
+    /// Overrides the global voice limit if set.
+    #[serde(default)]
+    pub voice_limit: Option<u8>,
+    /// Overrides the global steal policy if set.
+    #[serde(default)]
+    pub steal_policy: Option<StealPolicy>,
+    #[serde(default)]
+    pub morph: Morph,
+    #[serde(default)]
+    pub key_track: KeyTrack,
+    /// Seed for random modulation and noise. If set, voices are randomized
+    /// in the same sequence every time, so renders are reproducible.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// High-pass filter on each voice's output.
+    #[serde(default)]
+    pub subsonic: Subsonic,
+    /// Oversampling of generators and filters during playback.
+    #[serde(default)]
+    pub oversampling: Oversampling,
 }
 
 impl Patch {
@@ -512,14 +866,23 @@ impl Patch {
                     source: ModSource::Envelope(0),
                     target: ModTarget::Gain,
                     depth: Parameter(shared(1.0)),
+                    ..Default::default()
                 },
                 Modulation {
                     source: ModSource::Pressure,
                     target: ModTarget::Gain,
                     depth: Parameter(shared(1.0)),
+                    ..Default::default()
                 },
             ],
             version: Self::VERSION,
+            voice_limit: None,
+            steal_policy: None,
+            morph: Default::default(),
+            key_track: Default::default(),
+            seed: None,
+            subsonic: Default::default(),
+            oversampling: Default::default(),
         }
     }
 
@@ -538,9 +901,32 @@ impl Patch {
             pan: self.pan.shared_clone(),
             mod_matrix: self.mod_matrix.iter().map(|x| x.shared_clone()).collect(),
             version: self.version,
+            voice_limit: self.voice_limit,
+            steal_policy: self.steal_policy,
+            morph: self.morph.shared_clone(),
+            key_track: self.key_track,
+            seed: self.seed,
+            subsonic: self.subsonic,
+            oversampling: self.oversampling,
         }
     }
 
+    /// Returns the PCM data of the patch's generators.
+    pub fn pcm_data(&self) -> impl Iterator<Item = &PcmData> {
+        self.oscs.iter().filter_map(|osc| match &osc.waveform {
+            Waveform::Pcm(Some(data)) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// Returns the PCM data of the patch's generators.
+    pub fn pcm_data_mut(&mut self) -> impl Iterator<Item = &mut PcmData> {
+        self.oscs.iter_mut().filter_map(|osc| match &mut osc.waveform {
+            Waveform::Pcm(Some(data)) => Some(data),
+            _ => None,
+        })
+    }
+
     /// Initialize a loaded patch.
     pub fn init(&mut self) {
         // initialize PCM generators
@@ -605,6 +991,36 @@ impl Patch {
         Ok(fs::write(path, contents)?)
     }
 
+    /// Split the patch's first sample at its transients, returning a copy of
+    /// the patch for each slice. Slices play at original speed on `REF_PITCH`.
+    pub fn slice_sample(&self) -> Result<Vec<Self>, Box<dyn Error>> {
+        let Some((index, data)) = self.oscs.iter().enumerate()
+            .find_map(|(i, osc)| match &osc.waveform {
+                Waveform::Pcm(Some(data)) => Some((i, data)),
+                _ => None,
+            }) else {
+            return Ok(Vec::new())
+        };
+
+        let mut bounds = data.onsets();
+        bounds.push(data.len());
+
+        bounds.windows(2).enumerate().map(|(n, range)| {
+            let mut patch = self.clone();
+            let suffix = format!(" {}", n + 1);
+            patch.name = patch.name.chars()
+                .take(MAX_PATCH_NAME_CHARS - suffix.len())
+                .collect();
+            patch.name.push_str(&suffix);
+
+            let osc = &mut patch.oscs[index];
+            osc.waveform = Waveform::Pcm(Some(data.slice(range[0]..range[1])?));
+            osc.freq_ratio = Parameter::from(1.0);
+            osc.fine_pitch = Parameter::from(0.0);
+            Ok(patch)
+        }).collect()
+    }
+
     /// Create a copy of the patch. Copies share access to wave data.
     pub fn duplicate(&self) -> Self {
         let mut patch = self.clone();
@@ -635,13 +1051,76 @@ impl Patch {
         net
     }
 
+    /// Returns the DSP net for a parameter's base value. If the parameter
+    /// differs between the morph snapshots, the value is interpolated between
+    /// them instead. Live edits are smoothed to avoid clicks.
+    fn param_net(&self, vars: &VoiceVars, target: ModTarget, param: &Parameter) -> Net {
+        match self.morph.endpoints(target) {
+            Some((a, b)) => Net::wrap(Box::new(var(&vars.morph) * (b - a) + a)),
+            None => Net::wrap(Box::new(var(&param.0) >> smooth())),
+        }
+    }
+
+    /// Returns the mod-able parameters of the patch.
+    pub fn parameters(&self) -> Vec<(ModTarget, &Parameter)> {
+        let mut v = vec![
+            (ModTarget::Gain, &self.gain),
+            (ModTarget::Pan, &self.pan),
+            (ModTarget::ClipGain, &self.distortion),
+            (ModTarget::FxSend, &self.fx_send),
+        ];
+
+        for (i, osc) in self.oscs.iter().enumerate() {
+            v.push((ModTarget::Level(i), &osc.level));
+            v.push((ModTarget::OscPitch(i), &osc.freq_ratio));
+            v.push((ModTarget::OscFinePitch(i), &osc.fine_pitch));
+            v.push((ModTarget::Tone(i), &osc.tone));
+            v.push((ModTarget::AmDepth(i), &osc.am_depth));
+        }
+
+        for (i, filter) in self.filters.iter().enumerate() {
+            v.push((ModTarget::FilterCutoff(i), &filter.cutoff));
+            v.push((ModTarget::FilterQ(i), &filter.resonance));
+        }
+
+        for (i, lfo) in self.lfos.iter().enumerate() {
+            v.push((ModTarget::LFORate(i), &lfo.freq));
+        }
+
+        for (i, m) in self.mod_matrix.iter().enumerate() {
+            v.push((ModTarget::ModDepth(i), &m.depth));
+        }
+
+        v
+    }
+
+    /// Store the current parameter values in a snapshot slot.
+    pub fn store_snapshot(&mut self, i: usize) {
+        self.morph.snapshots[i] = Some(self.parameters().into_iter()
+            .map(|(t, p)| (t, p.0.value()))
+            .collect());
+    }
+
+    /// Set parameters to the values in a snapshot slot.
+    pub fn recall_snapshot(&self, i: usize) {
+        if let Some(snapshot) = &self.morph.snapshots[i] {
+            for (target, param) in self.parameters() {
+                if let Some((_, x)) = snapshot.iter().find(|(t, _)| *t == target) {
+                    param.0.set(*x);
+                }
+            }
+        }
+    }
+
     /// Returns valid modulation sources for the patch.
     pub fn mod_sources(&self) -> Vec<ModSource> {
         let mut v = vec![
             ModSource::Pitch,
             ModSource::Pressure,
             ModSource::Modulation,
-            ModSource::Random
+            ModSource::Random,
+            ModSource::EnvelopeFollower,
+            ModSource::KeyTrack,
         ];
 
         v.extend((0..self.envs.len()).map(|i| ModSource::Envelope(i)));
@@ -661,6 +1140,10 @@ impl Patch {
             ModTarget::FxSend,
         ];
 
+        if self.morph.is_active() {
+            v.push(ModTarget::Morph);
+        }
+
         for (i, osc) in self.oscs.iter().enumerate() {
             v.push(ModTarget::Level(i));
             v.push(ModTarget::OscPitch(i));
@@ -668,6 +1151,9 @@ impl Patch {
             if osc.waveform.has_tone_control() {
                 v.push(ModTarget::Tone(i));
             }
+            if matches!(osc.output, OscOutput::AM(_) | OscOutput::RM(_)) {
+                v.push(ModTarget::AmDepth(i));
+            }
         }
 
         for i in 0..self.filters.len() {
@@ -728,6 +1214,8 @@ impl Patch {
                 }
             }
         }
+
+        self.morph.remove_index(i, ModTarget::osc_mut);
     }
 
     /// Remove a filter, updating other settings as needed.
@@ -746,6 +1234,8 @@ impl Patch {
                 }
             }
         }
+
+        self.morph.remove_index(i, ModTarget::filter_mut);
     }
 
     /// Remove an envelope, updating other settings as needed.
@@ -783,6 +1273,8 @@ impl Patch {
                     }
                 }
             }
+
+            self.morph.remove_index(i, ModTarget::lfo_mut);
         }
     }
 
@@ -799,6 +1291,8 @@ impl Patch {
                     }
                 }
             }
+
+            self.morph.remove_index(i, ModTarget::mod_mut);
         }
     }
 
@@ -814,19 +1308,24 @@ impl Patch {
 
         let level = {
             let modu = self.mod_net(vars, ModTarget::Level(i), &[]);
-            (var(&self.oscs[i].level.0) >> smooth()) * (modu >> shape_fn(|x| x*x))
+            self.param_net(vars, ModTarget::Level(i), &self.oscs[i].level)
+                * (modu >> shape_fn(|x| x*x))
         };
         let mut net = self.oscs[i].make_net(self, vars, i, freq_mod) * level;
 
         // need to iterate multiple times because order of operations matters
 
         for (j, osc) in self.oscs.iter().enumerate() {
-            if j > i {
-                if osc.output == OscOutput::AM(i) {
-                    net = net * (1.0 + self.make_osc(j, vars));
-                } else if osc.output == OscOutput::RM(i) {
-                    net = net * self.make_osc(j, vars);
-                }
+            if j > i && matches!(osc.output, OscOutput::AM(n) | OscOutput::RM(n) if n == i) {
+                let depth = self.param_net(vars, ModTarget::AmDepth(j), &osc.am_depth)
+                    + self.mod_net(vars, ModTarget::AmDepth(j), &[])
+                    >> shape_fn(clamp01);
+                net = if osc.output == OscOutput::AM(i) {
+                    net * (1.0 + depth * self.make_osc(j, vars))
+                } else {
+                    // crossfade between dry and ring modulated signal
+                    net * (1.0 + depth * (self.make_osc(j, vars) - 1.0))
+                };
             }
         }
 
@@ -870,6 +1369,11 @@ impl Patch {
             })
     }
 
+    /// Returns true if any modulation uses a source.
+    fn uses_source(&self, source: ModSource) -> bool {
+        self.mod_matrix.iter().any(|m| m.source == source)
+    }
+
     /// Returns the maximum amount of time that it could take for this patch
     /// to release.
     fn release_time(&self) -> f32 {
@@ -888,6 +1392,135 @@ impl Patch {
     }
 }
 
+/// Parameter snapshots and the morph between two of them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Morph {
+    /// Parameter values, keyed by the modulation target of each parameter.
+    pub snapshots: [Option<Vec<(ModTarget, f32)>>; SNAPSHOT_NAMES.len()],
+    /// Snapshot at morph position 0.
+    pub from: usize,
+    /// Snapshot at morph position 1.
+    pub to: usize,
+    pub position: Parameter,
+}
+
+pub const SNAPSHOT_NAMES: [&str; 4] = ["A", "B", "C", "D"];
+
+impl Default for Morph {
+    fn default() -> Self {
+        Self {
+            snapshots: Default::default(),
+            from: 0,
+            to: 1,
+            position: Parameter(shared(0.0)),
+        }
+    }
+}
+
+impl Morph {
+    /// Returns true if both morph endpoints have stored snapshots.
+    pub fn is_active(&self) -> bool {
+        self.from != self.to
+            && self.snapshots[self.from].is_some()
+            && self.snapshots[self.to].is_some()
+    }
+
+    /// Returns the values of a parameter at either end of the morph, if it
+    /// differs between them.
+    fn endpoints(&self, target: ModTarget) -> Option<(f32, f32)> {
+        if !self.is_active() {
+            return None
+        }
+        let value = |i: usize| self.snapshots[i].as_ref()?.iter()
+            .find(|(t, _)| *t == target)
+            .map(|(_, x)| *x);
+        let (a, b) = (value(self.from)?, value(self.to)?);
+        (a != b).then_some((a, b))
+    }
+
+    /// Update snapshot targets after an item is removed. `index` returns the
+    /// target's index for the removed item's type.
+    fn remove_index(&mut self, i: usize, index: fn(&mut ModTarget) -> Option<&mut usize>) {
+        for snapshot in self.snapshots.iter_mut().flatten() {
+            snapshot.retain_mut(|(t, _)| match index(t) {
+                Some(n) if *n == i => false,
+                Some(n) => {
+                    if *n > i {
+                        *n -= 1;
+                    }
+                    true
+                }
+                None => true,
+            });
+        }
+    }
+
+    fn shared_clone(&self) -> Self {
+        Self {
+            snapshots: self.snapshots.clone(),
+            from: self.from,
+            to: self.to,
+            position: self.position.shared_clone(),
+        }
+    }
+}
+
+/// Highpass a signal with cutoff set by tone.
+fn drum_highpass(net: Net, tone: Net) -> Net {
+    let cutoff = tone
+        >> shape_fn(|x| MIN_DRUM_CUTOFF * pow(MAX_DRUM_CUTOFF / MIN_DRUM_CUTOFF, x));
+    (net | cutoff | constant(0.5)) >> highpass()
+}
+
+/// Macro controls for drum models.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct DrumMacros {
+    /// Amplitude decay time constant, in seconds.
+    pub decay: f32,
+    /// Kick pitch sweep, snare noise mix, or hat metallic mix.
+    pub character: f32,
+}
+
+impl Default for DrumMacros {
+    fn default() -> Self {
+        Self {
+            decay: 0.2,
+            character: 0.5,
+        }
+    }
+}
+
+/// Spectrum of a noise generator.
+#[derive(Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum NoiseColor {
+    /// Tone blends from pink to white.
+    #[default]
+    Blend,
+    White,
+    Pink,
+    Brown,
+    Blue,
+    /// LFSR noise, with tone setting the clock rate.
+    Retro,
+}
+
+impl NoiseColor {
+    pub const VARIANTS: [Self; 6] =
+        [Self::Blend, Self::White, Self::Pink, Self::Brown, Self::Blue, Self::Retro];
+
+    /// Returns the UI string for this color.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Blend => "Blend",
+            Self::White => "White",
+            Self::Pink => "Pink",
+            Self::Brown => "Brown",
+            Self::Blue => "Blue",
+            Self::Retro => "Retro",
+        }
+    }
+}
+
 /// Tone generator.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Oscillator {
@@ -899,6 +1532,17 @@ pub struct Oscillator {
     pub output: OscOutput,
     #[serde(default)]
     pub oversample: bool,
+    /// If true, the generator plays at `REF_FREQ * freq_ratio` regardless of
+    /// note pitch.
+    #[serde(default)]
+    pub fixed_freq: bool,
+    /// Amount of AM or RM applied to the target generator.
+    #[serde(default)]
+    pub am_depth: Parameter,
+    #[serde(default)]
+    pub noise_color: NoiseColor,
+    #[serde(default)]
+    pub drum: DrumMacros,
 }
 
 impl Default for Oscillator {
@@ -911,65 +1555,119 @@ impl Default for Oscillator {
             waveform: Waveform::Sine,
             output: OscOutput::Mix(0),
             oversample: false,
+            fixed_freq: false,
+            am_depth: Parameter(shared(1.0)),
+            noise_color: NoiseColor::Blend,
+            drum: Default::default(),
         }
     }
 }
 
 impl Oscillator {
+    /// Returns true if the generator makes use of the `tone` control.
+    pub fn uses_tone(&self) -> bool {
+        match self.waveform {
+            Waveform::Noise =>
+                matches!(self.noise_color, NoiseColor::Blend | NoiseColor::Retro),
+            _ => self.waveform.uses_tone(),
+        }
+    }
+
     /// Make a generator DSP net.
     fn make_net(&self, settings: &Patch, vars: &VoiceVars, index: usize, freq_mod: Net
     ) -> Net {
-        let var_freq = Net::wrap(if settings.glide_time == 0.0 {
-            Box::new(var(&vars.freq))
+        let var_freq = if self.fixed_freq {
+            Net::wrap(Box::new(constant(REF_FREQ)))
         } else {
-            let prev_freq = vars.prev_freq.unwrap_or(vars.freq.value());
-            let env = envelope2(move |t, x| if t == 0.0 { prev_freq } else { x });
-            Box::new(var(&vars.freq) >> env >> follow(settings.glide_time * 0.5))
-        });
+            let vibrato = (var(&vars.vibrato_rate) >> sine())
+                * (var(&vars.vibrato_depth) >> smooth())
+                >> pow_shape(SEMITONE_RATIO);
+            Net::wrap(if settings.glide_time == 0.0 {
+                Box::new(var(&vars.freq))
+            } else {
+                let glide_from = vars.glide_from.clone();
+                let env = envelope2(move |t, x| if t == 0.0 { glide_from.value() } else { x });
+                Box::new(var(&vars.freq) >> env >> follow(settings.glide_time * 0.5))
+            }) * vibrato
+        };
         let base_freq = var_freq
-            * var(&self.freq_ratio.0)
+            * settings.param_net(vars, ModTarget::OscPitch(index), &self.freq_ratio)
             * (settings.mod_net(vars, ModTarget::OscPitch(index), &[])
                 + settings.mod_net(vars, ModTarget::Pitch, &[])
                 >> pow_shape(MAX_PITCH_MOD))
             * ((settings.mod_net(vars, ModTarget::OscFinePitch(index), &[])
                 + settings.mod_net(vars, ModTarget::FinePitch, &[]))
-                * 0.5 + var(&self.fine_pitch.0) >> pow_shape(SEMITONE_RATIO))
+                * 0.5
+                + settings.param_net(vars, ModTarget::OscFinePitch(index), &self.fine_pitch)
+                >> pow_shape(SEMITONE_RATIO))
             * (1.0 + freq_mod * FM_DEPTH_MULTIPLIER);
-        let tone = var(&self.tone.0)
+        let tone = settings.param_net(vars, ModTarget::Tone(index), &self.tone)
             + settings.mod_net(vars, ModTarget::Tone(index), &[])
             >> shape_fn(clamp01);
+        // the generator checkbox predates patch oversampling
+        let quality = if self.oversample {
+            vars.oversampling.max(Oversampling::X2)
+        } else {
+            vars.oversampling
+        };
 
         match &self.waveform {
-            Waveform::Sawtooth => if self.oversample {
-                base_freq >> oversample(saw().phase(0.0))
-            } else {
-                base_freq >> saw().phase(0.0)
-            },
-            Waveform::Pulse => if self.oversample {
-                (base_freq | tone) >> oversample(pulse().phase(0.0))
-            } else {
-                (base_freq | tone) >> pulse().phase(0.0)
-            },
-            Waveform::Triangle => if self.oversample {
-                base_freq >> oversample(triangle().phase(0.0))
-            } else {
-                base_freq >> triangle().phase(0.0)
-            },
-            Waveform::Sine => if self.oversample {
-                base_freq >> oversample(sine().phase(0.0))
-            } else {
-                base_freq >> sine().phase(0.0)
+            Waveform::Sawtooth => base_freq >> quality.apply(saw().phase(0.0)),
+            Waveform::Pulse => (base_freq | tone) >> quality.apply(pulse().phase(0.0)),
+            Waveform::Triangle => base_freq >> quality.apply(triangle().phase(0.0)),
+            Waveform::Sine => base_freq >> quality.apply(sine().phase(0.0)),
+            Waveform::Hold => (noise().seed(vars.noise_seed(index)) | base_freq) >> hold(0.0),
+            Waveform::Noise => match self.noise_color {
+                NoiseColor::Blend => (noise().seed(vars.noise_seed(index)) | tone)
+                    >> (pinkpass() * (1.0 - pass()) & pass() * pass()),
+                NoiseColor::White => Net::wrap(Box::new(noise().seed(vars.noise_seed(index)))),
+                NoiseColor::Pink => Net::wrap(Box::new(noise().seed(vars.noise_seed(index)) >> pinkpass())),
+                NoiseColor::Brown => Net::wrap(Box::new(brown().seed(vars.noise_seed(index)))),
+                NoiseColor::Blue => Net::wrap(Box::new(
+                    noise().seed(vars.noise_seed(index)) >> pinkpass() >> differentiate())),
+                NoiseColor::Retro => tone
+                    >> shape_fn(|x| MIN_RETRO_CLOCK
+                        * pow(MAX_RETRO_CLOCK / MIN_RETRO_CLOCK, x))
+                    >> lfsr_noise() * 0.5,
             },
-            Waveform::Hold => (noise().seed(random()) | base_freq) >> hold(0.0),
-            Waveform::Noise => (noise().seed(random()) | tone)
-                >> (pinkpass() * (1.0 - pass()) & pass() * pass()),
             Waveform::Pcm(data) => if let Some(data) = data {
                 let f = data.wave.sample_rate() as f32 / vars.sample_rate / REF_FREQ;
-                base_freq * f >>
-                    resample(wavech(&data.wave, 0, data.loop_point))
+                if data.granular && data.can_granulate() {
+                    base_freq * f >> unit::<U1, U1>(data.make_granular_unit())
+                } else {
+                    base_freq * f >> resample(unit::<U0, U1>(data.make_unit()))
+                }
             } else {
                 Net::new(0, 1)
             },
+            Waveform::Kick => {
+                let DrumMacros { decay, character } = self.drum;
+                let octaves = character * MAX_KICK_SWEEP;
+                let sweep = envelope(move |t| pow(2.0, octaves * exp(-t / KICK_SWEEP_TIME)));
+                let amp = envelope(move |t| exp(-t / decay));
+                // tone drives the kick into saturation
+                ((base_freq * KICK_RATIO * sweep >> sine()) * amp | tone)
+                    >> map(|i: &Frame<f32, U2>| tanh(i[0] * (1.0 + i[1] * 4.0)))
+            },
+            Waveform::Snare => {
+                let DrumMacros { decay, character } = self.drum;
+                let body = (base_freq * SNARE_RATIO >> sine())
+                    * envelope(move |t| exp(-t / (decay * 0.5)));
+                let noise = drum_highpass(Net::wrap(Box::new(noise().seed(vars.noise_seed(index)))), tone)
+                    * envelope(move |t| exp(-t / decay));
+                body * (1.0 - character) + noise * character
+            },
+            Waveform::Hat => {
+                let DrumMacros { decay, character } = self.drum;
+                let mut bank = Net::wrap(Box::new(pass() * HAT_RATIOS[0] >> square()));
+                for r in &HAT_RATIOS[1..] {
+                    bank = bank & Net::wrap(Box::new(pass() * *r >> square()));
+                }
+                let metal = base_freq >> bank * (1.0 / HAT_RATIOS.len() as f32);
+                let mix = metal * character
+                    + Net::wrap(Box::new(noise().seed(vars.noise_seed(index)))) * (1.0 - character);
+                drum_highpass(mix, tone) * envelope(move |t| exp(-t / decay))
+            },
         }
     }
     
@@ -982,6 +1680,10 @@ impl Oscillator {
             waveform: self.waveform.clone(),
             output: self.output,
             oversample: self.oversample,
+            fixed_freq: self.fixed_freq,
+            am_depth: self.am_depth.shared_clone(),
+            noise_color: self.noise_color,
+            drum: self.drum,
         }
     }
 }
@@ -1057,29 +1759,30 @@ impl Filter {
     /// Filter DSP net.
     fn filter(&self, settings: &Patch, vars: &VoiceVars, index: usize, net: Net) -> Net {
         let cutoff = {
-            let kt_freq = Net::wrap(match self.key_tracking {
-                KeyTracking::None => Box::new(var(&self.cutoff.0)),
-                KeyTracking::Partial => Box::new(var(&self.cutoff.0)
-                    * var_fn(&vars.freq, |x| pow(x/REF_FREQ, 0.5))),
-                KeyTracking::Full => Box::new(var(&self.cutoff.0)
-                    * var_fn(&vars.freq, |x| x/REF_FREQ)),
-            });
+            let cutoff = settings.param_net(
+                vars, ModTarget::FilterCutoff(index), &self.cutoff);
+            let kt_freq = match self.key_tracking {
+                KeyTracking::None => cutoff,
+                KeyTracking::Partial =>
+                    cutoff * var_fn(&vars.freq, |x| pow(x/REF_FREQ, 0.5)),
+                KeyTracking::Full => cutoff * var_fn(&vars.freq, |x| x/REF_FREQ),
+            };
             let modu = settings.mod_net(vars, ModTarget::FilterCutoff(index), &[])
                 >> pow_shape(FILTER_CUTOFF_MOD_BASE);
             kt_freq * modu
                 >> shape_fn(|x| clamp(MIN_FILTER_CUTOFF, MAX_FILTER_CUTOFF, x))
         };
-        let reso = var(&self.resonance.0)
+        let reso = settings.param_net(vars, ModTarget::FilterQ(index), &self.resonance)
             + settings.mod_net(vars, ModTarget::FilterQ(index), &[])
             >> shape_fn(clamp01);
-        let filter = Net::wrap(match self.filter_type {
+        let filter: Box<dyn AudioUnit> = match self.filter_type {
             FilterType::Ladder => Box::new(moog()),
             FilterType::Lowpass => Box::new(lowpass()),
             FilterType::Highpass => Box::new(highpass()),
             FilterType::Bandpass => Box::new(bandpass()),
             FilterType::Notch => Box::new(notch()),
-        });
-        (net | cutoff | reso) >> filter
+        };
+        (net | cutoff | reso) >> vars.oversampling.apply(unit::<U3, U1>(filter))
     }
     
     fn shared_clone(&self) -> Self {
@@ -1171,6 +1874,16 @@ pub struct Modulation {
     pub source: ModSource,
     pub target: ModTarget,
     pub depth: Parameter,
+    #[serde(default)]
+    pub polarity: ModPolarity,
+    #[serde(default)]
+    pub invert: bool,
+    /// Number of steps to quantize the source to. Zero means no quantization.
+    #[serde(default)]
+    pub steps: u8,
+    /// Lag time applied to the source, in seconds.
+    #[serde(default)]
+    pub slew: f32,
 }
 
 impl Default for Modulation {
@@ -1179,6 +1892,10 @@ impl Default for Modulation {
             source: ModSource::Modulation,
             target: ModTarget::Gain,
             depth: Parameter(shared(0.0)),
+            polarity: ModPolarity::Source,
+            invert: false,
+            steps: 0,
+            slew: 0.0,
         }
     }
 }
@@ -1206,14 +1923,24 @@ impl Modulation {
                 Some(lfo) => lfo.make_net(settings, vars, i, &path),
                 None => Net::new(0, 1),
             }
+            ModSource::EnvelopeFollower =>
+                Net::wrap(Box::new(var(&vars.follower) >> shape_fn(clamp01))),
+            ModSource::KeyTrack => {
+                let KeyTrack { center, slope } = settings.key_track;
+                let center = midi_hz(center);
+                Net::wrap(Box::new(
+                    var_fn(&vars.freq, move |f| clamp11((f / center).log2() * slope))))
+            }
         };
-        let depth = var(&self.depth.0) >> smooth()
+        let net = self.process(net);
+        let depth = settings.param_net(vars, ModTarget::ModDepth(index), &self.depth)
+            >> smooth()
             + settings.mod_net(vars, ModTarget::ModDepth(index), &path) * 2.0;
 
         if self.target.is_additive() {
             // zero depth = +0 for additive targets
             net * depth
-        } else if self.source.is_bipolar() {
+        } else if self.is_bipolar() {
             // a bipolar source oscillates in [-1, 1] -- map that onto [0, 1]
             1.0 - (depth * (1.0 - 0.5 * (net + 1.0)) >> shape_fn(abs))
         } else if self.depth.0.value() >= 0.0 {
@@ -1223,11 +1950,77 @@ impl Modulation {
         }
     }
     
+    /// Returns true if the processed source oscillates in -1..1.
+    fn is_bipolar(&self) -> bool {
+        match self.polarity {
+            ModPolarity::Source => self.source.is_bipolar(),
+            ModPolarity::Unipolar => false,
+            ModPolarity::Bipolar => true,
+        }
+    }
+
+    /// Apply polarity, inversion, quantization, and slew to a source net.
+    fn process(&self, net: Net) -> Net {
+        if self.polarity == ModPolarity::Source && !self.invert
+            && self.steps == 0 && self.slew == 0.0 {
+            return net
+        }
+
+        let mut net = net;
+
+        // work in 0..1 and convert back at the end
+        if self.source.is_bipolar() {
+            net = net * 0.5 + 0.5;
+        }
+        if self.invert {
+            net = 1.0 - net;
+        }
+        if self.steps > 0 {
+            let steps = self.steps as f32;
+            net = net >> shape_fn(move |x| (x * steps).round() / steps);
+        }
+        if self.slew > 0.0 {
+            net = net >> follow(self.slew);
+        }
+        if self.is_bipolar() {
+            net = net * 2.0 - 1.0;
+        }
+
+        net
+    }
+
     fn shared_clone(&self) -> Self {
         Self {
             source: self.source,
             target: self.target,
             depth: self.depth.shared_clone(),
+            polarity: self.polarity,
+            invert: self.invert,
+            steps: self.steps,
+            slew: self.slew,
+        }
+    }
+}
+
+/// Output range of a processed mod source.
+#[derive(Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum ModPolarity {
+    /// Use the source's own range.
+    #[default]
+    Source,
+    Unipolar,
+    Bipolar,
+}
+
+impl ModPolarity {
+    pub const VARIANTS: [Self; 3] = [Self::Source, Self::Unipolar, Self::Bipolar];
+
+    /// Returns the UI string for this polarity.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Source => "Source",
+            Self::Unipolar => "0..1",
+            Self::Bipolar => "-1..1",
         }
     }
 }
@@ -1240,6 +2033,9 @@ pub enum ModSource {
     Random,
     Envelope(usize),
     LFO(usize),
+    /// Amplitude of the voice's output.
+    EnvelopeFollower,
+    KeyTrack,
 }
 
 impl Display for ModSource {
@@ -1251,6 +2047,8 @@ impl Display for ModSource {
             Self::Random => "Random",
             Self::Envelope(i) => &format!("Envelope {}", i + 1),
             Self::LFO(i) => &format!("LFO {}", i + 1),
+            Self::EnvelopeFollower => "Env follower",
+            Self::KeyTrack => "Key track",
         };
         f.write_str(s)
     }
@@ -1259,7 +2057,25 @@ impl Display for ModSource {
 impl ModSource {
     /// Returns true if the source oscillates in -1..1 rather than 0..1.
     fn is_bipolar(&self) -> bool {
-        matches!(*self, ModSource::LFO(_))
+        matches!(*self, ModSource::LFO(_) | ModSource::KeyTrack)
+    }
+}
+
+/// Settings for the key track mod source.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct KeyTrack {
+    /// MIDI pitch where the source outputs zero.
+    pub center: f32,
+    /// Source output per octave from the center.
+    pub slope: f32,
+}
+
+impl Default for KeyTrack {
+    fn default() -> Self {
+        Self {
+            center: REF_PITCH as f32,
+            slope: 0.25,
+        }
     }
 }
 
@@ -1281,6 +2097,8 @@ pub enum ModTarget {
     /// Distortion. Inaccurate name for legacy reasons.
     ClipGain,
     FxSend,
+    Morph,
+    AmDepth(usize),
 }
 
 impl ModTarget {
@@ -1292,8 +2110,8 @@ impl ModTarget {
     /// Returns the generator index, if any.
     fn osc(&self) -> Option<usize> {
         match *self {
-            Self::Level(n) | Self::OscPitch(n) |
-                Self::OscFinePitch(n) | Self::Tone(n) => Some(n),
+            Self::Level(n) | Self::OscPitch(n) | Self::OscFinePitch(n)
+                | Self::Tone(n) | Self::AmDepth(n) => Some(n),
             _ => None,
         }
     }
@@ -1301,8 +2119,8 @@ impl ModTarget {
     /// Returns the generator index, if any.
     fn osc_mut(&mut self) -> Option<&mut usize> {
         match self {
-            Self::Level(n) | Self::OscPitch(n) |
-                Self::OscFinePitch(n) | Self::Tone(n) => Some(n),
+            Self::Level(n) | Self::OscPitch(n) | Self::OscFinePitch(n)
+                | Self::Tone(n) | Self::AmDepth(n) => Some(n),
             _ => None,
         }
     }
@@ -1323,6 +2141,22 @@ impl ModTarget {
         }
     }
 
+    /// Returns the LFO index, if any.
+    fn lfo_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Self::LFORate(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Returns the mod matrix index, if any.
+    fn mod_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Self::ModDepth(i) => Some(i),
+            _ => None,
+        }
+    }
+
     /// Returns true if the attack level should be sqrt'ed. Since gain values
     /// are squared, this compensates and gives a linear attack.
     fn uses_sqrt_attack(&self) -> bool {
@@ -1348,6 +2182,8 @@ impl Display for ModTarget {
             Self::ModDepth(n) => &format!("Mod {} depth", n + 1),
             Self::ClipGain => "Distortion",
             Self::FxSend => "FX send",
+            Self::Morph => "Morph",
+            Self::AmDepth(n) => &format!("Gen {} AM/RM depth", n + 1),
         };
         f.write_str(s)
     }
@@ -1360,30 +2196,117 @@ struct Voice {
     /// Estimated length of release before deallocation.
     release_time: f32,
     event_id: EventId,
+    /// Higher values are newer voices.
+    serial: u64,
 }
 
-impl Voice {
-    /// Create and play a new voice.
-    fn new(pitch: f32, bend: f32, pressure: f32, modulation: f32, prev_freq: Option<f32>,
-        settings: &Patch, seq: &mut Sequencer, rate: f32, pan_polarity: &Shared,
+/// A voice DSP graph built ahead of time, so that starting a note doesn't
+/// require building one on the audio thread.
+struct VoiceTemplate {
+    vars: VoiceVars,
+    net: Net,
+}
+
+/// Prebuilt voices removed from a synth.
+pub struct VoiceCache(Vec<(usize, VoiceTemplate)>);
+
+impl VoiceCache {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Request from the audio thread for a voice to be built elsewhere.
+#[derive(Clone)]
+pub struct VoiceRequest {
+    pub track: usize,
+    patch: usize,
+    generation: u64,
+    sample_rate: f32,
+    gain: Shared,
+    scope: Arc<ScopeBuffer>,
+    voice_settings: VoiceSettings,
+}
+
+impl VoiceRequest {
+    /// Build the requested voice. Returns None if the patch doesn't exist.
+    pub fn build(&self, patches: &[Patch], pan_polarity: &Shared, seeded_voices: &mut u64
+    ) -> Option<PrebuiltVoice> {
+        let patch = patches.get(self.patch)?;
+        let seed = voice_seed(patch, seeded_voices);
+        let oversampling = self.voice_settings.for_patch(patch).oversampling;
+        Some(PrebuiltVoice {
+            track: self.track,
+            patch: self.patch,
+            generation: self.generation,
+            template: VoiceTemplate::new(patch, self.sample_rate, pan_polarity,
+                &self.gain, &self.scope, seed, oversampling.unwrap_or_default()),
+        })
+    }
+}
+
+/// Voice built from a `VoiceRequest`, ready to send to the audio thread.
+pub struct PrebuiltVoice {
+    pub track: usize,
+    patch: usize,
+    pub generation: u64,
+    template: VoiceTemplate,
+}
+
+/// Returns the seed for a new voice of a patch. Seeded patches produce the
+/// same sequence of seeds for each synth.
+fn voice_seed(patch: &Patch, seeded_voices: &mut u64) -> u64 {
+    match patch.seed {
+        Some(seed) => {
+            *seeded_voices += 1;
+            mix_seed(seed, *seeded_voices)
+        }
+        None => random(),
+    }
+}
+
+/// Combines a seed and a salt into a new, well-distributed seed.
+fn mix_seed(seed: u64, salt: u64) -> u64 {
+    // splitmix64 finalizer
+    let mut x = seed ^ salt.wrapping_mul(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+impl VoiceTemplate {
+    fn new(settings: &Patch, rate: f32, pan_polarity: &Shared, track_gain: &Shared,
+        scope: &Arc<ScopeBuffer>, seed: u64, oversampling: Oversampling,
     ) -> Self {
-        let gate = shared(1.0);
+        let mut rng = StdRng::seed_from_u64(seed);
         let vars = VoiceVars {
-            freq: shared(midi_hz(pitch + bend)),
-            gate,
-            pressure: shared(pressure),
-            modulation: shared(modulation),
-            random_values: settings.mod_matrix.iter().map(|_| random()).collect(),
-            lfo_phases: settings.lfos.iter().map(|_| random()).collect(),
-            prev_freq,
+            freq: shared(REF_FREQ),
+            gate: shared(1.0),
+            pressure: shared(DEFAULT_PRESSURE),
+            modulation: shared(0.0),
+            random_values: settings.mod_matrix.iter().map(|_| rng.gen()).collect(),
+            lfo_phases: settings.lfos.iter().map(|_| rng.gen()).collect(),
+            seed,
+            glide_from: shared(REF_FREQ),
+            morph: shared(settings.morph.position.0.value()),
+            follower: shared(0.0),
+            vibrato_rate: shared(0.0),
+            vibrato_depth: shared(0.0),
+            tremolo_rate: shared(0.0),
+            tremolo_depth: shared(0.0),
             sample_rate: rate,
+            oversampling,
         };
-        let gain = (var(&settings.gain.0) >> smooth())
-            * (settings.mod_net(&vars, ModTarget::Gain, &[]) >> shape_fn(|x| x*x));
+        let tremolo = 1.0 - (var(&vars.tremolo_depth) >> smooth())
+            * ((var(&vars.tremolo_rate) >> sine()) * 0.5 + 0.5);
+        let gain = settings.param_net(&vars, ModTarget::Gain, &settings.gain)
+            * (var(track_gain) >> smooth())
+            * (settings.mod_net(&vars, ModTarget::Gain, &[]) >> shape_fn(|x| x*x))
+            * tremolo;
 
         // use dry signal when distortion is zero
         let clip = (
-            var(&settings.distortion.0)
+            settings.param_net(&vars, ModTarget::ClipGain, &settings.distortion)
                 + settings.mod_net(&vars, ModTarget::ClipGain, &[])
             | pass()
         ) >> map(|i: &Frame<f32, U2>| if i[0] == 0.0 {
@@ -1392,11 +2315,22 @@ impl Voice {
             clamp11(i[1] * (1.0 - clamp01(i[0])).recip())
         });
 
-        let signal = (settings.filter(&vars, settings.make_osc(0, &vars)) >> clip) * gain;
-        let pan = (var(&settings.pan.0) >> smooth()
+        let mut signal = (settings.filter(&vars, settings.make_osc(0, &vars)) >> clip) * gain;
+        if let Some(subsonic) = settings.subsonic.make_mono_node() {
+            signal = signal >> subsonic;
+        }
+        if settings.uses_source(ModSource::EnvelopeFollower) {
+            // follower output feeds back into modulations, so they hear it
+            // one processing block late
+            signal = signal >> (pass() ^ (shape_fn(abs)
+                >> afollow(FOLLOWER_ATTACK, FOLLOWER_RELEASE)
+                >> monitor(&vars.follower, Meter::Sample) >> sink()));
+        }
+        signal = signal >> (pass() ^ scope_tap(scope));
+        let pan = (settings.param_net(&vars, ModTarget::Pan, &settings.pan)
             + settings.mod_net(&vars, ModTarget::Pan, &[]) * 2.0)
             * var(pan_polarity) >> shape_fn(clamp11);
-        let fx_send = (var(&settings.fx_send.0)
+        let fx_send = (settings.param_net(&vars, ModTarget::FxSend, &settings.fx_send)
             + settings.mod_net(&vars, ModTarget::FxSend, &[]))
             >> shape_fn(clamp01);
 
@@ -1405,12 +2339,42 @@ impl Voice {
             >> (multipass::<U2>()
                 | multipass::<U2>() * (fx_send >> split::<U2>()));
 
+        // write the morph position to a shared value for parameters to read.
+        // the morph goes first in the stack so that parameters read the
+        // position of the current block, not the previous one
+        let net = if settings.morph.is_active() {
+            let morph = (var(&settings.morph.position.0) >> smooth())
+                + settings.mod_net(&vars, ModTarget::Morph, &[])
+                >> shape_fn(clamp01) >> monitor(&vars.morph, Meter::Sample) >> sink();
+            morph | net
+        } else {
+            net
+        };
+
+        Self { vars, net }
+    }
+}
+
+impl Voice {
+    /// Play a new voice from a template.
+    fn new(template: VoiceTemplate, pitch: f32, bend: f32, pressure: f32,
+        modulation: f32, prev_freq: Option<f32>, settings: &Patch,
+        seq: &mut Sequencer, serial: u64,
+    ) -> Self {
+        let VoiceTemplate { vars, net } = template;
+        let freq = midi_hz(pitch + bend);
+        vars.freq.set(freq);
+        vars.glide_from.set(prev_freq.unwrap_or(freq));
+        vars.pressure.set(pressure);
+        vars.modulation.set(modulation);
+
         Self {
             vars,
             base_pitch: pitch,
             release_time: settings.release_time(),
             event_id: seq.push_relative(
                 0.0, f64::INFINITY, Fade::Smooth, 0.0, 0.0, Box::new(net)),
+            serial,
         }
     }
 
@@ -1435,7 +2399,30 @@ struct VoiceVars {
     random_values: Vec<f32>,
     /// Used to synchronize multiple DSP instances of the same logical LFO.
     lfo_phases: Vec<f32>,
+    /// Seed that the voice's noise generators are derived from.
+    seed: u64,
     /// Initial frequency to glide from.
-    prev_freq: Option<f32>,
+    glide_from: Shared,
+    /// Morph position, written by the voice net.
+    morph: Shared,
+    /// Output amplitude, written by the voice net.
+    follower: Shared,
+    /// Pattern vibrato rate in Hz.
+    vibrato_rate: Shared,
+    /// Pattern vibrato depth in semitones.
+    vibrato_depth: Shared,
+    /// Pattern tremolo rate in Hz.
+    tremolo_rate: Shared,
+    /// Pattern tremolo depth, from 0 to 1.
+    tremolo_depth: Shared,
     sample_rate: f32,
-}
\ No newline at end of file
+    /// Oversampling of generators and filters.
+    oversampling: Oversampling,
+}
+
+impl VoiceVars {
+    /// Returns the noise seed for a generator.
+    fn noise_seed(&self, generator: usize) -> u64 {
+        mix_seed(self.seed, generator as u64 + 1)
+    }
+}
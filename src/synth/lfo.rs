@@ -42,7 +42,7 @@ impl LFO {
         let f = {
             let f_mod = settings.mod_net(vars, ModTarget::LFORate(index), path)
                 >> pow_shape(MAX_LFO_RATE/MIN_LFO_RATE);
-            let f = var(&self.freq.0) * f_mod
+            let f = settings.param_net(vars, ModTarget::LFORate(index), &self.freq) * f_mod
                 >> shape_fn(|x| clamp(MIN_LFO_RATE, MAX_LFO_RATE, x));
             if self.audio_rate {
                 f * AR_RATE_MULTIPLIER
@@ -85,10 +85,12 @@ impl LFO {
             Waveform::Noise => Net::wrap(Box::new(
                 brown().seed((p * u64::MAX as f32) as u64) * d)),
             Waveform::Pcm(data) => Net::wrap(if let Some(data) = data {
-                Box::new(wavech(&data.wave, 0, data.loop_point))
+                data.make_unit()
             } else {
                 Box::new(zero())
             }),
+            // not available for LFOs
+            Waveform::Kick | Waveform::Snare | Waveform::Hat => Net::new(0, 1),
         }
     }
     
@@ -1,17 +1,22 @@
 //! PCM loading and manipulation.
 
-use std::{error::Error, fs, ops::RangeInclusive, path::{Path, PathBuf}, sync::Arc};
+pub(crate) mod stream;
 
-use fundsp::{math::db_amp, wave::Wave};
+use std::{borrow::Cow, error::Error, fs, ops::{Range, RangeInclusive}, path::{Path, PathBuf}, sync::Arc};
+
+use fundsp::{hacker32::{wavech, wavech_at, AudioUnit, An}, math::db_amp, wave::Wave};
 use memmem::{Searcher, TwoWaySearcher};
 use ordered_float::OrderedFloat;
 use pitch_detector::pitch::{HannedFftDetector, PitchDetector};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use stream::{StreamSource, StreamWave};
+
+use crate::dsp;
 
 /// Stores data for PCM waveforms.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PcmData {
-    data: Vec<u8>, // for serialization
+    data: RawData, // for serialization
     #[serde(skip)]
     #[serde(default = "empty_wave")]
     pub wave: Arc<Wave>,
@@ -22,6 +27,100 @@ pub struct PcmData {
     pub midi_pitch: Option<f32>,
     #[serde(default)]
     pub filename: String,
+    /// If true, play the sample from disk instead of memory.
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(skip)]
+    stream_source: Option<Arc<StreamSource>>,
+    /// If true, the sample is saved as a path to its file instead of being
+    /// embedded in the module.
+    #[serde(default)]
+    pub referenced: bool,
+    /// Path to a referenced sample, relative to the module file if possible.
+    #[serde(default)]
+    reference_path: Option<String>,
+    /// If true, play the sample with granular synthesis, so that playback
+    /// speed is independent of pitch.
+    #[serde(default)]
+    pub granular: bool,
+    /// Playback speed in granular mode, relative to the original.
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Grain length in granular mode, in seconds.
+    #[serde(default = "default_grain_size")]
+    pub grain_size: f32,
+    #[serde(default)]
+    pub loop_mode: LoopMode,
+    /// Length of the crossfade into the loop point, in seconds.
+    #[serde(default)]
+    pub crossfade: f32,
+    /// Wave with loop processing applied, if any is needed.
+    #[serde(skip)]
+    loop_wave: Option<Arc<Wave>>,
+}
+
+/// The file contents of a sample. Streamed samples keep theirs on disk.
+#[derive(Clone)]
+pub enum RawData {
+    Memory(Vec<u8>),
+    Disk(Arc<StreamSource>),
+}
+
+impl Default for RawData {
+    fn default() -> Self {
+        Self::Memory(Vec::new())
+    }
+}
+
+impl RawData {
+    /// Returns the file contents, reading them from disk if needed.
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, Box<dyn Error>> {
+        match self {
+            Self::Memory(data) => Ok(Cow::Borrowed(data)),
+            Self::Disk(source) => Ok(Cow::Owned(source.raw_data()?)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Memory(data) => data.is_empty(),
+            Self::Disk(_) => false,
+        }
+    }
+}
+
+// serialized the same as a plain byte vector
+impl Serialize for RawData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.bytes().map_err(serde::ser::Error::custom)?.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::Memory)
+    }
+}
+
+/// How a sample loop repeats.
+#[derive(Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum LoopMode {
+    #[default]
+    Forward,
+    /// Alternate between playing the loop forward and backward.
+    PingPong,
+}
+
+impl LoopMode {
+    pub const VARIANTS: [Self; 2] = [Self::Forward, Self::PingPong];
+
+    /// Returns the UI string for this mode.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Forward => "Forward",
+            Self::PingPong => "Ping-pong",
+        }
+    }
 }
 
 /// Default for serde.
@@ -29,11 +128,20 @@ fn empty_wave() -> Arc<Wave> {
     Arc::new(Wave::new(1, 44100.0))
 }
 
+/// Default for serde.
+fn default_speed() -> f32 { 1.0 }
+
+/// Default for serde.
+fn default_grain_size() -> f32 { 0.05 }
+
 impl PcmData {
     /// Supported file extensions for loading.
     pub const FILE_EXTENSIONS: [&str; 11] =
         ["aac", "aiff", "caf", "flac", "m4a", "mkv", "mp3", "mp4", "ogg", "wav", "webm"];
 
+    /// Samples longer than this are streamed from disk by default.
+    pub const STREAM_THRESHOLD_SECONDS: f64 = 30.0;
+
     /// Check whether a path has a loadable file extension.
     fn can_load_path(path: &Path) -> bool {
         path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
@@ -66,14 +174,27 @@ impl PcmData {
             .unwrap_or_default()
             .to_string();
 
-        Ok(Self {
+        let mut pcm = Self {
+            stream: wave.duration() > Self::STREAM_THRESHOLD_SECONDS,
             wave: Arc::new(wave),
-            data,
+            data: RawData::Memory(data),
             loop_point,
             path: Some(path.as_ref().to_path_buf()),
             midi_pitch,
             filename,
-        })
+            stream_source: None,
+            referenced: false,
+            reference_path: None,
+            granular: false,
+            speed: default_speed(),
+            grain_size: default_grain_size(),
+            loop_mode: Default::default(),
+            crossfade: 0.0,
+            loop_wave: None,
+        };
+        pcm.init_stream()?;
+        pcm.update_loop();
+        Ok(pcm)
     }
 
     /// Loads the audio file with position offset by `offset` in the file's
@@ -102,13 +223,253 @@ impl PcmData {
 
     /// Initialize deserialized PcmData before use.
     pub fn init(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut wave = Wave::load_slice(self.data.clone())?;
+        let data = self.data.bytes()?.into_owned();
+        let mut wave = Wave::load_slice(data.clone())?;
         // the stored data is the raw file, so we have to normalize on init
         wave.normalize();
         self.wave = Arc::new(wave);
+        self.data = RawData::Memory(data);
+        self.stream_source = None;
+        self.init_stream()?;
+        self.update_loop();
+        Ok(())
+    }
+
+    /// Rebuild the playback wave after a change to loop settings. Streamed
+    /// samples always loop forward without crossfade.
+    pub fn update_loop(&mut self) {
+        self.loop_wave = None;
+
+        let Some(pt) = self.loop_point else { return };
+        let len = self.wave.len();
+        if self.stream_source.is_some() || pt + 2 >= len {
+            return
+        }
+
+        let mut samples: Vec<_> = (0..len).map(|i| self.wave.at(0, i)).collect();
+        match self.loop_mode {
+            LoopMode::Forward => {
+                let n = ((self.crossfade as f64 * self.wave.sample_rate()) as usize)
+                    .min(pt).min(len - pt);
+                if n == 0 {
+                    return
+                }
+                // fade the end of the loop into the audio leading up to the
+                // loop point, so the wrap is continuous
+                for i in 0..n {
+                    let t = i as f32 / n as f32;
+                    let j = len - n + i;
+                    samples[j] = samples[j] * (1.0 - t) + samples[pt - n + i] * t;
+                }
+            }
+            LoopMode::PingPong => {
+                // append the loop reversed, excluding the turnaround frames
+                let reversed: Vec<_> = samples[pt + 1..len - 1].iter()
+                    .rev().copied().collect();
+                samples.extend(reversed);
+            }
+        }
+
+        self.loop_wave = Some(Arc::new(Wave::from_samples(self.wave.sample_rate(), &samples)));
+    }
+
+    /// Returns the wave to use for looped playback.
+    fn playback_wave(&self) -> &Arc<Wave> {
+        self.loop_wave.as_ref().unwrap_or(&self.wave)
+    }
+
+    /// If streaming is enabled, move the wave and file data to disk, keeping
+    /// only the head of the wave in memory.
+    fn init_stream(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.stream && self.stream_source.is_none() {
+            let source = Arc::new(StreamSource::new(&self.wave, &self.data.bytes()?)?);
+            self.wave = Arc::new(Wave::from_samples(self.wave.sample_rate(), source.head()));
+            self.data = RawData::Disk(source.clone());
+            self.stream_source = Some(source);
+        }
         Ok(())
     }
 
+    /// Prepare a referenced sample for saving a module in `module_dir`.
+    /// Returns the embedded data, which should be put back with
+    /// `restore_data` after saving. A reference whose file failed to load is
+    /// kept as it was.
+    pub fn take_referenced_data(&mut self, module_dir: &Path) -> Option<RawData> {
+        if self.referenced && self.data.is_empty() {
+            // embedding the empty data would lose the reference for good
+            return None
+        }
+        self.reference_path = None;
+        let path = self.path.as_ref().filter(|_| self.referenced)?;
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+        let dir = std::path::absolute(module_dir)
+            .unwrap_or_else(|_| module_dir.to_path_buf());
+        self.reference_path = Some(path_to_string(&relative_path(&dir, &path)));
+        Some(std::mem::take(&mut self.data))
+    }
+
+    /// Put back data taken by `take_referenced_data`.
+    pub fn restore_data(&mut self, data: RawData) {
+        self.data = data;
+    }
+
+    /// Read the file of a referenced sample in a module loaded from
+    /// `module_dir`. Call this before `init`.
+    pub fn load_reference(&mut self, module_dir: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(rel) = &self.reference_path {
+            let path = module_dir.join(rel);
+            self.data = RawData::Memory(fs::read(&path)
+                .map_err(|e| format!("error reading {}: {e}", path.display()))?);
+            self.path = Some(path);
+        }
+        Ok(())
+    }
+
+    /// Returns a copy with its data re-read from the sample's file, keeping
+    /// playback settings. The loop point is dropped if the file got shorter.
+    pub fn reload(&self) -> Result<Self, Box<dyn Error>> {
+        let path = self.path.as_ref().ok_or("sample has no file")?;
+        let mut pcm = self.clone();
+        pcm.data = RawData::Memory(fs::read(path)?);
+        pcm.init()?;
+        if pcm.loop_point.is_some_and(|pt| pt >= pcm.len()) {
+            pcm.loop_point = None;
+            pcm.update_loop();
+        }
+        Ok(pcm)
+    }
+
+    /// Copy a referenced sample's file into `dir`, if it isn't there already.
+    /// Returns true if the file was copied.
+    pub fn collect(&mut self, dir: &Path) -> Result<bool, Box<dyn Error>> {
+        let Some(path) = self.path.as_ref().filter(|_| self.referenced) else {
+            return Ok(false)
+        };
+        if path.parent().is_some_and(|p| p == dir) {
+            return Ok(false)
+        }
+
+        let name = Path::new(&self.filename);
+        let stem = name.file_stem().and_then(|s| s.to_str()).unwrap_or("sample");
+        let ext = name.extension().and_then(|s| s.to_str()).unwrap_or_default();
+        let data = self.data.bytes()?;
+        let mut dest = dir.join(name);
+        let mut i = 1;
+        while dest.exists() {
+            // reuse an identical file
+            if fs::read(&dest)? == *data {
+                self.path = Some(dest);
+                return Ok(false)
+            }
+            i += 1;
+            dest = dir.join(format!("{stem} {i}.{ext}"));
+        }
+
+        fs::create_dir_all(dir)?;
+        fs::write(&dest, &data)?;
+        self.path = Some(dest);
+        Ok(true)
+    }
+
+    /// Enable or disable streaming from disk.
+    pub fn set_stream(&mut self, stream: bool) -> Result<(), Box<dyn Error>> {
+        self.stream = stream;
+        if stream {
+            self.init_stream()
+        } else if self.stream_source.is_some() {
+            self.init()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the length of the sample in frames.
+    pub fn len(&self) -> usize {
+        match &self.stream_source {
+            Some(source) => source.len(),
+            None => self.wave.len(),
+        }
+    }
+
+    /// Returns the duration of the sample in seconds.
+    pub fn duration(&self) -> f64 {
+        self.len() as f64 / self.wave.sample_rate()
+    }
+
+    /// Returns first-channel frames in `range`, reading from disk if the
+    /// sample is streamed.
+    fn frames(&self, range: Range<usize>) -> Vec<f32> {
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        match &self.stream_source {
+            Some(source) => source.read(range.start, range.len()).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                Vec::new()
+            }),
+            None => range.map(|i| self.wave.at(0, i)).collect(),
+        }
+    }
+
+    /// Returns the frame indices at which transients occur in the sample.
+    pub fn onsets(&self) -> Vec<usize> {
+        dsp::detect_onsets(&self.frames(0..self.len()), self.wave.sample_rate())
+    }
+
+    /// Returns a new sample containing the first-channel frames in `range`,
+    /// at the original gain and root pitch.
+    pub fn slice(&self, range: Range<usize>) -> Result<Self, Box<dyn Error>> {
+        let wave = Wave::from_samples(self.wave.sample_rate(), &self.frames(range));
+        let mut data = Vec::new();
+        wave.write_wav32(&mut data)?;
+
+        Ok(Self {
+            data: RawData::Memory(data),
+            wave: Arc::new(wave),
+            loop_point: None,
+            path: None,
+            midi_pitch: self.midi_pitch,
+            filename: self.filename.clone(),
+            stream: false,
+            stream_source: None,
+            referenced: false,
+            reference_path: None,
+            granular: self.granular,
+            speed: self.speed,
+            grain_size: self.grain_size,
+            loop_mode: self.loop_mode,
+            crossfade: self.crossfade,
+            loop_wave: None,
+        })
+    }
+
+    /// Make a unit that plays the sample at its native rate.
+    pub fn make_unit(&self) -> Box<dyn AudioUnit> {
+        match &self.stream_source {
+            Some(source) => Box::new(An(StreamWave::new(source.clone(), self.loop_point, 0))),
+            None => Box::new(wavech(self.playback_wave(), 0, self.loop_point)),
+        }
+    }
+
+    /// Returns true if the sample can be played in granular mode.
+    pub fn can_granulate(&self) -> bool {
+        self.stream_source.is_none()
+    }
+
+    /// Make a unit that plays the sample in grains. Input is the grain
+    /// playback rate in frames per sample.
+    pub fn make_granular_unit(&self) -> Box<dyn AudioUnit> {
+        Box::new(dsp::granular_wave(self.playback_wave().clone(), self.loop_point,
+            self.speed, self.grain_size))
+    }
+
+    /// Make a unit that plays the sample once from frame `start`, ignoring
+    /// the loop point.
+    pub fn make_clip_unit(&self, start: usize) -> Box<dyn AudioUnit> {
+        match &self.stream_source {
+            Some(source) => Box::new(An(StreamWave::new(source.clone(), None, start))),
+            None => Box::new(wavech_at(&self.wave, 0, start, self.wave.len(), None)),
+        }
+    }
+
     /// Adjust loop point to be smoother.
     pub fn fix_loop_point(&mut self) {
         // look for a sample that's after a similar sample to the last sample
@@ -117,26 +478,28 @@ impl PcmData {
         // was unknowingly compensated for) but it often gets the "right"
         // answer so i don't want to mess with it right now.
 
-        if let Some(pt) = &mut self.loop_point {
+        if let Some(pt) = self.loop_point {
             // don't mess with the loop point if it's zero -- it might be a
             // single-cycle wave
-            if *pt == 0 || self.wave.len() < 3 {
+            let len = self.len();
+            if pt == 0 || len < 3 {
                 return
             }
 
             // don't move the point by more than 2 ms
             let max_distance = (self.wave.sample_rate() as f32 * 0.002) as usize;
             let window_start = pt.saturating_sub(max_distance);
-            let window_end = (*pt + max_distance).min(self.wave.len() - 3);
+            let window_end = (pt + max_distance).min(len - 3);
 
-            let last_sample = self.wave.at(0, self.wave.len() - 1);
-            let second_last_sample = self.wave.at(0, self.wave.len() - 2);
+            let [second_last_sample, last_sample] = self.frames(len - 2..len)[..] else {
+                return
+            };
             let delta = last_sample - second_last_sample;
             let mut matches = Vec::new();
+            let window = self.frames(window_start..window_end + 1);
 
-            for i in window_start..window_end {
-                let s1 = self.wave.at(0, i);
-                let s2 = self.wave.at(0, i + 1);
+            for (i, pair) in (window_start..).zip(window.windows(2)) {
+                let (s1, s2) = (pair[0], pair[1]);
                 let test_delta = s2 - s1;
 
                 if test_delta.signum() == delta.signum() {
@@ -146,7 +509,7 @@ impl PcmData {
 
             if let Some((i, _)) = matches.into_iter()
                 .min_by_key(|(_, s)| OrderedFloat((last_sample - s).abs())) {
-                *pt = i;
+                self.loop_point = Some(i);
             }
         }
     }
@@ -154,11 +517,14 @@ impl PcmData {
     /// Attempts to detect the fundamental frequency of the sample.
     pub fn detect_pitch(&self) -> Option<f64> {
         let signal: Vec<_> = (0..self.wave.len())
-            .map(|i| self.wave.at(0, i) as f64)
+            .map(|i| self.wave.at(0, i))
             .collect();
         let rate = self.wave.sample_rate();
 
-        HannedFftDetector::default().detect_pitch(&signal, rate)
+        dsp::detect_pitch(&signal, rate).or_else(|| {
+            let signal: Vec<_> = signal.iter().map(|&x| x as f64).collect();
+            HannedFftDetector::default().detect_pitch(&signal, rate)
+        })
     }
 }
 
@@ -197,6 +563,40 @@ impl SmplData {
     }
 }
 
+/// Returns `path` relative to `base`, or `path` itself if they have nothing
+/// in common.
+fn relative_path(base: &Path, path: &Path) -> PathBuf {
+    let base: Vec<_> = base.components().collect();
+    let target: Vec<_> = path.components().collect();
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    if common == 0 {
+        return path.to_path_buf()
+    }
+
+    let mut result = PathBuf::new();
+    for _ in common..base.len() {
+        result.push("..");
+    }
+    for c in &target[common..] {
+        result.push(c);
+    }
+    result
+}
+
+/// Convert a path to a string. Relative paths use forward slashes, so that
+/// modules saved on different platforms are compatible.
+fn path_to_string(path: &Path) -> String {
+    if path.is_relative() {
+        path.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    } else {
+        path.to_string_lossy().to_string()
+    }
+}
+
 fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
     let bytes = data.get(offset..(offset + 4))?;
     Some(u32::from_le_bytes(bytes.try_into().ok()?))
@@ -239,4 +639,42 @@ mod tests {
         assert_eq!(PcmData::can_load_path(wav_upper), true);
         assert_eq!(PcmData::can_load_path(png), false);
     }
+
+    #[test]
+    fn test_relative_path() {
+        let base = Path::new("/songs/album");
+        assert_eq!(relative_path(base, Path::new("/songs/album/samples/a.wav")),
+            Path::new("samples/a.wav"));
+        assert_eq!(relative_path(base, Path::new("/songs/b.wav")),
+            Path::new("../b.wav"));
+        assert_eq!(path_to_string(Path::new("samples/a.wav")), "samples/a.wav");
+    }
+
+    #[test]
+    fn test_streamed_data() {
+        let samples: Vec<_> = (0..3000).map(|i| i as f32 / 2999.0).collect();
+        let mut data = Vec::new();
+        Wave::from_samples(1000.0, &samples).write_wav32(&mut data).unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("osctet-test-{}.wav", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let mut pcm = PcmData::load(&path, false).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // file data moves to disk, but serializes the same
+        pcm.set_stream(true).unwrap();
+        assert!(matches!(pcm.data, RawData::Disk(_)));
+        assert_eq!(rmp_serde::to_vec(&pcm.data).unwrap(), rmp_serde::to_vec(&data).unwrap());
+
+        // reset seeks back to the start, even past the head
+        let _blocking = stream::set_blocking(true);
+        let mut unit = pcm.make_clip_unit(1500);
+        let first = unit.get_mono();
+        unit.get_mono();
+        unit.reset();
+        assert_eq!(unit.get_mono(), first);
+
+        pcm.set_stream(false).unwrap();
+        assert!(matches!(&pcm.data, RawData::Memory(x) if *x == data));
+    }
 }
\ No newline at end of file
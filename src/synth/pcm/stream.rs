@@ -0,0 +1,287 @@
+//! Disk streaming for long PCM samples.
+//!
+//! A streamed sample is decoded once into a raw f32 cache file, and its
+//! original file data is moved to disk alongside it. Only the first second
+//! of the decoded sample stays in memory; voices play that "head" while a
+//! background thread prefetches the rest of the file into a per-voice ring
+//! buffer.
+
+use std::{cell::Cell, error::Error, fs::{self, File}, io::{BufWriter, Read, Seek, SeekFrom, Write}, path::PathBuf, sync::{atomic::{AtomicUsize, Ordering}, mpsc::{self, Receiver, Sender}, Arc, Mutex, OnceLock}, thread, time::Duration};
+
+use fundsp::hacker32::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+/// Length of the in-memory head of a streamed sample, in seconds. This is
+/// also the size of each voice's prefetch buffer.
+const HEAD_SECONDS: f64 = 1.0;
+
+/// How long the prefetch thread sleeps when it has nothing to do.
+const PREFETCH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Bytes per cached frame.
+const FRAME_BYTES: usize = std::mem::size_of::<f32>();
+
+thread_local! {
+    /// If true, streamed voices on this thread wait for data instead of
+    /// outputting silence.
+    static BLOCKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Make streamed voices on the current thread wait for the prefetch thread
+/// when they run out of data. Use this for offline rendering, which runs
+/// faster than real time. The previous setting is restored when the returned
+/// guard is dropped.
+#[must_use]
+pub fn set_blocking(blocking: bool) -> BlockingGuard {
+    BlockingGuard(BLOCKING.with(|b| b.replace(blocking)))
+}
+
+/// Restores the previous blocking setting on drop.
+pub struct BlockingGuard(bool);
+
+impl Drop for BlockingGuard {
+    fn drop(&mut self) {
+        BLOCKING.with(|b| b.set(self.0));
+    }
+}
+
+/// Decoded sample data on disk.
+pub struct StreamSource {
+    path: PathBuf,
+    /// Path to the original file data.
+    raw_path: PathBuf,
+    head: Vec<f32>,
+    len: usize,
+}
+
+impl StreamSource {
+    /// Write the first channel of `wave` to a new cache file, and `data` to
+    /// another.
+    pub fn new(wave: &Wave, data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!("osctet-{}-{}.f32",
+            std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for i in 0..wave.len() {
+            writer.write_all(&wave.at(0, i).to_le_bytes())?;
+        }
+        writer.flush()?;
+        let raw_path = path.with_extension("raw");
+        fs::write(&raw_path, data)?;
+
+        let head_len = ((wave.sample_rate() * HEAD_SECONDS) as usize).min(wave.len());
+
+        Ok(Self {
+            path,
+            raw_path,
+            head: (0..head_len).map(|i| wave.at(0, i)).collect(),
+            len: wave.len(),
+        })
+    }
+
+    /// Returns the total number of frames in the sample.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the frames kept in memory.
+    pub fn head(&self) -> &[f32] {
+        &self.head
+    }
+
+    /// Read the original file data back from disk.
+    pub fn raw_data(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(fs::read(&self.raw_path)?)
+    }
+
+    /// Read frames from the cache file.
+    pub fn read(&self, start: usize, len: usize) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut file = File::open(&self.path)?;
+        read_frames(&mut file, start, len.min(self.len.saturating_sub(start)))
+    }
+}
+
+impl Drop for StreamSource {
+    fn drop(&mut self) {
+        for path in [&self.path, &self.raw_path] {
+            if let Err(e) = fs::remove_file(path) {
+                eprintln!("error removing stream cache: {e}");
+            }
+        }
+    }
+}
+
+fn read_frames(file: &mut File, start: usize, len: usize
+) -> Result<Vec<f32>, Box<dyn Error>> {
+    let mut bytes = vec![0; len * FRAME_BYTES];
+    file.seek(SeekFrom::Start((start * FRAME_BYTES) as u64))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes.chunks_exact(FRAME_BYTES)
+        .map(|b| f32::from_le_bytes(b.try_into().expect("chunk size is correct")))
+        .collect())
+}
+
+/// A voice's request for frames after the head.
+struct StreamRequest {
+    source: Arc<StreamSource>,
+    producer: Producer<f32>,
+    position: usize,
+    loop_point: Option<usize>,
+    file: Option<File>,
+}
+
+impl StreamRequest {
+    /// Fill as much of the buffer as possible. Returns the number of frames
+    /// written, or None if the request is finished.
+    fn fill(&mut self) -> Option<usize> {
+        if self.producer.is_abandoned() {
+            return None
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => match File::open(&self.source.path) {
+                Ok(file) => self.file.insert(file),
+                Err(e) => {
+                    eprintln!("error opening stream cache: {e}");
+                    return None
+                }
+            },
+        };
+
+        let mut written = 0;
+        while self.producer.slots() > 0 {
+            if self.position >= self.source.len {
+                self.position = self.loop_point?;
+            }
+            let n = self.producer.slots().min(self.source.len - self.position);
+            let frames = match read_frames(file, self.position, n) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    eprintln!("error reading stream cache: {e}");
+                    return None
+                }
+            };
+            for x in frames {
+                let _ = self.producer.push(x);
+            }
+            self.position += n;
+            written += n;
+        }
+
+        Some(written)
+    }
+}
+
+/// Returns a sender for the prefetch thread, starting it if needed.
+fn prefetch_sender() -> Sender<StreamRequest> {
+    static SENDER: OnceLock<Mutex<Sender<StreamRequest>>> = OnceLock::new();
+
+    SENDER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || prefetch_loop(rx));
+        Mutex::new(tx)
+    }).lock().expect("prefetch sender lock poisoned").clone()
+}
+
+fn prefetch_loop(rx: Receiver<StreamRequest>) {
+    let mut requests: Vec<StreamRequest> = Vec::new();
+
+    loop {
+        if requests.is_empty() {
+            match rx.recv() {
+                Ok(req) => requests.push(req),
+                Err(_) => return,
+            }
+        }
+        requests.extend(rx.try_iter());
+
+        let mut written = 0;
+        requests.retain_mut(|req| req.fill().inspect(|n| written += n).is_some());
+
+        if written == 0 {
+            thread::sleep(PREFETCH_INTERVAL);
+        }
+    }
+}
+
+/// Plays a streamed sample at its native rate.
+pub struct StreamWave {
+    source: Arc<StreamSource>,
+    loop_point: Option<usize>,
+    /// Frame to start playing from.
+    start: usize,
+    /// Position of the next frame, not accounting for loops.
+    index: usize,
+    consumer: Mutex<Consumer<f32>>,
+}
+
+impl StreamWave {
+    pub fn new(source: Arc<StreamSource>, loop_point: Option<usize>, start: usize) -> Self {
+        let consumer = Mutex::new(Self::request(&source, loop_point, start));
+        Self { source, loop_point, start, index: start, consumer }
+    }
+
+    /// Ask the prefetch thread for the frames after the head, or after
+    /// `start` if that's later.
+    fn request(source: &Arc<StreamSource>, loop_point: Option<usize>, start: usize
+    ) -> Consumer<f32> {
+        let (producer, consumer) = RingBuffer::new(source.head.len().max(1));
+        let req = StreamRequest {
+            source: source.clone(),
+            producer,
+            position: source.head.len().max(start),
+            loop_point,
+            file: None,
+        };
+        if prefetch_sender().send(req).is_err() {
+            eprintln!("prefetch thread is gone");
+        }
+        consumer
+    }
+}
+
+impl Clone for StreamWave {
+    fn clone(&self) -> Self {
+        Self::new(self.source.clone(), self.loop_point, self.start)
+    }
+}
+
+impl AudioNode for StreamWave {
+    const ID: u64 = 203;
+    type Inputs = U0;
+    type Outputs = U1;
+
+    fn reset(&mut self) {
+        if self.index != self.start {
+            self.index = self.start;
+            self.consumer = Mutex::new(
+                Self::request(&self.source, self.loop_point, self.start));
+        }
+    }
+
+    fn set_sample_rate(&mut self, _sample_rate: f64) {}
+
+    #[inline]
+    fn tick(&mut self, _input: &Frame<f32, Self::Inputs>) -> Frame<f32, Self::Outputs> {
+        let x = if let Some(&x) = self.source.head.get(self.index) {
+            x
+        } else {
+            let consumer = self.consumer.get_mut().expect("stream lock poisoned");
+            if BLOCKING.with(|b| b.get()) {
+                while consumer.is_empty() && !consumer.is_abandoned() {
+                    thread::yield_now();
+                }
+            }
+            consumer.pop().unwrap_or(0.0)
+        };
+        self.index += 1;
+
+        [x].into()
+    }
+
+    fn route(&mut self, _input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        SignalFrame::new(self.outputs())
+    }
+}
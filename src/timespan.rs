@@ -205,6 +205,24 @@ impl<'de> Visitor<'de> for TimespanVisitor {
             .ok_or_else(|| de::Error::invalid_length(1, &self))?;
         Ok(Timespan { n, d })
     }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>
+    {
+        let (mut n, mut d) = (None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "n" => n = Some(map.next_value()?),
+                "d" => d = Some(map.next_value()?),
+                _ => { map.next_value::<de::IgnoredAny>()?; }
+            }
+        }
+        Ok(Timespan {
+            n: n.ok_or_else(|| de::Error::missing_field("n"))?,
+            d: d.ok_or_else(|| de::Error::missing_field("d"))?,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -3,27 +3,39 @@
 //! Not polished for general reuse. Macroquad also has its own built-in UI
 //! library, but the demos don't give me much faith in it.
 
-use std::{collections::HashMap, fmt::Display, mem, ops::RangeInclusive};
+use std::{cell::Cell, collections::HashMap, fmt::Display, mem, ops::{Range, RangeInclusive}};
 
 use fundsp::shared::Shared;
 use info::{ControlInfo, Info};
 use macroquad::prelude::*;
 use rfd::FileDialog;
 use text::GlyphAtlas;
+use unicode_segmentation::UnicodeSegmentation;
 use textedit::TextEditState;
+use touch::TouchState;
 use theme::Theme;
 
-use crate::{config::Config, input::{Action, Hotkey, Modifiers}, module::EventData, pitch::Note, playback::PlayerShell, synth::Key, MAIN_TAB_ID, TAB_PATTERN};
+use crate::{config::{Config, MouseSettings}, input::{Action, Hotkey, Modifiers}, module::EventData, pitch::{Note, NoteNames}, playback::PlayerShell, synth::Key, MAIN_TAB_ID, TAB_PATTERN};
 
 pub mod general;
 pub mod pattern;
 pub mod instruments;
 pub mod settings;
 pub mod developer;
+pub mod perform;
 pub mod theme;
 pub mod text;
 mod textedit;
 pub mod info;
+mod minimap;
+mod pattern_text;
+mod preview;
+mod quantize;
+pub mod scopes;
+pub mod cheat_sheet;
+pub mod tutorial;
+pub mod lane;
+pub mod touch;
 
 const LINE_THICKNESS: f32 = 1.0;
 const SLIDER_WIDTH: f32 = 100.0;
@@ -32,10 +44,26 @@ const PANEL_Z_OFFSET: i8 = 10;
 const COMBO_Z_OFFSET: i8 = 20;
 const TOOLTIP_Z_OFFSET: i8 = 30;
 
+/// ID of the text field used to edit instrument list entries.
+const INSTRUMENT_LIST_TEXT_ID: &str = "instrument_list";
+
 /// Seconds before info popup.
 const INFO_DELAY: f32 = 0.1;
 
-pub const MAX_PATCH_NAME_CHARS: usize = 20;
+/// Seconds that hotkey input waits for the second stroke of a two-stroke
+/// hotkey.
+const CHORD_INPUT_TIME: f64 = 0.5;
+
+/// Maximum seconds between the clicks of a double click.
+const DOUBLE_CLICK_TIME: f64 = 0.4;
+
+/// Maximum distance between the clicks of a double click, in pixels.
+const DOUBLE_CLICK_DISTANCE: f32 = 4.0;
+
+/// Number of entered values remembered for each slider.
+const ENTRY_HISTORY_LEN: usize = 5;
+
+pub use crate::synth::MAX_PATCH_NAME_CHARS;
 
 /// Return a new file dialog. Use this instead of using `rfd` directly.
 pub fn new_file_dialog(player: &mut PlayerShell) -> FileDialog {
@@ -55,6 +83,41 @@ enum Dialog {
     OkCancel(String, Action),
 }
 
+/// Range of allowed UI scale factors.
+pub const SCALE_RANGE: RangeInclusive<f32> = 1.0..=4.0;
+
+thread_local! {
+    /// UI scale factor. This lives outside of `Ui` so that the free functions
+    /// below can convert between window and UI coordinates.
+    static SCALE: Cell<f32> = const { Cell::new(1.0) };
+}
+
+/// Returns the current UI scale factor.
+fn scale() -> f32 {
+    SCALE.with(|s| s.get())
+}
+
+/// Returns the UI scale factor to use if none is configured.
+pub fn auto_scale() -> f32 {
+    screen_dpi_scale().round().clamp(*SCALE_RANGE.start(), *SCALE_RANGE.end())
+}
+
+/// Returns mouse position in UI coordinates.
+fn mouse_position() -> (f32, f32) {
+    let (x, y) = macroquad::input::mouse_position();
+    (x / scale(), y / scale())
+}
+
+/// Returns window width in UI coordinates.
+fn screen_width() -> f32 {
+    macroquad::window::screen_width() / scale()
+}
+
+/// Returns window height in UI coordinates.
+fn screen_height() -> f32 {
+    macroquad::window::screen_height() / scale()
+}
+
 /// Returns mouse position as a `Vec2`.
 fn mouse_position_vec2() -> Vec2 {
     let (x, y) = mouse_position();
@@ -64,14 +127,37 @@ fn mouse_position_vec2() -> Vec2 {
 /// UI style, including font and color theme.
 pub struct Style {
     pub atlas: GlyphAtlas,
+    /// Font for the pattern grid. If None, `atlas` is used.
+    pub pattern_atlas: Option<GlyphAtlas>,
     pub theme: Theme,
     pub margin: f32,
+    /// If true, `atlas` and `pattern_atlas` are swapped.
+    pattern_font: bool,
 }
 
 impl Style {
     pub fn line_height(&self) -> f32 {
         self.atlas.cap_height() + self.margin * 2.0
     }
+
+    /// Returns the font used for the pattern grid.
+    pub fn pattern_atlas(&self) -> &GlyphAtlas {
+        if self.pattern_font {
+            &self.atlas
+        } else {
+            self.pattern_atlas.as_ref().unwrap_or(&self.atlas)
+        }
+    }
+
+    /// Returns the font to draw text with, given whether the pattern font was
+    /// active when the text was pushed.
+    fn text_atlas(&self, pattern: bool) -> &GlyphAtlas {
+        if pattern != self.pattern_font {
+            self.pattern_atlas.as_ref().unwrap_or(&self.atlas)
+        } else {
+            &self.atlas
+        }
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -87,6 +173,19 @@ pub enum Layout {
     Horizontal,
 }
 
+/// An edit made in an instrument list.
+pub enum ListEdit {
+    Rename(String),
+    /// Move the entry at one index to another.
+    Move(usize, usize),
+}
+
+/// Item being dragged for reordering.
+struct DragState {
+    id: String,
+    index: usize,
+}
+
 struct ComboBoxState {
     id: String,
     options: Vec<String>,
@@ -97,7 +196,8 @@ struct ComboBoxState {
 enum Graphic {
     Rect(Rect, Color, Option<Color>),
     Line(f32, f32, f32, f32, Color),
-    Text(f32, f32, String, Color),
+    /// Text, and whether it uses the pattern font.
+    Text(f32, f32, String, Color, bool),
 }
 
 impl Graphic {
@@ -113,8 +213,39 @@ impl Graphic {
             Self::Line(x1, y1, x2, y2, color) => {
                 draw_line(*x1, *y1, *x2, *y2, LINE_THICKNESS, *color);
             },
-            Self::Text(x, y, text, color) => {
-                style.atlas.draw_text(x + style.margin, y + style.margin, text, *color);
+            Self::Text(x, y, text, color, pattern) => {
+                style.text_atlas(*pattern)
+                    .draw_text(x + style.margin, y + style.margin, text, *color);
+            }
+        }
+    }
+
+    /// Returns the part of the graphic within a vertical range. Text is
+    /// omitted unless it fits in the range.
+    fn clip_vertical(self, clip: &Range<f32>, style: &Style) -> Option<Self> {
+        match self {
+            Self::Rect(rect, fill, stroke) => {
+                let y1 = rect.y.max(clip.start);
+                let y2 = (rect.y + rect.h).min(clip.end);
+                (y2 > y1).then(|| Self::Rect(Rect { y: y1, h: y2 - y1, ..rect }, fill, stroke))
+            }
+            Self::Line(x1, y1, x2, y2, color) => {
+                if y1.max(y2) < clip.start || y1.min(y2) > clip.end {
+                    None
+                } else if y1 == y2 {
+                    Some(Self::Line(x1, y1, x2, y2, color))
+                } else {
+                    let x_at = |y: f32| x1 + (x2 - x1) * (y - y1) / (y2 - y1);
+                    let cy1 = y1.clamp(clip.start, clip.end);
+                    let cy2 = y2.clamp(clip.start, clip.end);
+                    Some(Self::Line(x_at(cy1), cy1, x_at(cy2), cy2, color))
+                }
+            }
+            Self::Text(x, y, text, color, pattern) => {
+                let fits = y + style.margin >= clip.start
+                    && y + style.text_atlas(pattern).cap_height() + style.margin
+                        <= clip.end;
+                fits.then_some(Self::Text(x, y, text, color, pattern))
             }
         }
     }
@@ -124,8 +255,11 @@ impl Graphic {
             Self::Rect(rect, _, _) => rect,
             Self::Line(x1, y1, x2, y2, _) =>
                 &Rect::new(*x1, *y1, x2 - x1, y2 - y1),
-            Self::Text(x, y, text, _) =>
-                &Rect::new(*x, *y, style.atlas.text_width(text), style.line_height()),
+            Self::Text(x, y, text, _, pattern) => {
+                let atlas = style.text_atlas(*pattern);
+                &Rect::new(*x, *y, atlas.text_width(text),
+                    atlas.cap_height() + style.margin * 2.0)
+            }
         };
         this_rect.overlaps(rect)
     }
@@ -143,6 +277,8 @@ enum Focus {
     Text(TextEditState),
     Hotkey(usize),
     Note(String),
+    /// Slider or combo box selected by tab navigation.
+    Keyboard(String),
 }
 
 impl Focus {
@@ -153,7 +289,7 @@ impl Focus {
     fn id(&self) -> Option<&str> {
         match self {
             Self::ComboBox(state) => Some(&state.id),
-            Self::Slider(s) | Self::Note(s) => Some(s),
+            Self::Slider(s) | Self::Note(s) | Self::Keyboard(s) => Some(s),
             Self::Text(state) => Some(&state.id),
             _ => None,
         }
@@ -180,13 +316,23 @@ pub struct Ui {
     dialog: Option<Dialog>,
     dialog_first_frame: bool,
     group_rects: Vec<Rect>,
-    pub note_queue: Vec<(Key, EventData)>,
+    /// Note input, with the track that the input is routed to, if any.
+    pub note_queue: Vec<(Key, EventData, Option<usize>)>,
     instrument_edit_index: Option<usize>,
     mouse_consumed: Option<String>,
+    drag: Option<DragState>,
+    /// Bounds to restore when the side panel ends.
+    side_panel_bounds: Option<Rect>,
+    /// If set, graphics are cut off outside this vertical range.
+    vertical_clip: Option<Range<f32>>,
     v_scrollbar_grab_pos: Option<f32>,
     h_scrollbar_grab_pos: Option<f32>,
     notification: Option<Notification>,
     text_clipboard: Option<String>,
+    /// Recently entered values for each slider, most recent first.
+    entry_history: HashMap<String, Vec<String>>,
+    /// ID of the slider whose entry history is shown, and the list rect.
+    entry_list: Option<(String, Rect)>,
     /// Whether the current group expands based on graphics geometry.
     group_ignores_geometry: bool,
     /// Whether the top group on the stack is a widget.
@@ -206,6 +352,17 @@ pub struct Ui {
     lost_focus: Focus,
     /// (Position, ID) pairs for tab key navigation.
     tab_nav_list: Vec<(Vec2, String)>,
+    /// Key held to use the secondary keymap layer.
+    layer_key: KeyCode,
+    /// First stroke entered in the focused hotkey input, and when.
+    hotkey_prefix: Option<(Hotkey, f64)>,
+    mouse: MouseSettings,
+    /// If true, the mouse wheel was used by a control this frame.
+    wheel_consumed: bool,
+    /// Position and time of the last left click.
+    last_click: Option<(Vec2, f64)>,
+    double_click: bool,
+    touch: TouchState,
 }
 
 impl Ui {
@@ -220,7 +377,9 @@ impl Ui {
             style: Style {
                 margin: atlas.max_height() - atlas.cap_height(),
                 atlas,
+                pattern_atlas: None,
                 theme: theme.unwrap_or_default(),
+                pattern_font: false,
             },
             tabs: HashMap::new(),
             bounds: Default::default(),
@@ -235,10 +394,15 @@ impl Ui {
             note_queue: Vec::new(),
             instrument_edit_index: None,
             mouse_consumed: None,
+            drag: None,
+            side_panel_bounds: None,
+            vertical_clip: None,
             v_scrollbar_grab_pos: None,
             h_scrollbar_grab_pos: None,
             notification: None,
             text_clipboard: None,
+            entry_history: HashMap::new(),
+            entry_list: None,
             group_ignores_geometry: false,
             widget_on_stack: false,
             info: Info::None,
@@ -250,6 +414,13 @@ impl Ui {
             pending_focus: None,
             lost_focus: Focus::None,
             tab_nav_list: Vec::new(),
+            layer_key: KeyCode::Unknown,
+            hotkey_prefix: None,
+            mouse: Default::default(),
+            wheel_consumed: false,
+            last_click: None,
+            double_click: false,
+            touch: Default::default(),
         }
     }
 
@@ -257,6 +428,18 @@ impl Ui {
         self.v_scrollbar_grab_pos.is_some()
             || self.h_scrollbar_grab_pos.is_some()
             || self.focus.is_slider()
+            || self.touch.pinching()
+    }
+
+    /// Returns touch gesture state.
+    pub fn touch(&self) -> &TouchState {
+        &self.touch
+    }
+
+    /// Returns true if the left mouse button was pressed this frame as the
+    /// second click of a double click.
+    pub fn double_clicked(&self) -> bool {
+        self.double_click
     }
 
     pub fn get_tab(&self, key: &str) -> Option<usize> {
@@ -265,6 +448,10 @@ impl Ui {
 
     /// Start a new frame. Returns any action returned by a dialog.
     pub fn start_frame(&mut self, conf: &Config) -> Option<Action> {
+        let scale = conf.ui_scale.unwrap_or_else(auto_scale)
+            .clamp(*SCALE_RANGE.start(), *SCALE_RANGE.end());
+        SCALE.with(|s| s.set(scale));
+
         self.bounds = Rect {
             x: 0.0,
             y: 0.0,
@@ -275,15 +462,32 @@ impl Ui {
         self.cursor_x = self.style.margin;
         self.cursor_y = self.style.margin;
         self.cursor_z = 0;
+        self.layer_key = conf.layer_key.key;
+        self.mouse = conf.mouse;
+        self.wheel_consumed = false;
+
+        self.touch.update();
+        self.double_click = false;
+        if is_mouse_button_pressed(MouseButton::Left) {
+            let (pos, time) = (mouse_position_vec2(), get_time());
+            self.double_click = self.last_click.is_some_and(|(p, t)|
+                time - t < DOUBLE_CLICK_TIME && p.distance(pos) < DOUBLE_CLICK_DISTANCE);
+            self.last_click = (!self.double_click).then_some((pos, time));
+        }
 
         if !is_mouse_button_down(MouseButton::Left)
             && !is_mouse_button_released(MouseButton::Left) {
             self.mouse_consumed = None;
+            self.drag = None;
         }
 
         if self.focus.is_slider() && is_mouse_button_released(MouseButton::Left) {
             self.focus = Focus::None;
         }
+        if matches!(self.focus, Focus::Keyboard(_))
+            && (is_mouse_button_pressed(MouseButton::Left) || is_key_pressed(KeyCode::Escape)) {
+            self.focus = Focus::None;
+        }
         self.tab_nav_list.clear();
 
         clear_background(self.style.theme.panel_bg());
@@ -367,11 +571,17 @@ impl Ui {
     pub fn end_frame(&mut self, tab_nav: bool) {
         self.draw_list.sort_by_key(|x| x.z);
         let screen_rect = Rect::new(0.0, 0.0, screen_width(), screen_height());
+        set_camera(&Camera2D {
+            target: screen_rect.center(),
+            zoom: vec2(2.0 / screen_rect.w, 2.0 / screen_rect.h),
+            ..Default::default()
+        });
         for op in &self.draw_list {
             if op.graphic.overlaps(&self.style, &screen_rect) {
                 op.graphic.draw(&self.style);
             }
         }
+        set_default_camera();
         self.draw_list.clear();
 
         // drain input queues
@@ -419,6 +629,13 @@ impl Ui {
     }
 
     fn push_graphic(&mut self, graphic: Graphic) {
+        let graphic = match &self.vertical_clip {
+            Some(clip) => match graphic.clip_vertical(clip, &self.style) {
+                Some(g) => g,
+                None => return,
+            },
+            None => graphic,
+        };
         let (x, y) = match &graphic {
             Graphic::Line(x1, y1, x2, y2, _) => (x1.max(*x2), y1.max(*y2)),
             Graphic::Rect(rect, _, stroke) => if stroke.is_some() {
@@ -426,7 +643,7 @@ impl Ui {
             } else {
                 (rect.x, rect.y)
             },
-            Graphic::Text(x, y, text, _) => (
+            Graphic::Text(x, y, text, _, _) => (
                 x + self.style.atlas.text_width(text) + self.style.margin * 2.0,
                 y + self.style.line_height()
             ),
@@ -438,6 +655,24 @@ impl Ui {
         });
     }
 
+    /// Use the pattern font for subsequent text and measurements, or stop
+    /// doing so.
+    pub fn set_pattern_font(&mut self, enabled: bool) {
+        let style = &mut self.style;
+        if style.pattern_font != enabled {
+            if let Some(pattern_atlas) = &mut style.pattern_atlas {
+                std::mem::swap(&mut style.atlas, pattern_atlas);
+                style.pattern_font = enabled;
+            }
+        }
+    }
+
+    /// Cut off subsequent graphics outside a vertical range, or stop doing so
+    /// if None.
+    pub fn set_vertical_clip(&mut self, range: Option<Range<f32>>) {
+        self.vertical_clip = range;
+    }
+
     fn push_graphics(&mut self, gfx: Vec<Graphic>) {
         for gfx in gfx {
             self.push_graphic(gfx);
@@ -471,7 +706,7 @@ impl Ui {
             w: self.style.atlas.text_width(&text) + self.style.margin * 2.0,
             h: self.style.line_height(),
         };
-        self.push_graphic(Graphic::Text(x, y, text, color));
+        self.push_graphic(Graphic::Text(x, y, text, color, self.style.pattern_font));
         rect
     }
 
@@ -502,17 +737,57 @@ impl Ui {
         self.cursor_z -= PANEL_Z_OFFSET;
     }
 
-    /// Draws a scrollbar on the right edge of the current bounds.
+    /// Starts a panel `w` wide on the right side of the current bounds. Until
+    /// the panel ends, the panel is the current bounds.
+    pub fn start_side_panel(&mut self, w: f32) {
+        let rect = Rect {
+            x: self.bounds.x + self.bounds.w - w,
+            w,
+            ..self.bounds
+        };
+        self.cursor_z += PANEL_Z_OFFSET;
+        self.push_rect(rect, self.style.theme.panel_bg(), None);
+        self.push_line(rect.x + 0.5, rect.y, rect.x + 0.5, rect.y + rect.h,
+            self.style.theme.border_unfocused());
+        self.side_panel_bounds = Some(self.bounds);
+        self.bounds = rect;
+        self.cursor_x = rect.x;
+        self.cursor_y = rect.y;
+    }
+
+    /// Returns true if a side panel is being drawn.
+    pub fn in_side_panel(&self) -> bool {
+        self.side_panel_bounds.is_some()
+    }
+
+    /// Ends the side panel, leaving the rest of the original bounds.
+    pub fn end_side_panel(&mut self) {
+        if let Some(bounds) = self.side_panel_bounds.take() {
+            self.bounds = Rect {
+                w: bounds.w - self.bounds.w,
+                ..bounds
+            };
+        }
+        self.cursor_x = self.bounds.x;
+        self.cursor_y = self.bounds.y;
+        self.cursor_z -= PANEL_Z_OFFSET;
+    }
+
+    /// Draws a scrollbar on the right edge of the current bounds. The mouse
+    /// wheel scrolls when the cursor is within the horizontal extent of the
+    /// bounds.
     pub fn vertical_scrollbar(&mut self,
         current_y: &mut f32, max_y: f32, viewport_h: f32, keys: bool
     ) {
-        if !is_shift_down() && !is_ctrl_down() {
+        let (mouse_x, _) = mouse_position();
+        if !is_shift_down() && !is_ctrl_down() && !self.wheel_consumed
+            && (self.bounds.x..self.bounds.x + self.bounds.w).contains(&mouse_x) {
             let (_, y_scroll) = mouse_wheel();
             if y_scroll != 0.0 {
                 let increment = if is_alt_down() {
                     viewport_h / 2.0
                 } else {
-                    self.style.line_height() * 3.0
+                    self.style.line_height() * self.mouse.scroll_lines
                 };
                 *current_y += -y_scroll.signum() * increment;
             }
@@ -577,10 +852,10 @@ impl Ui {
     pub fn horizontal_scrollbar(&mut self,
          current_x: &mut f32, max_x: f32, viewport_w: f32
     ) {
-        if is_shift_down() && !is_ctrl_down() {
+        if is_shift_down() && !is_ctrl_down() && !self.wheel_consumed {
             let (_, y_scroll) = mouse_wheel();
             if y_scroll != 0.0 {
-                let increment = self.style.line_height() * 3.0;
+                let increment = self.style.line_height() * self.mouse.scroll_lines;
                 let dx = -y_scroll.signum() * increment;
                 *current_x += dx;
             }
@@ -645,6 +920,14 @@ impl Ui {
             }
         }
 
+        // occlusion by slider entry history
+        if let (Focus::Text(state), Some((owner, list_rect)))
+            = (&self.focus, &self.entry_list) {
+            if state.id == *owner && list_rect.contains(pt) {
+                return false
+            }
+        }
+
         // occlusion by bottom panel, tab menu, etc.
         if self.cursor_z < 1 && !self.bounds.contains(pt) {
             return false
@@ -795,6 +1078,25 @@ impl Ui {
                 self.cursor_y + margin, label.to_owned(), self.style.theme.fg());
         }
 
+        self.tab_nav_list.push((self.cursor_vec(), id.to_string()));
+        if self.pending_focus.as_deref() == Some(id) {
+            self.set_focus(Focus::Keyboard(id.to_owned()));
+        }
+
+        // keyboard selection
+        let mut key_choice = None;
+        if matches!(&self.focus, Focus::Keyboard(s) if s == id) {
+            self.push_rect(button_rect, Color { a: 0.0, ..Default::default() },
+                Some(self.style.theme.border_focused()));
+            let offset = key_offset();
+            if offset != 0 {
+                let options = get_options();
+                let i = options.iter().position(|s| s == button_text).unwrap_or_default();
+                key_choice = Some((i as isize + offset)
+                    .clamp(0, options.len() as isize - 1) as usize);
+            }
+        }
+
         // check to open list
         let open = match &self.focus {
             Focus::ComboBox(state) => state.id == id,
@@ -831,7 +1133,47 @@ impl Ui {
         }
 
         self.end_widget(id, info, ControlInfo::None);
-        return_val
+        return_val.or(key_choice)
+    }
+
+    /// Opens a context menu when `rect` is right-clicked, and draws it while
+    /// it's open. If an option was selected this frame, returns its index.
+    pub fn context_menu(&mut self, id: &str, rect: Rect,
+        get_options: impl Fn() -> Vec<String>
+    ) -> Option<usize> {
+        let open = matches!(&self.focus, Focus::ComboBox(state) if state.id == id);
+
+        if is_mouse_button_pressed(MouseButton::Right) || self.touch.long_press() {
+            if self.mouse_hits(rect, id) {
+                let (x, y) = mouse_position();
+                let h = self.style.line_height();
+                let options = get_options();
+                if options.is_empty() {
+                    return None
+                }
+                let button_rect = Rect { x, y: y - h, w: 0.0, h };
+                let list_rect = combo_box_list_rect(&self.style, button_rect, &options);
+                self.set_focus(Focus::ComboBox(ComboBoxState {
+                    id: id.to_owned(),
+                    options,
+                    button_rect,
+                    list_rect,
+                }));
+                return None
+            } else if open {
+                self.focus = Focus::None;
+                return None
+            }
+        }
+
+        if !open {
+            return None
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.focus = Focus::None;
+            return None
+        }
+        self.combo_box_list(true, Info::None)
     }
 
     /// Draw the list of the active combo box.
@@ -855,7 +1197,8 @@ impl Ui {
             };
             let mouse_pos = mouse_position_vec2();
             let mut return_val = None;
-            let lmb = is_mouse_button_released(MouseButton::Left);
+            let lmb = is_mouse_button_released(MouseButton::Left)
+                && !self.touch.long_release();
             for (i, option) in state.options.iter().enumerate() {
                 if hit_rect.contains(mouse_pos) {
                     gfx.push(Graphic::Rect(
@@ -866,7 +1209,7 @@ impl Ui {
                     }
                 }
                 gfx.push(Graphic::Text(hit_rect.x - 1.0, hit_rect.y - 1.0,
-                    option.to_owned(), self.style.theme.fg()));
+                    option.to_owned(), self.style.theme.fg(), false));
                 hit_rect.y += hit_rect.h;
             }
 
@@ -928,7 +1271,7 @@ impl Ui {
             };
             gfx.push(Graphic::Rect(Rect {w: r.w, ..r }, color, None));
             gfx.push(Graphic::Text(x, self.cursor_y,
-                label.to_string(), self.style.theme.fg()));
+                label.to_string(), self.style.theme.fg(), false));
             if i == 0 {
                 gfx.push(Graphic::Line(x - LINE_THICKNESS * 0.5, self.cursor_y,
                     x - LINE_THICKNESS *0.5, self.cursor_y + r.h,
@@ -974,6 +1317,10 @@ impl Ui {
         }
     }
 
+    pub fn set_tab(&mut self, id: &str, i: usize) {
+        self.tabs.insert(id.to_owned(), i);
+    }
+
     pub fn prev_tab(&mut self, id: &str, n: usize) {
         if let Some(i) = self.tabs.get_mut(id) {
             *i = (*i as isize - 1).rem_euclid(n as isize) as usize;
@@ -995,13 +1342,23 @@ impl Ui {
         range: RangeInclusive<f32>, power: i32, enabled: bool, info: Info,
         display: impl Fn(f32) -> String, convert: impl FnOnce(f32) -> f32,
     ) -> bool {
+        let unit = display_unit_name(&display(*val));
+
         // are we in text entry mode?
         if let Focus::Text(state) = &self.focus {
             if state.id == id {
-                return self.slider_text_entry(id, label, val, range, convert);
+                return self.slider_text_entry(id, label, val, range, &unit, convert);
             }
         }
 
+        if enabled {
+            self.tab_nav_list.push((self.cursor_vec(), id.to_string()));
+        }
+        if self.pending_focus.as_deref() == Some(id) {
+            self.set_focus(Focus::Keyboard(id.to_string()));
+        }
+        let key_focused = matches!(&self.focus, Focus::Keyboard(s) if s == id);
+
         self.start_widget();
         let h = self.style.atlas.cap_height();
 
@@ -1048,6 +1405,15 @@ impl Ui {
             *val = new_val;
             (self.style.theme.control_bg_click(), self.style.theme.border_focused(),
                 changed)
+        } else if key_focused {
+            let changed = step_slider(val, &range, power, slider_key_step());
+            (self.style.theme.control_bg_hover(), self.style.theme.border_focused(),
+                changed)
+        } else if hit && self.mouse.wheel_adjusts_sliders && mouse_wheel().1 != 0.0 {
+            self.wheel_consumed = true;
+            let changed = step_slider(val, &range, power, slider_wheel_step());
+            (self.style.theme.control_bg_hover(), self.style.theme.border_focused(),
+                changed)
         } else if hit {
             (self.style.theme.control_bg_hover(), self.style.theme.border_focused(), false)
         } else if enabled {
@@ -1077,23 +1443,16 @@ impl Ui {
             self.push_rect(r, Color { a: 0.0, ..Default::default() }, None);
         };
 
-        if hit || grabbed {
+        if hit || grabbed || key_focused {
             let text = display(*val);
             self.tooltip(&text, handle_rect.x,
                 self.cursor_y - (h + self.style.margin * 2.0));
         }
 
-        // TODO: duplication with slider_text_entry
         match &self.lost_focus {
             Focus::Text(state) if state.id == id => {
-                match state.text.parse::<f32>() {
-                    Ok(f) => {
-                        *val = convert(f).max(*range.start()).min(*range.end());
-                        changed = true;
-                    },
-                    Err(e) => self.report(e),
-                }
-                self.focus = Focus::None;
+                let text = state.text.clone();
+                changed = self.submit_slider_text(id, &text, val, range, &unit, convert);
             }
             _ => ()
         }
@@ -1102,9 +1461,10 @@ impl Ui {
         changed
     }
 
-    /// Handle a slider's text entry state.
+    /// Handle a slider's text entry state, showing recent entries below the
+    /// text box.
     fn slider_text_entry(&mut self, id: &str, label: &str, val: &mut f32,
-        range: RangeInclusive<f32>, convert: impl FnOnce(f32) -> f32,
+        range: RangeInclusive<f32>, unit: &str, convert: impl FnOnce(f32) -> f32,
     ) -> bool {
         let text = if let Focus::Text(state) = &mut self.focus {
             state.text.clone()
@@ -1112,19 +1472,118 @@ impl Ui {
             panic!("no focused text")
         };
 
-        let mut changed = false;
+        let box_pos = vec2(self.cursor_x, self.cursor_y) + self.style.margin;
+        if let Some(entry) = self.entry_history_list(id, box_pos) {
+            return self.submit_slider_text(id, &entry, val, range, unit, convert)
+        }
+
         let w = SLIDER_WIDTH + self.style.margin * 2.0;
         if self.text_box(id, label, w, &text, 10, Info::None) {
-            match text.parse::<f32>() {
-                Ok(f) => {
-                    *val = convert(f).max(*range.start()).min(*range.end());
-                    changed = true;
-                },
-                Err(e) => self.report(e),
+            return self.submit_slider_text(id, &text, val, range, unit, convert)
+        }
+        false
+    }
+
+    /// Draw the recent entries for a slider above or below its text box.
+    /// Returns the entry that was clicked, if any.
+    fn entry_history_list(&mut self, id: &str, box_pos: Vec2) -> Option<String> {
+        let entries = self.entry_history.get(id).cloned().unwrap_or_default();
+        if entries.is_empty() {
+            self.entry_list = None;
+            return None
+        }
+
+        let h = self.style.line_height();
+        let list_h = h * entries.len() as f32 + 2.0;
+        let list_rect = Rect {
+            x: box_pos.x,
+            y: if box_pos.y > screen_height() / 2.0 {
+                box_pos.y - list_h + 1.0
+            } else {
+                box_pos.y + h - 1.0
+            },
+            w: entries.iter().fold(0.0_f32, |w, s| w.max(self.style.atlas.text_width(s)))
+                + self.style.margin * 2.0,
+            h: list_h,
+        };
+        self.entry_list = Some((id.to_owned(), list_rect));
+
+        self.cursor_z += COMBO_Z_OFFSET;
+        let mut gfx = vec![Graphic::Rect(list_rect, self.style.theme.panel_bg(),
+            Some(self.style.theme.border_unfocused()))];
+        let mouse_pos = mouse_position_vec2();
+        let mut clicked = None;
+        for (i, entry) in entries.into_iter().enumerate() {
+            let hit_rect = Rect {
+                x: list_rect.x + 1.0,
+                y: list_rect.y + 1.0 + h * i as f32,
+                w: list_rect.w - 2.0,
+                h,
+            };
+            if hit_rect.contains(mouse_pos) {
+                gfx.push(Graphic::Rect(hit_rect, self.style.theme.panel_bg_hover(), None));
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    self.mouse_consumed = Some(id.to_owned());
+                    clicked = Some(entry.clone());
+                }
             }
-            self.focus = Focus::None;
+            gfx.push(Graphic::Text(hit_rect.x - 1.0, hit_rect.y - 1.0, entry,
+                self.style.theme.fg(), false));
         }
-        changed
+        self.push_graphics(gfx);
+        self.cursor_z -= COMBO_Z_OFFSET;
+
+        clicked
+    }
+
+    /// Set a slider's value from entered text, ending text entry. Valid
+    /// entries are added to the slider's history.
+    fn submit_slider_text(&mut self, id: &str, text: &str, val: &mut f32,
+        range: RangeInclusive<f32>, unit: &str, convert: impl FnOnce(f32) -> f32,
+    ) -> bool {
+        self.focus = Focus::None;
+        self.entry_list = None;
+        match parse_entry(text, unit) {
+            Ok(f) => {
+                *val = convert(f).max(*range.start()).min(*range.end());
+                let history = self.entry_history.entry(id.to_owned()).or_default();
+                let text = text.trim().to_owned();
+                history.retain(|s| *s != text);
+                history.insert(0, text);
+                history.truncate(ENTRY_HISTORY_LEN);
+                true
+            }
+            Err(e) => {
+                self.report(e);
+                false
+            }
+        }
+    }
+
+    /// Draw a small line plot of `values`, scaled from `min` to `max`.
+    pub fn plot(&mut self, values: &[f32], min: f32, max: f32, info: Info) {
+        let h = self.style.line_height();
+        let rect = Rect {
+            x: self.cursor_x + self.style.margin,
+            y: self.cursor_y + self.style.margin,
+            w: h * 3.0,
+            h,
+        };
+        self.start_widget();
+        self.push_rect(rect, self.style.theme.control_bg(),
+            Some(self.style.theme.border_unfocused()));
+
+        let point = |i: usize, v: f32| (
+            rect.x + i as f32 / (values.len() - 1).max(1) as f32 * rect.w,
+            rect.y + rect.h - ((v - min) / (max - min)).clamp(0.0, 1.0) * rect.h,
+        );
+        for (i, pair) in values.windows(2).enumerate() {
+            let (x1, y1) = point(i, pair[0]);
+            let (x2, y2) = point(i + 1, pair[1]);
+            self.push_line(x1, y1, x2, y2, self.style.theme.accent1_fg());
+        }
+
+        self.end_widget("plot", info, ControlInfo::None);
     }
 
     /// Draw a horizontal table of color swatches.
@@ -1160,15 +1619,22 @@ impl Ui {
 
     /// Widget for editing a value as text.
     pub fn edit_box(&mut self, label: &str, chars_wide: usize,
+        text: String, info: Info
+    ) -> Option<String> {
+        self.id_edit_box(label, label, chars_wide, text, info)
+    }
+
+    /// Like `edit_box`, but with an ID that's separate from the label.
+    pub fn id_edit_box(&mut self, id: &str, label: &str, chars_wide: usize,
         mut text: String, info: Info
     ) -> Option<String> {
-        self.tab_nav_list.push((self.cursor_vec(), label.to_string()));
+        self.tab_nav_list.push((self.cursor_vec(), id.to_string()));
 
         let w = chars_wide as f32 * self.style.atlas.char_width()
             + self.style.margin * 2.0;
 
         let mut result = match &self.lost_focus {
-            Focus::Text(state) if state.id == label => {
+            Focus::Text(state) if state.id == id => {
                 let s = state.text.clone();
                 text = s.clone();
                 self.lost_focus = Focus::None;
@@ -1177,7 +1643,7 @@ impl Ui {
             _ => None,
         };
 
-        if self.text_box(label, label, w, &text, chars_wide, info) {
+        if self.text_box(id, label, w, &text, chars_wide, info) {
             if let Focus::Text(state) = &self.focus {
                 let s = state.text.clone();
                 self.focus = Focus::None;
@@ -1253,11 +1719,12 @@ impl Ui {
         submit
     }
 
-    /// List box with editable values. Returns a string when an edit is submitted.
+    /// List box with editable and reorderable values. Returns an edit when a
+    /// name is submitted or an entry is dragged to a new position. The first
+    /// entry is fixed. Right-clicking an entry selects it.
     pub fn instrument_list(&mut self, options: &[String], index: &mut usize,
         min_chars: usize,
-    ) -> Option<String> {
-        const TEXT_ID: &str = "instrument_list";
+    ) -> Option<ListEdit> {
         let pointer = String::from(char::from_u32(0xbb).unwrap());
 
         let margin = self.style.margin;
@@ -1270,7 +1737,7 @@ impl Ui {
             w: options.iter().fold(0.0_f32, |w, s| w.max(atlas.text_width(s)))
                 .max(atlas.char_width() * min_chars as f32)
                 .max(match &self.focus {
-                    Focus::Text(state) if state.id == TEXT_ID =>
+                    Focus::Text(state) if state.id == INSTRUMENT_LIST_TEXT_ID =>
                         atlas.text_width(&state.text),
                     _ => 0.0,
                 })
@@ -1291,7 +1758,12 @@ impl Ui {
         };
         let lmb = is_mouse_button_released(MouseButton::Left);
         let mut return_val = None;
+        let mut item_rects = Vec::new();
         for (i, option) in options.iter().enumerate() {
+            if i > 0 {
+                item_rects.push(hit_rect);
+            }
+
             if i == *index {
                 self.push_rect(hit_rect, self.style.theme.content_bg_click(), None);
                 self.push_text(list_rect.x, hit_rect.y,
@@ -1311,13 +1783,13 @@ impl Ui {
                     self.instrument_edit_index = None;
                 }
                 match &self.focus {
-                    Focus::Text(state) if state.id == TEXT_ID => (),
+                    Focus::Text(state) if state.id == INSTRUMENT_LIST_TEXT_ID => (),
                     _ => {
                         self.instrument_edit_index = None;
                         match &self.lost_focus {
-                            Focus::Text(state) if state.id == TEXT_ID => {
+                            Focus::Text(state) if state.id == INSTRUMENT_LIST_TEXT_ID => {
                                 option = state.text.clone();
-                                return_val = Some(option.clone());
+                                return_val = Some(ListEdit::Rename(option.clone()));
                                 self.lost_focus = Focus::None;
                             }
                             _ => (),
@@ -1334,18 +1806,14 @@ impl Ui {
                 };
                 if self.editable_text(rect, MAX_PATCH_NAME_CHARS, MAX_PATCH_NAME_CHARS) {
                     if let Focus::Text(state) = &mut self.focus {
-                        return_val = Some(state.text.clone());
+                        return_val = Some(ListEdit::Rename(state.text.clone()));
                         self.focus = Focus::None;
                         self.instrument_edit_index = None;
                     }
                 }
             } else {
                 if self.mouse_hits(hit_rect, "instrument_list")
-                    && is_mouse_button_pressed(MouseButton::Right) && i > 0 {
-                    let text = option.clone();
-                    let f = Focus::Text(TextEditState::new(TEXT_ID.to_string(), text));
-                    self.set_focus(f);
-                    self.instrument_edit_index = Some(i);
+                    && is_mouse_button_pressed(MouseButton::Right) {
                     *index = i;
                 }
                 self.push_text(list_rect.x + char_width, hit_rect.y,
@@ -1354,10 +1822,62 @@ impl Ui {
             hit_rect.y += hit_rect.h;
         }
 
+        if let Some((src, dst)) = self.reorder_drag("instrument_list", &item_rects) {
+            return_val = Some(ListEdit::Move(src + 1, dst + 1));
+        }
+
         self.end_widget("instrument_list", Info::InstrumentList, ControlInfo::None);
         return_val
     }
 
+    /// Handles drag-and-drop reordering of items occupying `rects`, and draws
+    /// a drop indicator. If an item was dropped in a new position this frame,
+    /// returns its old and new indices.
+    pub fn reorder_drag(&mut self, id: &str, rects: &[Rect]) -> Option<(usize, usize)> {
+        let pt = mouse_position_vec2();
+        let hit = rects.iter().position(|r| r.contains(pt));
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            if let Some(index) = hit.filter(|&i| self.mouse_hits(rects[i], id)) {
+                self.drag = Some(DragState { id: id.to_owned(), index });
+            }
+            return None
+        }
+
+        let src = match &self.drag {
+            Some(drag) if drag.id == id => drag.index,
+            _ => return None,
+        };
+        let dst = hit.filter(|&i| i != src)?;
+
+        // keep other widgets from reacting to the drop
+        self.mouse_consumed = Some(id.to_owned());
+
+        // indicator goes on the side of the destination facing away from the source
+        let (a, b) = (rects[src], rects[dst]);
+        let after = dst > src;
+        let color = self.style.theme.border_focused();
+        if (b.y - a.y).abs() > (b.x - a.x).abs() {
+            let y = if after { b.y + b.h } else { b.y };
+            self.push_line(b.x, y, b.x + b.w, y, color);
+        } else {
+            let x = if after { b.x + b.w } else { b.x };
+            self.push_line(x, b.y, x, b.y + b.h, color);
+        }
+
+        if is_mouse_button_released(MouseButton::Left) {
+            self.drag = None;
+            return Some((src, dst))
+        }
+        None
+    }
+
+    /// Start editing the name of an instrument list entry.
+    pub fn rename_instrument(&mut self, index: usize, name: String) {
+        self.focus_text(INSTRUMENT_LIST_TEXT_ID.to_owned(), name);
+        self.instrument_edit_index = Some(index);
+    }
+
     /// Focus a new text field.
     fn focus_text(&mut self, id: String, text: String) {
         self.set_focus(Focus::Text(TextEditState::new(id, text)));
@@ -1441,7 +1961,7 @@ impl Ui {
             let text = if len <= display_width {
                 text
             } else {
-                text.chars().skip(len - display_width).collect()
+                text.graphemes(true).skip(len - display_width).collect()
             };
             self.push_text(rect.x, rect.y, text, self.style.theme.fg());
         }
@@ -1481,6 +2001,11 @@ impl Ui {
         self.open_dialog(Dialog::Alert(e.to_string()));
     }
 
+    /// Show a message in an alert dialog. Lines are drawn separately.
+    pub fn alert(&mut self, message: String) {
+        self.open_dialog(Dialog::Alert(message));
+    }
+
     /// Prompt for confirmation before performing an action.
     pub fn confirm(&mut self, prompt: &str, action: Action) {
         self.open_dialog(Dialog::OkCancel(prompt.to_owned(), action));
@@ -1499,6 +2024,11 @@ impl Ui {
             || matches!(self.dialog, Some(Dialog::Alert(_)))
     }
 
+    /// Returns true if a slider or combo box has keyboard focus.
+    pub fn keyboard_control_focused(&self) -> bool {
+        matches!(self.focus, Focus::Keyboard(_))
+    }
+
     pub fn accepting_note_input(&self) -> bool {
         matches!(self.focus, Focus::Note(_))
     }
@@ -1546,7 +2076,7 @@ impl Ui {
         let mut key = None;
         if focused {
             for evt in self.note_queue.iter() {
-                if let (k, EventData::Pitch(input_note)) = evt {
+                if let (k, EventData::Pitch(input_note), _) = evt {
                     *note = *input_note;
                     self.focus = Focus::None;
                     key = Some(k.clone());
@@ -1563,8 +2093,16 @@ impl Ui {
     }
 
     // TODO: code duplication with note_input
-    pub fn hotkey_input(&mut self, id: usize, hotkey: &mut Hotkey, info: Info) -> bool {
-        let label = hotkey.to_string();
+    /// Input for a hotkey. If `chords` is false, the first key pressed is
+    /// assigned as-is, without a prefix or layer.
+    pub fn hotkey_input(&mut self, id: usize, hotkey: &mut Hotkey, chords: bool,
+        info: Info
+    ) -> bool {
+        let label = match &self.hotkey_prefix {
+            Some((prefix, _)) if matches!(self.focus, Focus::Hotkey(s) if s == id) =>
+                format!("{prefix}, ..."),
+            _ => hotkey.to_string(),
+        };
         let margin = self.style.margin;
 
         let rect = Rect {
@@ -1592,11 +2130,31 @@ impl Ui {
             (self.style.theme.control_bg(), self.style.theme.border_unfocused())
         };
 
+        // a second key pressed soon after the first makes a two-stroke hotkey
         let mut changed = false;
         if focused {
             let key = get_keys_pressed().into_iter().find(|x| !is_mod(*x));
-            if let Some(key) = key {
-                *hotkey = Hotkey::new(Modifiers::current(), key);
+            let mut stroke = None;
+            if !chords {
+                stroke = key.map(|key| Hotkey::new(Modifiers::current(), key));
+            } else if let Some(key) = key {
+                let hk = Hotkey {
+                    layer: key != self.layer_key && is_key_down(self.layer_key),
+                    ..Hotkey::new(Modifiers::current(), key)
+                };
+                stroke = match self.hotkey_prefix.take() {
+                    Some((prefix, _)) => Some(hk.after(prefix)),
+                    None => {
+                        self.hotkey_prefix = Some((hk, get_time()));
+                        None
+                    }
+                };
+            } else if self.hotkey_prefix.as_ref()
+                .is_some_and(|(_, t)| get_time() - t > CHORD_INPUT_TIME) {
+                stroke = self.hotkey_prefix.take().map(|(hk, _)| hk);
+            }
+            if let Some(hk) = stroke {
+                *hotkey = hk;
                 self.focus = Focus::None;
                 changed = true;
             }
@@ -1684,21 +2242,26 @@ impl Ui {
         self.lost_focus = mem::take(&mut self.focus);
         self.focus = focus;
         self.pending_focus = None;
+        self.hotkey_prefix = None;
     }
 
     /// Pushes a note to the draw list. The notation is drawn in the space of
     /// 4 characters.
-    pub fn push_note_text(&mut self, x: f32, y: f32, note: &Note, color: Color) {
-        let base = format!("{}{}{}{}", note.arrow_char(), note.nominal.char(),
-            note.accidental_char(), note.equave);
+    pub fn push_note_text(&mut self, x: f32, y: f32, note: &Note, names: &NoteNames,
+        color: Color
+    ) {
+        let (arrow, arrows) = names.arrow_glyph(note);
+        let (accidental, sharps) = names.accidental_glyph(note);
+        let base = format!("{arrow}{}{accidental}{}",
+            names.nominal_char(note.nominal), note.equave);
 
-        if (3..).contains(&note.arrows.abs()) {
-            let s = text::digit_superscript(note.arrows.unsigned_abs()).to_string();
+        if let Some(n) = arrows {
+            let s = text::digit_superscript(n).to_string();
             self.push_text(x, y, s, color);
         }
 
-        if (3..).contains(&note.sharps.abs()) {
-            let s = text::digit_superscript(note.sharps.unsigned_abs()).to_string();
+        if let Some(n) = sharps {
+            let s = text::digit_superscript(n).to_string();
             self.push_text(x + self.style.atlas.char_width() * 2.0, y, s, color);
         }
 
@@ -1715,12 +2278,16 @@ impl Ui {
         if let Some(dialog) = &self.dialog {
             match dialog {
                 Dialog::Alert(s) => {
-                    let s = s.clone();
-                    let mut r = center(fit_strings(&self.style, &[s.clone()]));
+                    let lines: Vec<_> = s.lines().map(|s| s.to_owned()).collect();
+                    let mut r = center(fit_strings(&self.style, &lines));
                     r.h += self.style.margin;
                     self.push_rect(r, self.style.theme.panel_bg(),
                         Some(self.style.theme.border_unfocused()));
-                    self.push_text(r.x, r.y, s, self.style.theme.fg());
+                    let line_h = self.style.atlas.cap_height() + self.style.margin;
+                    for (i, line) in lines.into_iter().enumerate() {
+                        self.push_text(r.x, r.y + i as f32 * line_h, line,
+                            self.style.theme.fg());
+                    }
                     close = !self.dialog_first_frame && (is_any_key_pressed()
                         || (self.mouse_consumed.is_none()
                             && is_any_mouse_button_pressed()));
@@ -1841,7 +2408,7 @@ struct Notification {
     time_remaining: f32,
 }
 
-fn is_mod(key: KeyCode) -> bool {
+pub fn is_mod(key: KeyCode) -> bool {
     matches!(key,
         KeyCode::LeftAlt | KeyCode::RightAlt
         | KeyCode::LeftControl | KeyCode::RightControl
@@ -1849,6 +2416,74 @@ fn is_mod(key: KeyCode) -> bool {
         | KeyCode::LeftSuper | KeyCode::RightSuper)
 }
 
+/// Returns the unit of a slider's display text, e.g. "s" for "0.250 s".
+fn display_unit_name(display: &str) -> String {
+    display.split_whitespace().nth(1)
+        .filter(|s| s.chars().all(char::is_alphabetic))
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Parse slider text entry in the slider's display `unit`. Accepts a number
+/// or fraction, optionally followed by the unit with or without a metric
+/// prefix, e.g. "250ms" for seconds or "3.5kHz" for hertz.
+fn parse_entry(text: &str, unit: &str) -> Result<f32, String> {
+    let text = text.trim();
+    if let Ok(f) = text.parse::<f32>() {
+        return Ok(f)
+    }
+    let err = || format!("Could not parse \"{text}\"");
+
+    let (expr, suffix) = text.split_at(
+        text.trim_end_matches(|c: char| c.is_alphabetic()).len());
+    let scale = if suffix.is_empty() || suffix.eq_ignore_ascii_case(unit) {
+        1.0
+    } else {
+        let mut chars = suffix.chars();
+        let prefix = chars.next().ok_or_else(err)?;
+        if !chars.as_str().eq_ignore_ascii_case(unit) {
+            return Err(format!("Unknown unit \"{suffix}\""))
+        }
+        match prefix {
+            'k' | 'K' => 1e3,
+            'm' => 1e-3,
+            'u' | 'µ' => 1e-6,
+            _ => return Err(format!("Unknown unit \"{suffix}\"")),
+        }
+    };
+
+    let expr = expr.trim();
+    let value = match expr.split_once('/') {
+        Some((n, d)) => n.trim().parse::<f32>().ok()
+            .zip(d.trim().parse::<f32>().ok())
+            .filter(|(_, d)| *d != 0.0)
+            .map(|(n, d)| n / d),
+        None => expr.parse::<f32>().ok(),
+    };
+    value.map(|f| f * scale).ok_or_else(err)
+}
+
+/// Formats a duration in seconds as minutes, seconds, and milliseconds,
+/// e.g. "1:02.345".
+pub fn format_time(secs: f64) -> String {
+    let ms = (secs.max(0.0) * 1000.0).round() as u64;
+    format!("{}:{:02}.{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
+
+/// Parses a duration in seconds from text like "1:02.345", "62.345", or
+/// "1:00:00".
+pub fn parse_time(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let err = || format!("Could not parse \"{text}\"");
+    text.split(':').try_fold(0.0, |acc, part| {
+        part.trim().parse::<f64>().ok()
+            .filter(|f| *f >= 0.0)
+            .map(|f| acc * 60.0 + f)
+            .filter(|f| f.is_finite())
+            .ok_or_else(err)
+    })
+}
+
 /// Generates a `formatted_slider` display function for a unit.
 fn display_unit(unit: Option<&'static str>) -> Box<dyn Fn(f32) -> String> {
     if let Some(unit) = unit {
@@ -1859,6 +2494,70 @@ fn display_unit(unit: Option<&'static str>) -> Box<dyn Fn(f32) -> String> {
     }
 }
 
+/// Returns -1 or 1 if an arrow key was pressed to move a keyboard-focused
+/// control this frame, otherwise 0.
+fn key_offset() -> isize {
+    if is_key_pressed(KeyCode::Left) || is_key_pressed(KeyCode::Up) {
+        -1
+    } else if is_key_pressed(KeyCode::Right) || is_key_pressed(KeyCode::Down) {
+        1
+    } else {
+        0
+    }
+}
+
+// slider steps, as fractions of the slider's length
+const FINE_STEP: f32 = 0.002;
+const STEP: f32 = 0.02;
+const COARSE_STEP: f32 = 0.1;
+
+/// Moves a slider value by `step`, as a fraction of the slider's length.
+/// Returns true if the value changed.
+fn step_slider(val: &mut f32, range: &RangeInclusive<f32>, power: i32, step: f32
+) -> bool {
+    if step == 0.0 {
+        return false
+    }
+    let f = (deinterpolate(*val, range).powf(1.0/power as f32) + step)
+        .clamp(0.0, 1.0).powi(power);
+    let new_val = interpolate(f, range).max(*range.start()).min(*range.end());
+    let changed = new_val != *val;
+    *val = new_val;
+    changed
+}
+
+/// Returns the change in position of a keyboard-focused slider this frame,
+/// as a fraction of the slider's length. Shift gives fine steps and Ctrl or
+/// Page Up/Down give coarse steps.
+fn slider_key_step() -> f32 {
+    let step = if is_shift_down() {
+        FINE_STEP
+    } else if is_ctrl_down() {
+        COARSE_STEP
+    } else {
+        STEP
+    };
+
+    if is_key_pressed(KeyCode::Right) || is_key_pressed(KeyCode::Up) {
+        step
+    } else if is_key_pressed(KeyCode::Left) || is_key_pressed(KeyCode::Down) {
+        -step
+    } else if is_key_pressed(KeyCode::PageUp) {
+        COARSE_STEP
+    } else if is_key_pressed(KeyCode::PageDown) {
+        -COARSE_STEP
+    } else {
+        0.0
+    }
+}
+
+/// Returns the change in position of a hovered slider from the mouse wheel
+/// this frame. Shift gives fine steps.
+fn slider_wheel_step() -> f32 {
+    let step = if is_shift_down() { FINE_STEP } else { STEP };
+    mouse_wheel().1.signum() * step
+}
+
 /// Returns true if either Shift key is down.
 fn is_shift_down() -> bool {
     is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift)
@@ -1881,4 +2580,31 @@ fn is_any_key_pressed() -> bool {
 fn is_any_mouse_button_pressed() -> bool {
     [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
         .iter().cloned().any(is_mouse_button_pressed)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        assert_eq!(parse_entry("0.5", "s"), Ok(0.5));
+        assert_eq!(parse_entry("250ms", "s"), Ok(0.25));
+        assert_eq!(parse_entry("3.5kHz", "Hz"), Ok(3500.0));
+        assert_eq!(parse_entry("-6dB", "dB"), Ok(-6.0));
+        assert_eq!(parse_entry("1/4", ""), Ok(0.25));
+        assert!(parse_entry("250ms", "Hz").is_err());
+        assert!(parse_entry("1/0", "").is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        assert_eq!(format_time(62.345), "1:02.345");
+        assert_eq!(parse_time("1:02.5"), Ok(62.5));
+        assert_eq!(parse_time("90"), Ok(90.0));
+        assert_eq!(parse_time("1:00:00"), Ok(3600.0));
+        assert!(parse_time("1:x").is_err());
+        assert!(parse_time("inf").is_err());
+        assert!(parse_time("NaN").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Overlay listing the note layout and key commands.
+
+use crate::{config::Config, input::Action};
+
+use super::*;
+
+/// Mouse consumer ID for the click that dismisses the sheet.
+const ID: &str = "cheat_sheet";
+
+/// Checks for a click that dismisses the sheet, consuming it so that controls
+/// under the sheet don't receive it. Call before drawing the rest of the UI.
+/// Returns false if the sheet was dismissed.
+pub fn handle_click(ui: &mut Ui) -> bool {
+    if is_mouse_button_pressed(MouseButton::Left) {
+        ui.mouse_consumed = Some(ID.to_string());
+        return false
+    }
+    true
+}
+
+/// Draws the cheat sheet over the rest of the UI.
+pub fn draw(ui: &mut Ui, cfg: &Config) {
+    let margin = ui.style.margin;
+    let char_width = ui.style.atlas.char_width();
+    let line_h = ui.style.atlas.cap_height() + margin;
+    let (fg, header_fg) = (ui.style.theme.fg(), ui.style.theme.accent1_fg());
+
+    ui.cursor_z += PANEL_Z_OFFSET * 2;
+    let rect = Rect {
+        x: ui.bounds.x + margin * 4.0,
+        y: ui.bounds.y + margin * 4.0,
+        w: ui.bounds.w - margin * 8.0,
+        h: ui.bounds.h - margin * 8.0,
+    };
+    ui.push_rect(rect, ui.style.theme.panel_bg(), Some(ui.style.theme.border_focused()));
+
+    // note layout, in the same rows as the settings tab
+    let mut y = rect.y + margin;
+    ui.push_text(rect.x + margin, y, String::from("NOTE LAYOUT"), header_fg);
+    y += line_h;
+    let split = cfg.note_keys.len().min(17);
+    for keys in [&cfg.note_keys[split..], &cfg.note_keys[..split]] {
+        let cells: Vec<_> = keys.iter()
+            .map(|(hotkey, note)| (hotkey.to_string(), note.to_string()))
+            .collect();
+        let width = cells.iter()
+            .map(|(k, n)| k.chars().count().max(n.chars().count()))
+            .max()
+            .unwrap_or_default() + 1;
+        let (keys, notes): (String, String) = cells.iter()
+            .map(|(k, n)| (format!("{k:width$}"), format!("{n:width$}")))
+            .unzip();
+        ui.push_text(rect.x + margin, y, keys, fg);
+        ui.push_text(rect.x + margin, y + line_h, notes, header_fg);
+        y += line_h * 2.0 + margin;
+    }
+
+    // key commands, flowing into columns
+    let key_width = cfg.keymap().iter()
+        .map(|(k, _)| k.to_string().chars().count())
+        .max()
+        .unwrap_or_default() + 2;
+    let name_width = cfg.keymap().iter()
+        .map(|(_, a)| a.name().len())
+        .max()
+        .unwrap_or_default();
+    let col_w = (key_width + name_width) as f32 * char_width + margin * 4.0;
+    let top = y;
+    let mut x = rect.x + margin;
+    let mut push_line = |ui: &mut Ui, text: String, color: Color| {
+        if y + line_h > rect.y + rect.h {
+            x += col_w;
+            y = top;
+        }
+        ui.push_text(x, y, text, color);
+        y += line_h;
+    };
+    for category in Action::CATEGORIES {
+        push_line(ui, category.to_uppercase(), header_fg);
+        for (hotkey, action) in cfg.keymap().iter().filter(|(_, a)| a.category() == category) {
+            let key = hotkey.to_string();
+            push_line(ui, format!("{key:key_width$}{}", action.name()), fg);
+        }
+    }
+
+    ui.cursor_z -= PANEL_Z_OFFSET * 2;
+}
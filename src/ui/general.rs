@@ -1,7 +1,9 @@
+use std::path::PathBuf;
+
 use fundsp::math::{amp_db, db_amp};
 use info::Info;
 
-use crate::{config::{self, Config}, fx::{Compression, GlobalFX, SpatialFx}, module::Module, pitch::Tuning};
+use crate::{config::{self, Config}, diff::{self, Difference}, fx::{Compression, SpatialFx, Subsonic}, module::{Edit, Module, TrackTarget}, pitch::{NoteNames, Tuning}, playback::{RenderRange, PREVIEW_TIME}, timespan::Timespan, MODULE_EXT, MODULE_FILETYPE_NAME};
 
 use super::*;
 
@@ -10,6 +12,34 @@ use super::*;
 pub struct GeneralState {
     scroll: f32,
     table_cache: Option<TableCache>,
+    comparison: Option<Comparison>,
+    /// Module files queued in the playlist.
+    playlist: Vec<PathBuf>,
+    /// Span of the module used by WAV renders.
+    pub render_range: RenderRange,
+    /// Set when a render preview is requested.
+    pub preview_render: bool,
+    /// Tuning before the last tuning change, while retuning is offered.
+    pub retune_from: Option<Tuning>,
+    /// Set when the tutorial is requested.
+    pub start_tutorial: bool,
+    /// Index of the macro waiting for a hotkey to be assigned.
+    pub learning_macro: Option<usize>,
+}
+
+/// Module loaded for comparison.
+struct Comparison {
+    name: String,
+    module: Module,
+    diffs: Vec<Difference>,
+    rejected: Vec<Difference>,
+}
+
+impl Comparison {
+    fn update(&mut self, module: &Module) {
+        self.diffs = diff::diff(module, &self.module);
+        self.diffs.retain(|d| !self.rejected.contains(d));
+    }
 }
 
 /// Interval table cache.
@@ -18,34 +48,65 @@ struct TableCache {
     table: Vec<Vec<String>>,
 }
 
-/// Return values are (fx_changed, tuning_changed).
-pub fn draw(ui: &mut Ui, module: &mut Module, fx: &mut GlobalFX, cfg: &mut Config,
+/// Returns true if the tuning changed.
+pub fn draw(ui: &mut Ui, module: &mut Module, cfg: &mut Config,
     player: &mut PlayerShell, state: &mut GeneralState,
-) -> (bool, bool) {
+) -> bool {
     ui.layout = Layout::Horizontal;
     let old_y = ui.cursor_y;
     ui.cursor_y -= state.scroll;
     ui.cursor_z -= 1;
     ui.start_group();
 
+    if ui.button("Start tutorial", true, Info::Action(Action::StartTutorial)) {
+        state.start_tutorial = true;
+    }
+    ui.vertical_space();
     metadata_controls(ui, module);
     ui.vertical_space();
-    let mut fx_changed = spatial_fx_controls(ui, &mut module.fx.spatial, fx);
+    let old_fx = module.fx.clone();
+    let mut fx_changed = spatial_fx_controls(ui, &mut module.fx.spatial);
     ui.vertical_space();
-    fx_changed |= compression_controls(ui, &mut module.fx.comp, fx);
+    fx_changed |= compression_controls(ui, &mut module.fx.comp);
     ui.vertical_space();
-    let tuning_changed =
-        tuning_controls(ui, &mut module.tuning, cfg, player, &mut state.table_cache);
+    fx_changed |= subsonic_controls(ui, "master", &mut module.fx.subsonic);
+    if fx_changed {
+        let fx = std::mem::replace(&mut module.fx, old_fx);
+        module.push_gesture_edit(Edit::SetFX(fx));
+    }
+    ui.vertical_space();
+    let tuning_changed = tuning_controls(ui, &mut module.tuning, cfg, player,
+        &mut state.table_cache, &mut state.retune_from);
+    retune_controls(ui, module, &mut state.retune_from);
+    notation_controls(ui, &mut module.tuning.names);
     ui.vertical_space();
     interval_table(ui, &mut module.tuning, &mut state.table_cache);
+    ui.vertical_space();
+    render_controls(ui, module, &mut state.render_range, &mut state.preview_render);
+    if module.tracks.iter().any(|t| matches!(t.target, TrackTarget::Audio)) {
+        ui.vertical_space();
+        clip_controls(ui, module);
+    }
+    if !module.groups.is_empty() {
+        ui.vertical_space();
+        group_controls(ui, module);
+    }
+    if !module.macros.is_empty() {
+        ui.vertical_space();
+        macro_controls(ui, module, &mut state.learning_macro);
+    }
+    ui.vertical_space();
+    compare_controls(ui, module, cfg, player, &mut state.comparison);
+    ui.vertical_space();
+    playlist_controls(ui, cfg, player, &mut state.playlist);
 
     let scroll_h = ui.end_group().unwrap().h + ui.style.margin;
     ui.cursor_z += 1;
     ui.cursor_y = old_y;
     ui.vertical_scrollbar(&mut state.scroll,
         scroll_h, ui.bounds.y + ui.bounds.h - ui.cursor_y, true);
-    
-    (fx_changed, tuning_changed)
+
+    tuning_changed
 }
 
 fn metadata_controls(ui: &mut Ui, module: &mut Module) {
@@ -59,7 +120,7 @@ fn metadata_controls(ui: &mut Ui, module: &mut Module) {
 }
 
 /// Returns true if changes were made.
-fn spatial_fx_controls(ui: &mut Ui, spatial: &mut SpatialFx, fx: &mut GlobalFX) -> bool {
+fn spatial_fx_controls(ui: &mut Ui, spatial: &mut SpatialFx) -> bool {
     ui.header("SPATIAL FX", Info::None);
 
     let mut commit = false;
@@ -103,14 +164,26 @@ fn spatial_fx_controls(ui: &mut Ui, spatial: &mut SpatialFx, fx: &mut GlobalFX)
         }
     }
 
-    if commit {
-        fx.commit_spatial(&spatial);
+    commit
+}
+
+/// Returns true if changes were made. `id` distinguishes the slider from
+/// others of its kind.
+pub fn subsonic_controls(ui: &mut Ui, id: &str, subsonic: &mut Subsonic) -> bool {
+    ui.header("SUBSONIC FILTER", Info::Subsonic);
+
+    let mut commit = ui.checkbox("Enabled", &mut subsonic.enabled, true, Info::Subsonic);
+    if ui.formatted_slider(&format!("{id}_subsonic_cutoff"), "Cutoff", &mut subsonic.cutoff,
+        Subsonic::MIN_CUTOFF..=Subsonic::MAX_CUTOFF, 1, subsonic.enabled, Info::Subsonic,
+        |f| format!("{f:.0} Hz"), |f| f.round()) {
+        commit = true;
     }
+
     commit
 }
 
 /// Returns true if changes were made.
-fn compression_controls(ui: &mut Ui, comp: &mut Compression, fx: &mut GlobalFX) -> bool {
+fn compression_controls(ui: &mut Ui, comp: &mut Compression) -> bool {
     ui.header("COMPRESSION", Info::Compression);
 
     let mut commit = false;
@@ -146,15 +219,312 @@ fn compression_controls(ui: &mut Ui, comp: &mut Compression, fx: &mut GlobalFX)
         commit = true;
     }
 
-    if commit {
-        fx.commit_comp(comp);
-    }
     commit
 }
 
+fn clip_controls(ui: &mut Ui, module: &mut Module) {
+    ui.header("AUDIO CLIPS", Info::AudioClips);
+
+    let mut edit = None;
+
+    for (track_i, track) in module.tracks.iter().enumerate() {
+        for (clip_i, clip) in track.clips.iter().enumerate() {
+            ui.start_group();
+            ui.offset_label(&format!("Track {}, beat {:.2}: {}",
+                track_i, clip.tick.as_f64() + 1.0, clip.data.filename), Info::None);
+            ui.formatted_shared_slider(&format!("clip_{track_i}_{clip_i}_gain"), "Gain",
+                &clip.gain.0, 0.0..=2.0, 2, true, Info::None,
+                |x| format!("{:+.1} dB", amp_db(x)), db_amp);
+            ui.shared_slider(&format!("clip_{track_i}_{clip_i}_fade_in"), "Fade in",
+                &clip.fade_in.0, 0.0..=10.0, Some("s"), 2, true, Info::None);
+            ui.shared_slider(&format!("clip_{track_i}_{clip_i}_fade_out"), "Fade out",
+                &clip.fade_out.0, 0.0..=10.0, Some("s"), 2, true, Info::None);
+            if ui.button("X", true, Info::Remove("this clip")) {
+                let mut clips = track.clips.clone();
+                clips.remove(clip_i);
+                edit = Some(Edit::SetClips(track_i, clips));
+            }
+            ui.end_group();
+        }
+    }
+
+    if let Some(edit) = edit {
+        module.push_edit(edit);
+    }
+}
+
+fn render_controls(ui: &mut Ui, module: &Module, range: &mut RenderRange,
+    preview: &mut bool
+) {
+    ui.header("RENDER", Info::RenderRange);
+
+    let marker_name = |tick: Timespan| module.markers.iter()
+        .find(|m| m.tick == tick)
+        .map(|m| m.text.clone());
+    let beat_text = |tick: Timespan| format!("{}", tick.as_f64() + 1.0);
+
+    ui.start_group();
+    let text = marker_name(range.start).unwrap_or_else(|| if range.start == Timespan::ZERO {
+        String::from("Song start")
+    } else {
+        String::from("Beat")
+    });
+    if let Some(i) = ui.combo_box("render_start", "From", &text, Info::RenderRange,
+        || std::iter::once(String::from("Song start"))
+            .chain(module.markers.iter().map(|m| m.text.clone()))
+            .collect()) {
+        range.start = i.checked_sub(1).map_or(Timespan::ZERO, |i| module.markers[i].tick);
+    }
+    if let Some(s) = ui.id_edit_box("render_start_beat", "Beat", 8,
+        beat_text(range.start), Info::RenderRange) {
+        match s.parse::<f64>() {
+            Ok(f) if f >= 1.0 => range.start = Timespan::approximate(f - 1.0),
+            Ok(_) => ui.report("Beat must be at least 1"),
+            Err(e) => ui.report(e),
+        }
+    }
+    ui.end_group();
+
+    ui.start_group();
+    let text = match range.end {
+        Some(tick) => marker_name(tick).unwrap_or(String::from("Beat")),
+        None => String::from("End event"),
+    };
+    if let Some(i) = ui.combo_box("render_end", "To", &text, Info::RenderRange,
+        || std::iter::once(String::from("End event"))
+            .chain(module.markers.iter().map(|m| m.text.clone()))
+            .collect()) {
+        range.end = i.checked_sub(1).map(|i| module.markers[i].tick);
+    }
+    if let Some(s) = ui.id_edit_box("render_end_beat", "Beat", 8,
+        range.end.map(beat_text).unwrap_or_default(), Info::RenderRange) {
+        if s.is_empty() {
+            range.end = None;
+        } else {
+            match s.parse::<f64>() {
+                Ok(f) if f >= 1.0 => range.end = Some(Timespan::approximate(f - 1.0)),
+                Ok(_) => ui.report("Beat must be at least 1"),
+                Err(e) => ui.report(e),
+            }
+        }
+    }
+    ui.end_group();
+
+    if ui.button(&format!("Preview {PREVIEW_TIME} seconds"), true, Info::PreviewRender) {
+        *preview = true;
+    }
+}
+
+fn group_controls(ui: &mut Ui, module: &mut Module) {
+    ui.header("TRACK GROUPS", Info::TrackGroups);
+
+    let mut edit = None;
+    let mut volume_edit = None;
+
+    for (i, group) in module.groups.iter().enumerate() {
+        let tracks = group.tracks();
+        ui.start_group();
+        if let Some(s) = ui.edit_box(&format!("Tracks {}-{}", tracks.start, tracks.end - 1),
+            16, group.name.clone(), Info::None) {
+            let mut groups = module.groups.clone();
+            groups[i].name = s;
+            edit = Some(Edit::SetGroups(groups));
+        }
+        let mut volume = group.volume.0.value();
+        if ui.formatted_slider(&format!("group_{i}_volume"), "Volume",
+            &mut volume, 0.0..=2.0, 2, true, Info::None,
+            |x| format!("{:+.1} dB", amp_db(x)), db_amp) {
+            volume_edit = Some(Edit::SetGroupVolume(i, volume));
+        }
+        if ui.button("X", true, Info::Remove("this group")) {
+            let mut groups = module.groups.clone();
+            groups.remove(i);
+            edit = Some(Edit::SetGroups(groups));
+        }
+        ui.end_group();
+    }
+
+    if let Some(edit) = edit {
+        module.push_edit(edit);
+    } else if let Some(edit) = volume_edit {
+        module.push_gesture_edit(edit);
+    }
+}
+
+fn macro_controls(ui: &mut Ui, module: &mut Module, learning: &mut Option<usize>) {
+    ui.header("MACROS", Info::Macros);
+
+    let mut edit = None;
+
+    for (i, m) in module.macros.iter().enumerate() {
+        ui.start_group();
+        if let Some(s) = ui.id_edit_box(&format!("macro_{i}_name"), "Name", 16,
+            m.name.clone(), Info::Macros) {
+            let mut macros = module.macros.clone();
+            macros[i].name = s;
+            edit = Some(Edit::SetMacros(macros));
+        }
+        let hotkey = if *learning == Some(i) {
+            "(press key)".to_string()
+        } else {
+            m.hotkey.as_ref().map_or("(no hotkey)".to_string(), |k| k.to_string())
+        };
+        ui.offset_label(&hotkey, Info::MacroHotkey);
+        if ui.button("Learn", true, Info::MacroHotkey) {
+            *learning = Some(i);
+        }
+        if ui.button("X", true, Info::Remove("this macro")) {
+            let mut macros = module.macros.clone();
+            macros.remove(i);
+            edit = Some(Edit::SetMacros(macros));
+            *learning = None;
+        }
+        ui.end_group();
+    }
+
+    if let Some(edit) = edit {
+        module.push_edit(edit);
+    }
+}
+
+/// Controls for comparing and merging with another module.
+fn compare_controls(ui: &mut Ui, module: &mut Module,
+    cfg: &mut Config, player: &mut PlayerShell, comparison: &mut Option<Comparison>,
+) {
+    ui.header("COMPARE", Info::CompareModule);
+
+    ui.start_group();
+    if ui.button("Load module", true, Info::CompareModule) {
+        let dir = cfg.module_folder.clone().unwrap_or(String::from("."));
+        let dialog = new_file_dialog(player)
+            .add_filter(MODULE_FILETYPE_NAME, &[MODULE_EXT])
+            .set_directory(dir);
+        if let Some(path) = dialog.pick_file() {
+            cfg.module_folder = config::dir_as_string(&path);
+            match Module::load(&path) {
+                Ok(other) => {
+                    let mut c = Comparison {
+                        name: path.file_name()
+                            .map(|s| s.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                        module: other,
+                        diffs: Vec::new(),
+                        rejected: Vec::new(),
+                    };
+                    c.update(module);
+                    *comparison = Some(c);
+                }
+                Err(e) => ui.report(format!("Error loading module: {e}")),
+            }
+        }
+    }
+    if comparison.is_some() && ui.button("Close", true, Info::None) {
+        *comparison = None;
+    }
+    ui.end_group();
+
+    let Some(c) = comparison else {
+        return
+    };
+
+    if c.diffs.is_empty() {
+        ui.label(&format!("No differences from {}.", c.name), Info::None);
+        return
+    }
+
+    let mut accepted = None;
+    for &d in &c.diffs {
+        ui.start_group();
+        if ui.button("Accept", true, Info::AcceptDifference) {
+            accepted = Some(d);
+        }
+        if ui.button("Reject", true, Info::RejectDifference) {
+            c.rejected.push(d);
+        }
+        ui.offset_label(&d.describe(module, &c.module), Info::None);
+        ui.end_group();
+    }
+
+    if let Some(d) = accepted {
+        diff::accept(module, &c.module, d);
+        if let Difference::Track(_) = d {
+            player.update_synths(module.drain_track_history());
+        }
+    }
+    if accepted.is_some() || c.diffs.iter().any(|d| c.rejected.contains(d)) {
+        c.update(module);
+    }
+}
+
+/// Controls for playing other modules back to back.
+fn playlist_controls(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell,
+    playlist: &mut Vec<PathBuf>,
+) {
+    ui.header("PLAYLIST", Info::Playlist);
+
+    let position = player.playlist_position();
+
+    ui.start_group();
+    if ui.button("Add modules", position.is_none(), Info::Playlist) {
+        let dir = cfg.module_folder.clone().unwrap_or(String::from("."));
+        let dialog = new_file_dialog(player)
+            .add_filter(MODULE_FILETYPE_NAME, &[MODULE_EXT])
+            .set_directory(dir);
+        if let Some(paths) = dialog.pick_files() {
+            if let Some(path) = paths.first() {
+                cfg.module_folder = config::dir_as_string(path);
+            }
+            playlist.extend(paths);
+        }
+    }
+    if position.is_some() {
+        if ui.button("Stop", true, Info::None) {
+            player.stop_playlist();
+        }
+    } else if ui.button("Play", !playlist.is_empty(), Info::None) {
+        let modules: Result<Vec<_>, _> = playlist.iter().map(Module::load).collect();
+        match modules {
+            Ok(modules) => player.play_playlist(modules, cfg.playlist_crossfade as f64),
+            Err(e) => ui.report(format!("Error loading module: {e}")),
+        }
+    }
+    ui.slider("playlist_crossfade", "Crossfade", &mut cfg.playlist_crossfade,
+        0.0..=10.0, Some("s"), 2, position.is_none(), Info::PlaylistCrossfade);
+    ui.end_group();
+
+    let mut removed = None;
+    for (i, path) in playlist.iter().enumerate() {
+        ui.start_group();
+        if ui.button("X", position.is_none(), Info::Remove("this module")) {
+            removed = Some(i);
+        }
+        let name = path.file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let marker = if position == Some(i) { ">" } else { " " };
+        ui.offset_label(&format!("{marker} {name}"), Info::None);
+        ui.end_group();
+    }
+    if let Some(i) = removed {
+        playlist.remove(i);
+    }
+}
+
+/// Records a tuning change for retuning. If the tuning was changed back to
+/// the one that retuning would start from, retuning is no longer offered.
+fn record_tuning_change(old: Tuning, new: &Tuning, retune_from: &mut Option<Tuning>) {
+    match retune_from {
+        Some(from) if from == new => *retune_from = None,
+        Some(_) => (),
+        None if old != *new => *retune_from = Some(old),
+        None => (),
+    }
+}
+
 /// Returns true if changes were made.
 fn tuning_controls(ui: &mut Ui, tuning: &mut Tuning, cfg: &mut Config,
-    player: &mut PlayerShell, table_cache: &mut Option<TableCache>
+    player: &mut PlayerShell, table_cache: &mut Option<TableCache>,
+    retune_from: &mut Option<Tuning>
 ) -> bool {
     const OCTAVE_CHARS: usize = 7;
 
@@ -166,7 +536,8 @@ fn tuning_controls(ui: &mut Ui, tuning: &mut Tuning, cfg: &mut Config,
         match s.parse() {
             Ok(ratio) => match Tuning::divide(ratio, tuning.size(), tuning.arrow_steps) {
                 Ok(t) => {
-                    *tuning = t;
+                    let new = Tuning { names: tuning.names.clone(), ..t };
+                    record_tuning_change(std::mem::replace(tuning, new), tuning, retune_from);
                     *table_cache = None;
                 }
                 Err(e) => ui.report(e),
@@ -181,7 +552,8 @@ fn tuning_controls(ui: &mut Ui, tuning: &mut Tuning, cfg: &mut Config,
         match s.parse() {
             Ok(steps) => match Tuning::divide(tuning.equave(), steps, tuning.arrow_steps) {
                 Ok(t) => {
-                    *tuning = t;
+                    let new = Tuning { names: tuning.names.clone(), ..t };
+                    record_tuning_change(std::mem::replace(tuning, new), tuning, retune_from);
                     *table_cache = None;
                 }
                 Err(e) => ui.report(e),
@@ -195,7 +567,9 @@ fn tuning_controls(ui: &mut Ui, tuning: &mut Tuning, cfg: &mut Config,
     ) {
         match s.parse() {
             Ok(steps) => {
+                let old = tuning.clone();
                 tuning.arrow_steps = steps;
+                record_tuning_change(old, tuning, retune_from);
                 *table_cache = None;
             }
             Err(e) => ui.report(e),
@@ -212,14 +586,17 @@ fn tuning_controls(ui: &mut Ui, tuning: &mut Tuning, cfg: &mut Config,
             cfg.scale_folder = config::dir_as_string(&path);
             match Tuning::load(path, tuning.root) {
                 Ok(t) => {
-                    *tuning = t;
+                    let new = Tuning { names: tuning.names.clone(), ..t };
+                    record_tuning_change(std::mem::replace(tuning, new), tuning, retune_from);
                     *table_cache = None;
                 }
                 Err(e) => ui.report(format!("Error loading scale: {e}")),
             }
         }
     }
+    let root = tuning.root;
     if ui.note_input("root", &mut tuning.root, Info::TuningRoot).is_some() {
+        record_tuning_change(Tuning { root, ..tuning.clone() }, tuning, retune_from);
         *table_cache = None;
     }
     ui.offset_label("Scale root", Info::TuningRoot);
@@ -228,6 +605,55 @@ fn tuning_controls(ui: &mut Ui, tuning: &mut Tuning, cfg: &mut Config,
     table_cache.is_none()
 }
 
+/// Edits the custom characters used for notation.
+fn notation_controls(ui: &mut Ui, names: &mut NoteNames) {
+    let nominals = NoteNames::ORDER.iter().map(|n| names.nominal_char(*n)).collect();
+    if let Some(s) = ui.edit_box("Nominal names", 7, nominals, Info::NoteNames) {
+        let chars: Vec<_> = s.chars().collect();
+        if chars.is_empty() {
+            names.nominals = Default::default();
+        } else if chars.len() == names.nominals.len() {
+            let pairs = names.nominals.iter_mut().zip(chars).zip(NoteNames::ORDER);
+            for ((name, c), nominal) in pairs {
+                *name = (c != nominal.char()).then_some(c);
+            }
+        } else {
+            ui.report("Nominal names must be 7 characters, C to B");
+        }
+    }
+
+    ui.start_group();
+    for (label, c) in [
+        ("Sharp", &mut names.sharp),
+        ("Flat", &mut names.flat),
+        ("Up", &mut names.up),
+        ("Down", &mut names.down),
+    ] {
+        if let Some(s) = ui.edit_box(label, 1, c.map(String::from).unwrap_or_default(),
+            Info::NoteNames
+        ) {
+            *c = s.chars().next();
+        }
+    }
+    ui.end_group();
+}
+
+/// Offers to retune pattern notes after a tuning change.
+fn retune_controls(ui: &mut Ui, module: &mut Module, retune_from: &mut Option<Tuning>) {
+    let Some(old) = retune_from else { return };
+
+    ui.start_group();
+    if ui.button("Retune notes", true, Info::RetuneNotes) {
+        let n = module.retune_notes(old);
+        ui.notify(format!("Retuned {n} note(s)."));
+        *retune_from = None;
+    }
+    if ui.button("Keep notation", true, Info::None) {
+        *retune_from = None;
+    }
+    ui.end_group();
+}
+
 fn interval_table(ui: &mut Ui, tuning: &mut Tuning, table_cache: &mut Option<TableCache>) {
     ui.header("INVERVAL TABLE", Info::None);
     ui.start_group();
@@ -8,6 +8,9 @@ pub enum Info {
     OctaveSteps,
     ArrowSteps,
     Division,
+    DivisionPresets,
+    EditStep(&'static str),
+    SelectionSummary,
     Octave,
     DelayTime,
     DelayFeedback,
@@ -16,13 +19,23 @@ pub enum Info {
     CompRatio,
     CompAttack,
     CompRelease,
+    Subsonic,
     StereoWidth,
     Gamma,
     Chroma,
+    ColorRole,
+    Inspector,
+    HighContrast,
+    LowContrast,
     GlideTime,
+    FixedSeed,
+    NewSeed,
     Distortion,
     FxSend,
     LoopPoint,
+    StreamSample,
+    ReferenceSample,
+    CollectSamples,
     Tone,
     FreqRatio,
     FilterCutoff,
@@ -46,15 +59,75 @@ pub enum Info {
     ResetTheme(&'static str),
     FontSize(&'static str),
     ResetSettings,
+    ExportSettings,
+    ImportSettings,
+    KeyProfile,
     UseAftertouch,
     UseVelocity,
+    VelocityCurve,
+    VelocityExponent,
+    VelocityScale,
     TuningRoot,
     KitNoteIn,
     KitNoteOut,
+    KitName,
     Action(Action),
     GlobalTrack,
     KitTrack,
+    AudioTrack,
+    AddClip,
+    AudioClips,
+    RenderRange,
+    PreviewRender,
+    RetuneNotes,
+    NoteNames,
+    LayerKey,
+    MiddleDragPan,
+    WheelAdjustsSliders,
+    DoubleClick,
+    ScrollSpeed,
+    LaneTool,
+    TrackGroups,
+    Macros,
+    MacroHotkey,
+    SongTime,
+    Performance,
+    BarBeats,
+    ReleaseSection,
+    QueueSection,
+    SectionTrigger,
+    TrackGroup,
+    CollapseGroup,
+    GroupMute,
+    GroupSolo,
     MidiInput,
+    MidiChannelFilter,
+    MidiInputTrack,
+    MidiRoute,
+    KeyboardSplit,
+    SplitOctave,
+    SplitTrack,
+    SplitPatch,
+    RatioPreset,
+    FixedFreq,
+    FmRatio,
+    AmDepth,
+    NoiseColor,
+    DrumDecay,
+    DrumCharacter,
+    ModPolarity,
+    ModInvert,
+    ModSteps,
+    ModSlew,
+    KeyTrackCenter,
+    KeyTrackSlope,
+    Snapshots,
+    StoreSnapshot,
+    RecallSnapshot,
+    MorphEndpoints,
+    Morph,
+    SurfaceLayout,
+    SurfaceOutput,
     SpatialFxType,
     KitPatch,
     Waveform,
@@ -64,6 +137,8 @@ pub enum Info {
     ModSource,
     ModDest,
     TrackPatch,
+    TrackTranspose,
+    TrackDetune,
     SmoothPlayhead,
     ControlColumn,
     NoteColumn,
@@ -83,16 +158,70 @@ pub enum Info {
     HorizontalScrollbar,
     SaveTheme,
     LoadTheme,
+    ThemesFolder,
+    UiScale,
+    LoadFont,
+    BuiltinFont,
+    PatternFont,
+    AutoScale,
+    HexColor,
     InstrumentList,
     Font,
     Oversample,
     DuplicateKitEntry,
     LfoAudioRate,
     KeyjazzModulation,
+    Tuner,
     FollowCheckbox,
+    Markers,
     RenderFormat,
+    RenderQuality,
+    PatchOversampling,
     Autosave,
     TrimSamples,
+    VoiceLimit,
+    StealPolicy,
+    Minimap,
+    SmoothScroll,
+    PlayheadPosition,
+    RowNumbers,
+    RowHighlight,
+    LockCursor,
+    PasteSpecial,
+    PasteRepeat,
+    PasteTranspose,
+    QuantizeStrength,
+    Swing,
+    Groove,
+    ExtractGroove,
+    TrackLock,
+    KeyjazzLatch,
+    LearnChord,
+    ClearChord,
+    QuantizePreview,
+    Dither,
+    NoiseShaping,
+    NormalizeLoudness,
+    DetectSamplePitch,
+    WatchSamples,
+    SampleRoot,
+    SliceSample,
+    GranularSample,
+    SampleSpeed,
+    GrainSize,
+    LoopMode,
+    LoopCrossfade,
+    CompareModule,
+    AcceptDifference,
+    RejectDifference,
+    PatchUsage,
+    JumpToUsage,
+    MergeDuplicatePatches,
+    RemoveUnusedPatches,
+    ShapePreview,
+    InstrumentPanel,
+    Playlist,
+    PlaylistCrossfade,
 }
 
 impl Default for Info {
@@ -125,6 +254,17 @@ pub fn text(info: &Info, ctrl: &ControlInfo, conf: &Config) -> String {
     // keep max line width around 50 chars
     match info {
         Info::None => (),
+        Info::VoiceLimit => text =
+"Maximum number of voices per channel, including
+voices in the release phase. When the limit is
+reached, a voice is cut to make room. Renders use
+the default limit unless the patch sets one.".to_string(),
+        Info::StealPolicy => text =
+"Which voice to cut when the voice limit is reached.
+Releasing voices are always cut before held voices.
+Same pitch falls back to oldest if no voice matches
+the new note. Renders use the default policy unless
+the patch sets one.".to_string(),
         Info::TrimSamples => text =
 "Trim leading & trailing silence when loading PCM
 samples.".to_string(),
@@ -135,10 +275,45 @@ been made.".to_string(),
         Info::RenderFormat => text =
 "Format to use for audio renders. 16-bit uses integer
 encoding; 32-bit uses float encoding.".to_string(),
+        Info::RenderQuality => text =
+"Oversampling of generators and filters in renders.
+Higher factors reduce aliasing but render slower.
+Per patch uses each patch's own setting.".to_string(),
+        Info::PatchOversampling => text =
+"Run generators and filters at a multiple of the
+sample rate during playback, to reduce aliasing.
+Renders can override this in settings.".to_string(),
+        Info::Dither => text =
+"Add low-level noise to 16-bit renders, which masks
+distortion in quiet passages and fades.".to_string(),
+        Info::NoiseShaping => text =
+"Move dither noise toward high frequencies, where
+it is less audible.".to_string(),
+        Info::NormalizeLoudness => text =
+"Scale renders to a target integrated loudness.
+Measured loudness and peak level are reported
+after each render regardless.".to_string(),
         Info::FollowCheckbox => {
             text = "Toggle whether the pattern view tracks the playhead.".to_string();
             actions.push(Action::ToggleFollow);
         }
+        Info::InstrumentPanel => {
+            text = "Toggle whether the instrument editor is shown beside the pattern."
+                .to_string();
+            actions.push(Action::ToggleInstrumentPanel);
+        }
+        Info::Playlist => text =
+"Queue module files to play back to back. Each
+module plays until it ends or first loops. The
+playlist is separate from the module being edited.".to_string(),
+        Info::PlaylistCrossfade => text =
+"Time to crossfade between playlist modules. At
+zero, each module cuts straight to the next.".to_string(),
+        Info::Tuner => text =
+"Frequency of the last played note, its offset in
+cents from the nearest 12-ET note, and its just
+ratio above the tuning root if it is near a simple
+one.".to_string(),
         Info::KeyjazzModulation =>
             text = "Modulation level used for keyboard notes.".to_string(),
         Info::DuplicateKitEntry =>
@@ -146,18 +321,39 @@ encoding; 32-bit uses float encoding.".to_string(),
         Info::LfoAudioRate =>
             text = "Oscillate at audio rate, i.e. at audible frequencies.".to_string(),
         Info::Oversample => text =
-"Run the generator at twice the normal sample rate.
+"Run the generator at no less than twice the normal
+sample rate, regardless of patch oversampling.
 Mainly useful for avoiding inharmonic artifacts in
 high-pitched modulators.".to_string(),
         Info::Font =>
             text = "Font is a modified version of Dina by Jørgen Ibsen.".to_string(),
         Info::InstrumentList => text =
-"Patch list. Right-click to edit names.
+"Patch list. Drag to reorder, or right-click for
+patch options.
 
 Up - Previous entry
 Down - Next entry".to_string(),
         Info::LoadTheme => text = "Load color theme from disk.".to_string(),
         Info::SaveTheme => text = "Save color theme to disk.".to_string(),
+        Info::LoadFont => text =
+"Use a TrueType or OpenType font. Characters missing
+from the font are drawn with the built-in font.".to_string(),
+        Info::BuiltinFont => text = "Use the built-in bitmap font.".to_string(),
+        Info::PatternFont => text =
+"Built-in font size for the pattern grid. If unset,
+the pattern uses the interface font.".to_string(),
+        Info::UiScale => text =
+"Scale factor for the whole interface. Whole-number
+values keep the font sharpest.".to_string(),
+        Info::AutoScale => text =
+"If enabled, choose the UI scale based on display
+DPI.".to_string(),
+        Info::ThemesFolder => text =
+"Load a theme from the last folder a theme was saved
+to or loaded from.".to_string(),
+        Info::HexColor => text =
+"Color as RGB hex. Lightness is ignored for accent
+colors.".to_string(),
         Info::VerticalScrollbar => text =
 "Vertical scrollbar.
 
@@ -212,6 +408,9 @@ use a different interval as the scale period.".to_string(),
 By default an arrow means one step, but in large
 tunings it may be useful to notate multiple steps
 with one arrow.".to_string(),
+        Info::Markers => text =
+"Jump to a marker in the pattern. Markers can be
+added with the edit marker hotkey.".to_string(),
         Info::Division => {
             text =
 "Current number of rows per beat.
@@ -220,8 +419,20 @@ Ctrl+Scroll - Inc/dec division
 Ctrl+Alt+Scroll - Double/halve division".to_string();
             custom_actions = true;
             actions = vec![Action::IncrementDivision, Action::DecrementDivision,
-                Action::HalveDivision, Action::DoubleDivision];
+                Action::HalveDivision, Action::DoubleDivision, Action::ToggleTriplets];
+        },
+        Info::EditStep(columns) => text = format!(
+"Rows to move the cursor down after entering data
+in the {columns}. Saved with the song."),
+        Info::SelectionSummary => {
+            text =
+"Length of the selection in beats and seconds,
+number of events by kind, and range of pitches.".to_string();
+            actions.push(Action::AnalyzeIntervals);
         },
+        Info::DivisionPresets => text =
+"Set the division to a common grid: straight
+sixteenths, triplets, or quintuplets.".to_string(),
         Info::Octave => {
             text = "Current octave for note input.".to_string();
             actions = vec![Action::IncrementOctave, Action::DecrementOctave];
@@ -242,6 +453,9 @@ when the input level rises.".to_string(),
         Info::CompRelease => text =
 "Approximate time the compressor takes to disengage
 when the input level falls.".to_string(),
+        Info::Subsonic => text =
+"High-pass filter that removes DC offset and rumble
+below the cutoff, e.g. from FM or ring modulation.".to_string(),
         Info::StereoWidth => text =
 "Multiplier to instrument pan values. Can be used
 to check the mono mix, or to reverse panning. Does
@@ -252,6 +466,26 @@ make value differences look approximately uniform.".to_string(),
         Info::Chroma => text =
 "Colorfulness. Different hues reach full saturation
 at different points in the 130-180 range.".to_string(),
+        Info::Inspector => {
+            text = "Description of the events on the cursor row.".to_string();
+            actions.push(Action::CopyInspector);
+        }
+        Info::ColorRole => text =
+"Override the color of this kind of pattern data.
+Unset roles use colors from the theme. Roles are kept
+when the theme is reset or loaded.".to_string(),
+        Info::HighContrast => text =
+"Use black and white for foreground and background
+text, keeping the accent hues.".to_string(),
+        Info::LowContrast => text =
+"These colors have less than 4.5:1 contrast with the
+pattern background, and may be hard to read.".to_string(),
+        Info::FixedSeed => text =
+"Randomize voices in the same sequence on every play,
+so that noise and random modulation render the same
+way each time.".to_string(),
+        Info::NewSeed =>
+            text = "Pick a different fixed random sequence.".to_string(),
         Info::GlideTime => text =
 "Approximate time the patch takes to glide to new
 pitches.".to_string(),
@@ -263,9 +497,19 @@ pitches.".to_string(),
 "Position where loop begins. Snaps to values with
 smaller discontinuities. Loop end point is always
 the end of the sample.".to_string(),
+        Info::StreamSample => text =
+"If enabled, the sample is played from disk instead
+of kept in memory. Long samples stream by default.".to_string(),
+        Info::ReferenceSample => text =
+"If enabled, the module stores the path to the
+sample file instead of the sample data.".to_string(),
+        Info::CollectSamples => text =
+"Copy referenced sample files to a \"samples\" folder
+next to the module file.".to_string(),
         Info::Tone => text =
 "For pulse waves, sets the duty cycle. For noise,
-mixes between pink and white noise.".to_string(),
+mixes between pink and white noise, or sets the
+clock rate of retro noise.".to_string(),
         Info::FreqRatio => text =
 "Frequency ratio of this generator compared to the
 base frequency of the note. Integer values give
@@ -309,6 +553,64 @@ use less space in a save file.".to_string(),
             text = "Load the previous sample in the directory.".to_string(),
         Info::NextSample =>
             text = "Load the next sample in the directory.".to_string(),
+        Info::LoopMode => text =
+"Forward loops jump back to the loop point; ping-pong
+loops alternate direction. Streamed samples always
+loop forward.".to_string(),
+        Info::CompareModule => text =
+"Load another module and list how it differs from
+this one, so that changes can be merged.".to_string(),
+        Info::AcceptDifference => text =
+"Merge this difference into the current module.
+Pattern and track changes can be undone.".to_string(),
+        Info::RejectDifference => text =
+"Dismiss this difference, keeping the current
+module's version.".to_string(),
+        Info::PatchUsage => text =
+"List every note in the pattern that triggers the
+selected patch, including notes mapped through the
+kit.".to_string(),
+        Info::JumpToUsage => text =
+"Click to jump to this note in the Pattern tab.".to_string(),
+        Info::MergeDuplicatePatches => text =
+"Merge patches with identical settings into the
+first of each set, updating tracks and kit entries
+that use them. Names are ignored.".to_string(),
+        Info::RemoveUnusedPatches => text =
+"Remove patches that aren't assigned to any track
+or kit entry.".to_string(),
+        Info::ShapePreview => text =
+"Preview of the shape, based on current settings.
+Modulation is not shown.".to_string(),
+        Info::LoopCrossfade => text =
+"Blend the end of the loop into the audio before the
+loop point, smoothing clicks at the wrap.".to_string(),
+        Info::GranularSample => text =
+"Play the sample as overlapping grains, so that
+speed and pitch are independent. Not available for
+streamed samples.".to_string(),
+        Info::SampleSpeed => text =
+"Playback speed of the stretched sample, relative
+to the original.".to_string(),
+        Info::GrainSize => text =
+"Length of each grain. Shorter grains suit
+percussive material; longer grains suit sustained
+tones.".to_string(),
+        Info::SliceSample => text =
+"Split the patch's sample at its transients into
+new patches, mapped to consecutive kit notes. Use
+with drum loops to rearrange beats.".to_string(),
+        Info::DetectSamplePitch => text =
+"Detect the pitch of loaded samples that don't
+specify a root note, and tune the oscillator to
+match.".to_string(),
+        Info::WatchSamples => text =
+"Reload referenced samples when their files are
+changed by another program, such as a sample
+editor. Reloads can be undone.".to_string(),
+        Info::SampleRoot => text =
+"MIDI pitch at which the sample plays at its
+original speed. Edit to tune the sample manually.".to_string(),
         Info::DetectPitch => text =
 "Attempt to automatically set the sample pitch to
 match the default oscillator pitch. Works best with
@@ -319,12 +621,30 @@ harmonic spectra and strong fundamentals.".to_string(),
             format!("Reset colors to the default {variant} theme."),
         Info::FontSize(op) => text = format!("{op} font size."),
         Info::ResetSettings => text = "Reset all settings to defaults.".to_string(),
+        Info::ExportSettings => text =
+"Save settings, theme, and key commands to a file
+that can be imported on another machine. Folder
+paths are not included.".to_string(),
+        Info::ImportSettings => text =
+"Load settings from an exported file. Folder paths
+are kept.".to_string(),
+        Info::KeyProfile => text =
+"Replace all key commands with a built-in set.
+Note layout is not affected.".to_string(),
         Info::UseAftertouch => text =
 "If enabled, convert channel pressure and key pressure
 messages to pressure values.".to_string(),
         Info::UseVelocity => text =
 "If enabled, convert velocity messages to pressure
 values.".to_string(),
+        Info::VelocityCurve => text =
+"Response to MIDI velocity. Fixed ignores velocity.".to_string(),
+        Info::VelocityExponent => text =
+"Values above 1 require harder playing for high
+pressure. Values below 1 make soft playing louder.".to_string(),
+        Info::VelocityScale => text =
+"Pressure is the curve output times scale, plus
+offset.".to_string(),
         Info::TuningRoot => text =
 "Determines which note is mapped to the start of
 the loaded scale. For equal-step scales, this has
@@ -333,11 +653,23 @@ no effect.".to_string(),
             text = "The note that activates this kit mapping.".to_string(),
         Info::KitNoteOut =>
             text = "The pitch that this kit mapping plays at.".to_string(),
+        Info::KitName => text =
+"Short name, such as BD or SD, shown in place of
+the input note in kit tracks.".to_string(),
         Info::Action(action) => match action {
             Action::ShiftTrackLeft =>
                 text = "Move the selected track to the left.".to_string(),
             Action::ShiftTrackRight =>
                 text = "Move the selected track to the right.".to_string(),
+            Action::EditMarker => text =
+"Edit the text marker at the cursor row. Markers
+are shown to the right of the tracks and have no
+effect on playback. Submit empty text to remove a
+marker.".to_string(),
+            Action::GroupTracks => text =
+"Group the selected tracks. Groups can be collapsed
+and have shared mute, solo, and volume controls.
+Grouping replaces any overlapping groups.".to_string(),
             Action::RenderTracks => text =
 "Render each track to WAV. Compression will be
 applied on a per-track basis.".to_string(),
@@ -354,6 +686,10 @@ applied on a per-track basis.".to_string(),
             Action::PlayFromCursor =>
                 text = "Play/stop from the pattern cursor.".to_string(),
             Action::RenderSong => text = "Render song to WAV.".to_string(),
+            Action::ExportVideo => text =
+"Render a video with an oscilloscope for each track.
+Saving as MP4 requires ffmpeg. Saving as PNG writes
+numbered frames and a WAV of the song.".to_string(),
             Action::Undo => text = "Undo last pattern action.".to_string(),
             Action::Redo => text = "Redo last undone pattern action.".to_string(),
             Action::MixPaste => text =
@@ -369,6 +705,11 @@ in a Ctrl channel.".to_string(),
 "Mark the beginning loop point of the song. If this
 marker is present, the song will return to it when
 the End marker is reached. Can only be placed in a
+Ctrl channel.".to_string(),
+            Action::Skip => text =
+"Mark the start of a section to leave out of renders,
+e.g. unfinished parts. The section ends at the next
+marker or Section event. Can only be placed in a
 Ctrl channel.".to_string(),
             Action::TapTempo => text =
 "Insert a tempo change event. Tap in time to set
@@ -413,6 +754,16 @@ Enharmonic notes have unequal values in most tunings.".to_string(),
             Action::PlaceEvenly => text =
 "Place selected events evenly across the selected
 timespan.".to_string(),
+            Action::OffsetLater => text =
+"Move selected events later by 1/8 of a row, or
+events in the cursor row if nothing is selected.
+Events that would land on another event stay put.
+Offset events are dimmed.".to_string(),
+            Action::OffsetEarlier => text =
+"Move selected events earlier by 1/8 of a row, or
+events in the cursor row if nothing is selected.
+Events that would land on another event stay put.
+Offset events are dimmed.".to_string(),
             Action::PrevBeat =>
                 text = "Move the pattern cursor up by 1 beat.".to_string(),
             Action::NextBeat =>
@@ -440,6 +791,35 @@ column event.".to_string(),
             Action::SoloTrack => text =
 "Toggle muting all tracks except for the current
 track.".to_string(),
+            Action::AddSoloTrack => text =
+"Add the current track to the soloed tracks, or
+remove it if it's already soloed.".to_string(),
+            Action::EditCondition => text =
+"Edit the condition on the note at the cursor.
+Conditional notes are marked in the pattern.
+
+50% - Play with 50% chance
+2:4 - Play on the 2nd of every 4 loop passes
+
+Submit empty text to remove a condition.".to_string(),
+            Action::EditSlide => text =
+"Edit the slide on the note at the cursor. The
+previous note slides to the new pitch over a digit
+(1-F) number of eighth beats instead of retriggering.
+Sliding notes are marked in the pattern.
+
+Submit empty text to remove a slide.".to_string(),
+            Action::EditEffects => text =
+"Edit vibrato and tremolo in the modulation column
+at the cursor. Effects last until the next note.
+
+4xy - Vibrato with rate x and depth y
+7xy - Tremolo with rate x and depth y
+
+Separate multiple effects with spaces. Submit empty
+text to remove effects.".to_string(),
+            Action::MomentarySolo => text =
+"Solo the current track while the key is held.".to_string(),
             Action::Panic => text = "Cut all notes and stop playback.".to_string(),
             Action::InsertPaste => text =
 "Paste, shifting existing events by the size of the
@@ -447,6 +827,70 @@ clipboard.".to_string(),
             Action::StretchPaste => text =
 "Paste, stretching clipboard data to the length of
 the selected timespan.".to_string(),
+            Action::ToggleTrackLock => text =
+"Lock or unlock the current track against pattern
+edits.".to_string(),
+            Action::ToggleColumnLock => text =
+"Lock or unlock the current column type of the
+current track against pattern edits.".to_string(),
+            Action::CopyText => text =
+"Copy the selection as readable text with note,
+pressure, and modulation columns. Pasting this text
+back into a pattern is also supported.".to_string(),
+            Action::CopyMml => text =
+"Copy the notes in the selection as MML, one line
+per channel. Pitches are rounded to 12-EDO.".to_string(),
+            Action::ToggleSplitView => text =
+"Split the pattern view into two panes with separate
+cursors and scroll positions, or merge the panes.
+Click a pane to make it active.".to_string(),
+            Action::SwitchPane => text =
+"Make the other pane of a split pattern view active.".to_string(),
+            Action::AnalyzeIntervals => text =
+"Show the intervals between simultaneous notes in
+the selection, in cents and as the nearest simple
+just ratios.".to_string(),
+            Action::StartTutorial => text =
+"Load the demo song and take a guided tour of the
+editor.".to_string(),
+            Action::ToggleCheatSheet => text =
+"Show or hide an overlay listing the note layout
+and key commands.".to_string(),
+            Action::ToggleDrawMode => text =
+"While draw mode is on, click and drag down the
+pressure or modulation column to draw values. The
+horizontal position in the channel sets each value,
+and rows passed over are filled in.".to_string(),
+            Action::ToggleAutomationLane => text =
+"Show or hide a lane below the pattern that plots
+the cursor channel's pressure, or its modulation
+if the cursor is in the modulation column. Drag
+in the lane to edit the values.".to_string(),
+            Action::SaveMacro => text =
+"Save the selected events as a macro, which can be
+named and given a hotkey in the General tab.".to_string(),
+            Action::Quantize => text =
+"Open a panel for moving the selected events toward
+the nearest rows, with adjustable strength and
+swing. Outlines show where events will move before
+the change is applied.".to_string(),
+            Action::CopyInspector => text =
+"Copy a text description of the cursor position and
+the events on the cursor row.".to_string(),
+            Action::ToggleFullscreen => text =
+"Switch between fullscreen and windowed mode.".to_string(),
+            Action::ToggleInstrumentPanel => text =
+"Show or hide the instrument editor beside the
+pattern editor.".to_string(),
+            Action::ExportMidi => text =
+"Save the notes in the selection as a MIDI file. If
+no timespan is selected, save the current track.".to_string(),
+            Action::FillPaste => text =
+"Paste repeatedly until the clipboard data fills the
+selected timespan.".to_string(),
+            Action::PasteSpecial => text =
+"Open a panel for pasting only some columns,
+repeating, or transposing.".to_string(),
             Action::UseLastNote =>
                 text = "Insert a copy of the last note in the channel.".to_string(),
             Action::IncrementDivision => text = "Increase beat division by 1.".to_string(),
@@ -454,6 +898,12 @@ the selected timespan.".to_string(),
             Action::DoubleDivision => text = "Double the beat division.".to_string(),
             Action::HalveDivision => text = "Halve the beat division.".to_string(),
             Action::FocusDivision => text = "Focus the division field.".to_string(),
+            Action::ToggleTriplets => text =
+"Switch between a straight division and its triplet
+counterpart, e.g. 4 and 6 rows per beat.".to_string(),
+            Action::ToggleTripletGrid => text =
+"Draw lines at triplet positions in each beat of the
+active pattern pane.".to_string(),
             Action::StopPlayback => text = "Stop song playback.".to_string(),
             Action::NewSong =>
                 text = "Close the open song and start a new one.".to_string(),
@@ -489,7 +939,180 @@ track channels.".to_string(),
         Info::KitTrack => text =
 "Uses the patch & note mappings from the Kit entry
 in the Instruments tab.".to_string(),
-        Info::MidiInput => text = "MIDI input to use for note input.".to_string(),
+        Info::AudioTrack => text =
+"Plays audio clips. Clip settings are in the
+General tab.".to_string(),
+        Info::AddClip => text =
+"Load an audio file as a clip at the cursor row.".to_string(),
+        Info::AudioClips => text =
+"Clips placed on audio tracks. Fades are applied
+to the start and end of each clip.".to_string(),
+        Info::RenderRange => text =
+"Span of the song used by WAV renders. Choose a
+marker or type a beat. Without an end, renders stop
+at the End event. Sections marked with Skip events
+are left out.".to_string(),
+        Info::PreviewRender => text =
+"Render the start of the range with the current
+render format and loudness settings, and play it.".to_string(),
+        Info::RetuneNotes => text =
+"Rewrite notes in patch tracks as the nearest
+pitches in the new tuning, so that they sound about
+the same as before. Kit tracks are left alone.".to_string(),
+        Info::NoteNames => text =
+"Characters used to display and type notes, for
+notation systems other than the default. Nominal
+names are listed from C to B. Leave a box empty to
+use the default character.".to_string(),
+        Info::LayerKey => text =
+"While this key is held, key commands assigned to
+the layer are used instead of the normal ones. To
+assign one, hold this key while setting a hotkey.".to_string(),
+        Info::MiddleDragPan => text =
+"Drag with the middle mouse button to scroll the
+pattern in any direction.".to_string(),
+        Info::WheelAdjustsSliders => text =
+"Turning the mouse wheel over a slider changes its
+value instead of scrolling. Hold Shift for fine
+steps.".to_string(),
+        Info::DoubleClick => text =
+"Action performed by double-clicking in the
+pattern editor.".to_string(),
+        Info::ScrollSpeed => text =
+"Lines scrolled per step of the mouse wheel.".to_string(),
+        Info::LaneTool => text =
+"How dragging in the automation lane edits values.
+Draw sets a value on each row, Line glides
+straight between two points, and Curve glides
+with an eased curve.".to_string(),
+        Info::Performance => text =
+"Sections start at pattern markers and loop until
+another section is queued. Queued sections start
+at the next bar boundary.".to_string(),
+        Info::BarBeats => text =
+"Length of a bar in beats, counted from the start
+of the playing section.".to_string(),
+        Info::ReleaseSection => text =
+"Stop looping the playing section and continue
+through the song.".to_string(),
+        Info::QueueSection => text =
+"Queue this section to play at the next bar
+boundary. If stopped, play it immediately.".to_string(),
+        Info::SectionTrigger => text =
+"Key or MIDI note that queues this section while
+the Perform tab is open. Click Learn, then press a
+key or play a note to assign it.".to_string(),
+        Info::TrackGroups => text =
+"Groups of adjacent tracks. Group volume is applied
+on top of patch and clip gain.".to_string(),
+        Info::Macros => text =
+"Bundles of events saved from pattern selections.
+Pressing a macro's hotkey in the pattern editor
+inserts its events at the cursor: control events in
+the control track, and others in the cursor
+channel.".to_string(),
+        Info::SongTime => text =
+"Time from the start of the song to the cursor,
+following tempo changes but not loops. Enter a time
+like 1:30.5 to move the cursor there.".to_string(),
+        Info::MacroHotkey => text =
+"Key that inserts this macro in the pattern editor.
+Macro hotkeys take priority over key commands.
+Press Escape while learning to cancel.".to_string(),
+        Info::TrackGroup => text =
+"A group of tracks. Rename it and set its volume in
+the General tab.".to_string(),
+        Info::CollapseGroup =>
+            text = "Collapse or expand this group's tracks.".to_string(),
+        Info::GroupMute =>
+            text = "Toggle muting all tracks in this group.".to_string(),
+        Info::GroupSolo =>
+            text = "Toggle soloing all tracks in this group.".to_string(),
+        Info::MidiInput => text =
+"MIDI input to use for note input. Several inputs
+can be connected at once.".to_string(),
+        Info::MidiChannelFilter =>
+            text = "Only accept events on this MIDI channel.".to_string(),
+        Info::MidiRoute => text =
+"Play notes from a MIDI channel on a specific track,
+so a multi-channel controller or sequencer can drive
+several instruments at once. Unrouted channels play
+on the current track.".to_string(),
+        Info::KeyboardSplit => text =
+"Split the note keys into two zones for two-handed
+keyjazz. The lower zone is the bottom two letter
+rows, and the upper zone is the rest.".to_string(),
+        Info::SplitOctave =>
+            text = "Octave offset of this zone from the base octave.".to_string(),
+        Info::SplitTrack => text =
+"Track to play notes from this zone on. If blank,
+the current track is used.".to_string(),
+        Info::SplitPatch =>
+            text = "Patch to play notes from this zone with.".to_string(),
+        Info::MidiInputTrack => text =
+"Track to play notes from this input on. If blank,
+channel routing or the current track is used.".to_string(),
+        Info::RatioPreset =>
+            text = "Set the frequency ratio to a common value.".to_string(),
+        Info::FixedFreq => text =
+"Ignore note pitch. The frequency ratio is relative
+to middle C instead.".to_string(),
+        Info::FmRatio => text =
+"Frequency ratio of the carrier to this modulator,
+for generators with FM output.".to_string(),
+        Info::NoiseColor => text =
+"Spectrum of the noise. Blend uses tone to mix from
+pink to white noise. Retro uses tone to set the
+clock rate of a 15-bit LFSR.".to_string(),
+        Info::DrumDecay =>
+            text = "Decay time of the drum model.".to_string(),
+        Info::DrumCharacter => text =
+"Kick: depth of the pitch sweep.
+Snare: mix of noise versus body.
+Hat: mix of metallic tones versus noise.
+Tone sets kick drive and snare/hat brightness.".to_string(),
+        Info::AmDepth => text =
+"Amount of amplitude or ring modulation applied to
+the target generator.".to_string(),
+        Info::ModPolarity => text =
+"Output range of the processed source. Unipolar
+sources are stretched to -1..1, and bipolar sources
+are compressed to 0..1.".to_string(),
+        Info::ModInvert =>
+            text = "Flip the source within its range.".to_string(),
+        Info::ModSteps => text =
+"Quantize the source to a number of steps within
+its range.".to_string(),
+        Info::ModSlew => text =
+"Smooth changes in the source over a time, after
+quantization.".to_string(),
+        Info::KeyTrackCenter =>
+            text = "MIDI note where key track outputs zero.".to_string(),
+        Info::KeyTrackSlope => text =
+"Key track output per octave from the center. The
+output is limited to -1..1.".to_string(),
+        Info::Snapshots => text =
+"Stored values of every modulatable parameter. The
+morph slider interpolates between two snapshots.".to_string(),
+        Info::StoreSnapshot =>
+            text = "Store current parameter values in this slot.".to_string(),
+        Info::RecallSnapshot =>
+            text = "Set parameters to the values in this slot.".to_string(),
+        Info::MorphEndpoints =>
+            text = "Snapshots at either end of the morph slider.".to_string(),
+        Info::Morph => text =
+"Position between the morph snapshots. Parameters
+that differ between the snapshots ignore their own
+sliders. Can be modulated.".to_string(),
+        Info::SurfaceLayout => text =
+"Grid controller connected to the MIDI input. The
+top row of pads queues sections, the next row
+toggles mutes for the first 8 tracks, and the other
+pads play notes in fourths. Launchpads must be in
+programmer mode.".to_string(),
+        Info::SurfaceOutput => text =
+"MIDI output used to light the control surface's
+pads to show section and mute state.".to_string(),
         Info::SpatialFxType => text =
 "Type of global spatial FX to use. Individual send
 levels can be set in patch settings.".to_string(),
@@ -521,29 +1144,102 @@ of the note. The break-even point for key tracking
 is C4 (~261 Hz).".to_string(),
         Info::ModSource => text =
 "The source used for this modulation. Most sources
-operate in the range 0..1, but LFOs oscillate in
-the range -1..1.".to_string(),
+operate in the range 0..1, but LFOs and key track
+operate in the range -1..1. The envelope follower
+tracks the voice's output level.".to_string(),
         Info::ModDest => text = "The modulated parameter.".to_string(),
         Info::TrackPatch => text = "The patch controlled by this track.".to_string(),
+        Info::TrackTranspose => text =
+"Transpose notes on this track by a number of scale
+steps. Pattern data is unchanged.".to_string(),
+        Info::TrackDetune =>
+            text = "Detune notes on this track by a number of cents.".to_string(),
         Info::SmoothPlayhead => text =
 "If disabled, playhead visual and pattern follow
 will be quantized to the nearest row.".to_string(),
+        Info::KeyjazzLatch => text =
+"Sustain keyboard and MIDI notes after release.
+Playing a new note after all keys are released
+ends the held notes.".to_string(),
+        Info::LearnChord => text =
+"Play a chord, then click again to store it. Each
+note played afterward triggers the whole chord.".to_string(),
+        Info::ClearChord =>
+            text = "Forget the learned chord.".to_string(),
+        Info::TrackLock => text =
+"Lock the track against pattern edits. Locked
+columns are labeled in a different color.".to_string(),
+        Info::PasteSpecial => text =
+"Paste only the checked column types. Existing
+events in unchecked columns are kept.".to_string(),
+        Info::PasteRepeat => text =
+"Repeat clipboard data until it fills the selected
+timespan.".to_string(),
+        Info::PasteTranspose => text =
+"Transpose pasted notes by this many scale steps.".to_string(),
+        Info::QuantizeStrength => text =
+"How far events move toward the nearest row. At
+100%, events land exactly on the row.".to_string(),
+        Info::Swing => text =
+"Delay every other row by this fraction of a row.
+Around 33% gives a triplet feel. Disabled while a
+groove is selected.".to_string(),
+        Info::Groove => text =
+"Quantize to the timing of a groove instead of an
+even grid, and apply its accents to the pressure of
+quantized notes.".to_string(),
+        Info::ExtractGroove => text =
+"Save the timing and pressure of the notes in the
+cursor channel over the selection as a groove, one
+step per row.".to_string(),
+        Info::SmoothScroll => text =
+"If enabled, pattern follow scrolls smoothly
+between rows.".to_string(),
+        Info::PlayheadPosition => text =
+"Where the playhead sits on screen when the pattern
+follows playback.".to_string(),
+        Info::RowNumbers => text =
+"How rows between beats are labeled in the pattern.
+Fractions show each row's position within its beat,
+and absolute fractions show its tick from the start
+of the song.".to_string(),
+        Info::RowHighlight => text =
+"Shade every Nth row of the pattern, counting from
+the start of the song.".to_string(),
+        Info::QuantizePreview => text =
+"If enabled, notes played in the pattern editor
+during playback sound at the next row instead of
+immediately.".to_string(),
+        Info::LockCursor => text =
+"If enabled, the pattern cursor moves with the
+playhead when following playback, so that edits
+land on the playing row.".to_string(),
         Info::PressureColumn => text =
 "Pressure column.
 
 0..F - Enter digit
-Shift+0..F - Track enter digit".to_string(),
+Shift+0..F - Track enter digit
+Ctrl+click - Select individual events".to_string(),
         Info::ModulationColumn => text =
 "Modulation column.
 
 0..F - Enter digit
-Shift+0..F - Track enter digit".to_string(),
+Shift+0..F - Track enter digit
+Ctrl+click - Select individual events".to_string(),
+        Info::Minimap => text =
+"Song overview. Each column is a track, and brighter
+cells have more events. The highlighted area is the
+visible part of the pattern.
+
+Click or drag to scroll the pattern.".to_string(),
         Info::ControlColumn => {
             text =
-"Control column. Type to enter BPM values (ex. 120)
-or tempo ratios (ex. 3:2 or 3/2).".to_string();
+"Control column. Type to enter BPM values (ex. 120),
+tempo ratios (ex. 3:2 or 3/2), or a counted loop
+(ex. 2x), which plays its section that many times
+and then continues past the End marker.".to_string();
             actions =
-                vec![Action::TapTempo, Action::Loop, Action::End];
+                vec![Action::TapTempo, Action::Loop, Action::End, Action::Skip];
         },
         Info::NoteColumn => {
             let first_note = conf.note_keys.first().map(|(h, _)| h.to_string())
@@ -553,7 +1249,10 @@ or tempo ratios (ex. 3:2 or 3/2).".to_string();
             text = format!(
 "Note column.
 
-{}..{} - Enter note", first_note, last_note);
+{}..{} - Enter note
+Ctrl+click - Select individual events
+Drag selection - Move (Ctrl+release to copy)
+Drag bottom of selection - Stretch", first_note, last_note);
             custom_actions = true;
             actions = vec![Action::NoteOff, Action::CycleNotation, Action::UseLastNote];
         },
@@ -578,7 +1277,10 @@ or tempo ratios (ex. 3:2 or 3/2).".to_string();
     match ctrl {
         ControlInfo::None => (),
         ControlInfo::Slider => {
-            text.push_str("Right-click slider to edit value as text.")
+            text.push_str(
+"Right-click slider to edit value as text. When
+focused with Tab, use the arrow keys to adjust,
+with Shift for fine steps and Ctrl for coarse.")
         }
         ControlInfo::Note => {
             text.push_str(
@@ -588,7 +1290,8 @@ using the keyboard.")
         ControlInfo::Hotkey => {
             text.push_str(
 "Hotkey input. Click to focus, then press a key
-combination to set value.")
+combination to set value. Press a second key
+combination quickly to set a two-stroke hotkey.")
         }
     }
 
@@ -1,10 +1,12 @@
+use std::path::PathBuf;
+
 use lfo::{AR_RATE_MULTIPLIER, LFO, MAX_LFO_RATE, MIN_LFO_RATE};
 use macroquad::input::{KeyCode, is_key_pressed};
-use pcm::PcmData;
+use pcm::{LoopMode, PcmData};
 
-use crate::{config::{self, Config}, module::{Edit, Module, ModuleCommand, ModuleSync}, playback::PlayerShell, synth::*};
+use crate::{config::{self, Config}, module::{Edit, KitEntry, Module, ModuleCommand, ModuleSync, Position}, playback::PlayerShell, synth::*};
 
-use super::{info::Info, Layout, Ui};
+use super::{general, info::Info, preview, Layout, ListEdit, Ui};
 
 // for file dialogs
 const PATCH_FILTER_NAME: &str = "Instrument";
@@ -15,6 +17,9 @@ pub struct InstrumentsState {
     scroll: f32,
     /// If None, kit is selected.
     pub patch_index: Option<usize>,
+    usage: Option<PatchUsage>,
+    /// Pattern position to jump to, set by clicking a usage.
+    pub jump: Option<Position>,
 }
 
 impl InstrumentsState {
@@ -22,25 +27,67 @@ impl InstrumentsState {
         Self {
             scroll: 0.0,
             patch_index,
+            usage: None,
+            jump: None,
         }
     }
 }
 
+/// Results of a "where used" query.
+struct PatchUsage {
+    patch_index: usize,
+    /// Module edit count at the time of the query.
+    edit_count: u64,
+    positions: Vec<Position>,
+}
+
+/// Maximum number of usages to list.
+const MAX_USAGES: usize = 100;
+
+/// Fraction of the pattern tab's width used by the instrument panel.
+const PANEL_FRACTION: f32 = 0.5;
+
+/// Options in the patch list context menu.
+const PATCH_MENU: [&str; 5] = ["Rename", "Duplicate", "Save", "Where used", "Remove"];
+
 pub fn draw(ui: &mut Ui, module: &mut Module, state: &mut InstrumentsState,
     cfg: &mut Config, player: &mut PlayerShell, module_sync: &mut ModuleSync,
+    save_path: Option<&PathBuf>,
 ) {
-    if is_key_pressed(KeyCode::Up) {
-        shift_patch_index(-1, &mut state.patch_index, module.patches.len());
-    } else if is_key_pressed(KeyCode::Down) {
-        shift_patch_index(1, &mut state.patch_index, module.patches.len());
+    // arrow keys adjust the focused control, if any
+    if !ui.keyboard_control_focused() {
+        if is_key_pressed(KeyCode::Up) {
+            shift_patch_index(-1, &mut state.patch_index, module.patches.len());
+        } else if is_key_pressed(KeyCode::Down) {
+            shift_patch_index(1, &mut state.patch_index, module.patches.len());
+        }
     }
 
+    draw_editor(ui, module, state, cfg, player, module_sync, save_path);
+}
+
+/// Draws the instrument editor in a side panel of the pattern tab. Keys are
+/// left to the pattern editor.
+pub fn draw_panel(ui: &mut Ui, module: &mut Module, state: &mut InstrumentsState,
+    cfg: &mut Config, player: &mut PlayerShell, module_sync: &mut ModuleSync,
+    save_path: Option<&PathBuf>,
+) {
+    ui.start_side_panel((ui.bounds.w * PANEL_FRACTION).round());
+    draw_editor(ui, module, state, cfg, player, module_sync, save_path);
+    ui.end_side_panel();
+}
+
+/// Draws the patch list and the controls for the selected patch.
+fn draw_editor(ui: &mut Ui, module: &mut Module, state: &mut InstrumentsState,
+    cfg: &mut Config, player: &mut PlayerShell, module_sync: &mut ModuleSync,
+    save_path: Option<&PathBuf>,
+) {
     ui.layout = Layout::Horizontal;
     let old_y = ui.cursor_y;
     ui.cursor_y -= state.scroll;
     ui.cursor_z -= 1;
 
-    patch_list(ui, module, &mut state.patch_index, cfg, player);
+    patch_list(ui, module, state, cfg, player, save_path);
     ui.space(1.0);
     ui.start_group();
     if let Some(index) = &state.patch_index {
@@ -59,15 +106,18 @@ pub fn draw(ui: &mut Ui, module: &mut Module, state: &mut InstrumentsState,
     ui.cursor_y += state.scroll;
     let scroll_h = ui.end_group().unwrap().h + ui.style.margin;
     ui.cursor_y = old_y;
+    let keys = !ui.in_side_panel();
     ui.vertical_scrollbar(&mut state.scroll,
-        scroll_h, ui.bounds.y + ui.bounds.h - ui.cursor_y, true);
+        scroll_h, ui.bounds.y + ui.bounds.h - ui.cursor_y, keys);
 }
 
-fn patch_list(ui: &mut Ui, module: &mut Module, patch_index: &mut Option<usize>,
-    cfg: &mut Config, player: &mut PlayerShell
+fn patch_list(ui: &mut Ui, module: &mut Module, state: &mut InstrumentsState,
+    cfg: &mut Config, player: &mut PlayerShell, save_path: Option<&PathBuf>
 ) {
     ui.start_group();
 
+    let patch_index = &mut state.patch_index;
+
     let mut edits = Vec::new();
     let patches = &mut module.patches;
 
@@ -75,18 +125,34 @@ fn patch_list(ui: &mut Ui, module: &mut Module, patch_index: &mut Option<usize>,
     names.extend(patches.iter().map(|x| x.name.clone()));
 
     let mut list_index = patch_index.map(|i| i + 1).unwrap_or_default();
-    if let Some(s) = ui.instrument_list(&names, &mut list_index, 10) {
-        if list_index > 0 {
+    ui.start_group();
+    match ui.instrument_list(&names, &mut list_index, 10) {
+        Some(ListEdit::Rename(s)) => if list_index > 0 {
             if let Some(patch) = patches.get_mut(list_index - 1) {
                 patch.name = s;
             }
         }
+        Some(ListEdit::Move(src, dst)) => {
+            edits.push(Edit::ShiftPatch(src - 1, dst as isize - src as isize));
+            list_index = dst;
+        }
+        None => (),
     }
+    let list_rect = ui.end_group().unwrap();
     *patch_index = match list_index {
         0 => None,
         i => Some(i - 1),
     };
 
+    let menu = ui.context_menu("patch_menu", list_rect, || if list_index > 0 {
+        PATCH_MENU.iter().map(|s| s.to_string()).collect()
+    } else {
+        Vec::new()
+    }).map(|i| PATCH_MENU[i]);
+    if menu == Some("Rename") {
+        ui.rename_instrument(list_index, names[list_index].clone());
+    }
+
     ui.start_group();
     if ui.button("Add", true, Info::Add("a new patch with default settings")) {
         let mut name = String::from("Init");
@@ -99,7 +165,8 @@ fn patch_list(ui: &mut Ui, module: &mut Module, patch_index: &mut Option<usize>,
         *patch_index = Some(patches.len());
     }
 
-    if ui.button("Remove", patch_index.is_some(), Info::Remove("the selected patch")) {
+    if ui.button("Remove", patch_index.is_some(), Info::Remove("the selected patch"))
+        || menu == Some("Remove") {
         if let Some(index) = patch_index {
             edits.push(Edit::RemovePatch(*index));
         }
@@ -108,7 +175,7 @@ fn patch_list(ui: &mut Ui, module: &mut Module, patch_index: &mut Option<usize>,
 
     ui.start_group();
     let patches = &mut module.patches;
-    if ui.button("Save", patch_index.is_some(), Info::SavePatch) {
+    if ui.button("Save", patch_index.is_some(), Info::SavePatch) || menu == Some("Save") {
         if let Some(patch) = patch_index.map(|i| patches.get(i)).flatten() {
             let dialog = super::new_file_dialog(player)
                 .add_filter(PATCH_FILTER_NAME, &[PATCH_FILTER_EXT])
@@ -152,7 +219,8 @@ fn patch_list(ui: &mut Ui, module: &mut Module, patch_index: &mut Option<usize>,
     }
     ui.end_group();
 
-    if ui.button("Duplicate", patch_index.is_some(), Info::DuplicatePatch) {
+    if ui.button("Duplicate", patch_index.is_some(), Info::DuplicatePatch)
+        || menu == Some("Duplicate") {
         let index = patch_index.unwrap();
         if let Some(p) = patches.get(index).map(|p| p.duplicate()) {
             edits.push(Edit::InsertPatch(patches.len(), p));
@@ -160,14 +228,123 @@ fn patch_list(ui: &mut Ui, module: &mut Module, patch_index: &mut Option<usize>,
         }
     }
 
+    let has_sample = patch_index.and_then(|i| patches.get(i)).is_some_and(|p|
+        p.oscs.iter().any(|osc| matches!(osc.waveform, Waveform::Pcm(Some(_)))));
+    let mut slices = Vec::new();
+    if ui.button("Slice to kit", has_sample, Info::SliceSample) {
+        if let Some(patch) = patch_index.and_then(|i| patches.get(i)) {
+            match patch.slice_sample() {
+                Ok(v) => slices = v,
+                Err(e) => ui.report(format!("Error slicing sample: {e}")),
+            }
+        }
+    }
+
+    if ui.button("Collect samples", save_path.is_some(), Info::CollectSamples) {
+        if let Some(path) = save_path {
+            match module.collect_samples(path) {
+                Ok(n) => {
+                    if n > 0 {
+                        module.has_unsaved_changes = true;
+                    }
+                    ui.notify(format!("Copied {n} sample(s)."));
+                }
+                Err(e) => ui.report(format!("Error collecting samples: {e}")),
+            }
+        }
+    }
+
     for edit in edits {
         module.push_edit(edit);
         fix_patch_index(patch_index, module.patches.len());
     }
 
+    if !slices.is_empty() {
+        ui.notify(format!("Mapped {} slice(s) to kit.", slices.len()));
+        map_slices(module, slices);
+    }
+
+    ui.start_group();
+    if ui.button("Merge duplicates", module.patches.len() > 1, Info::MergeDuplicatePatches) {
+        let n = module.merge_duplicate_patches();
+        ui.notify(format!("Merged {n} duplicate patch(es)."));
+    }
+    if ui.button("Remove unused", !module.patches.is_empty(), Info::RemoveUnusedPatches) {
+        let n = module.remove_unused_patches();
+        ui.notify(format!("Removed {n} unused patch(es)."));
+    }
+    fix_patch_index(&mut state.patch_index, module.patches.len());
+    ui.end_group();
+
+    usage_list(ui, module, state, menu == Some("Where used"));
+
     ui.end_group();
 }
 
+/// "Where used" controls for the selected patch. `query` runs the query
+/// without the button being clicked.
+fn usage_list(ui: &mut Ui, module: &Module, state: &mut InstrumentsState, query: bool) {
+    if ui.button("Where used", state.patch_index.is_some(), Info::PatchUsage) || query {
+        if let Some(patch_index) = state.patch_index {
+            state.usage = Some(PatchUsage {
+                patch_index,
+                edit_count: module.edit_count(),
+                positions: module.patch_usage(patch_index),
+            });
+        }
+    }
+
+    let Some(usage) = &mut state.usage else { return };
+    if Some(usage.patch_index) != state.patch_index {
+        state.usage = None;
+        return
+    }
+    if usage.edit_count != module.edit_count() {
+        usage.edit_count = module.edit_count();
+        usage.positions = module.patch_usage(usage.patch_index);
+    }
+
+    if usage.positions.is_empty() {
+        ui.label("Not used", Info::None);
+    }
+    for pos in usage.positions.iter().take(MAX_USAGES) {
+        let label = format!("Track {}, channel {}, beat {:.2}",
+            pos.track, pos.channel + 1, pos.tick.as_f64() + 1.0);
+        if ui.button(&label, true, Info::JumpToUsage) {
+            state.jump = Some(*pos);
+        }
+    }
+    if usage.positions.len() > MAX_USAGES {
+        ui.label(&format!("...and {} more", usage.positions.len() - MAX_USAGES),
+            Info::None);
+    }
+}
+
+/// Add slice patches to the module, mapping them to consecutive kit notes
+/// above the highest existing mapping. This is a single edit.
+fn map_slices(module: &mut Module, slices: Vec<Patch>) {
+    let mut note = module.kit.iter()
+        .map(|entry| entry.input_note)
+        .max_by(|a, b| module.tuning.midi_pitch(a).total_cmp(&module.tuning.midi_pitch(b)))
+        .map(|note| note.step_shift(1, &module.tuning))
+        .unwrap_or_default();
+
+    let mut patches = module.patches.clone();
+    let mut kit = module.kit.clone();
+    for patch in slices {
+        kit.push(KitEntry {
+            input_note: note,
+            patch_index: patches.len(),
+            patch_note: Default::default(),
+            name: String::new(),
+        });
+        patches.push(patch);
+        note = note.step_shift(1, &module.tuning);
+    }
+    let targets = module.tracks.iter().map(|t| t.target).collect();
+    module.push_edit(Edit::SetPatches { patches, kit, targets });
+}
+
 /// Correct the patch index if it's out of bounds.
 pub fn fix_patch_index(index: &mut Option<usize>, len: usize) {
     if len == 0 {
@@ -228,6 +405,16 @@ fn kit_controls(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell) -> b
             }
         });
 
+        labeled_group(ui, "Name", Info::KitName, |ui| {
+            for (i, entry) in module.kit.iter_mut().enumerate() {
+                if let Some(s) = ui.id_edit_box(&format!("kit_{i}_name"), "",
+                    KitEntry::MAX_NAME_CHARS, entry.name.clone(), Info::KitName) {
+                    entry.name = s.chars().take(KitEntry::MAX_NAME_CHARS).collect();
+                    changed = true;
+                }
+            }
+        });
+
         labeled_group(ui, "", Info::None, |ui| {
             for i in 0..module.kit.len() {
                 if ui.button("X", true, Info::Remove("this mapping")) {
@@ -277,11 +464,59 @@ fn patch_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
     ui.shared_slider("fx_send", "FX send", &patch.fx_send.0,
         0.0..=1.0, None, 1, true, Info::FxSend);
 
+    // zero means "use global setting"
+    let mut limit = patch.voice_limit.unwrap_or_default() as f32;
+    if ui.formatted_slider("voice_limit", "Voice limit", &mut limit,
+        0.0..=MAX_VOICE_LIMIT as f32, 1, true, Info::VoiceLimit,
+        |x| if x.round() == 0.0 {
+            String::from("Global")
+        } else {
+            format!("{}", x.round())
+        }, |x| x.round()
+    ) {
+        patch.voice_limit = Some(limit as u8).filter(|n| *n > 0);
+        changed = true;
+    }
+    let policy_name = patch.steal_policy.as_ref().map(|x| x.name()).unwrap_or("Global");
+    if let Some(i) = ui.combo_box("steal_policy", "Steal policy", policy_name,
+        Info::StealPolicy, || {
+            let mut v = vec![String::from("Global")];
+            v.extend(StealPolicy::VARIANTS.map(|x| x.name().to_owned()));
+            v
+        }
+    ) {
+        patch.steal_policy = i.checked_sub(1).map(|i| StealPolicy::VARIANTS[i]);
+        changed = true;
+    }
+    if let Some(i) = ui.combo_box("patch_oversampling", "Oversampling",
+        patch.oversampling.name(), Info::PatchOversampling,
+        || Oversampling::VARIANTS.map(|x| x.name().to_owned()).to_vec()
+    ) {
+        patch.oversampling = Oversampling::VARIANTS[i];
+        changed = true;
+    }
+
+    ui.start_group();
+    let mut seeded = patch.seed.is_some();
+    if ui.checkbox("Fixed seed", &mut seeded, true, Info::FixedSeed) {
+        patch.seed = seeded.then(rand::random);
+        changed = true;
+    }
+    if ui.button("New seed", seeded, Info::NewSeed) {
+        patch.seed = Some(rand::random());
+        changed = true;
+    }
+    ui.end_group();
+
+    ui.vertical_space();
+    changed |= snapshot_controls(ui, patch);
     ui.vertical_space();
     changed |= generator_controls(ui, patch, cfg, player);
     ui.vertical_space();
     changed |= filter_controls(ui, patch);
     ui.vertical_space();
+    changed |= general::subsonic_controls(ui, "patch", &mut patch.subsonic);
+    ui.vertical_space();
     changed |= envelope_controls(ui, patch);
     ui.vertical_space();
     changed |= lfo_controls(ui, patch);
@@ -291,6 +526,50 @@ fn patch_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
     changed
 }
 
+fn snapshot_controls(ui: &mut Ui, patch: &mut Patch) -> bool {
+    let mut changed = false;
+    ui.header("SNAPSHOTS", Info::Snapshots);
+
+    ui.start_group();
+    for (i, name) in SNAPSHOT_NAMES.iter().enumerate() {
+        let stored = patch.morph.snapshots[i].is_some();
+        ui.start_group();
+        ui.label(name, Info::None);
+        if ui.button("Store", true, Info::StoreSnapshot) {
+            patch.store_snapshot(i);
+            changed = true;
+        }
+        if ui.button("Recall", stored, Info::RecallSnapshot) {
+            patch.recall_snapshot(i);
+        }
+        if ui.button("X", stored, Info::Remove("this snapshot")) {
+            patch.morph.snapshots[i] = None;
+            changed = true;
+        }
+        ui.end_group();
+    }
+    ui.end_group();
+
+    let names = || SNAPSHOT_NAMES.map(|x| x.to_owned()).to_vec();
+    ui.start_group();
+    if let Some(i) = ui.combo_box("morph_from", "Morph from",
+        SNAPSHOT_NAMES[patch.morph.from], Info::MorphEndpoints, names) {
+        patch.morph.from = i;
+        changed = true;
+    }
+    if let Some(i) = ui.combo_box("morph_to", "to",
+        SNAPSHOT_NAMES[patch.morph.to], Info::MorphEndpoints, names) {
+        patch.morph.to = i;
+        changed = true;
+    }
+    ui.end_group();
+    ui.formatted_shared_slider("morph", "Morph", &patch.morph.position.0,
+        0.0..=1.0, 1, patch.morph.is_active(), Info::Morph,
+        |f| format!("{f:.2}"), |f| f);
+
+    changed
+}
+
 fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
     player: &mut PlayerShell
 ) -> bool {
@@ -350,6 +629,20 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
                         changed = true;
                     }
 
+                    let root = REF_PITCH as f32 - 12.0 * osc.freq_ratio.0.value().log2()
+                        - osc.fine_pitch.0.value();
+                    if let Some(s) = ui.edit_box("Root", 5, format!("{root:.1}"),
+                        Info::SampleRoot
+                    ) {
+                        match s.parse::<f32>() {
+                            Ok(pitch) => {
+                                set_sample_root(&osc.freq_ratio, &osc.fine_pitch, pitch);
+                                changed = true;
+                            }
+                            Err(e) => ui.report(e),
+                        }
+                    }
+
                     let mut on = data.loop_point.is_some();
                     if ui.checkbox("Loop", &mut on, true, Info::None) {
                         data.loop_point = if on {
@@ -357,17 +650,64 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
                         } else {
                             None
                         };
+                        data.update_loop();
+                        changed = true;
+                    }
+
+                    let mut stream = data.stream;
+                    if ui.checkbox("Stream", &mut stream, true, Info::StreamSample) {
+                        if let Err(e) = data.set_stream(stream) {
+                            ui.report(format!("Error streaming sample: {e}"));
+                        }
+                        changed = true;
+                    }
+
+                    if ui.checkbox("Stretch", &mut data.granular, data.can_granulate(),
+                        Info::GranularSample) {
+                        changed = true;
+                    }
+                    if data.granular {
+                        changed |= ui.slider(&format!("osc_{}_speed", i), "Speed",
+                            &mut data.speed, 0.25..=4.0, None, 2, true, Info::SampleSpeed);
+                        changed |= ui.slider(&format!("osc_{}_grain", i), "Grain",
+                            &mut data.grain_size, 0.01..=0.2, Some("s"), 2, true,
+                            Info::GrainSize);
+                    }
+
+                    let can_reference = data.path.is_some();
+                    if ui.checkbox("Reference", &mut data.referenced, can_reference,
+                        Info::ReferenceSample) {
                         changed = true;
                     }
 
+                    let duration = data.duration() as f32;
                     if let Some(pt) = &mut data.loop_point {
                         let sr = data.wave.sample_rate() as f32;
                         let mut pt2 = *pt as f32 / sr;
                         if ui.slider(&format!("osc_{}_loop", i), "Loop point", &mut pt2,
-                            0.0..=data.wave.duration() as f32, Some("s"), 1, true,
+                            0.0..=duration, Some("s"), 1, true,
                             Info::LoopPoint) {
                             *pt = (pt2 * sr).round() as usize;
                             data.fix_loop_point();
+                            data.update_loop();
+                            changed = true;
+                        }
+                    }
+
+                    if data.loop_point.is_some() {
+                        let mut loop_changed = false;
+                        if let Some(j) = ui.combo_box(&format!("osc_{}_loop_mode", i),
+                            "Loop mode", data.loop_mode.name(), Info::LoopMode,
+                            || LoopMode::VARIANTS.map(|x| x.name().to_owned()).to_vec()
+                        ) {
+                            data.loop_mode = LoopMode::VARIANTS[j];
+                            loop_changed = true;
+                        }
+                        loop_changed |= ui.slider(&format!("osc_{}_crossfade", i),
+                            "Crossfade", &mut data.crossfade, 0.0..=0.5, Some("s"), 2,
+                            data.loop_mode == LoopMode::Forward, Info::LoopCrossfade);
+                        if loop_changed {
+                            data.update_loop();
                             changed = true;
                         }
                     }
@@ -380,9 +720,13 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
                 if loaded_sample {
                     changed = true;
                     if let Some(pitch) = data.as_ref().and_then(|d| d.midi_pitch) {
-                        osc.freq_ratio.0.set(clamp_freq_ratio(
-                            2.0_f32.powf((REF_PITCH as f32 - pitch) / 12.0)));
-                        osc.fine_pitch.0.set(0.0);
+                        set_sample_root(&osc.freq_ratio, &osc.fine_pitch, pitch);
+                    } else if cfg.detect_sample_pitch {
+                        if let Some(freq) = data.as_ref().and_then(|d| d.detect_pitch()) {
+                            osc.freq_ratio.0
+                                .set(clamp_freq_ratio(REF_FREQ / freq as f32));
+                            osc.fine_pitch.0.set(0.0);
+                        }
                     }
                 }
 
@@ -395,7 +739,7 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
     labeled_group(ui, "Tone", Info::Tone, |ui| {
         for (i, osc) in patch.oscs.iter_mut().enumerate() {
             ui.shared_slider(&format!("osc_{}_tone", i), "", &osc.tone.0,
-                0.0..=1.0, None, 1, osc.waveform.uses_tone(), Info::Tone);
+                0.0..=1.0, None, 1, osc.uses_tone(), Info::Tone);
 
             if let Waveform::Pcm(_) = osc.waveform {
                 ui.offset_label("", Info::None);
@@ -405,9 +749,30 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
 
     labeled_group(ui, "Freq. ratio", Info::FreqRatio, |ui| {
         for (i, osc) in patch.oscs.iter_mut().enumerate() {
-            ui.shared_slider(&format!("osc_{}_ratio", i),
-                "", &osc.freq_ratio.0, MIN_FREQ_RATIO..=MAX_FREQ_RATIO, None, 2,
-                osc.waveform.uses_freq(), Info::FreqRatio);
+            let fixed = osc.fixed_freq;
+            ui.formatted_shared_slider(&format!("osc_{}_ratio", i),
+                "", &osc.freq_ratio.0, MIN_FREQ_RATIO..=MAX_FREQ_RATIO, 2,
+                osc.waveform.uses_freq(), Info::FreqRatio,
+                move |x| if fixed {
+                    format!("{:.1} Hz", x * REF_FREQ)
+                } else {
+                    format!("{:.3}", x)
+                },
+                move |x| if fixed { x / REF_FREQ } else { x });
+
+            if let Waveform::Pcm(_) = osc.waveform {
+                ui.offset_label("" , Info::None);
+            }
+        }
+    });
+
+    labeled_group(ui, "", Info::None, |ui| {
+        for (i, osc) in patch.oscs.iter_mut().enumerate() {
+            if let Some(i) = ui.combo_box(&format!("osc_{}_ratio_preset", i),
+                "", "Preset", Info::RatioPreset,
+                || RATIO_PRESETS.map(|x| x.to_string()).to_vec()) {
+                osc.freq_ratio.0.set(RATIO_PRESETS[i]);
+            }
 
             if let Waveform::Pcm(_) = osc.waveform {
                 ui.offset_label("" , Info::None);
@@ -415,6 +780,17 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
         }
     });
 
+    labeled_group(ui, "Fixed", Info::FixedFreq, |ui| {
+        for osc in patch.oscs.iter_mut() {
+            changed |= ui.checkbox("", &mut osc.fixed_freq,
+                osc.waveform.uses_freq(), Info::FixedFreq);
+
+            if let Waveform::Pcm(_) = osc.waveform {
+                ui.offset_label("", Info::None);
+            }
+        }
+    });
+
     labeled_group(ui, "Finetune", Info::None, |ui| {
         for (i, osc) in patch.oscs.iter_mut().enumerate() {
             ui.formatted_shared_slider(&format!("osc_{}_tune", i),
@@ -442,6 +818,80 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
         }
     });
 
+    labeled_group(ui, "Shape", Info::ShapePreview, |ui| {
+        for osc in &patch.oscs {
+            match preview::oscillator(osc) {
+                Some(values) => ui.plot(&values, -1.0, 1.0, Info::ShapePreview),
+                None => ui.offset_label("", Info::None),
+            }
+
+            if let Waveform::Pcm(_) = osc.waveform {
+                ui.offset_label("", Info::None);
+            }
+        }
+    });
+
+    if patch.oscs.iter().any(|osc| matches!(osc.waveform, Waveform::Noise)) {
+        labeled_group(ui, "Color", Info::NoiseColor, |ui| {
+            for (i, osc) in patch.oscs.iter_mut().enumerate() {
+                if let Waveform::Noise = osc.waveform {
+                    if let Some(i) = ui.combo_box(&format!("osc_{}_color", i),
+                        "", osc.noise_color.name(), Info::NoiseColor,
+                        || NoiseColor::VARIANTS.map(|x| x.name().to_owned()).to_vec()) {
+                        osc.noise_color = NoiseColor::VARIANTS[i];
+                        changed = true;
+                    }
+                } else {
+                    ui.offset_label("", Info::None);
+                }
+
+                if let Waveform::Pcm(_) = osc.waveform {
+                    ui.offset_label("", Info::None);
+                }
+            }
+        });
+    }
+
+    if patch.oscs.iter().any(|osc| osc.waveform.is_drum()) {
+        labeled_group(ui, "Decay", Info::DrumDecay, |ui| {
+            for (i, osc) in patch.oscs.iter_mut().enumerate() {
+                if osc.waveform.is_drum() {
+                    changed |= ui.slider(&format!("osc_{}_decay", i), "",
+                        &mut osc.drum.decay, 0.01..=2.0, Some("s"), 2, true,
+                        Info::DrumDecay);
+                } else {
+                    ui.offset_label("", Info::None);
+                }
+
+                if let Waveform::Pcm(_) = osc.waveform {
+                    ui.offset_label("", Info::None);
+                }
+            }
+        });
+
+        labeled_group(ui, "Character", Info::DrumCharacter, |ui| {
+            for (i, osc) in patch.oscs.iter_mut().enumerate() {
+                if osc.waveform.is_drum() {
+                    let display: fn(f32) -> String = match osc.waveform {
+                        Waveform::Kick =>
+                            |x| format!("{:.1} oct sweep", x * MAX_KICK_SWEEP),
+                        Waveform::Snare => |x| format!("{:.0}% noise", x * 100.0),
+                        _ => |x| format!("{:.0}% metal", x * 100.0),
+                    };
+                    changed |= ui.formatted_slider(&format!("osc_{}_character", i), "",
+                        &mut osc.drum.character, 0.0..=1.0, 1, true,
+                        Info::DrumCharacter, display, |x| x);
+                } else {
+                    ui.offset_label("", Info::None);
+                }
+
+                if let Waveform::Pcm(_) = osc.waveform {
+                    ui.offset_label("", Info::None);
+                }
+            }
+        });
+    }
+
     labeled_group(ui, "Output", Info::GenOutput, |ui| {
         for (i, osc) in patch.oscs.iter_mut().enumerate() {
             let outputs = OscOutput::choices(i);
@@ -458,6 +908,40 @@ fn generator_controls(ui: &mut Ui, patch: &mut Patch, cfg: &mut Config,
         }
     });
 
+    labeled_group(ui, "AM/RM", Info::AmDepth, |ui| {
+        for (i, osc) in patch.oscs.iter_mut().enumerate() {
+            ui.shared_slider(&format!("osc_{}_am_depth", i), "", &osc.am_depth.0,
+                0.0..=1.0, None, 1,
+                matches!(osc.output, OscOutput::AM(_) | OscOutput::RM(_)),
+                Info::AmDepth);
+
+            if let Waveform::Pcm(_) = osc.waveform {
+                ui.offset_label("", Info::None);
+            }
+        }
+    });
+
+    labeled_group(ui, "C:M", Info::FmRatio, |ui| {
+        for osc in &patch.oscs {
+            let ratio = match osc.output {
+                OscOutput::FM(n) => patch.oscs.get(n).map(|carrier| {
+                    if carrier.fixed_freq || osc.fixed_freq {
+                        String::from("Fixed")
+                    } else {
+                        format!("{}:{}", round_ratio(carrier.freq_ratio.0.value()),
+                            round_ratio(osc.freq_ratio.0.value()))
+                    }
+                }),
+                _ => None,
+            };
+            ui.offset_label(&ratio.unwrap_or_default(), Info::FmRatio);
+
+            if let Waveform::Pcm(_) = osc.waveform {
+                ui.offset_label("", Info::None);
+            }
+        }
+    });
+
     labeled_group(ui, "2X", Info::Oversample, |ui| {
         for osc in patch.oscs.iter_mut() {
             changed |= ui.checkbox("", &mut osc.oversample,
@@ -651,6 +1135,12 @@ fn envelope_controls(ui: &mut Ui, patch: &mut Patch) -> bool {
             }
         });
 
+        labeled_group(ui, "Shape", Info::ShapePreview, |ui| {
+            for env in &patch.envs {
+                ui.plot(&preview::envelope(env), 0.0, 1.0, Info::ShapePreview);
+            }
+        });
+
         labeled_group(ui, "", Info::None, |ui| {
             for i in 0..patch.envs.len() {
                 if ui.button("X", true, Info::Remove("this envelope")) {
@@ -695,6 +1185,12 @@ fn lfo_controls(ui: &mut Ui, patch: &mut Patch) -> bool {
             }
         });
 
+        labeled_group(ui, "Shape", Info::ShapePreview, |ui| {
+            for lfo in &patch.lfos {
+                ui.plot(&preview::lfo(&lfo.waveform), -1.0, 1.0, Info::ShapePreview);
+            }
+        });
+
         labeled_group(ui, "Rate", Info::None, |ui| {
             for (i, lfo) in patch.lfos.iter_mut().enumerate() {
                 let scale = if lfo.audio_rate {
@@ -791,6 +1287,47 @@ fn modulation_controls(ui: &mut Ui, patch: &mut Patch) -> bool {
             }
         });
 
+        labeled_group(ui, "Range", Info::ModPolarity, |ui| {
+            for (i, m) in patch.mod_matrix.iter_mut().enumerate() {
+                if let Some(i) = ui.combo_box(&format!("mod_{}_polarity", i),
+                    "", m.polarity.name(), Info::ModPolarity,
+                    || ModPolarity::VARIANTS.map(|x| x.name().to_owned()).to_vec()) {
+                    m.polarity = ModPolarity::VARIANTS[i];
+                    changed = true;
+                }
+            }
+        });
+
+        labeled_group(ui, "Inv.", Info::ModInvert, |ui| {
+            for m in patch.mod_matrix.iter_mut() {
+                changed |= ui.checkbox("", &mut m.invert, true, Info::ModInvert);
+            }
+        });
+
+        labeled_group(ui, "Steps", Info::ModSteps, |ui| {
+            for (i, m) in patch.mod_matrix.iter_mut().enumerate() {
+                let mut steps = m.steps as f32;
+                if ui.formatted_slider(&format!("mod_{}_steps", i), "", &mut steps,
+                    0.0..=16.0, 1, true, Info::ModSteps,
+                    |x| if x.round() == 0.0 {
+                        String::from("Off")
+                    } else {
+                        format!("{}", x.round())
+                    }, |x| x.round()
+                ) {
+                    m.steps = steps.round() as u8;
+                    changed = true;
+                }
+            }
+        });
+
+        labeled_group(ui, "Slew", Info::ModSlew, |ui| {
+            for (i, m) in patch.mod_matrix.iter_mut().enumerate() {
+                changed |= ui.slider(&format!("mod_{}_slew", i), "", &mut m.slew,
+                    0.0..=2.0, Some("s"), 2, true, Info::ModSlew);
+            }
+        });
+
         labeled_group(ui, "", Info::None, |ui| {
             for i in 0..patch.mod_matrix.len() {
                 if ui.button("X", true, Info::Remove("this modulation")) {
@@ -812,6 +1349,15 @@ fn modulation_controls(ui: &mut Ui, patch: &mut Patch) -> bool {
         changed = true;
     }
 
+    if patch.mod_matrix.iter().any(|m| m.source == ModSource::KeyTrack) {
+        changed |= ui.formatted_slider("key_track_center", "Key track center",
+            &mut patch.key_track.center, 0.0..=127.0, 1, true, Info::KeyTrackCenter,
+            |f| format!("MIDI {}", f.round()), |f| f.round());
+        changed |= ui.formatted_slider("key_track_slope", "Key track slope",
+            &mut patch.key_track.slope, -1.0..=1.0, 1, true, Info::KeyTrackSlope,
+            |f| format!("{f:+.2}/octave"), |f| f);
+    }
+
     changed
 }
 
@@ -843,7 +1389,8 @@ fn display_mod(target: &ModTarget) -> Box<dyn Fn(f32) -> String> {
         ModTarget::FilterCutoff(_) =>
             Box::new(|d| format!("{:+.2} octaves", d * FILTER_CUTOFF_MOD_BASE.log2())),
         ModTarget::ClipGain | ModTarget::FilterQ(_) | ModTarget::Tone(_)
-            | ModTarget::FxSend => Box::new(|d| format!("{:+.2}", d)),
+            | ModTarget::FxSend | ModTarget::Morph | ModTarget::AmDepth(_) =>
+            Box::new(|d| format!("{:+.2}", d)),
         ModTarget::FinePitch | ModTarget::OscFinePitch(_) =>
             Box::new(|d| format!("{:+.1} cents", d * 50.0)),
         ModTarget::Gain | ModTarget::Level(_) =>
@@ -865,7 +1412,8 @@ fn convert_mod(target: &ModTarget) -> Box<dyn FnOnce(f32) -> f32> {
         ModTarget::FilterCutoff(_) =>
             Box::new(|f| f / FILTER_CUTOFF_MOD_BASE.log2()),
         ModTarget::ClipGain | ModTarget::FilterQ(_) | ModTarget::Tone(_)
-            | ModTarget::FxSend => Box::new(|f| f),
+            | ModTarget::FxSend | ModTarget::Morph | ModTarget::AmDepth(_) =>
+            Box::new(|f| f),
         ModTarget::FinePitch | ModTarget::OscFinePitch(_) =>
             Box::new(|f| f / 50.0),
         ModTarget::Gain | ModTarget::Level(_) =>
@@ -891,12 +1439,24 @@ fn shift_patch_index(offset: isize, patch_index: &mut Option<usize>, n: usize) {
     }
 }
 
+/// Round a frequency ratio for display.
+fn round_ratio(r: f32) -> f32 {
+    (r * 100.0).round() / 100.0
+}
+
 fn signed_sqrt(f: f32) -> f32 {
     f.abs().sqrt() * f.signum()
 }
 
 /// Clamps `r` to the freq. ratio range that can be set in the UI,
 /// by adding or removing octaves.
+/// Tune a PCM oscillator so that its sample plays at original speed at the
+/// given MIDI pitch.
+fn set_sample_root(freq_ratio: &Parameter, fine_pitch: &Parameter, pitch: f32) {
+    freq_ratio.0.set(clamp_freq_ratio(2.0_f32.powf((REF_PITCH as f32 - pitch) / 12.0)));
+    fine_pitch.0.set(0.0);
+}
+
 pub fn clamp_freq_ratio(mut r: f32) -> f32 {
     while r > MAX_FREQ_RATIO {
         r *= 0.5;
@@ -925,4 +1485,21 @@ mod tests {
         assert_eq!(clamp_freq_ratio(40.0), 10.0);
         assert_eq!(clamp_freq_ratio(0.1), 0.4);
     }
+
+    #[test]
+    fn test_map_slices() {
+        let mut module = Module::new(Default::default());
+        let slices = vec![Patch::new(String::from("a")), Patch::new(String::from("b"))];
+        map_slices(&mut module, slices);
+        assert_eq!(module.patches.len(), 3);
+        assert_eq!(module.kit.len(), 2);
+        assert_eq!(module.kit[1].patch_index, 2);
+
+        // one command is one undo step, and redo restores the kit
+        assert!(module.undo());
+        assert!(module.patches.len() == 1 && module.kit.is_empty());
+        assert!(module.redo());
+        assert_eq!(module.patches.len(), 3);
+        assert_eq!(module.kit.len(), 2);
+    }
 }
\ No newline at end of file
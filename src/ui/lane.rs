@@ -0,0 +1,324 @@
+//! Automation lane showing a channel's pressure or modulation as a curve,
+//! docked below the pattern editor.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{module::{Channel, Edit, Event, EventData, LocatedEvent, Module, Position, MOD_COLUMN, VEL_COLUMN}, timespan::Timespan};
+
+use super::*;
+
+/// Height of the lane, in pattern rows.
+pub const LANE_ROWS: f32 = 6.0;
+
+/// Number of glide segments used to approximate a curve.
+const CURVE_SEGMENTS: i32 = 4;
+
+/// Size of the markers drawn on value events, in pixels.
+const POINT_SIZE: f32 = 3.0;
+
+/// How mouse drags in the lane edit values.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum LaneTool {
+    /// Freehand drawing, one event per row.
+    #[default]
+    Draw,
+    /// Straight glide between two values.
+    Line,
+    /// Eased glide between two values, made of several straight segments.
+    Curve,
+}
+
+impl LaneTool {
+    pub const VARIANTS: [Self; 3] = [Self::Draw, Self::Line, Self::Curve];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Draw => "Draw",
+            Self::Line => "Line",
+            Self::Curve => "Curve",
+        }
+    }
+}
+
+/// Values painted by a freehand stroke, not yet committed.
+pub struct DrawStroke {
+    pub track: usize,
+    pub channel: usize,
+    pub column: u8,
+    /// Row and value as of the last update, for filling skipped rows.
+    last: (Timespan, f32),
+    values: BTreeMap<Timespan, u8>,
+}
+
+impl DrawStroke {
+    pub fn new(track: usize, channel: usize, column: u8, tick: Timespan, value: f32
+    ) -> Self {
+        Self {
+            track,
+            channel,
+            column,
+            last: (tick, value),
+            values: BTreeMap::from([(tick, value.round() as u8)]),
+        }
+    }
+
+    /// Extends the stroke to a new point, interpolating values for any rows
+    /// passed over since the last point.
+    pub fn extend(&mut self, tick: Timespan, value: f32, row: Timespan) {
+        let (last_tick, last_value) = self.last;
+        let steps = ((tick - last_tick).as_f64() / row.as_f64()).round() as i32;
+        for i in 0..=steps.abs() {
+            let t = last_tick + row * Timespan::new(i * steps.signum(), 1);
+            let f = if steps == 0 { 1.0 } else { i as f32 / steps.abs() as f32 };
+            let v = last_value + (value - last_value) * f;
+            self.values.insert(t, v.round() as u8);
+        }
+        self.last = (tick, value);
+    }
+
+    /// Returns the drawn values by tick.
+    pub fn values(&self) -> impl Iterator<Item = (Timespan, u8)> + use<'_> {
+        self.values.iter().map(|(t, v)| (*t, *v))
+    }
+
+    /// Returns an edit that replaces the column's events over the stroke
+    /// with the drawn values.
+    pub fn edit(&self, module: &Module, row: Timespan) -> Option<Edit> {
+        let first = *self.values.first_key_value()?.0;
+        let last = *self.values.last_key_value()?.0;
+        let start = Position {
+            track: self.track,
+            channel: self.channel,
+            column: self.column,
+            tick: first,
+        };
+        let end = Position { tick: last + row, ..start };
+        let remove = module.scan_events(start, end).iter()
+            .filter(|x| x.event.data.logical_column() == self.column)
+            .map(|x| x.position())
+            .collect();
+        let add = self.values().map(|(tick, v)| LocatedEvent::from_position(
+            Position { tick, ..start }, value_data(self.column, v))).collect();
+        Some(Edit::PatternData { remove, add })
+    }
+}
+
+/// State of the automation lane.
+#[derive(Default)]
+pub struct Lane {
+    pub visible: bool,
+    pub tool: LaneTool,
+    stroke: Option<DrawStroke>,
+    /// Start point of a line or curve drag.
+    anchor: Option<(Timespan, f32)>,
+}
+
+impl Lane {
+    /// Draws the lane in `rect` for the channel at `cursor`, with `visible`
+    /// as the tick range shown in the pattern. Returns an edit if the user
+    /// finished drawing.
+    pub fn draw(&mut self, ui: &mut Ui, module: &Module, rect: Rect, cursor: Position,
+        visible: Range<Timespan>, division: u8
+    ) -> Option<Edit> {
+        let margin = ui.style.margin;
+        ui.push_rect(rect, ui.style.theme.panel_bg(),
+            Some(ui.style.theme.border_unfocused()));
+
+        if cursor.track == 0 {
+            ui.push_text(rect.x + margin, rect.y + margin,
+                String::from("The control track has no automation."), ui.style.theme.fg());
+            return None
+        }
+
+        let column = if cursor.column == MOD_COLUMN { MOD_COLUMN } else { VEL_COLUMN };
+        let (name, color) = if column == MOD_COLUMN {
+            ("MODULATION", ui.style.theme.modulation_fg())
+        } else {
+            ("PRESSURE", ui.style.theme.pressure_fg())
+        };
+        let label = format!("{name} - {}, channel {}",
+            pattern::track_name(module.tracks[cursor.track].target, &module.patches),
+            cursor.channel + 1);
+        ui.push_text(rect.x + margin, rect.y + margin, label, ui.style.theme.fg());
+
+        // plot area below the label
+        let top = rect.y + ui.style.line_height() + margin;
+        let area = Rect {
+            x: rect.x + margin,
+            y: top,
+            w: rect.w - margin * 2.0,
+            h: rect.y + rect.h - margin - top,
+        };
+        let len = (visible.end - visible.start).as_f32().max(f32::EPSILON);
+        let max = EventData::DIGIT_MAX as f32;
+        let tick_x = |t: Timespan| area.x + (t - visible.start).as_f32() / len * area.w;
+        let value_y = |v: f32| area.y + area.h - v / max * area.h;
+
+        // current values, sampled every other pixel
+        let channel = &module.tracks[cursor.track].channels[cursor.channel];
+        let mut prev: Option<Vec2> = None;
+        let mut x = area.x;
+        while x <= area.x + area.w {
+            let f = (x - area.x) / area.w;
+            let t = visible.start + Timespan::approximate((f * len).into());
+            let p = value_at(channel, column, t).map(|v| vec2(x, value_y(v)));
+            if let (Some(a), Some(b)) = (prev, p) {
+                ui.push_line(a.x, a.y, b.x, b.y, color);
+            }
+            prev = p;
+            x += 2.0;
+        }
+        for event in channel.events.iter()
+            .filter(|e| e.data.logical_column() == column && visible.contains(&e.tick)) {
+            if let EventData::Pressure(v) | EventData::Modulation(v) = event.data {
+                let (x, y) = (tick_x(event.tick), value_y(v as f32));
+                ui.push_rect(Rect {
+                    x: x - POINT_SIZE * 0.5,
+                    y: y - POINT_SIZE * 0.5,
+                    w: POINT_SIZE,
+                    h: POINT_SIZE,
+                }, color, None);
+            }
+        }
+
+        // mouse input
+        let mouse = mouse_position_vec2();
+        let point = || {
+            let f = ((mouse.x - area.x) / area.w).clamp(0.0, 1.0);
+            let t = visible.start.as_f64() + (f * len) as f64;
+            let tick = Timespan::new((t * division as f64).round().max(0.0) as i32, division);
+            (tick, ((area.y + area.h - mouse.y) / area.h * max).clamp(0.0, max))
+        };
+        let row = Timespan::new(1, division);
+        if ui.mouse_hits(rect, "lane") && is_mouse_button_pressed(MouseButton::Left) {
+            let (tick, value) = point();
+            match self.tool {
+                LaneTool::Draw => self.stroke = Some(DrawStroke::new(
+                    cursor.track, cursor.channel, column, tick, value)),
+                LaneTool::Line | LaneTool::Curve => self.anchor = Some((tick, value)),
+            }
+        }
+
+        let down = is_mouse_button_down(MouseButton::Left);
+        let preview = Color { a: 0.5, ..color };
+        if let Some(stroke) = &mut self.stroke {
+            if down {
+                let (tick, value) = point();
+                stroke.extend(tick, value, row);
+                for (tick, v) in stroke.values() {
+                    let (x, y) = (tick_x(tick), value_y(v as f32));
+                    ui.push_rect(Rect { x: x - POINT_SIZE * 0.5, y, w: POINT_SIZE,
+                        h: area.y + area.h - y }, preview, None);
+                }
+            } else {
+                return self.stroke.take().and_then(|s| s.edit(module, row))
+            }
+        }
+        if let Some(anchor) = self.anchor {
+            let points = glide_points(anchor, point(), self.tool, division);
+            if down {
+                for pair in points.windows(2) {
+                    let (a, b) = (pair[0], pair[1]);
+                    ui.push_line(tick_x(a.0), value_y(a.1 as f32),
+                        tick_x(b.0), value_y(b.1 as f32), preview);
+                }
+            } else {
+                self.anchor = None;
+                let start = Position { column, ..cursor };
+                return Some(glide_edit(module, start, &points, row))
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the event data for a digit value in a column.
+fn value_data(column: u8, value: u8) -> EventData {
+    if column == MOD_COLUMN {
+        EventData::Modulation(value)
+    } else {
+        EventData::Pressure(value)
+    }
+}
+
+/// Returns the value of a digit column at `tick`, accounting for glides, or
+/// None if no value has been set yet.
+fn value_at(channel: &Channel, column: u8, tick: Timespan) -> Option<f32> {
+    let value = |e: &Event| match e.data {
+        EventData::Pressure(v) | EventData::Modulation(v) => v as f32,
+        _ => 0.0,
+    };
+    let mut events = channel.events.iter().filter(|e| e.data.logical_column() == column);
+    let mut prev = None;
+    let mut next = None;
+    for e in events.by_ref() {
+        if e.tick <= tick {
+            prev = Some(e);
+        } else {
+            next = Some(e);
+            break
+        }
+    }
+    let prev = prev?;
+    match next {
+        Some(next) if channel.is_interpolated(column, tick) => {
+            let f = ((tick - prev.tick) / (next.tick - prev.tick)).as_f32();
+            Some(value(prev) + (value(next) - value(prev)) * f)
+        }
+        _ => Some(value(prev)),
+    }
+}
+
+/// Returns the breakpoints of a line or curve between two points, in tick
+/// order. Curves ease in, so they change slowly at first.
+fn glide_points(a: (Timespan, f32), b: (Timespan, f32), tool: LaneTool, division: u8
+) -> Vec<(Timespan, u8)> {
+    let (a, b) = if a.0 <= b.0 { (a, b) } else { (b, a) };
+    let rows = ((b.0 - a.0).as_f64() * division as f64).round() as i32;
+    let segments = match tool {
+        LaneTool::Curve => CURVE_SEGMENTS.min(rows),
+        _ => rows.min(1),
+    };
+    let mut points: Vec<(Timespan, u8)> = Vec::new();
+    for i in 0..=segments {
+        let f = if segments == 0 { 1.0 } else { i as f32 / segments as f32 };
+        let eased = if tool == LaneTool::Curve { f * f } else { f };
+        let row = (rows as f32 * f).round() as i32;
+        let tick = a.0 + Timespan::new(row, division);
+        let value = (a.1 + (b.1 - a.1) * eased).round() as u8;
+        match points.last_mut() {
+            Some(p) if p.0 == tick => p.1 = value,
+            _ => points.push((tick, value)),
+        }
+    }
+    points
+}
+
+/// Returns an edit that replaces a column's values and glides between the
+/// first and last points with the points, gliding between them.
+fn glide_edit(module: &Module, start: Position, points: &[(Timespan, u8)], row: Timespan
+) -> Edit {
+    let column = start.column;
+    let first = points.first().map_or(start.tick, |p| p.0);
+    let last = points.last().map_or(start.tick, |p| p.0);
+    let remove = module.scan_events(Position { tick: first, ..start },
+        Position { tick: last + row, ..start }).iter()
+        .filter(|x| {
+            let col = x.event.data.logical_column();
+            col == column || col == column | EventData::INTERP_COL_FLAG
+        })
+        .map(|x| x.position())
+        .collect();
+    let mut add: Vec<_> = points.iter()
+        .map(|(tick, v)| LocatedEvent::from_position(
+            Position { tick: *tick, ..start }, value_data(column, *v)))
+        .collect();
+    if first != last {
+        add.push(LocatedEvent::from_position(Position { tick: first, ..start },
+            EventData::StartGlide(column)));
+        add.push(LocatedEvent::from_position(Position { tick: last, ..start },
+            EventData::EndGlide(column)));
+    }
+    Edit::PatternData { remove, add }
+}
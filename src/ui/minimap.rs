@@ -0,0 +1,107 @@
+//! Compact overview of pattern activity, docked beside the pattern editor.
+
+use std::ops::Range;
+
+use crate::module::Module;
+
+use super::*;
+
+/// Width of each track's column, in pixels.
+const COLUMN_WIDTH: f32 = 2.0;
+
+/// Event count at which a cell is drawn at full opacity.
+const FULL_ACTIVITY: f32 = 8.0;
+
+/// Per-beat event counts for each track.
+#[derive(Default)]
+pub struct Minimap {
+    /// Module edit count as of the last rebuild.
+    edit_count: Option<u64>,
+    /// Number of events in each beat, indexed by track then beat.
+    activity: Vec<Vec<u16>>,
+}
+
+impl Minimap {
+    /// Returns the width of the minimap for `module`.
+    pub fn width(module: &Module) -> f32 {
+        (0..module.tracks.len()).filter(|i| !module.track_hidden(*i)).count() as f32
+            * COLUMN_WIDTH
+    }
+
+    /// Recount events if the module has changed since the last update.
+    fn update(&mut self, module: &Module) {
+        if self.edit_count == Some(module.edit_count()) {
+            return
+        }
+
+        self.activity = module.tracks.iter().map(|track| {
+            let mut beats = Vec::new();
+            for event in track.channels.iter().flat_map(|c| &c.events) {
+                let beat = event.tick.as_f64().max(0.0) as usize;
+                if beat >= beats.len() {
+                    beats.resize(beat + 1, 0);
+                }
+                beats[beat] = beats[beat].saturating_add(1);
+            }
+            beats
+        }).collect();
+        self.edit_count = Some(module.edit_count());
+    }
+
+    /// Draw the minimap in `rect`, with `visible` as the beat range shown in
+    /// the pattern. Returns a beat to scroll to if the minimap was clicked.
+    pub fn draw(&mut self, ui: &mut Ui, module: &Module, rect: Rect,
+        visible: Range<f64>, playhead: Option<f64>
+    ) -> Option<f64> {
+        self.update(module);
+
+        let len = self.activity.iter().map(|v| v.len()).max().unwrap_or_default()
+            .max(visible.end.ceil() as usize).max(1);
+
+        // zoom out by powers of two until the whole song fits
+        let mut beats_per_px = 1;
+        while (len / beats_per_px) as f32 > rect.h {
+            beats_per_px *= 2;
+        }
+        let beat_y = |beat: f64| rect.y + (beat / beats_per_px as f64) as f32;
+
+        ui.push_rect(rect, ui.style.theme.panel_bg(), None);
+
+        let color = ui.style.theme.accent1_fg();
+        let tracks = self.activity.iter().enumerate()
+            .filter(|(i, _)| !module.track_hidden(*i));
+        for (col, (_, beats)) in tracks.enumerate() {
+            let x = rect.x + col as f32 * COLUMN_WIDTH;
+            for (row, chunk) in beats.chunks(beats_per_px).enumerate() {
+                let n = chunk.iter().copied().max().unwrap_or_default();
+                if n > 0 {
+                    let cell = Rect { x, y: rect.y + row as f32, w: COLUMN_WIDTH, h: 1.0 };
+                    let a = (n as f32 / FULL_ACTIVITY).min(1.0);
+                    ui.push_rect(cell, Color { a, ..color }, None);
+                }
+            }
+        }
+
+        // mark the visible range and playhead
+        let view = Rect {
+            y: beat_y(visible.start),
+            h: (beat_y(visible.end) - beat_y(visible.start)).max(1.0),
+            ..rect
+        };
+        ui.push_rect(view, Color { a: 0.15, ..ui.style.theme.fg() }, None);
+        if let Some(beat) = playhead {
+            let y = beat_y(beat);
+            ui.push_line(rect.x, y, rect.x + rect.w, y, ui.style.theme.fg());
+        }
+
+        if ui.mouse_hits(rect, "minimap") {
+            ui.info = Info::Minimap;
+            if is_mouse_button_down(MouseButton::Left) && !ui.grabbed() {
+                let y = mouse_position().1 - rect.y;
+                return Some((y * beats_per_px as f32) as f64)
+            }
+        }
+
+        None
+    }
+}
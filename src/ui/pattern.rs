@@ -1,15 +1,44 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, ops::Range};
 
 use fundsp::math::delerp;
+use macroquad::miniquad::window::{clipboard_get, clipboard_set};
+use serde::{Deserialize, Serialize};
 
-use crate::{config::Config, input::{self, Action}, module::*, synth::Patch, timespan::Timespan};
+use crate::{config::{Config, DoubleClickAction, RowNumbers}, input::{self, Action}, module::*, pitch::NoteNames, synth::{pcm::PcmData, Patch}, timespan::Timespan};
 
-use super::*;
+use super::{lane::{DrawStroke, Lane, LANE_ROWS}, minimap::Minimap, pattern_text,
+    quantize::{self, QuantizeOptions, MAX_SWING}, *};
 
 /// Narrower margin used in the pattern grid.
 const PATTERN_MARGIN: f32 = 2.0;
 
 const CTRL_COLUMN_TEXT_ID: &str = "ctrl_column";
+const MARKER_TEXT_ID: &str = "marker";
+
+/// Maximum length of marker text, in characters.
+const MARKER_MAX_WIDTH: usize = 24;
+
+/// Number of fine offset steps in a row.
+const FINE_OFFSETS_PER_ROW: u8 = 8;
+
+/// Actions in the pattern context menu.
+const PATTERN_MENU: [Action; 8] = [
+    Action::Cut,
+    Action::Copy,
+    Action::Paste,
+    Action::MixPaste,
+    Action::Interpolate,
+    Action::Delete,
+    Action::CopyText,
+    Action::CopyMml,
+];
+
+/// Common beat divisions, by name.
+pub const DIVISION_PRESETS: [(&str, u8); 3] = [
+    ("Straight", 4),
+    ("Triplet", 6),
+    ("Quintuplet", 5),
+];
 
 /// These actions are valid ways to exit pattern text entry.
 /// Defining what's on this list is a little hairy since there are pattern
@@ -32,6 +61,9 @@ pub struct PatternEditor {
     edit_start: Position,
     edit_end: Position,
     pub beat_division: u8,
+    /// Division before the last triplet toggle, so that toggling back
+    /// returns to it.
+    triplet_origin: Option<u8>,
     beat_scroll: Timespan,
     h_scroll: f32,
     tap_tempo_intervals: Vec<f32>,
@@ -43,9 +75,56 @@ pub struct PatternEditor {
     /// Highest visible tick. Lowest is `beat_scroll`.
     screen_tick_max: Timespan,
     text_position: Option<Position>,
+    /// If true, note column text entry edits a slide instead of a condition.
+    slide_text: bool,
+    marker_tick: Option<Timespan>,
+    /// Cursor track as of the last frame, for skipping hidden tracks.
+    last_track: usize,
+    /// Mute states to restore when the momentary solo key is released.
+    momentary_mutes: Option<Vec<bool>>,
+    minimap: Minimap,
+    /// Positions of individually selected events. If non-empty, this
+    /// overrides the rectangular selection for some operations.
+    marked: Vec<Position>,
+    /// Options from the last paste special command.
+    paste_options: PasteOptions,
+    /// True if the paste special panel is open.
+    paste_special: bool,
+    quantize_options: QuantizeOptions,
+    /// True if the quantize panel is open.
+    quantize: bool,
+    /// State of the inactive pane, if the view is split.
+    split: Option<PaneView>,
+    /// Shade triplet positions within each beat.
+    triplet_grid: bool,
+    /// Selection corners and module edit count of the cached summary.
+    selection_summary: Option<((Position, Position, u64), String)>,
+    /// Mouse position as of the last frame of a middle-button pan.
+    pan_origin: Option<Vec2>,
+    selection_drag: Option<SelectionDrag>,
+    /// If true, dragging in pressure and modulation columns draws values.
+    pub draw_mode: bool,
+    draw_stroke: Option<DrawStroke>,
+    pub lane: Lane,
+}
+
+/// Cursor and scroll state of a pattern viewport.
+struct PaneView {
+    edit_start: Position,
+    edit_end: Position,
+    beat_scroll: Timespan,
+    marked: Vec<Position>,
+    screen_tick_max: Timespan,
+    triplet_grid: bool,
+    /// True if this pane is drawn below the other.
+    below: bool,
 }
 
+/// First line of pattern data in the system clipboard.
+const CLIP_TEXT_HEADER: &str = "# osctet pattern data";
+
 /// Pattern data clipboard.
+#[derive(Serialize, Deserialize)]
 struct PatternClip {
     start: Position,
     end: Position,
@@ -53,16 +132,77 @@ struct PatternClip {
     channels: usize,
 }
 
+impl PatternClip {
+    /// Serialize to text for the system clipboard.
+    fn to_text(&self) -> Result<String, toml::ser::Error> {
+        Ok(format!("{CLIP_TEXT_HEADER}\n{}", toml::to_string(self)?))
+    }
+
+    /// Parse text from the system clipboard. Returns None if the text isn't
+    /// pattern data.
+    fn from_text(s: &str) -> Option<Self> {
+        toml::from_str(s.trim_start().strip_prefix(CLIP_TEXT_HEADER)?).ok()
+    }
+}
+
 /// Different behavior variants for the paste command.
 #[derive(PartialEq)]
 enum PasteMode {
     Normal,
     Mix,
     Stretch,
+    Special(PasteOptions),
+}
+
+/// Options for the paste special command.
+#[derive(Clone, Copy, PartialEq)]
+struct PasteOptions {
+    /// Paste note and control column events.
+    notes: bool,
+    pressure: bool,
+    modulation: bool,
+    /// Tile clipboard data to fill the selection.
+    repeat: bool,
+    /// Transposition of pasted notes, in scale steps.
+    transpose: i8,
+}
+
+impl Default for PasteOptions {
+    fn default() -> Self {
+        Self {
+            notes: true,
+            pressure: true,
+            modulation: true,
+            repeat: false,
+            transpose: 0,
+        }
+    }
+}
+
+impl PasteOptions {
+    /// Returns true if events of this type should be pasted.
+    fn includes(&self, data: &EventData) -> bool {
+        match data.spatial_column() {
+            NOTE_COLUMN => self.notes,
+            VEL_COLUMN => self.pressure,
+            MOD_COLUMN => self.modulation,
+            _ => true,
+        }
+    }
+}
+
+/// Mouse drag of the selection.
+#[derive(Clone, Copy)]
+enum SelectionDrag {
+    /// Moving the selection, grabbed at a position. Holding Ctrl on release
+    /// copies instead.
+    Move(Position),
+    /// Stretching the selection by its bottom edge.
+    Stretch,
 }
 
 /// Event in the pattern data clipboard.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 struct ClipEvent {
     channel_offset: usize,
     event: Event,
@@ -80,6 +220,7 @@ impl Default for PatternEditor {
             edit_start: edit_cursor,
             edit_end: edit_cursor,
             beat_division: 4,
+            triplet_origin: None,
             beat_scroll: Timespan::ZERO,
             h_scroll: 0.0,
             tap_tempo_intervals: Vec::new(),
@@ -89,6 +230,24 @@ impl Default for PatternEditor {
             record: false,
             screen_tick_max: Timespan::ZERO,
             text_position: None,
+            slide_text: false,
+            marker_tick: None,
+            last_track: 0,
+            momentary_mutes: None,
+            minimap: Minimap::default(),
+            marked: Vec::new(),
+            paste_options: Default::default(),
+            paste_special: false,
+            quantize_options: Default::default(),
+            quantize: false,
+            split: None,
+            triplet_grid: false,
+            selection_summary: None,
+            pan_origin: None,
+            selection_drag: None,
+            draw_mode: false,
+            draw_stroke: None,
+            lane: Default::default(),
         }
     }
 }
@@ -112,6 +271,18 @@ impl PatternEditor {
         self.set_division(self.beat_division / 2);
     }
 
+    /// Switch between a straight division and its triplet counterpart.
+    pub fn toggle_triplets(&mut self) {
+        match self.triplet_origin.take() {
+            Some(origin) if triplet_toggled(origin) == self.beat_division =>
+                self.set_division(origin),
+            _ => {
+                self.triplet_origin = Some(self.beat_division);
+                self.set_division(triplet_toggled(self.beat_division));
+            }
+        }
+    }
+
     /// Set division, adjusting other parameters as necessary.
     pub fn set_division(&mut self, division: u8) {
         let division = division.max(1);
@@ -145,11 +316,130 @@ impl PatternEditor {
         self.edit_start.track
     }
 
+    /// Returns the tracks and timespan of the selection, or the cursor track
+    /// and the whole song if no timespan is selected.
+    pub fn export_range(&self, module: &Module) -> (Range<usize>, Range<Timespan>) {
+        let (start, end) = self.selection_corners_with_tail();
+        if start.tick == end.tick {
+            let end_tick = module.last_event_tick().unwrap_or_default()
+                + self.row_timespan();
+            (start.track..start.track + 1, Timespan::ZERO..end_tick)
+        } else {
+            (start.track..end.track + 1, start.tick..end.tick)
+        }
+    }
+
     /// Returns the tick the cursor is on.
     pub fn cursor_tick(&self) -> Timespan {
         self.edit_start.tick
     }
 
+    /// Start editing the marker at the cursor row.
+    pub fn edit_marker(&mut self, module: &Module, ui: &mut Ui) {
+        let tick = self.cursor_tick();
+        let text = module.marker_at(tick).map(|m| m.text.clone()).unwrap_or_default();
+        self.marker_tick = Some(tick);
+        ui.focus_text(MARKER_TEXT_ID.into(), text);
+    }
+
+    /// Start text entry for the note condition at the cursor.
+    pub fn edit_condition(&mut self, module: &Module, ui: &mut Ui) {
+        let pos = self.edit_start;
+        if pos.track == 0 || pos.column != NOTE_COLUMN {
+            return
+        }
+
+        let text = module.tracks[pos.track].channels[pos.channel]
+            .condition_at(pos.tick)
+            .map(|c| c.to_string())
+            .unwrap_or_default();
+        self.text_position = Some(pos);
+        self.slide_text = false;
+        ui.focus_text(CTRL_COLUMN_TEXT_ID.into(), text);
+    }
+
+    /// Start text entry for the note slide at the cursor.
+    pub fn edit_slide(&mut self, module: &Module, ui: &mut Ui) {
+        let pos = self.edit_start;
+        if pos.track == 0 || pos.column != NOTE_COLUMN {
+            return
+        }
+
+        let text = module.tracks[pos.track].channels[pos.channel]
+            .slide_at(pos.tick)
+            .map(|n| format!("{:X}", n))
+            .unwrap_or_default();
+        self.text_position = Some(pos);
+        self.slide_text = true;
+        ui.focus_text(CTRL_COLUMN_TEXT_ID.into(), text);
+    }
+
+    /// Start text entry for the vibrato and tremolo at the cursor.
+    pub fn edit_effects(&mut self, module: &Module, ui: &mut Ui) {
+        let pos = self.edit_start;
+        if pos.track == 0 || pos.column != MOD_COLUMN {
+            return
+        }
+
+        let effects = module.tracks[pos.track].channels[pos.channel]
+            .effects_at(pos.tick)
+            .into_iter()
+            .map(|data| match *data {
+                EventData::Vibrato(rate, depth) => Some(format!("4{:X}{:X}", rate, depth)),
+                EventData::Tremolo(rate, depth) => Some(format!("7{:X}{:X}", rate, depth)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(effects) = effects else { return };
+        let text = effects.join(" ");
+        self.text_position = Some(pos);
+        ui.focus_text(CTRL_COLUMN_TEXT_ID.into(), text);
+    }
+
+    /// Move the cursor to a tick and scroll to it.
+    pub fn jump_to(&mut self, tick: Timespan) {
+        self.edit_start.tick = tick;
+        self.edit_end.tick = tick;
+        self.scroll_to(tick);
+    }
+
+    /// Move the cursor to a position and scroll to it.
+    pub fn jump_to_position(&mut self, pos: Position) {
+        self.edit_start = pos;
+        self.edit_end = pos;
+        self.scroll_to(pos.tick);
+    }
+
+    /// Split the view into two panes, or merge it back into one. A new pane
+    /// starts with the same cursor and scroll as the current one.
+    fn toggle_split(&mut self) {
+        self.split = match self.split {
+            Some(_) => None,
+            None => Some(PaneView {
+                edit_start: self.edit_start,
+                edit_end: self.edit_end,
+                beat_scroll: self.beat_scroll,
+                marked: Vec::new(),
+                screen_tick_max: self.screen_tick_max,
+                triplet_grid: self.triplet_grid,
+                below: true,
+            }),
+        };
+    }
+
+    /// Exchange the active pane's cursor and scroll with the inactive pane's.
+    fn swap_panes(&mut self, tracks: &[Track]) {
+        let Some(split) = self.split.as_mut() else { return };
+        std::mem::swap(&mut self.edit_start, &mut split.edit_start);
+        std::mem::swap(&mut self.edit_end, &mut split.edit_end);
+        std::mem::swap(&mut self.beat_scroll, &mut split.beat_scroll);
+        std::mem::swap(&mut self.marked, &mut split.marked);
+        std::mem::swap(&mut self.screen_tick_max, &mut split.screen_tick_max);
+        std::mem::swap(&mut self.triplet_grid, &mut split.triplet_grid);
+        split.below = !split.below;
+        fix_cursors(&mut self.edit_start, &mut self.edit_end, tracks);
+    }
+
     /// Check whether the cursor is in the digit column.
     pub fn in_digit_column(&self, ui: &Ui) -> bool {
         ui.tabs.get(MAIN_TAB_ID) == Some(&TAB_PATTERN)
@@ -164,12 +454,13 @@ impl PatternEditor {
 
     /// Return the current height of a beat, in pixels.
     fn beat_height(&self, ui: &Ui) -> f32 {
-        line_height(&ui.style.atlas) * self.beat_division as f32
+        line_height(ui.style.pattern_atlas()) * self.beat_division as f32
     }
 
     /// Convert mouse coordinates to a Position.
-    fn position_from_mouse(&self, ui: &Ui, track_xs: &[f32], tracks: &[Track])
+    fn position_from_mouse(&self, ui: &Ui, track_xs: &[f32], module: &Module)
     -> Position {
+        let tracks = &module.tracks;
         let (x, y) = mouse_position();
         let mut pos = Position {
             tick: self.round_tick(self.y_tick(y, ui)),
@@ -178,7 +469,7 @@ impl PatternEditor {
 
         // skip last track_x since it's not the start of a track
         for (i, tx) in track_xs.split_last().unwrap().1.iter().enumerate() {
-            if x >= *tx {
+            if x >= *tx && !module.track_hidden(i) {
                 let chan_width = channel_width(i, &ui.style);
                 pos.track = i;
                 pos.channel = (tracks[i].channels.len() - 1)
@@ -204,7 +495,7 @@ impl PatternEditor {
     /// Returns the beat position of a vertical screen position.
     fn y_tick(&self, y: f32, ui: &Ui) -> Timespan {
         let beat_height = self.beat_height(ui);
-        let f = (y - ui.cursor_y - line_height(&ui.style.atlas) * 0.5) / beat_height;
+        let f = (y - ui.cursor_y - line_height(ui.style.pattern_atlas()) * 0.5) / beat_height;
         Timespan::approximate(f.into())
     }
 
@@ -250,38 +541,412 @@ impl PatternEditor {
         (start, end)
     }
 
-    /// Draws the cursor/selection.
-    fn draw_cursor(&self, ui: &mut Ui, track_xs: &[f32]) {
-        let (tl, br) = self.selection_corners();
+    /// Returns the screen rect spanned by two selection corners.
+    fn corners_rect(&self, ui: &Ui, track_xs: &[f32], tl: Position, br: Position) -> Rect {
         let beat_height = self.beat_height(ui);
         let start = position_coords(tl, &ui.style, track_xs, false, beat_height);
         let end = position_coords(br, &ui.style, track_xs, true, beat_height);
 
-        let selection_rect = Rect {
+        Rect {
             x: ui.style.margin + start.x,
             y: ui.cursor_y + start.y,
             w: end.x - start.x,
             h: end.y - start.y,
-        };
+        }
+    }
+
+    /// Draws the cursor/selection.
+    fn draw_cursor(&self, ui: &mut Ui, track_xs: &[f32]) {
+        let (tl, br) = self.selection_corners();
+        let selection_rect = self.corners_rect(ui, track_xs, tl, br);
         let color = Color { a: 0.1, ..ui.style.theme.fg() };
         ui.push_rect(selection_rect, color, None);
     }
 
+    /// Outlines where a selection drag would put the selection.
+    fn draw_drag_preview(&self, ui: &mut Ui, module: &Module, track_xs: &[f32],
+        drag: SelectionDrag
+    ) {
+        let pos = self.position_from_mouse(ui, track_xs, module);
+        let (tl, br) = self.drag_destination(drag, pos, module);
+        let rect = self.corners_rect(ui, track_xs, tl, br);
+        ui.push_rect(rect, BLANK, Some(ui.style.theme.accent1_fg()));
+    }
+
+    /// Returns the row and digit value under the mouse for a draw stroke in
+    /// a channel. The value is set by the horizontal position in the channel.
+    fn draw_point(&self, ui: &Ui, track_xs: &[f32], track: usize, channel: usize
+    ) -> (Timespan, f32) {
+        let (x, y) = mouse_position();
+        let chan_width = channel_width(track, &ui.style);
+        let chan_x = track_xs[track] + chan_width * channel as f32;
+        let f = ((x - chan_x) / chan_width).clamp(0.0, 1.0);
+        let tick = self.round_tick(self.y_tick(y, ui)).max(Timespan::ZERO);
+        (tick, f * EventData::DIGIT_MAX as f32)
+    }
+
+    /// Starts a draw stroke if `pos` is in a pressure or modulation column.
+    fn start_stroke(&mut self, ui: &Ui, track_xs: &[f32], pos: Position) {
+        if pos.track == 0 || !matches!(pos.column, VEL_COLUMN | MOD_COLUMN) {
+            return
+        }
+        let (tick, value) = self.draw_point(ui, track_xs, pos.track, pos.channel);
+        self.draw_stroke = Some(DrawStroke::new(pos.track, pos.channel, pos.column,
+            tick, value));
+    }
+
+    /// Extends the draw stroke to the mouse position, interpolating values
+    /// for any rows passed over since the last frame.
+    fn continue_stroke(&mut self, ui: &Ui, track_xs: &[f32]) {
+        let Some(stroke) = &self.draw_stroke else { return };
+        let (tick, value) = self.draw_point(ui, track_xs, stroke.track, stroke.channel);
+        let row = self.row_timespan();
+        if let Some(stroke) = &mut self.draw_stroke {
+            stroke.extend(tick, value, row);
+        }
+    }
+
+    /// Replaces the stroke's column events with the drawn values, as a
+    /// single edit.
+    fn commit_stroke(&mut self, module: &mut Module) {
+        let edit = self.draw_stroke.take()
+            .and_then(|stroke| stroke.edit(module, self.row_timespan()));
+        if let Some(edit) = edit {
+            module.push_edit(edit);
+        }
+    }
+
+    /// Draws the uncommitted values of the draw stroke as bars.
+    fn draw_stroke_preview(&self, ui: &mut Ui, track_xs: &[f32]) {
+        let Some(stroke) = &self.draw_stroke else { return };
+        let color = Color { a: 0.5, ..ui.style.theme.accent1_fg() };
+        for (tick, v) in stroke.values() {
+            let pos = Position {
+                track: stroke.track,
+                channel: stroke.channel,
+                column: stroke.column,
+                tick,
+            };
+            let cell = self.corners_rect(ui, track_xs, pos, pos);
+            ui.push_rect(cell, ui.style.theme.content_bg(), None);
+            let w = cell.w * v as f32 / EventData::DIGIT_MAX as f32;
+            ui.push_rect(Rect { w: w.max(1.0), ..cell }, color, None);
+        }
+    }
+
+    /// Returns the events that the quantize panel's options would move, with
+    /// their new ticks, and the accents a groove would add.
+    fn quantize_changes(&self, module: &Module
+    ) -> (Vec<(LocatedEvent, Timespan)>, Vec<LocatedEvent>) {
+        let opts = &self.quantize_options;
+        let events = self.selected_events(module);
+        let groove = opts.groove.and_then(|i| module.grooves.get(i));
+        let moves = opts.moves(&events, self.beat_division, groove);
+        let accents = groove
+            .map(|groove| opts.accents(&events, self.beat_division, groove))
+            .unwrap_or_default();
+        (moves, accents)
+    }
+
+    /// Outlines where the quantize panel's options would move or add events.
+    fn draw_quantize_preview(&self, ui: &mut Ui, module: &Module, track_xs: &[f32]) {
+        let (moves, accents) = self.quantize_changes(module);
+        let moved = moves.into_iter().map(|(e, tick)| Position {
+            tick,
+            column: e.event.data.spatial_column(),
+            ..e.position()
+        });
+        for pos in moved.chain(accents.iter().map(|e| e.position())) {
+            let cell = self.corners_rect(ui, track_xs, pos, pos);
+            ui.push_rect(cell, BLANK, Some(ui.style.theme.accent1_fg()));
+        }
+    }
+
+    /// Saves the selected events as a new macro, with ticks relative to the
+    /// first event. Events in the control track and the first other channel
+    /// in the selection are kept.
+    fn save_macro(&self, module: &mut Module) {
+        let events = self.selected_events(module);
+        let Some(start) = events.iter().map(|e| e.event.tick).min() else { return };
+        let source = events.iter().find(|e| e.track != 0).map(|e| (e.track, e.channel));
+        let relative = |e: &LocatedEvent| Event {
+            tick: e.event.tick - start,
+            data: e.event.data.clone(),
+        };
+        let mut macros = module.macros.clone();
+        macros.push(EventMacro {
+            name: format!("Macro {}", macros.len() + 1),
+            hotkey: None,
+            control: events.iter().filter(|e| e.track == 0).map(relative).collect(),
+            channel: events.iter()
+                .filter(|e| Some((e.track, e.channel)) == source)
+                .map(relative)
+                .collect(),
+        });
+        module.push_edit(Edit::SetMacros(macros));
+    }
+
+    /// Inserts the events of a macro at the cursor, replacing events in their
+    /// positions. Channel events are skipped if the cursor is in the control
+    /// track.
+    pub fn insert_macro(&self, module: &mut Module, index: usize) {
+        let Some(m) = module.macros.get(index) else { return };
+        let cursor = self.edit_start;
+        let mut events: Vec<_> = m.control.iter().map(|e| (0, 0, e)).collect();
+        if cursor.track != 0 {
+            events.extend(m.channel.iter().map(|e| (cursor.track, cursor.channel, e)));
+        }
+        let add: Vec<_> = events.into_iter().map(|(track, channel, e)| LocatedEvent {
+            track,
+            channel,
+            event: Event {
+                tick: cursor.tick + e.tick,
+                data: e.data.clone(),
+            },
+        }).collect();
+        let remove = add.iter().map(|e| e.position()).collect();
+        module.push_edit(Edit::PatternData { remove, add });
+    }
+
+    /// Saves the rhythm of the cursor channel over the selection as a new
+    /// groove, and selects it for quantizing.
+    fn extract_groove(&mut self, ui: &mut Ui, module: &mut Module) {
+        let (start, end) = self.selection_corners_with_tail();
+        let channel = &module.tracks[start.track].channels[start.channel];
+        let name = format!("Groove {}", module.grooves.len() + 1);
+        match quantize::extract_groove(channel, start.tick, end.tick, self.beat_division,
+            name.clone()
+        ) {
+            Some(groove) => {
+                let mut grooves = module.grooves.clone();
+                grooves.push(groove);
+                self.quantize_options.groove = Some(grooves.len() - 1);
+                module.push_edit(Edit::SetGrooves(grooves));
+                ui.notify(format!("Saved {name}."));
+            }
+            None => ui.report("No notes in the selected channel."),
+        }
+    }
+
+    /// Returns the drag that a left click at `pos` would start, if any.
+    fn selection_drag_at(&self, ui: &Ui, track_xs: &[f32], pos: Position
+    ) -> Option<SelectionDrag> {
+        if self.edit_start == self.edit_end || !self.marked.is_empty() || is_shift_down() {
+            return None
+        }
+        let (tl, br) = self.selection_corners();
+        let rect = self.corners_rect(ui, track_xs, tl, br);
+        let mouse = mouse_position_vec2();
+        let on_edge = (mouse.y - (rect.y + rect.h)).abs() < ui.style.margin * 2.0
+            && (rect.x..rect.x + rect.w).contains(&mouse.x);
+        if on_edge && tl.tick != br.tick {
+            Some(SelectionDrag::Stretch)
+        } else if rect.contains(mouse) {
+            Some(SelectionDrag::Move(pos))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the selection corners that a drag released at `pos` would
+    /// produce.
+    fn drag_destination(&self, drag: SelectionDrag, pos: Position, module: &Module
+    ) -> (Position, Position) {
+        let (tl, br) = self.selection_corners();
+        match drag {
+            SelectionDrag::Move(grab) => {
+                let index = |p: Position|
+                    module.channels_between(Default::default(), p) as isize;
+                let last = module.tracks.iter()
+                    .map(|t| t.channels.len())
+                    .sum::<usize>() as isize - 1;
+                // keep note events out of the control track and vice versa
+                let ctrl = module.tracks[0].channels.len() as isize;
+                let min = if tl.track == 0 { 0 } else { ctrl } - index(tl);
+                let max = if br.track == 0 { ctrl - 1 } else { last } - index(br);
+                let channels = (index(pos) - index(grab)).clamp(min, max);
+                let ticks = (pos.tick - grab.tick).max(-tl.tick);
+                let shift = |p: Position| {
+                    let origin = Position { track: 0, channel: 0, ..p };
+                    Position {
+                        tick: p.tick + ticks,
+                        ..origin.add_channels((index(p) + channels) as usize, &module.tracks)
+                            .unwrap_or(p)
+                    }
+                };
+                (shift(tl), shift(br))
+            }
+            SelectionDrag::Stretch => (tl, Position { tick: pos.tick.max(tl.tick), ..br }),
+        }
+    }
+
+    /// Applies a selection drag released at `pos`, as a single edit. A move
+    /// that didn't go anywhere acts as a click instead. If `ctrl` is true,
+    /// moved events are copied.
+    fn drop_selection(&mut self, drag: SelectionDrag, pos: Position, module: &mut Module,
+        ctrl: bool
+    ) {
+        if matches!(drag, SelectionDrag::Move(grab) if grab == pos) {
+            if ctrl {
+                self.toggle_marked(pos, module);
+            } else {
+                self.marked.clear();
+                self.edit_start = pos;
+                self.edit_end = pos;
+            }
+            return
+        }
+
+        let (start, end) = self.selection_corners_with_tail();
+        let (tl, br) = self.drag_destination(drag, pos, module);
+        let clip = self.selection_clip(module);
+        let copy = matches!(drag, SelectionDrag::Move(_)) && ctrl;
+        let (mode, dest_end) = match drag {
+            SelectionDrag::Move(_) => (PasteMode::Normal, tl),
+            SelectionDrag::Stretch => (PasteMode::Stretch, Position {
+                tick: br.tick + self.row_timespan(),
+                ..br
+            }),
+        };
+        let (mut remove, add) = paste_events(&clip, module, tl, dest_end, &mode);
+        if !copy {
+            // only remove sources that were re-added at the destination
+            let sources = module.scan_events(start, end);
+            remove.extend(sources.iter().zip(&clip.events)
+                .filter(|(_, x)| tl.add_channels(x.channel_offset, &module.tracks)
+                    .is_some_and(|p| x.event.data.goes_in_track(p.track)))
+                .map(|(e, _)| e.position()));
+        }
+        if !add.is_empty() || !remove.is_empty() {
+            module.push_edit(Edit::PatternData { remove, add });
+        }
+        self.edit_start = tl;
+        self.edit_end = br;
+    }
+
+    /// Draws highlights on individually selected events.
+    fn draw_marked(&self, ui: &mut Ui, module: &Module, track_xs: &[f32]) {
+        let beat_height = self.beat_height(ui);
+        let color = Color { a: 0.25, ..ui.style.theme.accent1_fg() };
+
+        for evt in self.marked_events(module) {
+            if module.track_hidden(evt.track) {
+                continue
+            }
+            let pos = Position { column: evt.event.data.spatial_column(), ..evt.position() };
+            let start = position_coords(pos, &ui.style, track_xs, false, beat_height);
+            let end = position_coords(pos, &ui.style, track_xs, true, beat_height);
+            let rect = Rect {
+                x: ui.style.margin + start.x,
+                y: ui.cursor_y + start.y,
+                w: end.x - start.x,
+                h: end.y - start.y,
+            };
+            ui.push_rect(rect, color, None);
+        }
+    }
+
+    /// Returns the individually selected events that still exist.
+    fn marked_events(&self, module: &Module) -> Vec<LocatedEvent> {
+        self.marked.iter().filter_map(|pos| {
+            module.tracks.get(pos.track)?.channels.get(pos.channel)?.events.iter()
+                .find(|e| e.tick == pos.tick && e.data.logical_column() == pos.column)
+                .map(|e| LocatedEvent {
+                    track: pos.track,
+                    channel: pos.channel,
+                    event: e.clone(),
+                })
+        }).collect()
+    }
+
+    /// Returns the individually selected events if there are any, otherwise
+    /// the events in the rectangular selection.
+    fn selected_events(&self, module: &Module) -> Vec<LocatedEvent> {
+        if self.marked.is_empty() {
+            let (start, end) = self.selection_corners_with_tail();
+            module.scan_events(start, end)
+        } else {
+            self.marked_events(module)
+        }
+    }
+
+    /// Add or remove events in the row at `pos` from the individual selection.
+    fn toggle_marked(&mut self, pos: Position, module: &Module) {
+        let Some(channel) = module.tracks.get(pos.track)
+            .and_then(|t| t.channels.get(pos.channel)) else {
+            return
+        };
+        let end = pos.tick + self.row_timespan();
+
+        for evt in &channel.events {
+            if evt.tick >= pos.tick && evt.tick < end
+                && evt.data.spatial_column() == pos.column {
+                let evt_pos = Position {
+                    tick: evt.tick,
+                    column: evt.data.logical_column(),
+                    ..pos
+                };
+                if let Some(i) = self.marked.iter().position(|p| *p == evt_pos) {
+                    self.marked.remove(i);
+                } else {
+                    self.marked.push(evt_pos);
+                }
+            }
+        }
+    }
+
     /// Handles a pattern-editor-specific action.
     pub fn action(&mut self, action: Action, module: &mut Module, cfg: &Config,
         player: &mut PlayerShell
     ) {
+        let selection = (self.edit_start, self.edit_end);
+
         match action {
             Action::Cut => self.cut(module),
             Action::Copy => self.copy(module),
-            Action::Paste => self.paste(module, PasteMode::Normal),
-            Action::MixPaste => self.paste(module, PasteMode::Mix),
+            Action::CopyText => self.copy_text(module, false),
+            Action::CopyMml => self.copy_text(module, true),
+            Action::CopyInspector => clipboard_set(&self.inspector_text(module)),
+            Action::Paste => {
+                self.load_system_clipboard(&module.tuning.names);
+                self.paste(module, PasteMode::Normal);
+            }
+            Action::MixPaste => {
+                self.load_system_clipboard(&module.tuning.names);
+                self.paste(module, PasteMode::Mix);
+            }
             Action::InsertPaste => {
+                self.load_system_clipboard(&module.tuning.names);
                 self.selection_to_clip(module);
                 self.push_rows(module);
                 self.paste(module, PasteMode::Normal);
             },
-            Action::StretchPaste => self.paste(module, PasteMode::Stretch),
+            Action::StretchPaste => {
+                self.load_system_clipboard(&module.tuning.names);
+                self.paste(module, PasteMode::Stretch);
+            }
+            Action::PasteSpecial => self.paste_special = true,
+            Action::Quantize => self.quantize = true,
+            Action::ToggleTrackLock => {
+                let track = &mut module.tracks[self.cursor_track()];
+                let locked = track.locks.iter().all(|x| *x);
+                track.locks = [!locked; 3];
+                module.has_unsaved_changes = true;
+            }
+            Action::ToggleColumnLock => {
+                let column = self.edit_start.column as usize;
+                if let Some(x) = module.tracks[self.cursor_track()].locks.get_mut(column) {
+                    *x = !*x;
+                    module.has_unsaved_changes = true;
+                }
+            }
+            Action::FillPaste => {
+                self.load_system_clipboard(&module.tuning.names);
+                self.paste(module, PasteMode::Special(PasteOptions {
+                    repeat: true,
+                    ..Default::default()
+                }));
+            }
             Action::PrevRow => self.translate_cursor(-self.row_timespan()),
             Action::NextRow => self.translate_cursor(self.row_timespan()),
             Action::PrevColumn => shift_column_left(
@@ -294,7 +959,15 @@ impl PatternEditor {
                 &mut self.edit_start, &mut self.edit_end, &module.tracks),
             Action::Delete => {
                 let (start, end) = self.selection_corners_with_tail();
-                if start.x_tuple() == end.x_tuple() && is_shift_down() {
+                if !self.marked.is_empty() {
+                    let remove: Vec<_> = self.marked_events(module).iter()
+                        .map(|e| e.position())
+                        .collect();
+                    if !remove.is_empty() {
+                        module.push_edit(Edit::PatternData { remove, add: Vec::new() });
+                    }
+                    self.marked.clear();
+                } else if start.x_tuple() == end.x_tuple() && is_shift_down() {
                     self.multi_channel_delete(module);
                 } else {
                     module.delete_events(start, end);
@@ -305,6 +978,8 @@ impl PatternEditor {
                 insert_event_at_cursor(module, &self.edit_start, EventData::End, false),
             Action::Loop =>
                 insert_event_at_cursor(module, &self.edit_start, EventData::Loop, false),
+            Action::Skip =>
+                insert_event_at_cursor(module, &self.edit_start, EventData::Skip, false),
             Action::TapTempo => self.tap_tempo(module),
             Action::InsertRows => self.push_rows(module),
             Action::DeleteRows => self.pull_rows(module),
@@ -312,7 +987,7 @@ impl PatternEditor {
                 | Action::NudgeSharp | Action::NudgeFlat
                 | Action::NudgeOctaveUp | Action::NudgeOctaveDown
                 | Action::NudgeEnharmonic =>
-                    nudge_notes(module, self.selection_corners_with_tail(), cfg),
+                    nudge_notes(module, self.selected_events(module), cfg),
             Action::ToggleFollow => self.follow = !self.follow,
             // TODO: re-enable this if & when recording is implemented
             // Action::ToggleRecord => if self.record {
@@ -325,6 +1000,8 @@ impl PatternEditor {
             Action::SelectAllChannels => self.select_all_channels(module),
             Action::SelectAllRows => self.select_all_rows(module),
             Action::PlaceEvenly => self.place_events_evenly(module),
+            Action::OffsetLater => self.offset_events(module, 1),
+            Action::OffsetEarlier => self.offset_events(module, -1),
             Action::NextBeat => self.translate_cursor(Timespan::new(1, 1)),
             Action::PrevBeat => self.translate_cursor(Timespan::new(-1, 1)),
             Action::NextEvent => self.next_event(module),
@@ -338,17 +1015,46 @@ impl PatternEditor {
             Action::Interpolate => self.interpolate(module),
             Action::MuteTrack => player.toggle_mute(self.cursor_track()),
             Action::SoloTrack => player.toggle_solo(self.cursor_track()),
+            Action::AddSoloTrack => player.add_solo(self.cursor_track()),
+            Action::MomentarySolo => if self.momentary_mutes.is_none() {
+                let mutes = player.tracks_muted().to_vec();
+                let track = self.cursor_track();
+                player.set_mutes((0..mutes.len()).map(|i| i != 0 && i != track).collect());
+                self.momentary_mutes = Some(mutes);
+            },
             Action::UnmuteAllTracks => player.unmute_all(),
             Action::CycleNotation => self.cycle_notation(module),
             Action::UseLastNote => self.use_last_note(module),
             Action::ShiftTrackLeft => self.shift_track(-1, module, player),
             Action::ShiftTrackRight => self.shift_track(1, module, player),
+            Action::GroupTracks => {
+                let (start, end) = self.selection_corners();
+                module.group_tracks(start.track..end.track + 1);
+            }
+            Action::ToggleSplitView => self.toggle_split(),
+            Action::SwitchPane => self.swap_panes(&module.tracks),
+            Action::ToggleTripletGrid => self.triplet_grid = !self.triplet_grid,
+            Action::ToggleDrawMode => self.draw_mode = !self.draw_mode,
+            Action::ToggleAutomationLane => self.lane.visible = !self.lane.visible,
+            Action::SaveMacro => self.save_macro(module),
             _ => (),
         }
 
         if action != Action::TapTempo {
             self.clear_tap_tempo_state();
         }
+        // switching panes brings that pane's marks along
+        if action != Action::SwitchPane {
+            self.unmark_if_moved(selection);
+        }
+    }
+
+    /// Clear the individual selection if the rectangular selection has
+    /// changed from `selection`.
+    fn unmark_if_moved(&mut self, selection: (Position, Position)) {
+        if (self.edit_start, self.edit_end) != selection {
+            self.marked.clear();
+        }
     }
 
     fn shift_track(&mut self, offset: isize,
@@ -357,7 +1063,11 @@ impl PatternEditor {
         let src_track = self.cursor_track();
         let dst_track = src_track.saturating_add_signed(offset);
         if src_track > 1 && dst_track > 1 && dst_track < module.tracks.len() {
-            module.push_edit(Edit::ShiftTrack(src_track, offset));
+            module.push_edit(Edit::ShiftTrack {
+                index: src_track,
+                offset,
+                groups: None,
+            });
             player.update_synths(module.drain_track_history());
             self.edit_start.track = self.edit_start.track.wrapping_add_signed(offset);
             self.edit_end.track = self.edit_end.track.wrapping_add_signed(offset);
@@ -365,6 +1075,37 @@ impl PatternEditor {
         }
     }
 
+    /// Move cursors off tracks hidden by collapsed groups, continuing in the
+    /// direction that the cursor last moved.
+    fn skip_hidden_tracks(&mut self, module: &Module) {
+        let forward = self.edit_end.track >= self.last_track;
+
+        for cursor in [&mut self.edit_start, &mut self.edit_end] {
+            if !module.track_hidden(cursor.track) {
+                continue
+            }
+            let visible = |i: &usize| !module.track_hidden(*i);
+            let next = (cursor.track + 1..module.tracks.len()).find(visible);
+            let prev = (0..cursor.track).rev().find(visible);
+            let track = if forward { next.or(prev) } else { prev.or(next) }
+                .unwrap_or_default();
+
+            cursor.channel = if track > cursor.track {
+                0
+            } else {
+                module.tracks[track].channels.len() - 1
+            };
+            cursor.column = if track == 0 || track > cursor.track {
+                NOTE_COLUMN
+            } else {
+                MOD_COLUMN
+            };
+            cursor.track = track;
+        }
+
+        self.last_track = self.edit_end.track;
+    }
+
     fn clear_tap_tempo_state(&mut self) {
         self.tap_tempo_intervals.clear();
         self.pending_interval = None;
@@ -373,11 +1114,10 @@ impl PatternEditor {
     /// Expands the selection to the bounds of what would be pasted.
     fn selection_to_clip(&mut self, module: &Module) {
         if let Some(clip) = &self.clipboard {
-            let channel_offset = module.channels_between(clip.start, clip.end);
             self.edit_end = Position {
                 tick: self.edit_start.tick + clip.end.tick - clip.start.tick,
                 column: clip.end.column,
-                ..self.edit_start.add_channels(channel_offset, &module.tracks)
+                ..self.edit_start.add_channels(clip.channels, &module.tracks)
                     .unwrap_or(Position {
                         track: module.tracks.len() - 1,
                         channel: module.tracks.last().unwrap().channels.len() - 1,
@@ -512,9 +1252,7 @@ impl PatternEditor {
 
     /// Handle the "increment/decrement values" key commands.
     fn shift_values(&self, offset: i8, module: &mut Module) {
-        let (start, end) = self.selection_corners_with_tail();
-
-        let replacements = module.scan_events(start, end).iter().filter_map(|evt| {
+        let replacements = self.selected_events(module).iter().filter_map(|evt| {
             let mut evt = evt.clone();
 
             match &mut evt.event.data {
@@ -547,9 +1285,7 @@ impl PatternEditor {
 
     /// Handle the "cycle notation" key command.
     fn cycle_notation(&self, module: &mut Module) {
-        let (start, end) = self.selection_corners_with_tail();
-
-        let replacements = module.scan_events(start, end).into_iter()
+        let replacements = self.selected_events(module).into_iter()
             .filter_map(|mut evt| {
                 match &mut evt.event.data {
                     EventData::Pitch(note) => {
@@ -648,6 +1384,11 @@ impl PatternEditor {
         Timespan::new(1, self.beat_division)
     }
 
+    /// Return the length of a single row in beats.
+    pub fn row_beats(&self) -> f64 {
+        self.row_timespan().as_f64()
+    }
+
     /// Handle the "place events evenly" key command.
     fn place_events_evenly(&self, module: &mut Module) {
         let (start, end) = self.selection_corners_with_tail();
@@ -678,6 +1419,56 @@ impl PatternEditor {
         })
     }
 
+    /// Nudge selected events by `steps` fine offsets, snapping each event to
+    /// the fine grid. Without a selection, nudges the events nearest to the
+    /// cursor row. Events that would land on another event aren't moved.
+    fn offset_events(&self, module: &mut Module, steps: i32) {
+        let row = self.row_timespan();
+        let unit = row * Timespan::new(1, FINE_OFFSETS_PER_ROW);
+        let (mut start, mut end) = self.selection_corners_with_tail();
+        if start.tick == end.tick {
+            // events offset from the cursor row still belong to it
+            start.tick = (start.tick - row * Timespan::new(1, 2)).max(Timespan::ZERO);
+            end.tick += row * Timespan::new(1, 2);
+        }
+        let mut moves: Vec<_> = module.scan_events(start, end).into_iter()
+            .filter_map(|e| {
+                let fine = (e.event.tick / unit).as_f64().round() as i32 + steps;
+                let tick = unit * Timespan::new(fine, 1);
+                (tick >= Timespan::ZERO && tick != e.event.tick).then_some((e, tick))
+            }).collect();
+
+        // drop moves onto events that stay put or onto other moved events,
+        // until no more are dropped
+        let occupied = |pos: &Position| module.tracks[pos.track].channels[pos.channel].events
+            .iter().any(|e| e.tick == pos.tick && e.data.logical_column() == pos.column);
+        loop {
+            let sources: Vec<_> = moves.iter().map(|(e, _)| e.position()).collect();
+            let dests: Vec<_> = moves.iter()
+                .map(|(e, tick)| Position { tick: *tick, ..e.position() })
+                .collect();
+            let len = moves.len();
+            moves = moves.into_iter().zip(&dests)
+                .filter(|(_, dest)| (!occupied(dest) || sources.contains(dest))
+                    && dests.iter().filter(|x| x == dest).count() == 1)
+                .map(|(m, _)| m)
+                .collect();
+            if moves.len() == len {
+                break
+            }
+        }
+        if moves.is_empty() {
+            return
+        }
+
+        let remove = moves.iter().map(|(e, _)| e.position()).collect();
+        let add = moves.into_iter().map(|(mut e, tick)| {
+            e.event.tick = tick;
+            e
+        }).collect();
+        module.push_edit(Edit::PatternData { remove, add });
+    }
+
     /// Handle raw keys for digit input.
     fn handle_key(&mut self, key: KeyCode, module: &mut Module, ui: &mut Ui) {
         if !(is_ctrl_down() || is_alt_down()) {
@@ -702,10 +1493,16 @@ impl PatternEditor {
             };
 
             match self.edit_start.column {
-                VEL_COLUMN => insert_event_at_cursor(module, &self.edit_start,
-                    EventData::Pressure(value), is_shift_down()),
-                MOD_COLUMN => insert_event_at_cursor(module, &self.edit_start,
-                    EventData::Modulation(value), is_shift_down()),
+                VEL_COLUMN => {
+                    insert_event_at_cursor(module, &self.edit_start,
+                        EventData::Pressure(value), is_shift_down());
+                    self.step_after_entry(module);
+                }
+                MOD_COLUMN => {
+                    insert_event_at_cursor(module, &self.edit_start,
+                        EventData::Modulation(value), is_shift_down());
+                    self.step_after_entry(module);
+                }
                 GLOBAL_COLUMN => if self.edit_start.track == 0 && value < 10 {
                     self.text_position = Some(self.edit_start);
                     ui.focus_text(CTRL_COLUMN_TEXT_ID.into(), value.to_string());
@@ -734,98 +1531,141 @@ impl PatternEditor {
         module.delete_events(start, end);
     }
 
-    /// Copy selection to the clipboard.
-    fn copy(&mut self, module: &Module) {
+    /// Returns the selected pattern data.
+    fn selection_clip(&self, module: &Module) -> PatternClip {
         let (start, end) = self.selection_corners_with_tail();
         let events = module.scan_events(start, end).iter().map(|x| ClipEvent {
             channel_offset: module.channels_between(start, x.position()),
             event: x.event.clone(),
         }).collect();
-        self.clipboard = Some(PatternClip {
+        PatternClip {
             start,
             end,
             events,
             channels: module.channels_between(start, end),
-        });
+        }
     }
 
-    /// Paste from the clipboard.
-    fn paste(&self, module: &mut Module, mode: PasteMode) {
-        if let Some(clip) = &self.clipboard {
-            let (start, end) = self.selection_corners_with_tail();
-            let start = Position {
-                column: clip.start.column,
-                ..start
-            };
-            let end = Position {
-                tick: match mode {
-                    PasteMode::Stretch => end.tick,
-                    _ => start.tick + clip.end.tick - clip.start.tick,
-                },
-                column: clip.end.column,
-                ..start.add_channels(clip.channels, &module.tracks)
-                    .unwrap_or(Position {
-                        track: module.tracks.len() - 1,
-                        channel: module.tracks.last().unwrap().channels.len() - 1,
-                        ..Default::default()
-                    })
-            };
-
-            let event_positions: Vec<_> = module.scan_events(start, end)
-                .iter().map(|x| x.position()).collect();
-            let scale = if mode == PasteMode::Stretch && end.tick != start.tick {
-                (end.tick - start.tick) / (clip.end.tick - clip.start.tick)
-            } else {
-                Timespan::new(1, 1)
-            };
-
-            let add: Vec<_> = clip.events.iter().filter_map(|x| {
-                let start_offset = x.event.tick - clip.start.tick;
-                let tick = start.tick + start_offset * scale;
-                start.add_channels(x.channel_offset, &module.tracks)
-                    .and_then(|pos| {
-                        if x.event.data.goes_in_track(pos.track)
-                            && (mode != PasteMode::Mix
-                                || !event_positions.contains(&Position {
-                                    tick,
-                                    ..pos
-                                })) {
-                            Some(LocatedEvent {
-                                track: pos.track,
-                                channel: pos.channel,
-                                event: Event {
-                                    tick,
-                                    data: x.event.data.clone(),
-                                },
-                            })
-                        } else {
-                            None
-                        }
-                    })
-            }).collect();
+    /// Copy selection to the clipboard.
+    fn copy(&mut self, module: &Module) {
+        let clip = self.selection_clip(module);
+        match clip.to_text() {
+            Ok(s) => clipboard_set(&s),
+            Err(e) => eprintln!("error serializing pattern data: {e}"),
+        }
+        self.clipboard = Some(clip);
+    }
 
-            let remove = if mode == PasteMode::Mix {
-                add.iter().map(|x| x.position()).collect()
-            } else {
-                event_positions
-            };
+    /// Returns a description of the cursor position and the events on the
+    /// cursor row.
+    pub fn inspector_text(&self, module: &Module) -> String {
+        pattern_text::describe(module, self.edit_start, self.beat_division)
+    }
 
-            if !add.is_empty() || !remove.is_empty() {
-                module.push_edit(Edit::PatternData {
-                    remove,
-                    add,
-                });
-            }
+    /// Returns a summary of the selection, or None if nothing is selected.
+    /// The summary is recomputed only when the selection or module changes.
+    pub fn selection_summary(&mut self, module: &Module) -> Option<&str> {
+        if self.edit_start == self.edit_end {
+            return None
         }
+        let (start, end) = self.selection_corners_with_tail();
+        let key = (start, end, module.edit_count());
+        if self.selection_summary.as_ref().is_none_or(|(k, _)| *k != key) {
+            self.selection_summary = Some((key, pattern_text::summarize(module, start, end)));
+        }
+        self.selection_summary.as_ref().map(|(_, s)| s.as_str())
     }
 
-    fn draw_channel(&self, ui: &mut Ui, channel: &Channel, muted: bool, index: usize) {
-        self.draw_channel_line(ui, index == 0);
-        self.draw_interpolation(ui, channel);
-        let beat_height = self.beat_height(ui);
-        for event in &channel.events {
-            self.draw_event(ui, event, beat_height, muted);
-        }
+    /// Show the intervals between simultaneous notes in the selection.
+    pub fn analyze_intervals(&self, module: &Module, ui: &mut Ui) {
+        let (start, end) = self.selection_corners_with_tail();
+        ui.alert(pattern_text::analyze_intervals(module, start, end));
+    }
+
+    /// Copy selection to the system clipboard as plain text or MML.
+    fn copy_text(&self, module: &Module, mml: bool) {
+        let (start, end) = self.selection_corners_with_tail();
+        let s = if mml {
+            pattern_text::to_mml(module, start, end, self.beat_division)
+        } else {
+            pattern_text::to_text(module, start, end, self.beat_division)
+        };
+        clipboard_set(&s);
+    }
+
+    /// Replace the clipboard with pattern data from the system clipboard, if
+    /// it has any. This allows pasting between instances.
+    fn load_system_clipboard(&mut self, names: &NoteNames) {
+        let Some(s) = clipboard_get() else { return };
+
+        if let Some(clip) = PatternClip::from_text(&s) {
+            self.clipboard = Some(clip);
+        } else if let Some(text) = pattern_text::parse_text(&s, names) {
+            self.clipboard = Some(PatternClip {
+                start: Position {
+                    column: NOTE_COLUMN,
+                    ..Default::default()
+                },
+                end: Position {
+                    tick: text.length,
+                    column: MOD_COLUMN,
+                    ..Default::default()
+                },
+                events: text.events.into_iter().map(|(channel_offset, event)| ClipEvent {
+                    channel_offset,
+                    event,
+                }).collect(),
+                channels: text.channels.saturating_sub(1),
+            });
+        }
+    }
+
+    /// Paste from the clipboard.
+    fn paste(&self, module: &mut Module, mode: PasteMode) {
+        if let Some(clip) = &self.clipboard {
+            let (start, end) = self.selection_corners_with_tail();
+            let (remove, add) = paste_events(clip, module, start, end, &mode);
+            if !add.is_empty() || !remove.is_empty() {
+                module.push_edit(Edit::PatternData {
+                    remove,
+                    add,
+                });
+            }
+        }
+    }
+
+    /// Draws a channel's events. Notes that match a named kit entry are
+    /// labeled with its name.
+    fn draw_channel(&self, ui: &mut Ui, channel: &Channel, muted: bool, index: usize,
+        kit: &[KitEntry], names: &NoteNames
+    ) {
+        self.draw_channel_line(ui, index == 0);
+        self.draw_interpolation(ui, channel);
+        let beat_height = self.beat_height(ui);
+        for event in &channel.events {
+            self.draw_event(ui, event, beat_height, muted, kit, names);
+        }
+    }
+
+    /// Draw the start positions and names of audio clips.
+    fn draw_clips(&self, ui: &mut Ui, clips: &[AudioClip], muted: bool) {
+        const MAX_CHARS: usize = 6;
+
+        let beat_height = self.beat_height(ui);
+        let x = ui.cursor_x + column_x(VEL_COLUMN, &ui.style);
+        let mut color = ui.style.theme.accent2_fg();
+        if muted {
+            color = Color { a: 0.25, ..color };
+        }
+
+        for clip in clips {
+            let y = ui.cursor_y + clip.tick.as_f32() * beat_height;
+            if y < 0.0 || y > ui.bounds.y + ui.bounds.h {
+                continue
+            }
+            let name: String = clip.data.filename.chars().take(MAX_CHARS).collect();
+            ui.push_text(x, y - ui.style.margin + PATTERN_MARGIN, format!(">{name}"), color);
+        }
     }
 
     /// Draw a vertical line to separate channels.
@@ -850,11 +1690,10 @@ impl PatternEditor {
         ui.cursor_z -= 1;
         let beat_height = self.beat_height(ui);
         let tpr = self.row_timespan();
-        let colors = [
-            Color { a: 0.5, ..ui.style.theme.fg() },
-            Color { a: 0.5, ..ui.style.theme.accent1_fg() },
-            Color { a: 0.5, ..ui.style.theme.accent2_fg() },
-        ];
+        let colors: [_; NUM_COLS] = std::array::from_fn(|i| Color {
+            a: 0.5,
+            ..ui.style.theme.interpolation_fg(i)
+        });
 
         let mut interp: Vec<_> = (0..NUM_COLS).map(|_| Vec::new()).collect();
         for evt in &channel.events {
@@ -939,8 +1778,14 @@ impl PatternEditor {
 
     /// Scroll to a position that centers the given tick.
     fn scroll_to(&mut self, tick: Timespan) {
+        self.scroll_to_fraction(tick, Timespan::new(1, 2));
+    }
+
+    /// Scroll to a position that puts the given tick `fraction` of the way
+    /// down the viewport.
+    fn scroll_to_fraction(&mut self, tick: Timespan, fraction: Timespan) {
         let offset = (self.screen_tick_max - self.beat_scroll - self.row_timespan())
-            * Timespan::new(1, 2);
+            * fraction;
         self.beat_scroll = (tick - offset).max(Timespan::ZERO);
     }
 
@@ -991,25 +1836,30 @@ impl PatternEditor {
         }
     }
 
-    /// Handle event input in record mode.
-    fn record_event(&mut self, data: EventData, module: &mut Module) {
+    /// Handle event input in record mode. Input routed to a track other than
+    /// the cursor's is recorded into the first channel of that track.
+    fn record_event(&mut self, data: EventData, track: Option<usize>, module: &mut Module) {
         let cursor = self.edit_start;
-        if !data.goes_in_track(cursor.track) {
+        let (track, channel) = match track {
+            Some(track) if track != cursor.track => (track, 0),
+            _ => (cursor.track, cursor.channel),
+        };
+        if !data.goes_in_track(track) {
             return
         }
 
         // skip to next open row
         let mut pos = Position {
-            track: cursor.track,
+            track,
             tick: cursor.tick,
-            channel: cursor.channel,
+            channel,
             column: data.logical_column(),
         };
         if module.event_at(&pos).is_some_and(|e| e.data != EventData::NoteOff) {
             pos.tick += self.row_timespan();
         }
 
-        module.insert_event(cursor.track, cursor.channel, Event {
+        module.insert_event(track, channel, Event {
             tick: pos.tick,
             data,
         });
@@ -1027,6 +1877,18 @@ impl PatternEditor {
         self.scroll_to_cursor();
     }
 
+    /// Move the cursor down by the edit step for its column.
+    fn step_after_entry(&mut self, module: &Module) {
+        let step = module.edit_step.for_column(self.edit_start.column);
+        if step > 0 {
+            let tick = self.edit_start.tick
+                + self.row_timespan() * Timespan::new(step as i32, 1);
+            self.edit_start.tick = tick;
+            self.edit_end.tick = tick;
+            self.scroll_to_cursor();
+        }
+    }
+
     /// If cursor is off-screen, scroll to center the cursor.
     fn scroll_to_cursor(&mut self) {
         let tick = self.edit_end.tick;
@@ -1041,7 +1903,9 @@ impl PatternEditor {
     }
 
     /// Draw a single pattern event.
-    fn draw_event(&self, ui: &mut Ui, evt: &Event, beat_height: f32, muted: bool) {
+    fn draw_event(&self, ui: &mut Ui, evt: &Event, beat_height: f32, muted: bool,
+        kit: &[KitEntry], names: &NoteNames
+    ) {
         let y = ui.cursor_y + evt.tick.as_f32() * beat_height;
         if y < 0.0 || y > ui.bounds.y + ui.bounds.h {
             return
@@ -1055,22 +1919,61 @@ impl PatternEditor {
         let mut color = match evt.data {
             EventData::Pressure(x) => Color {
                 a: 0.5 + x as f32 / (EventData::DIGIT_MAX as f32 * 2.0),
-                ..ui.style.theme.accent1_fg()
+                ..ui.style.theme.pressure_fg()
             },
             EventData::Modulation(x) => Color {
                 a: 0.5 + x as f32 / (EventData::DIGIT_MAX as f32 * 2.0),
-                ..ui.style.theme.accent2_fg()
+                ..ui.style.theme.modulation_fg()
             },
+            EventData::Condition(_) => ui.style.theme.accent2_fg(),
+            EventData::Slide(_) | EventData::Vibrato(..) => ui.style.theme.accent1_fg(),
+            EventData::Tremolo(..) => ui.style.theme.accent2_fg(),
+            EventData::Pitch(_) | EventData::NoteOff => ui.style.theme.note_fg(),
             _ => ui.style.theme.fg(),
         };
         if muted || self.off_division(evt.tick) {
             color = Color { a: 0.25, ..color };
         }
 
+        // conditional notes are marked with a bar at the left of the cell
+        if let EventData::Condition(_) = evt.data {
+            let h = line_height(ui.style.pattern_atlas());
+            let rect = Rect { x, y, w: PATTERN_MARGIN, h };
+            ui.push_rect(rect, color, None);
+            return
+        }
+
+        // vibrato is marked with a bar at the left of the modulation cell
+        if let EventData::Vibrato(..) = evt.data {
+            let h = line_height(ui.style.pattern_atlas());
+            let rect = Rect { x, y, w: PATTERN_MARGIN, h };
+            ui.push_rect(rect, color, None);
+            return
+        }
+
+        // sliding notes and tremolo are marked with a bar at the right of the cell
+        if let EventData::Slide(_) | EventData::Tremolo(..) = evt.data {
+            let right = if col == MOD_COLUMN { 3 } else { VEL_COLUMN };
+            let rect = Rect {
+                x: x + column_x(right, &ui.style) - column_x(col, &ui.style)
+                    - PATTERN_MARGIN,
+                y,
+                w: PATTERN_MARGIN,
+                h: line_height(ui.style.pattern_atlas()),
+            };
+            ui.push_rect(rect, color, None);
+            return
+        }
+
         let y = y - ui.style.margin + PATTERN_MARGIN;
         let text = match evt.data {
             EventData::Pitch(note) => {
-                ui.push_note_text(x, y, &note, color);
+                match kit.iter().find(|e| e.input_note == note && !e.name.is_empty()) {
+                    Some(entry) => {
+                        ui.push_text(x, y, entry.name.clone(), color);
+                    }
+                    None => ui.push_note_text(x, y, &note, names, color),
+                }
                 return
             },
             EventData::NoteOff => String::from(" ---"),
@@ -1078,7 +1981,9 @@ impl PatternEditor {
             EventData::Modulation(v) => format!("{:X}", v),
             EventData::End => String::from("End"),
             EventData::Loop => String::from("Loop"),
+            EventData::LoopCount(n) => format!("Lx{n}"),
             EventData::Section => String::from("Sect"),
+            EventData::Skip => String::from("Skip"),
             EventData::Tempo(t) => t.round().to_string(),
             EventData::RationalTempo(n, d) => format!("{}:{}", n, d),
             EventData::InterpolatedPitch(_)
@@ -1087,7 +1992,11 @@ impl PatternEditor {
                 => panic!("interpolated event in pattern"),
             EventData::StartGlide(_)
                 | EventData::EndGlide(_)
-                | EventData::TickGlide(_) => return,
+                | EventData::TickGlide(_)
+                | EventData::Condition(_)
+                | EventData::Slide(_)
+                | EventData::Vibrato(..)
+                | EventData::Tremolo(..) => return,
             EventData::Bend(c) => format!("{:+}", c),
         };
         ui.push_text(x, y, text, color);
@@ -1112,10 +2021,30 @@ impl PatternEditor {
         }
     }
 
-    /// Handle entered control column text.
+    /// Handle entered marker text.
+    fn enter_marker_text(&mut self, s: String, module: &mut Module) {
+        if let Some(tick) = self.marker_tick.take() {
+            let text = Some(s.trim().to_owned()).filter(|s| !s.is_empty());
+            let old = module.marker_at(tick);
+            if text.as_deref() != old.map(|m| m.text.as_str()) {
+                let trigger = old.and_then(|m| m.trigger.clone());
+                let marker = text.map(|text| Marker { tick, text, trigger });
+                module.push_edit(Edit::SetMarker(tick, marker));
+            }
+        }
+    }
+
+    /// Handle entered control column, note condition, note slide, or
+    /// vibrato/tremolo text.
     fn enter_ctrl_text(&mut self, s: String, module: &mut Module, ui: &mut Ui) {
         if let Some(pos) = self.text_position.take() {
-            if !s.is_empty() {
+            if pos.track != 0 && pos.column == MOD_COLUMN {
+                enter_effect_text(&s, pos, module, ui);
+            } else if pos.track != 0 && self.slide_text {
+                enter_slide_text(&s, pos, module, ui);
+            } else if pos.track != 0 {
+                enter_condition_text(&s, pos, module, ui);
+            } else if !s.is_empty() {
                 match parse_ctrl_text(&s) {
                     Some(data) => {
                         let event = Event { tick: pos.tick, data };
@@ -1128,12 +2057,189 @@ impl PatternEditor {
     }
 }
 
+/// Returns the positions to clear and the events to add to paste `clip`
+/// into the selection from `start` to `end`.
+fn paste_events(clip: &PatternClip, module: &Module, start: Position, end: Position,
+    mode: &PasteMode
+) -> (Vec<Position>, Vec<LocatedEvent>) {
+    let options = match *mode {
+        PasteMode::Special(options) => options,
+        _ => PasteOptions::default(),
+    };
+    let clip_len = clip.end.tick - clip.start.tick;
+    let start = Position {
+        column: clip.start.column,
+        ..start
+    };
+    let end = Position {
+        tick: match mode {
+            PasteMode::Stretch => end.tick,
+            _ if options.repeat && end.tick > start.tick + clip_len => end.tick,
+            _ => start.tick + clip_len,
+        },
+        column: clip.end.column,
+        ..start.add_channels(clip.channels, &module.tracks)
+            .unwrap_or(Position {
+                track: module.tracks.len() - 1,
+                channel: module.tracks.last().unwrap().channels.len() - 1,
+                ..Default::default()
+            })
+    };
+
+    let event_positions: Vec<_> = module.scan_events(start, end).iter()
+        .filter(|x| options.includes(&x.event.data))
+        .map(|x| x.position())
+        .collect();
+    let scale = if *mode == PasteMode::Stretch && end.tick != start.tick {
+        (end.tick - start.tick) / clip_len
+    } else {
+        Timespan::new(1, 1)
+    };
+    let repeats = if options.repeat && clip_len > Timespan::ZERO {
+        ((end.tick - start.tick).as_f64() / clip_len.as_f64()).ceil().max(1.0) as i32
+    } else {
+        1
+    };
+
+    let add: Vec<_> = (0..repeats)
+        .flat_map(|i| clip.events.iter().map(move |x| (i, x)))
+        .filter(|(_, x)| options.includes(&x.event.data))
+        .filter_map(|(i, x)| {
+            let start_offset = x.event.tick - clip.start.tick;
+            let tick = start.tick + start_offset * scale + clip_len * Timespan::new(i, 1);
+            if i > 0 && tick >= end.tick {
+                return None
+            }
+            let mut data = x.event.data.clone();
+            if let EventData::Pitch(note) = &mut data {
+                if options.transpose != 0 {
+                    *note = note.step_shift(options.transpose as isize, &module.tuning);
+                }
+            }
+            start.add_channels(x.channel_offset, &module.tracks)
+                .and_then(|pos| {
+                    if x.event.data.goes_in_track(pos.track)
+                        && (*mode != PasteMode::Mix
+                            || !event_positions.contains(&Position {
+                                tick,
+                                ..pos
+                            })) {
+                        Some(LocatedEvent {
+                            track: pos.track,
+                            channel: pos.channel,
+                            event: Event { tick, data },
+                        })
+                    } else {
+                        None
+                    }
+                })
+        }).collect();
+
+    let remove = if *mode == PasteMode::Mix {
+        add.iter().map(|x| x.position()).collect()
+    } else {
+        event_positions
+    };
+
+    (remove, add)
+}
+
+/// Set or remove the note condition at `pos`.
+fn enter_condition_text(s: &str, pos: Position, module: &mut Module, ui: &mut Ui) {
+    if s.is_empty() {
+        let channel = &module.tracks[pos.track].channels[pos.channel];
+        if channel.condition_at(pos.tick).is_some() {
+            module.push_edit(Edit::PatternData {
+                remove: vec![Position {
+                    column: NOTE_COLUMN | EventData::COND_COL_FLAG,
+                    ..pos
+                }],
+                add: Vec::new(),
+            });
+        }
+    } else if let Some(cond) = NoteCondition::parse(s) {
+        let event = Event { tick: pos.tick, data: EventData::Condition(cond) };
+        module.insert_event(pos.track, pos.channel, event);
+    } else {
+        ui.report("Could not parse note condition");
+    }
+}
+
+/// Set or remove the note slide at `pos`.
+fn enter_slide_text(s: &str, pos: Position, module: &mut Module, ui: &mut Ui) {
+    if s.is_empty() {
+        let channel = &module.tracks[pos.track].channels[pos.channel];
+        if channel.slide_at(pos.tick).is_some() {
+            module.push_edit(Edit::PatternData {
+                remove: vec![Position {
+                    column: NOTE_COLUMN | EventData::SLIDE_COL_FLAG,
+                    ..pos
+                }],
+                add: Vec::new(),
+            });
+        }
+    } else {
+        match u8::from_str_radix(s, 16) {
+            Ok(n) if (1..=EventData::DIGIT_MAX).contains(&n) => {
+                let event = Event { tick: pos.tick, data: EventData::Slide(n) };
+                module.insert_event(pos.track, pos.channel, event);
+            }
+            _ => ui.report("Slide length must be a digit from 1 to F"),
+        }
+    }
+}
+
+/// Replace the vibrato and tremolo at `pos`.
+fn enter_effect_text(s: &str, pos: Position, module: &mut Module, ui: &mut Ui) {
+    let mut add = Vec::new();
+    for word in s.split_whitespace() {
+        match parse_effect_text(word) {
+            Some(data) => add.push(LocatedEvent {
+                track: pos.track,
+                channel: pos.channel,
+                event: Event { tick: pos.tick, data },
+            }),
+            None => {
+                ui.report("Effects must be 4xy (vibrato) or 7xy (tremolo)");
+                return
+            }
+        }
+    }
+
+    let channel = &module.tracks[pos.track].channels[pos.channel];
+    if add.is_empty() && channel.effects_at(pos.tick).is_empty() {
+        return
+    }
+
+    module.push_edit(Edit::PatternData {
+        remove: [EventData::VIB_COL_FLAG, EventData::TREM_COL_FLAG].into_iter()
+            .map(|flag| Position { column: MOD_COLUMN | flag, ..pos })
+            .collect(),
+        add,
+    });
+}
+
+/// Parse tracker-style effect text, where x is rate and y is depth.
+fn parse_effect_text(s: &str) -> Option<EventData> {
+    let digits: Vec<_> = s.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect();
+    match digits[..] {
+        [Some(4), Some(rate), Some(depth)] => Some(EventData::Vibrato(rate, depth)),
+        [Some(7), Some(rate), Some(depth)] => Some(EventData::Tremolo(rate, depth)),
+        _ => None,
+    }
+}
+
 /// Parse control column text into an event.
 fn parse_ctrl_text(s: &str) -> Option<EventData> {
     if let Ok(f) = s.parse::<f32>() {
         if f > 0.0 {
             return Some(EventData::Tempo(f))
         }
+    } else if let Some(n) = s.strip_suffix(['x', 'X']) {
+        let n = n.parse::<u8>().ok()?;
+        if n > 0 {
+            return Some(EventData::LoopCount(n))
+        }
     } else if let Some((n, d)) = s.split_once(['/', ':']) {
         let n = n.parse::<u8>().ok()?;
         let d = d.parse::<u8>().ok()?;
@@ -1157,31 +2263,48 @@ pub fn draw(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
 
     // raw key input
     if !ui.accepting_keyboard_input() {
+        let selection = (pe.edit_start, pe.edit_end);
         for key in get_keys_pressed() {
             pe.handle_key(key, module, ui);
         }
+        pe.unmark_if_moved(selection);
     }
 
     // note input
     let cursor = pe.edit_start;
     if pe.record {
-        while let Some((_, data)) = ui.note_queue.pop() {
-            pe.record_event(data, module);
+        while let Some((_, data, track)) = ui.note_queue.pop() {
+            pe.record_event(data, track, module);
         }
     } else if !ui.accepting_note_input() && cursor.column == NOTE_COLUMN {
-        while let Some((_, data)) = ui.note_queue.pop() {
+        let mut entered = false;
+        while let Some((_, data, _)) = ui.note_queue.pop() {
             match data {
                 EventData::NoteOff => (),
-                _ => insert_event_at_cursor(module, &cursor, data, false),
+                _ => {
+                    insert_event_at_cursor(module, &cursor, data, false);
+                    entered = true;
+                }
             }
         }
+        if entered {
+            pe.step_after_entry(module);
+        }
+    }
+
+    pe.skip_hidden_tracks(module);
+
+    if !conf.action_is_down(Action::MomentarySolo) {
+        if let Some(mutes) = pe.momentary_mutes.take() {
+            player.set_mutes(mutes);
+        }
     }
 
     // draw track headers
     ui.start_group();
     ui.cursor_x -= pe.h_scroll;
     let left_x = ui.cursor_x;
-    let track_xs = draw_track_headers(ui, module, player, pe);
+    let track_xs = draw_track_headers(ui, module, player, pe, conf);
     let rect = Rect {
         w: ui.bounds.w - left_x.min(0.0),
         ..ui.end_group().unwrap()
@@ -1189,9 +2312,95 @@ pub fn draw(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
     ui.cursor_z -= 1;
     ui.push_rect(rect, ui.style.theme.panel_bg(), None);
 
-    // set up pattern viewport
+    // dock the automation lane below the pattern
+    let lane_z = ui.cursor_z;
+    let lane_rect = pe.lane.visible.then(|| {
+        let h = (line_height(&ui.style.atlas) * LANE_ROWS).round();
+        ui.bounds.h -= h;
+        Rect { y: ui.bounds.y + ui.bounds.h, h, ..ui.bounds }
+    });
+
+    let minimap_w = Minimap::width(module);
+    {
+        let max_x = track_xs.last().unwrap() - left_x
+            + ui.style.margin * 4.0 + ui.style.atlas.char_width() + minimap_w;
+        ui.horizontal_scrollbar(&mut pe.h_scroll, max_x, ui.bounds.w);
+    }
+
+    if pe.split.is_none() {
+        draw_pane(ui, module, player, pe, conf, &track_xs, left_x, true);
+    } else {
+        draw_split_panes(ui, module, player, pe, conf, &track_xs, left_x);
+    }
+
+    if let Some(rect) = lane_rect {
+        ui.cursor_z = lane_z;
+        let visible = pe.beat_scroll..pe.screen_tick_max;
+        if let Some(edit) = pe.lane.draw(ui, module, rect, pe.edit_start, visible,
+            pe.beat_division) {
+            module.push_edit(edit);
+        }
+        ui.bounds.h += rect.h;
+    }
+}
+
+/// Draws the two panes of a split view, one above the other.
+fn draw_split_panes(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
+    pe: &mut PatternEditor, conf: &Config, track_xs: &[f32], left_x: f32
+) {
+    // the inactive pane's state is swapped in while it's drawn
+    let bounds = ui.bounds;
+    let mid_y = ((ui.cursor_y + bounds.y + bounds.h) * 0.5).round();
+    for below in [false, true] {
+        ui.bounds = if below {
+            Rect { y: mid_y, h: bounds.y + bounds.h - mid_y, ..bounds }
+        } else {
+            Rect { h: mid_y - bounds.y, ..bounds }
+        };
+        ui.cursor_y = ui.bounds.y.max(ui.cursor_y);
+        ui.set_vertical_clip(Some(ui.cursor_y..ui.bounds.y + ui.bounds.h));
+        let z = ui.cursor_z;
+
+        if pe.split.as_ref().is_some_and(|split| split.below != below) {
+            draw_pane(ui, module, player, pe, conf, track_xs, left_x, true);
+        } else {
+            pe.swap_panes(&module.tracks);
+            let marker_tick = pe.marker_tick.take();
+            let clicked = draw_pane(ui, module, player, pe, conf, track_xs, left_x, false);
+            pe.marker_tick = marker_tick;
+            if clicked {
+                pe.text_position = None;
+            } else {
+                pe.swap_panes(&module.tracks);
+            }
+        }
+
+        ui.set_vertical_clip(None);
+        ui.cursor_z = z;
+        ui.cursor_y = mid_y;
+    }
+    ui.bounds = bounds;
+}
+
+/// Draws a pattern viewport in the current bounds. Inactive viewports don't
+/// scroll or take input, except for a click that places the cursor; returns
+/// true if that happened.
+#[allow(clippy::too_many_arguments)]
+fn draw_pane(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
+    pe: &mut PatternEditor, conf: &Config, track_xs: &[f32], left_x: f32, active: bool
+) -> bool {
+    let mut clicked = false;
+    if active {
+        // spreading two fingers zooms in
+        match ui.touch().zoom_step() {
+            1 => pe.double_division(),
+            -1 => pe.halve_division(),
+            _ => (),
+        }
+    }
     let beat_height = pe.beat_height(ui);
-    let end_y = ui.bounds.h - ui.cursor_y
+    let viewport_h = ui.bounds.h + ui.bounds.y - ui.cursor_y;
+    let end_y = viewport_h
         + (module.last_event_tick().unwrap_or_default()
             .max(pe.edit_start.tick).max(pe.edit_end.tick).as_f32())
         * beat_height;
@@ -1203,25 +2412,37 @@ pub fn draw(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
     } else {
         pe.round_tick(player.get_tick())
     };
-    if (pe.follow || pe.record) && player.is_playing() {
-        pe.scroll_to(playhead_tick);
+    let following = active && (pe.follow || pe.record) && player.is_playing();
+    if following {
+        let tick = if conf.smooth_scroll { player.get_tick() } else { playhead_tick };
+        pe.scroll_to_fraction(tick, conf.playhead_position.fraction());
     }
-    if pe.record {
+    if active && (pe.record || (following && conf.lock_cursor)) {
         let tick = pe.round_tick(player.get_tick());
         pe.edit_start.tick = tick;
         pe.edit_end.tick = tick;
     }
     let mut scroll = pe.scroll(ui);
-    if !(pe.follow || pe.record) || !player.is_playing() {
-        let viewport_h = ui.bounds.h + ui.bounds.y - ui.cursor_y;
+    if active && (!(pe.follow || pe.record) || !player.is_playing()) {
+        let pan = ui.touch().pan();
+        scroll -= pan.y;
+        pe.h_scroll = (pe.h_scroll - pan.x).max(0.0);
+        if conf.mouse.middle_drag_pan {
+            let mouse = mouse_position_vec2();
+            if let Some(origin) = pe.pan_origin
+                .filter(|_| is_mouse_button_down(MouseButton::Middle)) {
+                scroll -= mouse.y - origin.y;
+                pe.h_scroll = (pe.h_scroll - (mouse.x - origin.x)).max(0.0);
+                pe.pan_origin = Some(mouse);
+            } else {
+                pe.pan_origin = (is_mouse_button_pressed(MouseButton::Middle)
+                    && ui.bounds.contains(mouse)).then_some(mouse);
+            }
+        }
         ui.vertical_scrollbar(&mut scroll, end_y, viewport_h, false);
         pe.set_scroll(scroll, ui);
     }
-    {
-        let max_x = track_xs.last().unwrap() - left_x
-            + ui.style.margin * 4.0 + ui.style.atlas.char_width();
-        ui.horizontal_scrollbar(&mut pe.h_scroll, max_x, ui.bounds.w);
-    }
+    let minimap_w = if active { Minimap::width(module) } else { 0.0 };
     ui.cursor_x = track_xs[0];
     let viewport = Rect {
         x: ui.bounds.x,
@@ -1234,16 +2455,66 @@ pub fn draw(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
 
     pe.set_metrics(viewport, ui);
 
+    // dock the minimap to the left of the scrollbar
+    let minimap_rect = Rect {
+        x: viewport.x + viewport.w - ui.style.margin * 2.0 - minimap_w,
+        w: minimap_w,
+        ..viewport
+    };
+
     // handle mouse input
-    if ui.mouse_hits(viewport, "pattern") {
-        let pos = pe.position_from_mouse(ui, &track_xs, &module.tracks);
-        if is_mouse_button_pressed(MouseButton::Left) {
+    if !active {
+        if ui.mouse_hits(viewport, "pattern") && is_mouse_button_pressed(MouseButton::Left) {
+            pe.marked.clear();
+            pe.edit_end = pe.position_from_mouse(ui, track_xs, module);
+            pe.edit_start = pe.edit_end;
+            clicked = true;
+        }
+    } else if pe.draw_stroke.is_some() {
+        if is_mouse_button_down(MouseButton::Left) {
+            pe.continue_stroke(ui, track_xs);
+        } else {
+            pe.commit_stroke(module);
+        }
+    } else if let Some(drag) = pe.selection_drag {
+        if !is_mouse_button_down(MouseButton::Left) {
+            pe.selection_drag = None;
+            let pos = pe.position_from_mouse(ui, track_xs, module);
+            pe.drop_selection(drag, pos, module, is_ctrl_down());
+        }
+    } else if ui.mouse_hits(viewport, "pattern")
+        && !minimap_rect.contains(mouse_position_vec2()) {
+        let pos = pe.position_from_mouse(ui, track_xs, module);
+        let drag = is_mouse_button_pressed(MouseButton::Left)
+            .then(|| pe.selection_drag_at(ui, track_xs, pos))
+            .flatten();
+        if pe.draw_mode && is_mouse_button_pressed(MouseButton::Left) {
+            pe.start_stroke(ui, track_xs, pos);
+        }
+        if pe.draw_stroke.is_some() {
+            pe.edit_start = pos;
+            pe.edit_end = pos;
+        } else if drag.is_some() {
+            pe.selection_drag = drag;
+        } else if is_mouse_button_pressed(MouseButton::Left) && is_ctrl_down() {
+            pe.toggle_marked(pos, module);
+        } else if is_mouse_button_pressed(MouseButton::Left) {
+            pe.marked.clear();
             pe.edit_end = pos;
             if !is_shift_down() {
                 pe.edit_start = pe.edit_end;
             }
             pe.clear_tap_tempo_state();
-        } else if is_mouse_button_down(MouseButton::Left) && !ui.grabbed() {
+            if ui.double_clicked() {
+                match conf.mouse.double_click {
+                    DoubleClickAction::None => (),
+                    DoubleClickAction::SelectAllRows =>
+                        pe.action(Action::SelectAllRows, module, conf, player),
+                    DoubleClickAction::PlayFromRow => player.toggle_play_from(pos.tick),
+                }
+            }
+        } else if is_mouse_button_down(MouseButton::Left) && !ui.grabbed()
+            && !is_ctrl_down() {
             pe.edit_end = pos;
         }
 
@@ -1258,34 +2529,71 @@ pub fn draw(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
         }
     }
 
+    if active {
+        let menu_rect = Rect { w: minimap_rect.x - viewport.x, ..viewport };
+        if let Some(i) = ui.context_menu("pattern_menu", menu_rect,
+            || PATTERN_MENU.iter().map(|a| a.name().to_owned()).collect()) {
+            pe.action(PATTERN_MENU[i], module, conf, player);
+        }
+    }
+
     // draw background visuals
+    ui.set_pattern_font(true);
     ui.cursor_z -= 1;
     ui.push_rect(viewport, ui.style.theme.content_bg(), None);
-    draw_beats(ui, left_x, beat_height);
+    draw_beats(ui, left_x, beat_height, pe.beat_division, pe.triplet_grid, conf);
     ui.cursor_z += 1;
     if player.is_playing() {
         draw_playhead(ui, playhead_tick, left_x + pe.h_scroll, beat_height);
     }
-    pe.draw_cursor(ui, &track_xs);
+    pe.draw_cursor(ui, track_xs);
+    if let Some(drag) = pe.selection_drag.filter(|_| active) {
+        pe.draw_drag_preview(ui, module, track_xs, drag);
+    }
+    if active {
+        pe.draw_stroke_preview(ui, track_xs);
+        if pe.quantize {
+            pe.draw_quantize_preview(ui, module, track_xs);
+        }
+    }
+    pe.draw_marked(ui, module, track_xs);
 
     // draw channel data
     for (track_i, track) in module.tracks.iter().enumerate() {
+        if module.track_hidden(track_i) {
+            continue
+        }
         let chan_width = channel_width(track_i, &ui.style);
         for (channel_i, channel) in track.channels.iter().enumerate() {
             ui.cursor_x = track_xs[track_i] + chan_width * channel_i as f32;
-            pe.draw_channel(ui, channel, player.track_muted(track_i), channel_i);
+            let kit = match track.target {
+                TrackTarget::Kit => module.kit.as_slice(),
+                _ => &[],
+            };
+            pe.draw_channel(ui, channel, player.track_muted(track_i), channel_i, kit,
+                &module.tuning.names);
+        }
+        if let TrackTarget::Audio = track.target {
+            ui.cursor_x = track_xs[track_i];
+            pe.draw_clips(ui, &track.clips, player.track_muted(track_i));
         }
     }
 
     // handle text entry
-    if let Some(pos) = pe.text_position {
-        let max_width = 4;
-        let coords = position_coords(pos, &ui.style, &track_xs, false, beat_height);
+    if let Some(pos) = pe.text_position.filter(|_| active) {
+        let max_width = if pos.track == 0 {
+            4
+        } else if pos.column == MOD_COLUMN {
+            7
+        } else {
+            5
+        };
+        let coords = position_coords(pos, &ui.style, track_xs, false, beat_height);
         let rect = Rect {
             x: coords.x + ui.style.margin,
             y: coords.y + ui.cursor_y,
-            w: ui.style.atlas.char_width() * max_width as f32,
-            h: line_height(&ui.style.atlas),
+            w: ui.style.pattern_atlas().char_width() * max_width as f32,
+            h: line_height(ui.style.pattern_atlas()),
         };
         let action = TEXT_EXIT_ACTIONS.iter().find(|a| conf.action_is_down(**a));
         if let Some(s) = ui.pattern_edit_box(
@@ -1300,23 +2608,223 @@ pub fn draw(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
 
     ui.cursor_x += channel_width(1, &ui.style);
     pe.draw_channel_line(ui, true);
+
+    draw_markers(ui, module, pe, *track_xs.last().unwrap(), beat_height);
+    ui.set_pattern_font(false);
+
+    if !active {
+        return clicked
+    }
+
+    if pe.paste_special {
+        draw_paste_special(ui, module, pe, viewport);
+    } else if pe.quantize {
+        draw_quantize(ui, module, pe, viewport);
+    }
+
+    let visible = pe.beat_scroll.as_f64()..pe.screen_tick_max.as_f64();
+    let playhead = player.is_playing().then(|| playhead_tick.as_f64());
+    if let Some(beat) = pe.minimap.draw(ui, module, minimap_rect, visible, playhead) {
+        pe.scroll_to(Timespan::approximate(beat));
+    }
+    false
+}
+
+/// Draws the paste special panel over the top-left of the pattern.
+fn draw_paste_special(ui: &mut Ui, module: &mut Module, pe: &mut PatternEditor,
+    viewport: Rect
+) {
+    let old_cursor = (ui.cursor_x, ui.cursor_y, ui.cursor_z);
+    ui.cursor_x = viewport.x + ui.style.margin;
+    ui.cursor_y = viewport.y + ui.style.margin;
+    ui.cursor_z += PANEL_Z_OFFSET;
+    ui.layout = Layout::Horizontal;
+    ui.start_group();
+
+    let opts = &mut pe.paste_options;
+    ui.offset_label("Paste special", Info::PasteSpecial);
+    ui.checkbox("Notes", &mut opts.notes, true, Info::PasteSpecial);
+    ui.checkbox("Pressure", &mut opts.pressure, true, Info::PasteSpecial);
+    ui.checkbox("Modulation", &mut opts.modulation, true, Info::PasteSpecial);
+    ui.checkbox("Repeat to fill selection", &mut opts.repeat, true, Info::PasteRepeat);
+    if let Some(s) = ui.edit_box("Transpose", 4, opts.transpose.to_string(),
+        Info::PasteTranspose
+    ) {
+        match s.parse::<i8>() {
+            Ok(n) => opts.transpose = n,
+            Err(e) => ui.report(e),
+        }
+    }
+
+    ui.start_group();
+    let mut close = is_key_pressed(KeyCode::Escape);
+    if ui.button("Paste", true, Info::None) {
+        pe.load_system_clipboard(&module.tuning.names);
+        pe.paste(module, PasteMode::Special(pe.paste_options));
+        close = true;
+    }
+    if ui.button("Cancel", true, Info::None) {
+        close = true;
+    }
+    ui.end_group();
+
+    if let Some(rect) = ui.end_group() {
+        ui.cursor_z -= 1;
+        ui.push_rect(rect, ui.style.theme.panel_bg(), Some(ui.style.theme.border_unfocused()));
+    }
+
+    pe.paste_special = !close;
+    (ui.cursor_x, ui.cursor_y, ui.cursor_z) = old_cursor;
+}
+
+/// Draws the quantize panel over the top-left of the pattern.
+fn draw_quantize(ui: &mut Ui, module: &mut Module, pe: &mut PatternEditor, viewport: Rect) {
+    let old_cursor = (ui.cursor_x, ui.cursor_y, ui.cursor_z);
+    ui.cursor_x = viewport.x + ui.style.margin;
+    ui.cursor_y = viewport.y + ui.style.margin;
+    ui.cursor_z += PANEL_Z_OFFSET;
+    ui.layout = Layout::Horizontal;
+    ui.start_group();
+
+    let opts = &mut pe.quantize_options;
+    ui.offset_label("Quantize", Info::Action(Action::Quantize));
+    let percent = |x: f32| format!("{:.0}%", x * 100.0);
+    ui.formatted_slider("quantize_strength", "Strength", &mut opts.strength,
+        0.0..=1.0, 1, true, Info::QuantizeStrength, percent, |x| x / 100.0);
+    ui.formatted_slider("quantize_swing", "Swing", &mut opts.swing,
+        0.0..=MAX_SWING, 1, opts.groove.is_none(), Info::Swing, percent, |x| x / 100.0);
+
+    let grooves = &module.grooves;
+    let groove = opts.groove.and_then(|i| grooves.get(i));
+    if let Some(i) = ui.combo_box("quantize_groove", "Groove",
+        groove.map_or("None", |g| g.name.as_str()), Info::Groove,
+        || ["None"].into_iter().chain(grooves.iter().map(|g| g.name.as_str()))
+            .map(|s| s.to_owned())
+            .collect()
+    ) {
+        opts.groove = i.checked_sub(1);
+    }
+    ui.start_group();
+    if ui.button("Extract groove", true, Info::ExtractGroove) {
+        pe.extract_groove(ui, module);
+    }
+    let selected = pe.quantize_options.groove.filter(|i| *i < module.grooves.len());
+    if ui.button("Remove groove", selected.is_some(), Info::None) {
+        if let Some(i) = selected {
+            let mut grooves = module.grooves.clone();
+            grooves.remove(i);
+            module.push_edit(Edit::SetGrooves(grooves));
+            pe.quantize_options.groove = None;
+        }
+    }
+    ui.end_group();
+
+    ui.start_group();
+    let mut close = is_key_pressed(KeyCode::Escape);
+    if ui.button("Apply", true, Info::None) {
+        let (moves, accents) = pe.quantize_changes(module);
+        if let Some(edit) = quantize::move_edit(module, moves, accents) {
+            module.push_edit(edit);
+        }
+        close = true;
+    }
+    if ui.button("Cancel", true, Info::None) {
+        close = true;
+    }
+    ui.end_group();
+
+    if let Some(rect) = ui.end_group() {
+        ui.cursor_z -= 1;
+        ui.push_rect(rect, ui.style.theme.panel_bg(), Some(ui.style.theme.border_unfocused()));
+    }
+
+    pe.quantize = !close;
+    (ui.cursor_x, ui.cursor_y, ui.cursor_z) = old_cursor;
 }
 
-/// Draws beat numbers and lines.
-fn draw_beats(ui: &mut Ui, x: f32, beat_height: f32) {
+/// Draws marker text to the right of the tracks, and handles marker text entry.
+fn draw_markers(ui: &mut Ui, module: &mut Module, pe: &mut PatternEditor,
+    x: f32, beat_height: f32
+) {
+    let x = x + ui.style.margin * 2.0;
+    let line_height = line_height(ui.style.pattern_atlas());
+
+    for marker in &module.markers {
+        if Some(marker.tick) == pe.marker_tick {
+            continue
+        }
+        let y = ui.cursor_y + marker.tick.as_f32() * beat_height;
+        if y + line_height >= ui.bounds.y && y < ui.bounds.y + ui.bounds.h {
+            ui.push_text(x, y - ui.style.margin + PATTERN_MARGIN,
+                marker.text.clone(), ui.style.theme.accent1_fg());
+        }
+    }
+
+    if let Some(tick) = pe.marker_tick {
+        let rect = Rect {
+            x: x + ui.style.margin,
+            y: ui.cursor_y + tick.as_f32() * beat_height,
+            w: ui.style.pattern_atlas().char_width() * MARKER_MAX_WIDTH as f32,
+            h: line_height,
+        };
+        // escape or losing focus cancels the edit instead of clearing the marker
+        if is_key_pressed(KeyCode::Escape) || ui.focus.id() != Some(MARKER_TEXT_ID) {
+            pe.marker_tick = None;
+        } else if let Some(s) = ui.pattern_edit_box(
+            MARKER_TEXT_ID, rect, MARKER_MAX_WIDTH, PATTERN_MARGIN, false
+        ) {
+            pe.enter_marker_text(s, module);
+        }
+    }
+}
+
+/// Draws beat rows and numbers, plus row labels, highlights, and triplet
+/// lines if enabled.
+fn draw_beats(ui: &mut Ui, x: f32, beat_height: f32, division: u8,
+    triplet_grid: bool, conf: &Config
+) {
     let mut beat = 1;
     let mut y = ui.cursor_y;
-    let line_height = line_height(&ui.style.atlas);
+    let line_height = line_height(ui.style.pattern_atlas());
+    let per_row = conf.row_numbers != RowNumbers::Off || conf.row_highlight > 0;
     while y < ui.bounds.y + ui.bounds.h {
-        if y >= 0.0 {
-            ui.push_rect(Rect {
+        if y + beat_height < 0.0 {
+            beat += 1;
+            y += beat_height;
+            continue
+        }
+        let rows = if per_row { division } else { 1 };
+        for row in 0..rows {
+            let row_y = y + row as f32 * line_height;
+            if row_y < 0.0 {
+                continue
+            }
+            let row_rect = Rect {
                 x: ui.bounds.x,
-                y,
+                y: row_y,
                 w: ui.bounds.w,
                 h: line_height,
-            }, ui.style.theme.panel_bg(), None);
-            ui.push_text(x, y - ui.style.margin + PATTERN_MARGIN, beat.to_string(),
-                ui.style.theme.fg());
+            };
+            let text_y = row_y - ui.style.margin + PATTERN_MARGIN;
+            if row == 0 {
+                ui.push_rect(row_rect, ui.style.theme.panel_bg(), None);
+                ui.push_text(x, text_y, beat.to_string(), ui.style.theme.fg());
+                continue
+            }
+            let index = (beat - 1) * division as u32 + row as u32;
+            if conf.row_highlight > 0 && index % conf.row_highlight as u32 == 0 {
+                ui.push_rect(row_rect, ui.style.theme.content_bg_hover(), None);
+            }
+            if let Some(label) = conf.row_numbers.label(beat - 1, row, division) {
+                ui.push_text(x, text_y, label, ui.style.theme.border_unfocused());
+            }
+        }
+        if triplet_grid {
+            for i in 1..3 {
+                let line_y = y + beat_height * i as f32 / 3.0;
+                ui.push_line(ui.bounds.x, line_y, ui.bounds.x + ui.bounds.w, line_y,
+                    ui.style.theme.border_unfocused());
+            }
         }
         beat += 1;
         y += beat_height;
@@ -1326,18 +2834,61 @@ fn draw_beats(ui: &mut Ui, x: f32, beat_height: f32) {
 /// Returns x positions of each track, plus the position of the last track's
 /// right edge.
 fn draw_track_headers(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
-    pe: &mut PatternEditor
+    pe: &mut PatternEditor, conf: &Config
 ) -> Vec<f32> {
     let mut edit = None;
+    let mut clip_track = None;
+    let mut collapse_group = None;
+    let mut set_locks = None;
+    let mut track_menu = None;
+    let mut header_rects = Vec::new();
     ui.layout = Layout::Horizontal;
 
     // offset for beat width
-    ui.cursor_x += ui.style.atlas.char_width() * 4.0 + ui.style.margin * 2.0;
+    ui.cursor_x += beat_column_width(&ui.style, conf);
 
+    let top_y = ui.cursor_y;
+    let groups = &module.groups;
     let mut xs = vec![ui.cursor_x];
     xs.extend(module.tracks.iter_mut().enumerate().map(|(i, track)| {
+        let group_i = groups.iter().position(|g| g.tracks().contains(&i));
+        let group = group_i.map(|j| &groups[j]);
+        let first_in_group = group.is_some_and(|g| g.start == i);
+        if group.is_some_and(|g| g.collapsed) && !first_in_group {
+            return ui.cursor_x
+        }
+
         ui.start_group();
 
+        // group controls, or space for them
+        if let (Some(j), Some(group), true) = (group_i, group, first_in_group) {
+            ui.start_group();
+            if ui.button(if group.collapsed { "+" } else { "-" }, true,
+                Info::CollapseGroup) {
+                collapse_group = Some(j);
+            }
+            if ui.button("M", true, Info::GroupMute) {
+                player.toggle_group_mute(group.tracks());
+            }
+            if ui.button("S", true, Info::GroupSolo) {
+                player.solo_tracks(group.tracks());
+            }
+            ui.offset_label(&group.name, Info::TrackGroup);
+            if ui.button("X", true, Info::Remove("this group")) {
+                let mut groups = groups.clone();
+                groups.remove(j);
+                edit = Some(Edit::SetGroups(groups));
+            }
+            ui.end_group();
+
+            if group.collapsed {
+                ui.end_group();
+                return ui.cursor_x
+            }
+        } else if !groups.is_empty() {
+            ui.cursor_y += ui.style.line_height() + ui.style.margin;
+        }
+
         // track name & delete button
         let name = track_name(track.target, &module.patches);
         match track.target {
@@ -1357,9 +2908,20 @@ fn draw_track_headers(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell
             }
             TrackTarget::Global => ui.offset_label(name, Info::GlobalTrack),
             TrackTarget::Kit => ui.offset_label(name, Info::KitTrack),
+            TrackTarget::Audio => {
+                ui.start_group();
+                ui.offset_label(name, Info::AudioTrack);
+                if ui.button("Clip", true, Info::AddClip) {
+                    clip_track = Some(i);
+                }
+                if ui.button("X", true, Info::Remove("this track")) {
+                    edit = Some(Edit::RemoveTrack(i));
+                }
+                ui.end_group();
+            }
         }
 
-        // chanel add/remove buttons
+        // chanel add/remove buttons & lock
         ui.start_group();
         if ui.button("-", track.channels.len() > 1, Info::Remove("the last channel")) {
             edit = Some(Edit::RemoveChannel(i));
@@ -1367,28 +2929,124 @@ fn draw_track_headers(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell
         if ui.button("+", true, Info::Add("a new channel")) {
             edit = Some(Edit::AddChannel(i, Channel::default()));
         }
+        let mut locked = track.locks.iter().all(|x| *x);
+        if ui.checkbox("", &mut locked, true, Info::TrackLock) {
+            set_locks = Some((i, [locked; 3]));
+        }
         ui.end_group();
 
+        // transpose & detune
+        if let TrackTarget::Global | TrackTarget::Audio = track.target {
+            ui.cursor_y += ui.style.line_height() + ui.style.margin;
+        } else {
+            ui.start_group();
+            if let Some(s) = ui.id_edit_box(&format!("track_{i}_transpose"), "", 3,
+                track.transpose.to_string(), Info::TrackTranspose) {
+                match s.parse() {
+                    Ok(n) => edit = Some(Edit::SetTrackOffsets(i, n, track.detune)),
+                    Err(e) => ui.report(e),
+                }
+            }
+            if let Some(s) = ui.id_edit_box(&format!("track_{i}_detune"), "", 3,
+                track.detune.to_string(), Info::TrackDetune) {
+                match s.parse() {
+                    Ok(n) => edit = Some(Edit::SetTrackOffsets(i, track.transpose, n)),
+                    Err(e) => ui.report(e),
+                }
+            }
+            ui.end_group();
+        }
+
         // column labels
         ui.start_group();
         for _ in 0..track.channels.len() {
-            let color = ui.style.theme.border_unfocused();
+            // locked columns are labeled in a different color
+            let colors = track.locks.map(|locked| if locked {
+                ui.style.theme.accent2_fg()
+            } else {
+                ui.style.theme.border_unfocused()
+            });
             if i == 0 {
-                ui.colored_label("Ctrl", Info::ControlColumn, color)
+                ui.colored_label("Ctrl", Info::ControlColumn, colors[0])
             } else {
-                ui.colored_label("Note", Info::NoteColumn, color);
+                ui.colored_label("Note", Info::NoteColumn, colors[0]);
                 ui.cursor_x -= ui.style.margin;
-                ui.colored_label("P", Info::PressureColumn, color);
+                ui.colored_label("P", Info::PressureColumn, colors[1]);
                 ui.cursor_x -= ui.style.margin;
-                ui.colored_label("M", Info::ModulationColumn, color);
+                ui.colored_label("M", Info::ModulationColumn, colors[2]);
             }
         }
         ui.end_group();
 
-        ui.end_group();
+        let rect = ui.end_group().unwrap();
+        if i > 1 && !matches!(track.target, TrackTarget::Kit) {
+            header_rects.push((i, rect));
+        }
+        if i > 0 {
+            let options = track_menu_options(track.target);
+            if let Some(j) = ui.context_menu(&format!("track_{i}_menu"), rect,
+                || options.iter().map(|s| s.to_string()).collect()) {
+                track_menu = Some((i, options[j]));
+            }
+        }
         ui.cursor_x
     }));
 
+    let rects: Vec<_> = header_rects.iter().map(|(_, r)| *r).collect();
+    if let Some((src, dst)) = ui.reorder_drag("track_header", &rects) {
+        let (src, dst) = (header_rects[src].0, header_rects[dst].0);
+        edit = Some(Edit::ShiftTrack {
+            index: src,
+            offset: dst as isize - src as isize,
+            groups: None,
+        });
+    }
+
+    if let Some((i, option)) = track_menu {
+        match option {
+            "Mute" => player.toggle_mute(i),
+            "Solo" => player.toggle_solo(i),
+            "Move left" if i > 2 =>
+                edit = Some(Edit::ShiftTrack { index: i, offset: -1, groups: None }),
+            "Move right" if i > 1 && i + 1 < module.tracks.len() =>
+                edit = Some(Edit::ShiftTrack { index: i, offset: 1, groups: None }),
+            "Add channel" => edit = Some(Edit::AddChannel(i, Channel::default())),
+            "Remove channel" if module.tracks[i].channels.len() > 1 =>
+                edit = Some(Edit::RemoveChannel(i)),
+            "Duplicate" => edit = Some(Edit::DuplicateTrack { index: i, events: true }),
+            "Duplicate without events" =>
+                edit = Some(Edit::DuplicateTrack { index: i, events: false }),
+            "Remove" => edit = Some(Edit::RemoveTrack(i)),
+            _ => (),
+        }
+    }
+
+    // line under group controls, spanning member tracks
+    for group in module.groups.iter().filter(|g| !g.collapsed) {
+        let y = top_y + ui.style.line_height() + ui.style.margin * 1.5;
+        ui.push_line(xs[group.start], y, xs[group.tracks().end], y,
+            ui.style.theme.border_unfocused());
+    }
+
+    if let Some((i, locks)) = set_locks {
+        module.tracks[i].locks = locks;
+        module.has_unsaved_changes = true;
+    }
+
+    if let Some(i) = collapse_group {
+        let mut groups = module.groups.clone();
+        groups[i].collapsed = !groups[i].collapsed;
+        edit = Some(Edit::SetGroups(groups));
+    }
+
+    if let Some(i) = clip_track {
+        if let Some(clip) = load_clip(ui, player, conf, pe.cursor_tick()) {
+            let mut clips = module.tracks[i].clips.clone();
+            clips.push(clip);
+            edit = Some(Edit::SetClips(i, clips));
+        }
+    }
+
     if let Some(edit) = edit {
         module.push_edit(edit);
         player.update_synths(module.drain_track_history());
@@ -1399,13 +3057,48 @@ fn draw_track_headers(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell
         module.add_track();
         player.update_synths(module.drain_track_history());
     }
+    if ui.button("+ Audio", true, Info::Add("a new audio track")) {
+        module.add_audio_track();
+        player.update_synths(module.drain_track_history());
+    }
 
     xs
 }
 
+/// Returns the context menu options for a track header.
+fn track_menu_options(target: TrackTarget) -> Vec<&'static str> {
+    let kit = matches!(target, TrackTarget::Kit);
+    let mut options = vec!["Mute", "Solo"];
+    if !kit {
+        options.extend(["Move left", "Move right"]);
+    }
+    options.extend(["Add channel", "Remove channel"]);
+    if !kit {
+        options.extend(["Duplicate", "Duplicate without events", "Remove"]);
+    }
+    options
+}
+
+/// Browse for an audio file and return it as a clip at `tick`.
+fn load_clip(ui: &mut Ui, player: &mut PlayerShell, conf: &Config, tick: Timespan
+) -> Option<AudioClip> {
+    let path = new_file_dialog(player)
+        .add_filter("Audio file", &PcmData::FILE_EXTENSIONS)
+        .set_directory(conf.sample_folder.clone().unwrap_or(String::from(".")))
+        .pick_file()?;
+
+    match PcmData::load(path, conf.trim_samples) {
+        Ok(data) => Some(AudioClip::new(tick, data)),
+        Err(e) => {
+            ui.report(format!("Error loading audio: {e}"));
+            None
+        }
+    }
+}
+
 /// Adjust selected notes for transposition commands.
-fn nudge_notes(module: &mut Module, (start, end): (Position, Position), cfg: &Config) {
-    let replacements = module.scan_events(start, end).into_iter().filter_map(|mut evt| {
+fn nudge_notes(module: &mut Module, events: Vec<LocatedEvent>, cfg: &Config) {
+    let replacements = events.into_iter().filter_map(|mut evt| {
         if let EventData::Pitch(note) = &mut evt.event.data {
             *note = input::adjust_note_for_modifier_keys(*note, cfg, &module.tuning);
             Some(evt)
@@ -1454,11 +3147,12 @@ fn insert_event_at_cursor(module: &mut Module, cursor: &Position, data: EventDat
 }
 
 /// Returns the UI display string for a track.
-fn track_name(target: TrackTarget, patches: &[Patch]) -> &str {
+pub fn track_name(target: TrackTarget, patches: &[Patch]) -> &str {
     match target {
         TrackTarget::None => "(none)",
         TrackTarget::Global => "Global",
         TrackTarget::Kit => "Kit",
+        TrackTarget::Audio => "Audio",
         TrackTarget::Patch(i) => patches.get(i)
             .map(|x| x.name.as_ref())
             .unwrap_or("(unknown)"),
@@ -1477,7 +3171,7 @@ fn draw_playhead(ui: &mut Ui, tick: Timespan, x: f32, beat_height: f32) {
         x,
         y: ui.cursor_y + tick.as_f32() * beat_height,
         w: ui.bounds.w,
-        h: line_height(&ui.style.atlas),
+        h: line_height(ui.style.pattern_atlas()),
     };
     let color = Color { a: 0.1, ..ui.style.theme.fg() };
     ui.push_rect(rect, color, None);
@@ -1594,13 +3288,22 @@ fn position_coords(pos: Position, style: &Style, track_xs: &[f32],
             column_x(pos.column, style)
         };
     let y = pos.beat() * beat_height + if bottom_left {
-        line_height(&style.atlas)
+        line_height(style.pattern_atlas())
     } else {
         0.0
     };
     Vec2 { x, y }
 }
 
+/// Returns the width of the beat number column.
+fn beat_column_width(style: &Style, conf: &Config) -> f32 {
+    let chars = match conf.row_numbers {
+        RowNumbers::Absolute => 7.0,
+        _ => 4.0,
+    };
+    style.pattern_atlas().char_width() * chars + style.margin * 2.0
+}
+
 /// Returns the minimum visual width of a channel.
 fn channel_width(track_index: usize, style: &Style) -> f32 {
     if track_index == 0 {
@@ -1612,7 +3315,7 @@ fn channel_width(track_index: usize, style: &Style) -> f32 {
 
 /// Returns the x offset for a pattern column.
 fn column_x(column: u8, style: &Style) -> f32 {
-    let char_width = style.atlas.char_width();
+    let char_width = style.pattern_atlas().char_width();
     let margin = style.margin;
 
     match column {
@@ -1625,6 +3328,18 @@ fn column_x(column: u8, style: &Style) -> f32 {
     }
 }
 
+/// Returns the triplet counterpart of a straight division, or the straight
+/// counterpart of a triplet division.
+fn triplet_toggled(division: u8) -> u8 {
+    if division % 3 == 0 {
+        division / 3 * 2
+    } else if division % 2 == 0 {
+        (division / 2).saturating_mul(3)
+    } else {
+        division.saturating_mul(3)
+    }
+}
+
 /// Return the line height used in the pattern grid.
 fn line_height(atlas: &GlyphAtlas) -> f32 {
     atlas.cap_height() + PATTERN_MARGIN * 2.0
@@ -1644,5 +3359,117 @@ mod tests {
         assert_eq!(parse_ctrl_text("60.5"), Some(EventData::Tempo(60.5)));
         assert_eq!(parse_ctrl_text("1/2"), Some(EventData::RationalTempo(1, 2)));
         assert_eq!(parse_ctrl_text("4:3"), Some(EventData::RationalTempo(4, 3)));
+        assert_eq!(parse_ctrl_text("3x"), Some(EventData::LoopCount(3)));
+        assert_eq!(parse_ctrl_text("0x"), None);
+    }
+
+    #[test]
+    fn test_clip_text() {
+        let clip = PatternClip {
+            start: Position::new(Timespan::new(1, 2), 1, 0, NOTE_COLUMN),
+            end: Position::new(Timespan::new(3, 2), 2, 1, MOD_COLUMN),
+            events: vec![ClipEvent {
+                channel_offset: 1,
+                event: Event { tick: Timespan::new(1, 1), data: EventData::Bend(-50) },
+            }],
+            channels: 2,
+        };
+        let parsed = PatternClip::from_text(&clip.to_text().unwrap()).unwrap();
+        assert_eq!(parsed.start, clip.start);
+        assert_eq!(parsed.end, clip.end);
+        assert_eq!(parsed.channels, clip.channels);
+        assert_eq!(parsed.events[0].event.tick, clip.events[0].event.tick);
+        assert_eq!(parsed.events[0].event.data, clip.events[0].event.data);
+        assert!(PatternClip::from_text("hello").is_none());
+    }
+
+    #[test]
+    fn test_triplet_toggled() {
+        assert_eq!(triplet_toggled(4), 6);
+        assert_eq!(triplet_toggled(6), 4);
+        assert_eq!(triplet_toggled(8), 12);
+        assert_eq!(triplet_toggled(5), 15);
+        assert_eq!(triplet_toggled(3), 2);
+
+        // toggling twice always returns to the starting division
+        let mut pe = PatternEditor::default();
+        for division in 1..=u8::MAX {
+            pe.set_division(division);
+            pe.toggle_triplets();
+            pe.toggle_triplets();
+            assert_eq!(pe.beat_division, division);
+        }
+    }
+
+    #[test]
+    fn test_split_panes() {
+        let tracks = Module::new(Default::default()).tracks;
+        let mut pe = PatternEditor::default();
+        pe.toggle_split();
+        pe.edit_start.track = 2;
+        pe.beat_scroll = Timespan::new(4, 1);
+
+        pe.swap_panes(&tracks);
+        assert_eq!(pe.edit_start.track, 0);
+        assert_eq!(pe.beat_scroll, Timespan::ZERO);
+        assert!(pe.split.as_ref().is_some_and(|s| !s.below));
+
+        pe.swap_panes(&tracks);
+        assert_eq!(pe.edit_start.track, 2);
+        assert_eq!(pe.beat_scroll, Timespan::new(4, 1));
+        assert!(pe.split.as_ref().is_some_and(|s| s.below));
+    }
+
+    #[test]
+    fn test_drop_selection() {
+        let mut module = Module::new(Default::default());
+        module.insert_event(1, 0, Event { tick: Timespan::ZERO, data: EventData::NoteOff });
+        let mut pe = PatternEditor::default();
+        let grab = Position::new(Timespan::ZERO, 1, 0, NOTE_COLUMN);
+        pe.edit_start = grab;
+        pe.edit_end = grab;
+
+        // note events can't be dragged into the control track
+        let ctrl = Position::new(Timespan::ZERO, 0, 0, NOTE_COLUMN);
+        let (tl, _) = pe.drag_destination(SelectionDrag::Move(grab), ctrl, &module);
+        assert_eq!(tl.track, 1);
+        pe.drop_selection(SelectionDrag::Move(grab), ctrl, &mut module, false);
+        assert_eq!(module.tracks[1].channels[0].events.len(), 1);
+
+        let dst = Position::new(Timespan::new(1, 1), 2, 0, NOTE_COLUMN);
+        pe.drop_selection(SelectionDrag::Move(grab), dst, &mut module, false);
+        assert!(module.tracks[1].channels[0].events.is_empty());
+        assert_eq!(module.tracks[2].channels[0].events[0].tick, Timespan::new(1, 1));
+    }
+
+    #[test]
+    fn test_offset_events() {
+        let mut module = Module::new(Default::default());
+        let mut pe = PatternEditor::default();
+        pe.edit_start.track = 1;
+        pe.edit_end.track = 1;
+        let unit = pe.row_timespan() * Timespan::new(1, FINE_OFFSETS_PER_ROW);
+        let ticks = |module: &Module| module.tracks[1].channels[0].events.iter()
+            .map(|e| e.tick)
+            .collect::<Vec<_>>();
+        for tick in [Timespan::ZERO, unit] {
+            module.insert_event(1, 0, Event { tick, data: EventData::NoteOff });
+        }
+        module.insert_event(1, 0, Event {
+            tick: unit * Timespan::new(3, 1),
+            data: EventData::Pressure(1),
+        });
+
+        // repeated offsets keep working without a selection
+        pe.offset_events(&mut module, 1);
+        pe.offset_events(&mut module, 1);
+        assert_eq!(ticks(&module)[..2], [unit * Timespan::new(2, 1), unit * Timespan::new(3, 1)]);
+
+        // events don't replace other events
+        pe.offset_events(&mut module, -1);
+        pe.offset_events(&mut module, -1);
+        pe.offset_events(&mut module, -1);
+        assert_eq!(ticks(&module)[..2], [Timespan::ZERO, unit]);
+        assert_eq!(ticks(&module).len(), 3);
     }
 }
\ No newline at end of file
@@ -0,0 +1,491 @@
+//! Plain-text representations of pattern data, for sharing outside Osctet.
+
+use crate::{module::*, pitch::{nearest_just_ratio, Note, NoteNames}, timespan::Timespan};
+
+/// First line of pattern text.
+const TEXT_HEADER: &str = "# osctet pattern text";
+
+/// Cell text for an empty note column.
+const EMPTY_NOTE: &str = "...";
+
+/// Cell text for a note off.
+const NOTE_OFF: &str = "===";
+
+/// Returns the (track, channel) pairs between two positions, excluding the
+/// global track.
+fn selected_channels(module: &Module, start: Position, end: Position) -> Vec<(usize, usize)> {
+    (0..=module.channels_between(start, end))
+        .filter_map(|i| start.add_channels(i, &module.tracks))
+        .filter(|pos| pos.track > 0)
+        .map(|pos| (pos.track, pos.channel))
+        .collect()
+}
+
+/// Returns the row index of `tick`, rounded down.
+fn row_of(tick: Timespan, start: Timespan, rows_per_beat: u8) -> usize {
+    ((tick - start).as_f64() * rows_per_beat as f64 + 1e-9).floor() as usize
+}
+
+/// Returns the number of rows needed to cover a timespan.
+fn row_count(start: Timespan, end: Timespan, rows_per_beat: u8) -> usize {
+    (((end - start).as_f64() * rows_per_beat as f64 - 1e-9).ceil() as usize).max(1)
+}
+
+/// Returns tracker-style text for the selection, one line per row, with
+/// note, pressure, and modulation columns for each channel.
+pub fn to_text(module: &Module, start: Position, end: Position, rows_per_beat: u8
+) -> String {
+    let rows = row_count(start.tick, end.tick, rows_per_beat);
+    let channels = selected_channels(module, start, end);
+
+    // [note, pressure, modulation] text for each channel and row
+    let mut cells = vec![vec![[None, None, None]; rows]; channels.len()];
+    for (i, &(track, channel)) in channels.iter().enumerate() {
+        for event in &module.tracks[track].channels[channel].events {
+            if event.tick < start.tick || event.tick >= end.tick {
+                continue
+            }
+            let text = match event.data {
+                EventData::Pitch(note) => note_text(&note, &module.tuning.names),
+                EventData::NoteOff => NOTE_OFF.to_owned(),
+                EventData::Pressure(v) | EventData::Modulation(v) => format!("{v:X}"),
+                _ => continue,
+            };
+            let cell = &mut cells[i][row_of(event.tick, start.tick, rows_per_beat)]
+                [event.data.spatial_column() as usize];
+            if cell.is_none() {
+                *cell = Some(text);
+            }
+        }
+    }
+
+    let width = cells.iter().flatten()
+        .filter_map(|cell| cell[0].as_ref().map(|s| s.len()))
+        .max().unwrap_or_default()
+        .max(EMPTY_NOTE.len());
+
+    let mut lines = vec![format!("{TEXT_HEADER}, {rows_per_beat} rows per beat")];
+    for row in 0..rows {
+        let line: Vec<_> = cells.iter().map(|channel| {
+            let [note, pressure, modulation] = &channel[row];
+            format!("{:<width$} {} {}",
+                note.as_deref().unwrap_or(EMPTY_NOTE),
+                pressure.as_deref().unwrap_or("."),
+                modulation.as_deref().unwrap_or("."))
+        }).collect();
+        lines.push(line.join(" | "));
+    }
+    lines.join("\n")
+}
+
+/// Returns MML for the selection, one line per channel. Pitches are rounded
+/// to the nearest 12-EDO note.
+pub fn to_mml(module: &Module, start: Position, end: Position, rows_per_beat: u8
+) -> String {
+    const NAMES: [&str; 12] =
+        ["c", "c+", "d", "d+", "e", "f", "f+", "g", "g+", "a", "a+", "b"];
+
+    let rows = row_count(start.tick, end.tick, rows_per_beat);
+    let tempo = module.tempo_at(start.tick).round();
+    let mut lines = Vec::new();
+
+    for (track, channel) in selected_channels(module, start, end) {
+        // (MIDI key or rest, start row) for each segment of the channel
+        let mut segments: Vec<(Option<u8>, usize)> = vec![(None, 0)];
+        let mut events: Vec<_> = module.tracks[track].channels[channel].events.iter()
+            .filter(|e| e.tick >= start.tick && e.tick < end.tick)
+            .collect();
+        events.sort_by_key(|e| e.tick);
+
+        for event in events {
+            let row = ((event.tick - start.tick).as_f64() * rows_per_beat as f64)
+                .round() as usize;
+            let key = match event.data {
+                EventData::Pitch(note) => Some(module.track_pitch(&note, track)
+                    .round().clamp(0.0, 127.0) as u8),
+                EventData::NoteOff => None,
+                _ => continue,
+            };
+            if segments.last().is_some_and(|(_, r)| *r == row) {
+                segments.pop();
+            }
+            segments.push((key, row));
+        }
+
+        let mut mml = format!("t{tempo}");
+        let mut octave = None;
+        for (i, &(key, row)) in segments.iter().enumerate() {
+            let len = segments.get(i + 1).map(|s| s.1).unwrap_or(rows).min(rows)
+                .saturating_sub(row);
+            if len == 0 {
+                continue
+            }
+            let name = match key {
+                Some(key) => {
+                    let o = key / 12;
+                    if octave != Some(o) {
+                        mml.push_str(&format!(" o{}", o as i32 - 1));
+                        octave = Some(o);
+                    }
+                    NAMES[key as usize % 12]
+                }
+                None => "r",
+            };
+            let tie = if key.is_some() { "&" } else { "" };
+            let lengths: Vec<_> = mml_lengths(len, rows_per_beat as usize * 4)
+                .into_iter()
+                .map(|l| format!("{name}{l}"))
+                .collect();
+            mml.push(' ');
+            mml.push_str(&lengths.join(tie));
+        }
+        lines.push(mml);
+    }
+
+    lines.join("\n")
+}
+
+/// Decompose a duration of `rows` into MML note lengths, given the length
+/// value of a single row.
+fn mml_lengths(mut rows: usize, row_length: usize) -> Vec<usize> {
+    let mut lengths = Vec::new();
+    while rows > 0 {
+        // largest power-of-two multiple of the row that divides a whole note
+        let mut k = 1;
+        while k * 2 <= rows && row_length % (k * 2) == 0 {
+            k *= 2;
+        }
+        lengths.push(row_length / k);
+        rows -= k;
+    }
+    lengths
+}
+
+/// Returns a description of a pattern position and the events on its row,
+/// e.g. "Track 2 chan 1, beat 3.25: D#4, pressure A".
+pub fn describe(module: &Module, pos: Position, rows_per_beat: u8) -> String {
+    let track = match pos.track {
+        0 => String::from("Global"),
+        i => format!("Track {i}"),
+    };
+    let beat = ((pos.tick.as_f64() + 1.0) * 1000.0).round() / 1000.0;
+    let row_end = pos.tick + Timespan::new(1, rows_per_beat);
+
+    let mut events: Vec<_> = module.tracks.get(pos.track)
+        .and_then(|t| t.channels.get(pos.channel))
+        .map(|c| c.events.iter()
+            .filter(|e| e.tick >= pos.tick && e.tick < row_end)
+            .collect())
+        .unwrap_or_default();
+    events.sort_by_key(|e| (e.data.spatial_column(), e.tick));
+
+    let descriptions: Vec<_> = events.iter()
+        .map(|e| describe_event(&e.data, &module.tuning.names))
+        .collect();
+    let contents = if descriptions.is_empty() {
+        String::from("empty")
+    } else {
+        descriptions.join(", ")
+    };
+    format!("{track} chan {}, beat {beat}: {contents}", pos.channel + 1)
+}
+
+/// Returns a summary of the selection between two positions: its length,
+/// event counts, and pitch range.
+pub fn summarize(module: &Module, start: Position, end: Position) -> String {
+    let beats = ((end.tick - start.tick).as_f64() * 1000.0).round() / 1000.0;
+    let seconds = module.time_between(start.tick, end.tick);
+    let events = module.scan_events(start, end);
+
+    let (mut notes, mut pressure, mut modulation, mut other) = (0, 0, 0, 0);
+    let mut range: Option<((Note, f32), (Note, f32))> = None;
+    for evt in &events {
+        match evt.event.data {
+            EventData::Pitch(note) => {
+                notes += 1;
+                let entry = (note, module.track_pitch(&note, evt.track));
+                range = Some(match range {
+                    Some((lo, hi)) => (
+                        if entry.1 < lo.1 { entry } else { lo },
+                        if entry.1 > hi.1 { entry } else { hi },
+                    ),
+                    None => (entry, entry),
+                });
+            }
+            EventData::Pressure(_) | EventData::InterpolatedPressure(_) => pressure += 1,
+            EventData::Modulation(_) | EventData::InterpolatedModulation(_) => modulation += 1,
+            _ => other += 1,
+        }
+    }
+
+    let mut parts = vec![format!("{beats} beats, {seconds:.2} s")];
+    let counts: Vec<_> = [(notes, "notes"), (pressure, "pressure"),
+        (modulation, "modulation"), (other, "other")].iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, kind)| format!("{n} {kind}"))
+        .collect();
+    parts.push(if counts.is_empty() { String::from("empty") } else { counts.join(", ") });
+    if let Some(((lo, _), (hi, _))) = range {
+        let names = &module.tuning.names;
+        parts.push(format!("{} to {}",
+            names.note_string(&lo, ""), names.note_string(&hi, "")));
+    }
+    parts.join("; ")
+}
+
+/// Returns a report of the intervals between simultaneous notes in a
+/// selection, listed at each tick where a note starts.
+pub fn analyze_intervals(module: &Module, start: Position, end: Position) -> String {
+    /// Lines shown before the rest are summarized.
+    const MAX_LINES: usize = 24;
+
+    let channels = selected_channels(module, start, end);
+    let mut ticks: Vec<_> = module.scan_events(start, end).into_iter()
+        .filter(|e| matches!(e.event.data, EventData::Pitch(_)))
+        .map(|e| e.event.tick)
+        .collect();
+    ticks.sort();
+    ticks.dedup();
+
+    let name = |n: &Note| module.tuning.names.note_string(n, "");
+    let mut lines = Vec::new();
+    for tick in ticks {
+        let mut notes: Vec<_> = channels.iter()
+            .filter_map(|&(track, channel)|
+                sounding_note(&module.tracks[track].channels[channel], tick)
+                    .map(|n| (n, module.track_pitch(&n, track))))
+            .collect();
+        if notes.len() < 2 {
+            continue
+        }
+        notes.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let names: Vec<_> = notes.iter().map(|(n, _)| name(n)).collect();
+        lines.push(format!("Beat {:.2}: {}", tick.as_f64() + 1.0, names.join(" ")));
+        for (i, (lo, lo_pitch)) in notes.iter().enumerate() {
+            for (hi, hi_pitch) in &notes[i + 1..] {
+                let cents = (hi_pitch - lo_pitch) * 100.0;
+                let ((n, d), error) = nearest_just_ratio(cents);
+                lines.push(format!("  {} to {}: {cents:.1}c, {n}/{d} {error:+.1}c",
+                    name(lo), name(hi)));
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        return String::from("No simultaneous notes in selection")
+    }
+    if lines.len() > MAX_LINES {
+        let rest = lines.len() - MAX_LINES;
+        lines.truncate(MAX_LINES);
+        lines.push(format!("({rest} more lines)"));
+    }
+    lines.join("\n")
+}
+
+/// Returns the note sounding in a channel at `tick`, if any.
+fn sounding_note(channel: &Channel, tick: Timespan) -> Option<Note> {
+    let event = channel.events.iter()
+        .filter(|e| e.tick <= tick
+            && matches!(e.data, EventData::Pitch(_) | EventData::NoteOff))
+        .max_by_key(|e| e.tick)?;
+    match event.data {
+        EventData::Pitch(note) => Some(note),
+        _ => None,
+    }
+}
+
+/// Returns a description of an event.
+fn describe_event(data: &EventData, names: &NoteNames) -> String {
+    const COLUMNS: [&str; 3] = ["pitch", "pressure", "modulation"];
+
+    match *data {
+        EventData::Pitch(note) => names.note_string(&note, ""),
+        EventData::NoteOff => String::from("note off"),
+        EventData::Pressure(v) => format!("pressure {v:X}"),
+        EventData::Modulation(v) => format!("modulation {v:X}"),
+        EventData::Tempo(t) => format!("tempo {t}"),
+        EventData::RationalTempo(n, d) => format!("tempo {n}:{d}"),
+        EventData::End => String::from("end"),
+        EventData::Loop => String::from("loop"),
+        EventData::LoopCount(n) => format!("loop {n} times"),
+        EventData::Skip => String::from("skip"),
+        EventData::InterpolatedPitch(_)
+            | EventData::InterpolatedPressure(_)
+            | EventData::InterpolatedModulation(_) => String::from("interpolated"),
+        EventData::StartGlide(i) | EventData::EndGlide(i) | EventData::TickGlide(i) =>
+            format!("{} glide", COLUMNS.get(i as usize).unwrap_or(&"")),
+        EventData::Bend(cents) => format!("bend {cents:+} cents"),
+        EventData::Section => String::from("section"),
+        EventData::Condition(c) => format!("condition {c}"),
+        EventData::Slide(n) => format!("slide {n}"),
+        EventData::Vibrato(rate, depth) => format!("vibrato {rate:X}{depth:X}"),
+        EventData::Tremolo(rate, depth) => format!("tremolo {rate:X}{depth:X}"),
+    }
+}
+
+/// Parsed pattern text.
+pub struct PatternText {
+    /// Events with their channel offsets.
+    pub events: Vec<(usize, Event)>,
+    pub channels: usize,
+    /// Total length of the text.
+    pub length: Timespan,
+}
+
+/// Parse text produced by `to_text`. Returns None if the text doesn't have
+/// the pattern text header. Unreadable cells are skipped.
+pub fn parse_text(s: &str, names: &NoteNames) -> Option<PatternText> {
+    let mut lines = s.trim().lines();
+    let header = lines.next()?.strip_prefix(TEXT_HEADER)?;
+    let rows_per_beat = header.trim_start_matches(',').split_whitespace().next()
+        .and_then(|s| s.parse::<u8>().ok())
+        .filter(|n| *n > 0)?;
+
+    let mut events = Vec::new();
+    let mut channels = 0;
+    let mut rows = 0;
+    for (row, line) in lines.enumerate() {
+        let tick = Timespan::new(row as i32, rows_per_beat);
+        rows = row + 1;
+        for (channel, cell) in line.split('|').enumerate() {
+            channels = channels.max(channel + 1);
+            let mut tokens = cell.split_whitespace();
+            match tokens.next() {
+                Some(NOTE_OFF) => events.push((channel, Event {
+                    tick,
+                    data: EventData::NoteOff,
+                })),
+                Some(s) => if let Some(note) = parse_note(s, names) {
+                    events.push((channel, Event { tick, data: EventData::Pitch(note) }));
+                },
+                None => (),
+            }
+            for f in [EventData::Pressure, EventData::Modulation] {
+                if let Some(v) = tokens.next().and_then(|s| u8::from_str_radix(s, 16).ok()) {
+                    events.push((channel, Event {
+                        tick,
+                        data: f(v.min(EventData::DIGIT_MAX)),
+                    }));
+                }
+            }
+        }
+    }
+
+    Some(PatternText {
+        events,
+        channels,
+        length: Timespan::new(rows as i32, rows_per_beat),
+    })
+}
+
+/// Returns text for a note, e.g. "C-4" or "^Eb3". The text is ASCII unless
+/// the tuning has custom note names.
+fn note_text(note: &Note, names: &NoteNames) -> String {
+    names.note_string(note, "-")
+}
+
+/// Parse text produced by `note_text`. Both custom and ASCII names are
+/// accepted.
+fn parse_note(s: &str, names: &NoteNames) -> Option<Note> {
+    let is_up = |c: char| c == '^' || Some(c) == names.up;
+    let is_down = |c: char| c == 'v' || Some(c) == names.down;
+    let arrows = s.chars().take_while(|c| is_up(*c) || is_down(*c))
+        .map(|c| if is_up(c) { 1 } else { -1 })
+        .sum();
+    let s = s.trim_start_matches(|c| is_up(c) || is_down(c));
+
+    let mut chars = s.chars();
+    let nominal = names.parse_nominal(chars.next()?)?;
+
+    let rest = chars.as_str();
+    let (sharps, equave) = if let Some(rest) = rest.strip_prefix('-') {
+        (0, rest)
+    } else {
+        let is_sharp = |c: char| c == '#' || Some(c) == names.sharp;
+        let is_flat = |c: char| c == 'b' || Some(c) == names.flat;
+        let equave = rest.trim_start_matches(|c| is_sharp(c) || is_flat(c));
+        let sharps = rest[..rest.len() - equave.len()].chars()
+            .map(|c| if is_sharp(c) { 1 } else { -1 })
+            .sum();
+        (sharps, equave)
+    };
+
+    Some(Note::new(arrows, nominal, sharps, equave.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::Nominal;
+
+    #[test]
+    fn test_note_text_round_trip() {
+        let names = NoteNames::default();
+        let custom = NoteNames {
+            nominals: [None, None, None, None, None, None, Some('H')],
+            flat: Some('\u{266d}'),
+            up: Some('\u{2191}'),
+            ..Default::default()
+        };
+        for note in [
+            Note::new(0, Nominal::C, 0, 4),
+            Note::new(0, Nominal::F, 1, 3),
+            Note::new(0, Nominal::B, -2, 5),
+            Note::new(1, Nominal::E, -1, 2),
+            Note::new(-2, Nominal::A, 0, -1),
+        ] {
+            assert_eq!(parse_note(&note_text(&note, &names), &names), Some(note));
+            assert_eq!(parse_note(&note_text(&note, &custom), &custom), Some(note));
+        }
+        assert_eq!(note_text(&Note::new(1, Nominal::E, -1, 2), &custom), "\u{2191}E\u{266d}2");
+        assert_eq!(parse_note("Hb4", &custom), Some(Note::new(0, Nominal::B, -1, 4)));
+    }
+
+    #[test]
+    fn test_summarize() {
+        let mut module = Module::new(Default::default());
+        let notes = [Note::new(0, Nominal::G, 0, 4), Note::new(0, Nominal::C, 0, 5)];
+        for (i, note) in notes.into_iter().enumerate() {
+            module.insert_event(2, 0, Event {
+                tick: Timespan::new(i as i32, 1),
+                data: EventData::Pitch(note),
+            });
+        }
+        let start = Position { track: 2, ..Default::default() };
+        let end = Position { tick: Timespan::new(2, 1), ..start };
+        assert_eq!(summarize(&module, start, end), "2 beats, 1.00 s; 2 notes; G4 to C5");
+
+        // track transposition counts toward the range
+        module.tracks[1].transpose = 12;
+        module.insert_event(1, 0, Event {
+            tick: Timespan::ZERO,
+            data: EventData::Pitch(Note::new(0, Nominal::E, 0, 4)),
+        });
+        let start = Position { track: 1, ..start };
+        assert_eq!(summarize(&module, start, end), "2 beats, 1.00 s; 3 notes; G4 to E4");
+    }
+
+    #[test]
+    fn test_mml_lengths() {
+        // sixteenth-note rows
+        assert_eq!(mml_lengths(1, 16), vec![16]);
+        assert_eq!(mml_lengths(4, 16), vec![4]);
+        assert_eq!(mml_lengths(6, 16), vec![4, 8]);
+        // triplet rows
+        assert_eq!(mml_lengths(3, 12), vec![6, 12]);
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut module = Module::new(Default::default());
+        let events = &mut module.tracks[2].channels[0].events;
+        let tick = Timespan::new(9, 4);
+        events.push(Event { tick, data: EventData::Pressure(0xa) });
+        events.push(Event { tick, data: EventData::Pitch(Note::new(0, Nominal::D, 1, 4)) });
+        let pos = Position { tick, track: 2, ..Default::default() };
+        assert_eq!(describe(&module, pos, 4), "Track 2 chan 1, beat 3.25: D#4, pressure A");
+        let pos = Position { tick: Timespan::new(2, 1), ..pos };
+        assert_eq!(describe(&module, pos, 4), "Track 2 chan 1, beat 3: empty");
+    }
+}
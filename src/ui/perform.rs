@@ -0,0 +1,215 @@
+use std::ops::Range;
+
+use info::Info;
+
+use crate::{input::is_lower_zone_key, module::{Module, SectionTrigger}, playback::PlayerShell};
+
+use super::*;
+
+/// Keyjazz settings for one zone of a split keyboard.
+#[derive(Clone, Copy, Default)]
+pub struct SplitZone {
+    /// Offset from the base octave.
+    pub octave: i8,
+    /// Track to play on. If None, the keyjazz track is used.
+    pub track: Option<usize>,
+    /// Patch to play. If None, the track's patch is used.
+    pub patch: Option<usize>,
+}
+
+/// State for the performance tab UI.
+pub struct PerformState {
+    scroll: f32,
+    /// Length of a bar in beats. Queued sections start on bar boundaries.
+    bar_beats: u8,
+    /// Index of the marker waiting for a trigger to be assigned.
+    learning: Option<usize>,
+    /// If true, the note keys are split into lower and upper zones.
+    split: bool,
+    /// Lower and upper keyboard zones.
+    zones: [SplitZone; 2],
+}
+
+impl Default for PerformState {
+    fn default() -> Self {
+        Self {
+            scroll: 0.0,
+            bar_beats: 4,
+            learning: None,
+            split: false,
+            zones: Default::default(),
+        }
+    }
+}
+
+impl PerformState {
+    /// Returns the keyboard split zone that a key belongs to, if the
+    /// keyboard is split.
+    pub fn split_zone(&self, key: KeyCode) -> Option<&SplitZone> {
+        self.split.then(|| &self.zones[if is_lower_zone_key(key) { 0 } else { 1 }])
+    }
+
+    /// Handle a trigger input. Returns true if the input was consumed.
+    pub fn trigger(&mut self, trigger: SectionTrigger, module: &mut Module,
+        player: &mut PlayerShell
+    ) -> bool {
+        if let Some(i) = self.learning.take() {
+            for marker in &mut module.markers {
+                if marker.trigger.as_ref() == Some(&trigger) {
+                    marker.trigger = None;
+                }
+            }
+            if let Some(marker) = module.markers.get_mut(i) {
+                marker.trigger = Some(trigger);
+                module.has_unsaved_changes = true;
+            }
+            return true
+        }
+
+        if let Some(i) = module.markers.iter()
+            .position(|m| m.trigger.as_ref() == Some(&trigger)) {
+            self.queue(i, module, player);
+            return true
+        }
+
+        false
+    }
+
+    /// Queue the section starting at a marker index, if it exists.
+    pub fn queue(&self, marker_index: usize, module: &Module, player: &mut PlayerShell) {
+        if marker_index < module.markers.len() {
+            player.queue_section(section(module, marker_index), self.bar_beats as f64);
+        }
+    }
+}
+
+/// Controls for splitting the note keys into two keyjazz zones.
+fn split_controls(ui: &mut Ui, module: &Module, state: &mut PerformState) {
+    ui.checkbox("Split keyboard", &mut state.split, true, Info::KeyboardSplit);
+    if !state.split {
+        return
+    }
+
+    for (i, (name, zone)) in ["Lower", "Upper"].iter().zip(&mut state.zones).enumerate() {
+        ui.start_group();
+        ui.offset_label(name, Info::KeyboardSplit);
+
+        if let Some(s) = ui.id_edit_box(&format!("split_{i}_octave"), "Octave", 3,
+            format!("{:+}", zone.octave), Info::SplitOctave) {
+            match s.trim().parse::<i8>() {
+                Ok(n) => zone.octave = n,
+                Err(e) => ui.report(e),
+            }
+        }
+
+        let track = zone.track.map(|t| t.to_string()).unwrap_or_default();
+        if let Some(s) = ui.id_edit_box(&format!("split_{i}_track"), "Track", 3,
+            track, Info::SplitTrack) {
+            if s.trim().is_empty() {
+                zone.track = None;
+            } else {
+                match s.trim().parse::<usize>() {
+                    Ok(n) => zone.track = Some(n),
+                    Err(e) => ui.report(e),
+                }
+            }
+        }
+
+        let patch = zone.patch
+            .and_then(|i| module.patches.get(i))
+            .map_or("(track)", |p| p.name.as_str());
+        if let Some(j) = ui.combo_box(&format!("split_{i}_patch"), "Patch", patch,
+            Info::SplitPatch, || {
+                let mut v = vec![String::from("(track)")];
+                v.extend(module.patches.iter().map(|p| p.name.clone()));
+                v
+            }) {
+            zone.patch = j.checked_sub(1);
+        }
+        ui.end_group();
+    }
+}
+
+/// Returns the beat range of the section starting at a marker. The last
+/// section is open-ended.
+fn section(module: &Module, marker_index: usize) -> Range<f64> {
+    let start = module.markers[marker_index].tick.as_f64();
+    let end = module.markers.get(marker_index + 1)
+        .map_or(f64::INFINITY, |m| m.tick.as_f64());
+    start..end
+}
+
+pub fn draw(ui: &mut Ui, module: &mut Module, player: &mut PlayerShell,
+    state: &mut PerformState
+) {
+    ui.layout = Layout::Horizontal;
+    let old_y = ui.cursor_y;
+    ui.cursor_y -= state.scroll;
+    ui.cursor_z -= 1;
+    ui.start_group();
+
+    ui.header("PERFORMANCE", Info::Performance);
+
+    ui.start_group();
+    if let Some(s) = ui.edit_box("Beats per bar", 2, state.bar_beats.to_string(),
+        Info::BarBeats
+    ) {
+        match s.parse::<u8>() {
+            Ok(0) => ui.report("Bar must be at least 1 beat"),
+            Ok(n) => state.bar_beats = n,
+            Err(e) => ui.report(e),
+        }
+    }
+    if ui.button("Release", player.perf_section().is_some(), Info::ReleaseSection) {
+        player.end_performance();
+    }
+    ui.end_group();
+
+    split_controls(ui, module, state);
+
+    if module.markers.is_empty() {
+        ui.label("Add markers in the pattern to define sections.", Info::None);
+    }
+
+    let mut clear_trigger = None;
+
+    for (i, marker) in module.markers.iter().enumerate() {
+        let start = marker.tick.as_f64();
+        ui.start_group();
+        if ui.button(&marker.text, true, Info::QueueSection) {
+            state.queue(i, module, player);
+        }
+        let status = if player.perf_queued() == Some(start) {
+            "Queued"
+        } else if player.perf_section() == Some(start) {
+            "Playing"
+        } else {
+            ""
+        };
+        ui.offset_label(status, Info::None);
+        let trigger = if state.learning == Some(i) {
+            "(press key or MIDI note)".to_string()
+        } else {
+            marker.trigger.as_ref().map_or("(no trigger)".to_string(), |t| t.to_string())
+        };
+        ui.offset_label(&trigger, Info::SectionTrigger);
+        if ui.button("Learn", true, Info::SectionTrigger) {
+            state.learning = Some(i);
+        }
+        if ui.button("X", marker.trigger.is_some(), Info::Remove("this trigger")) {
+            clear_trigger = Some(i);
+        }
+        ui.end_group();
+    }
+
+    if let Some(i) = clear_trigger {
+        module.markers[i].trigger = None;
+        module.has_unsaved_changes = true;
+    }
+
+    let scroll_h = ui.end_group().unwrap().h + ui.style.margin;
+    ui.cursor_z += 1;
+    ui.cursor_y = old_y;
+    ui.vertical_scrollbar(&mut state.scroll,
+        scroll_h, ui.bounds.y + ui.bounds.h - ui.cursor_y, true);
+}
@@ -0,0 +1,100 @@
+//! Shape previews for instrument editor controls, computed from parameters
+//! rather than taken from the audio thread.
+
+use std::f32::consts::TAU;
+
+use crate::{dsp, synth::{Oscillator, Waveform, ADSR}};
+
+/// Number of points in a preview.
+const POINTS: usize = 48;
+
+/// Number of steps in a sample-and-hold preview.
+const HOLD_STEPS: usize = 8;
+
+/// Returns one cycle of a generator's waveform in -1..=1, or None if the
+/// waveform can't be previewed. Samples are shown in full.
+pub fn oscillator(osc: &Oscillator) -> Option<Vec<f32>> {
+    match &osc.waveform {
+        Waveform::Pcm(Some(data)) => {
+            let len = data.wave.len();
+            if len == 0 {
+                return None
+            }
+            Some((0..POINTS).map(|i| {
+                // largest magnitude in each window, so transients stay visible
+                let start = i * len / POINTS;
+                let end = ((i + 1) * len / POINTS).max(start + 1);
+                (start..end).map(|j| data.wave.at(0, j.min(len - 1)))
+                    .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                    .unwrap_or_default()
+            }).collect())
+        }
+        Waveform::Pcm(None) | Waveform::Kick | Waveform::Snare | Waveform::Hat => None,
+        waveform => Some(cycles(waveform, osc.tone.0.value(), 1.0)),
+    }
+}
+
+/// Returns two cycles of an LFO waveform in -1..=1.
+pub fn lfo(waveform: &Waveform) -> Vec<f32> {
+    cycles(waveform, 0.5, 2.0)
+}
+
+/// Returns the shape of an envelope in 0..=1, with a sustain segment a
+/// quarter the length of the other segments.
+pub fn envelope(env: &ADSR) -> Vec<f32> {
+    let hold = ((env.attack + env.decay + env.release) * 0.25).max(0.01);
+    let gate_off = env.attack + env.decay + hold;
+    let total = gate_off + env.release;
+    let release_level = dsp::ads(env.attack, env.decay, env.sustain, gate_off, false);
+
+    (0..POINTS).map(|i| {
+        let t = i as f32 / (POINTS - 1) as f32 * total;
+        if t < gate_off {
+            dsp::ads(env.attack, env.decay, env.sustain, t, false)
+        } else {
+            release_level * (1.0 - (t - gate_off) / env.release.max(0.001)).clamp(0.0, 1.0)
+        }
+    }).collect()
+}
+
+/// Returns `n` cycles of a periodic waveform. `tone` is the pulse width.
+fn cycles(waveform: &Waveform, tone: f32, n: f32) -> Vec<f32> {
+    (0..POINTS).map(|i| {
+        let phase = (i as f32 / (POINTS - 1) as f32 * n).fract();
+        match waveform {
+            Waveform::Sawtooth => phase * 2.0 - 1.0,
+            Waveform::Pulse => if phase < tone { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs(),
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Hold => noise((phase * HOLD_STEPS as f32) as usize),
+            _ => noise(i),
+        }
+    }).collect()
+}
+
+/// Returns a repeatable pseudorandom value in -1..=1.
+fn noise(i: usize) -> f32 {
+    let mut x = (i as u32).wrapping_add(1).wrapping_mul(0x9e3779b9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85ebca6b);
+    x ^= x >> 13;
+    x as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_shape() {
+        let mut env = ADSR::default();
+        env.attack = 1.0;
+        env.sustain = 0.5;
+        env.release = 1.0;
+        let shape = envelope(&env);
+        assert_eq!(shape[0], 0.0);
+        assert_eq!(*shape.last().unwrap(), 0.0);
+        assert!(shape.iter().all(|x| (0.0..=1.0).contains(x)));
+        assert!(shape.iter().any(|x| *x > 0.9));
+    }
+}
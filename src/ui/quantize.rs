@@ -0,0 +1,194 @@
+//! Timing quantization of pattern events.
+
+use crate::{module::{Channel, Edit, EventData, Groove, LocatedEvent, Module, Position, NOTE_COLUMN, VEL_COLUMN}, timespan::Timespan};
+
+/// Maximum swing, as a fraction of a row.
+pub const MAX_SWING: f32 = 0.5;
+
+/// Options for the quantize command.
+#[derive(Clone, Copy, PartialEq)]
+pub struct QuantizeOptions {
+    /// Fraction of the distance to the grid that events are moved.
+    pub strength: f32,
+    /// Delay of every other row, as a fraction of a row.
+    pub swing: f32,
+    /// Index of the module groove to quantize to. Overrides swing.
+    pub groove: Option<usize>,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            swing: 0.0,
+            groove: None,
+        }
+    }
+}
+
+impl QuantizeOptions {
+    /// Returns the tick of grid row `n`, accounting for swing or groove.
+    fn grid_tick(&self, n: i32, division: u8, groove: Option<&Groove>) -> Timespan {
+        let offset = match groove {
+            Some(groove) => groove.offset(n),
+            None if n.rem_euclid(2) == 1 => self.swing,
+            None => 0.0,
+        };
+        Timespan::new(n, division) + Timespan::new(1, division) * hundredths(offset)
+    }
+
+    /// Returns the nearest grid row to `tick` and its tick.
+    fn nearest_row(&self, tick: Timespan, division: u8, groove: Option<&Groove>
+    ) -> (i32, Timespan) {
+        // groove offsets can reach half a row in either direction
+        let n = (tick.as_f64() * division as f64).floor() as i32;
+        (n - 1..=n + 2)
+            .map(|n| (n, self.grid_tick(n, division, groove)))
+            .min_by_key(|(_, t)| (*t - tick).abs())
+            .unwrap_or((n, tick))
+    }
+
+    /// Returns the tick that `tick` quantizes to on a grid of `division`
+    /// rows per beat.
+    pub fn quantize(&self, tick: Timespan, division: u8, groove: Option<&Groove>
+    ) -> Timespan {
+        let (_, target) = self.nearest_row(tick, division, groove);
+        tick + (target - tick) * hundredths(self.strength)
+    }
+
+    /// Returns the events that quantization would move, with their new ticks.
+    pub fn moves(&self, events: &[LocatedEvent], division: u8, groove: Option<&Groove>
+    ) -> Vec<(LocatedEvent, Timespan)> {
+        events.iter().filter_map(|e| {
+            let tick = self.quantize(e.event.tick, division, groove);
+            (tick != e.event.tick).then(|| (e.clone(), tick))
+        }).collect()
+    }
+
+    /// Returns pressure events that apply a groove's accents to the notes in
+    /// `events`, at their quantized ticks.
+    pub fn accents(&self, events: &[LocatedEvent], division: u8, groove: &Groove
+    ) -> Vec<LocatedEvent> {
+        events.iter().filter_map(|e| {
+            let EventData::Pitch(_) = e.event.data else { return None };
+            let (n, _) = self.nearest_row(e.event.tick, division, Some(groove));
+            let position = Position {
+                tick: self.quantize(e.event.tick, division, Some(groove)),
+                column: VEL_COLUMN,
+                ..e.position()
+            };
+            groove.accent(n).map(|v| LocatedEvent::from_position(position,
+                EventData::Pressure(v)))
+        }).collect()
+    }
+}
+
+/// Returns a groove made from the notes of a channel between two ticks,
+/// with one step per row. Returns None if there are no notes in the range.
+pub fn extract_groove(channel: &Channel, start: Timespan, end: Timespan, division: u8,
+    name: String
+) -> Option<Groove> {
+    let row = Timespan::new(1, division);
+    let first = (start.as_f64() * division as f64).round() as i32;
+    let len = ((end - start).as_f64() * division as f64).round().max(1.0) as i32;
+    let mut offsets = vec![0.0; len as usize];
+    let mut accents = vec![None; len as usize];
+    let mut found = false;
+
+    for n in first..first + len {
+        let tick = Timespan::new(n, division);
+        let note = channel.events.iter().find(|e| {
+            e.data.spatial_column() == NOTE_COLUMN
+                && matches!(e.data, EventData::Pitch(_))
+                && (e.tick - tick).abs() * Timespan::new(2, 1) < row
+        });
+        if let Some(note) = note {
+            let i = n.rem_euclid(len) as usize;
+            offsets[i] = ((note.tick - tick) / row).as_f32();
+            accents[i] = channel.events.iter()
+                .find(|e| e.tick == note.tick && e.data.logical_column() == VEL_COLUMN)
+                .and_then(|e| match e.data {
+                    EventData::Pressure(v) => Some(v),
+                    _ => None,
+                });
+            found = true;
+        }
+    }
+
+    found.then_some(Groove { name, offsets, accents })
+}
+
+/// Converts a fraction to a timespan, rounded to hundredths.
+fn hundredths(f: f32) -> Timespan {
+    Timespan::new((f * 100.0).round() as i32, 100)
+}
+
+/// Returns an edit that moves events to new ticks and adds accent events.
+/// Events already at a destination are replaced, and if several events land
+/// on the same position, accents win, followed by the last moved event.
+pub fn move_edit(module: &Module, moves: Vec<(LocatedEvent, Timespan)>,
+    accents: Vec<LocatedEvent>
+) -> Option<Edit> {
+    if moves.is_empty() && accents.is_empty() {
+        return None
+    }
+
+    let mut remove: Vec<Position> = moves.iter().map(|(e, _)| e.position()).collect();
+    let mut add: Vec<LocatedEvent> = Vec::new();
+    let moved = moves.into_iter().map(|(mut e, tick)| {
+        e.event.tick = tick;
+        e
+    });
+    for e in moved.chain(accents).rev() {
+        if !add.iter().any(|x| x.position() == e.position()) {
+            add.push(e);
+        }
+    }
+
+    for pos in add.iter().map(|e| e.position()) {
+        let occupied = module.tracks[pos.track].channels[pos.channel].events.iter()
+            .any(|e| e.tick == pos.tick && e.data.logical_column() == pos.column);
+        if occupied && !remove.contains(&pos) {
+            remove.push(pos);
+        }
+    }
+
+    Some(Edit::PatternData { remove, add })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let opts = QuantizeOptions::default();
+        assert_eq!(opts.quantize(Timespan::new(3, 10), 4, None), Timespan::new(1, 4));
+        assert_eq!(opts.quantize(Timespan::new(4, 10), 4, None), Timespan::new(2, 4));
+
+        let opts = QuantizeOptions { strength: 0.5, ..opts };
+        assert_eq!(opts.quantize(Timespan::new(3, 10), 4, None), Timespan::new(11, 40));
+    }
+
+    #[test]
+    fn test_swing() {
+        let opts = QuantizeOptions { swing: 0.5, ..Default::default() };
+        assert_eq!(opts.quantize(Timespan::new(1, 4), 4, None), Timespan::new(3, 8));
+        assert_eq!(opts.quantize(Timespan::new(1, 2), 4, None), Timespan::new(1, 2));
+    }
+
+    #[test]
+    fn test_groove() {
+        let groove = Groove {
+            name: String::new(),
+            offsets: vec![0.0, 0.25],
+            accents: vec![Some(8), None],
+        };
+        let opts = QuantizeOptions { swing: 0.5, ..Default::default() };
+        let quantize = |tick| opts.quantize(tick, 4, Some(&groove));
+        assert_eq!(quantize(Timespan::new(1, 4)), Timespan::new(5, 16));
+        assert_eq!(quantize(Timespan::new(3, 4)), Timespan::new(13, 16));
+        assert_eq!(groove.accent(2), Some(8));
+        assert_eq!(groove.accent(-1), None);
+    }
+}
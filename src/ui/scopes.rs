@@ -0,0 +1,103 @@
+//! Oscilloscope view of each track's output during playback.
+
+use crate::{module::Module, playback::PlayerShell};
+
+use super::*;
+
+/// Number of samples shown in each scope.
+const WINDOW: usize = 1024;
+
+/// State for the scopes tab UI.
+#[derive(Default)]
+pub struct ScopesState {
+    /// Last displayed samples of each track, used to stabilize the next frame.
+    prev: Vec<Vec<f32>>,
+}
+
+pub fn draw(ui: &mut Ui, module: &Module, player: &PlayerShell, state: &mut ScopesState) {
+    let tracks: Vec<_> = (1..module.tracks.len())
+        .filter(|i| !module.track_hidden(*i))
+        .collect();
+    state.prev.resize(module.tracks.len(), Vec::new());
+
+    let margin = ui.style.margin;
+    let area = Rect {
+        x: ui.bounds.x + margin,
+        y: ui.cursor_y + margin,
+        w: ui.bounds.w - margin * 2.0,
+        h: ui.bounds.y + ui.bounds.h - ui.cursor_y - margin * 2.0,
+    };
+    let cols = (tracks.len() as f32).sqrt().ceil().max(1.0) as usize;
+    let rows = tracks.len().div_ceil(cols).max(1);
+    let cell_w = area.w / cols as f32;
+    let cell_h = area.h / rows as f32;
+
+    for (n, &track) in tracks.iter().enumerate() {
+        let rect = Rect {
+            x: area.x + (n % cols) as f32 * cell_w,
+            y: area.y + (n / cols) as f32 * cell_h,
+            w: cell_w - margin,
+            h: cell_h - margin,
+        };
+        ui.push_rect(rect, ui.style.theme.control_bg(),
+            Some(ui.style.theme.border_unfocused()));
+
+        if let Some(scope) = player.scopes().get(track) {
+            let samples = stabilize(&scope.read(), &state.prev[track]);
+            let point = |i: usize| (
+                rect.x + i as f32 / (samples.len() - 1).max(1) as f32 * rect.w,
+                rect.y + (0.5 - samples[i].clamp(-1.0, 1.0) * 0.5) * rect.h,
+            );
+            // one segment per pixel is plenty
+            let step = (samples.len() as f32 / rect.w).ceil().max(1.0) as usize;
+            let color = ui.style.theme.accent1_fg();
+            for i in (step..samples.len()).step_by(step) {
+                let (x1, y1) = point(i - step);
+                let (x2, y2) = point(i);
+                ui.push_line(x1, y1, x2, y2, color);
+            }
+            state.prev[track] = samples;
+        }
+
+        let name = pattern::track_name(module.tracks[track].target, &module.patches);
+        ui.push_text(rect.x, rect.y, format!("{track}: {name}"), ui.style.theme.fg());
+    }
+}
+
+/// Returns the window of `samples` that starts on the rising zero crossing
+/// most correlated with `prev`, so that periodic waves hold still and keep
+/// their phase between frames. Without a previous window, the latest crossing
+/// is used.
+fn stabilize(samples: &[f32], prev: &[f32]) -> Vec<f32> {
+    let latest = samples.len().saturating_sub(WINDOW);
+    let earliest = latest.saturating_sub(WINDOW).max(1);
+    let window = |start: usize| &samples[start..(start + WINDOW).min(samples.len())];
+    let score = |start: usize| if prev.is_empty() {
+        start as f32
+    } else {
+        window(start).iter().zip(prev).map(|(a, b)| a * b).sum()
+    };
+
+    let start = (earliest..=latest)
+        .filter(|&i| samples[i - 1] <= 0.0 && samples[i] > 0.0)
+        .max_by(|&a, &b| score(a).total_cmp(&score(b)))
+        .unwrap_or(latest);
+    window(start).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stabilize() {
+        // square wave with a period of 100 samples
+        let samples: Vec<f32> = (0..4000)
+            .map(|i| if (i + 30) % 100 < 50 { -1.0 } else { 1.0 })
+            .collect();
+        let window = stabilize(&samples, &[]);
+        assert_eq!(window.len(), WINDOW);
+        assert_eq!(window[0], 1.0);
+        assert_eq!(stabilize(&samples, &window), window);
+    }
+}
@@ -1,6 +1,9 @@
+use std::path::{Path, PathBuf};
+
+use macroquad::color::Color;
 use palette::Lchuv;
 
-use crate::{config::{self, Config, RenderFormat}, playback::PlayerShell, Midi};
+use crate::{config::{self, Config, DoubleClickAction, KeyProfile, PlayheadPosition, RenderFormat, RowNumbers, VelocityCurve}, exe_relative_path, playback::PlayerShell, surface::{ControlSurface, SurfaceLayout}, synth::{Oversampling, StealPolicy, MAX_VOICE_LIMIT}, Midi};
 
 use super::{info::Info, text::{self, GlyphAtlas}, theme::Theme, Layout, Ui};
 
@@ -20,7 +23,7 @@ impl SettingsState {
 }
 
 pub fn draw(ui: &mut Ui, cfg: &mut Config, state: &mut SettingsState,
-    player: &mut PlayerShell, midi: &mut Midi
+    player: &mut PlayerShell, midi: &mut Midi, surface: &mut ControlSurface
 ) {
     ui.layout = Layout::Horizontal;
     let old_y = ui.cursor_y;
@@ -28,12 +31,14 @@ pub fn draw(ui: &mut Ui, cfg: &mut Config, state: &mut SettingsState,
     ui.cursor_z -= 1;
     ui.start_group();
 
-    general_controls(ui, cfg);
+    general_controls(ui, cfg, player);
     ui.vertical_space();
-    io_controls(ui, cfg, state.sample_rate, midi, player);
+    io_controls(ui, cfg, state.sample_rate, midi, surface, player);
     ui.vertical_space();
     appearance_controls(ui, cfg, player);
     ui.vertical_space();
+    mouse_controls(ui, cfg);
+    ui.vertical_space();
     let id = hotkey_controls(ui, cfg);
     ui.vertical_space();
     note_key_controls(ui, cfg, id);
@@ -46,19 +51,193 @@ pub fn draw(ui: &mut Ui, cfg: &mut Config, state: &mut SettingsState,
         scroll_h, ui.bounds.y + ui.bounds.h - ui.cursor_y, true);
 }
 
-fn general_controls(ui: &mut Ui, cfg: &mut Config) {
+fn general_controls(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell) {
     ui.header("GENERAL", Info::None);
 
     if ui.button("Reset to defaults", true, Info::ResetSettings) {
         cfg.reset();
         ui.style.theme = Default::default();
+        load_font(cfg, ui);
+        player.set_voice_settings(cfg.voices);
+    }
+    ui.start_group();
+    if ui.button("Export settings", true, Info::ExportSettings) {
+        export_settings(ui, cfg, player);
+    }
+    if ui.button("Import settings", true, Info::ImportSettings) {
+        import_settings(ui, cfg, player);
     }
+    ui.end_group();
     ui.checkbox("Smooth playhead", &mut cfg.smooth_playhead, true, Info::SmoothPlayhead);
+    ui.checkbox("Smooth follow", &mut cfg.smooth_scroll, true, Info::SmoothScroll);
+    if let Some(i) = ui.combo_box("playhead_position", "Playhead position",
+        &cfg.playhead_position.to_string(), Info::PlayheadPosition,
+        || PlayheadPosition::VARIANTS.map(|x| x.to_string()).to_vec()
+    ) {
+        cfg.playhead_position = PlayheadPosition::VARIANTS[i];
+    }
+    if let Some(i) = ui.combo_box("row_numbers", "Row numbers",
+        &cfg.row_numbers.to_string(), Info::RowNumbers,
+        || RowNumbers::VARIANTS.map(|x| x.to_string()).to_vec()
+    ) {
+        cfg.row_numbers = RowNumbers::VARIANTS[i];
+    }
+    let mut highlight = cfg.row_highlight as f32;
+    if ui.formatted_slider("row_highlight", "Highlight every", &mut highlight,
+        0.0..=64.0, 1, true, Info::RowHighlight,
+        |x| if x == 0.0 { String::from("Off") } else { format!("{} rows", x.round()) },
+        |x| x.round()
+    ) {
+        cfg.row_highlight = highlight as u8;
+    }
+    ui.checkbox("Lock cursor to playhead", &mut cfg.lock_cursor, true, Info::LockCursor);
+    ui.checkbox("Quantize note preview", &mut cfg.quantize_preview, true,
+        Info::QuantizePreview);
     ui.checkbox("Display info text", &mut cfg.display_info, true, Info::DisplayInfo);
+
+    let mut limit = cfg.voices.limit as f32;
+    if ui.formatted_slider("voice_limit", "Voice limit", &mut limit,
+        1.0..=MAX_VOICE_LIMIT as f32, 1, true, Info::VoiceLimit,
+        |x| format!("{}", x.round()), |x| x.round()
+    ) {
+        cfg.voices.limit = limit as u8;
+        player.set_voice_settings(cfg.voices);
+    }
+    if let Some(i) = ui.combo_box("steal_policy", "Steal policy",
+        cfg.voices.steal_policy.name(), Info::StealPolicy,
+        || StealPolicy::VARIANTS.map(|x| x.name().to_owned()).to_vec()
+    ) {
+        cfg.voices.steal_policy = StealPolicy::VARIANTS[i];
+        player.set_voice_settings(cfg.voices);
+    }
+}
+
+fn velocity_controls(ui: &mut Ui, cfg: &mut Config, enabled: bool) {
+    let velocity = &mut cfg.velocity;
+    ui.start_group();
+    if let Some(i) = ui.combo_box("velocity_curve", "Velocity curve",
+        velocity.curve.name(), Info::VelocityCurve,
+        || VelocityCurve::VARIANTS.map(|x| x.name().to_owned()).to_vec()
+    ) {
+        velocity.curve = VelocityCurve::VARIANTS[i];
+    }
+    match velocity.curve {
+        VelocityCurve::Linear => (),
+        VelocityCurve::Exponential => {
+            ui.formatted_slider("velocity_exponent", "Exponent", &mut velocity.exponent,
+                0.25..=4.0, 2, enabled, Info::VelocityExponent,
+                |f| format!("{f:.2}"), |f| f);
+        }
+        VelocityCurve::Fixed => {
+            ui.formatted_slider("velocity_fixed", "Value", &mut velocity.fixed,
+                0.0..=1.0, 1, enabled, Info::None, |f| format!("{f:.2}"), |f| f);
+        }
+    }
+    ui.formatted_slider("velocity_scale", "Scale", &mut velocity.scale,
+        0.0..=2.0, 1, enabled, Info::VelocityScale, |f| format!("{f:.2}x"), |f| f);
+    ui.formatted_slider("velocity_offset", "Offset", &mut velocity.offset,
+        -1.0..=1.0, 1, enabled, Info::VelocityScale, |f| format!("{f:+.2}"), |f| f);
+    ui.end_group();
+}
+
+/// Controls for the MIDI channel to track routing table.
+fn midi_route_controls(ui: &mut Ui, cfg: &mut Config) {
+    let mut removed = None;
+
+    for (i, (channel, track)) in cfg.midi_routes.iter_mut().enumerate() {
+        ui.start_group();
+        if let Some(j) = ui.combo_box(&format!("midi_route_{i}_channel"), "Route channel",
+            &(*channel + 1).to_string(), Info::MidiRoute,
+            || (1..=16).map(|c| c.to_string()).collect()) {
+            *channel = j as u8;
+        }
+        if let Some(s) = ui.id_edit_box(&format!("midi_route_{i}_track"), "to track", 3,
+            track.to_string(), Info::MidiRoute) {
+            match s.trim().parse::<usize>() {
+                Ok(n) => *track = n,
+                Err(e) => ui.report(e),
+            }
+        }
+        if ui.button("X", true, Info::Remove("this route")) {
+            removed = Some(i);
+        }
+        ui.end_group();
+    }
+
+    if let Some(i) = removed {
+        cfg.midi_routes.remove(i);
+    }
+
+    let unrouted = (0..16).find(|c| cfg.midi_route(*c).is_none());
+    if ui.button("Add channel route", unrouted.is_some(), Info::MidiRoute) {
+        if let Some(channel) = unrouted {
+            cfg.midi_routes.push((channel, 1));
+        }
+    }
+}
+
+/// Controls for adding, removing, and routing MIDI inputs.
+fn midi_input_controls(ui: &mut Ui, cfg: &mut Config, input: &midir::MidiInput) {
+    let mut removed = None;
+
+    for (i, settings) in cfg.midi_inputs.iter_mut().enumerate() {
+        ui.start_group();
+        ui.offset_label(&settings.port_name, Info::MidiInput);
+
+        let channel = settings.channel
+            .map(|c| (c + 1).to_string())
+            .unwrap_or(String::from("All"));
+        if let Some(j) = ui.combo_box(&format!("midi_input_{i}_channel"), "Channel",
+            &channel, Info::MidiChannelFilter, || {
+                let mut v = vec![String::from("All")];
+                v.extend((1..=16).map(|c| c.to_string()));
+                v
+            }) {
+            settings.channel = j.checked_sub(1).map(|c| c as u8);
+        }
+
+        let track = settings.track.map(|t| t.to_string()).unwrap_or_default();
+        if let Some(s) = ui.id_edit_box(&format!("midi_input_{i}_track"), "Track", 3,
+            track, Info::MidiInputTrack) {
+            if s.trim().is_empty() {
+                settings.track = None;
+            } else {
+                match s.trim().parse::<usize>() {
+                    Ok(n) => settings.track = Some(n),
+                    Err(e) => ui.report(e),
+                }
+            }
+        }
+
+        if ui.button("X", true, Info::Remove("this MIDI input")) {
+            removed = Some(i);
+        }
+        ui.end_group();
+    }
+
+    if let Some(i) = removed {
+        cfg.midi_inputs.remove(i);
+    }
+
+    midi_route_controls(ui, cfg);
+
+    let available = || {
+        let mut v = vec![String::from("(none)")];
+        v.extend(input_names(input).into_iter()
+            .skip(1)
+            .filter(|name| !cfg.midi_inputs.iter().any(|s| s.port_name == *name)));
+        v
+    };
+    if let Some(i) = ui.combo_box("midi_input", "Add MIDI input", "(none)",
+        Info::MidiInput, available) {
+        if let Some(name) = available().get(i).filter(|_| i > 0) {
+            cfg.midi_inputs.push(config::MidiInputSettings::new(name.clone()));
+        }
+    }
 }
 
 fn io_controls(ui: &mut Ui, cfg: &mut Config, sample_rate: u32, midi: &mut Midi,
-    player: &mut PlayerShell
+    surface: &mut ControlSurface, player: &mut PlayerShell
 ) {
     ui.header("I/O", Info::None);
 
@@ -74,40 +253,33 @@ fn io_controls(ui: &mut Ui, cfg: &mut Config, sample_rate: u32, midi: &mut Midi,
         ui.label(&format!("Actual sample rate: {} Hz", sample_rate), Info::None);
     }
 
-    if midi.input.is_some() {
-        ui.start_group();
+    if let Some(input) = &midi.input {
+        midi_input_controls(ui, cfg, input);
 
-        // midi input selection
-        let s = if let Some(name) = &midi.port_name {
-            name
-        } else {
-            "(none)"
-        };
-        if let Some(i) = ui.combo_box("midi_input", "MIDI input", s,
-            Info::MidiInput, || input_names(midi.input.as_ref().unwrap())) {
-            midi.port_selection = if i == 0 {
-                None
-            } else {
-                input_names(midi.input.as_ref().unwrap()).get(i).cloned()
-            };
-        }
+        ui.start_group();
 
         let mut v = cfg.midi_send_pressure.unwrap_or(true);
-        if ui.checkbox("Use aftertouch", &mut v, midi.port_name.is_some(),
+        if ui.checkbox("Use aftertouch", &mut v, midi.is_connected(),
             Info::UseAftertouch) {
             cfg.midi_send_pressure = Some(v);
         }
 
-        if ui.checkbox("Use velocity", &mut cfg.midi_send_velocity, midi.port_name.is_some(),
+        if ui.checkbox("Use velocity", &mut cfg.midi_send_velocity, midi.is_connected(),
             Info::UseVelocity) {
             player.reset_memory();
         }
 
         ui.end_group();
+
+        if cfg.midi_send_velocity {
+            velocity_controls(ui, cfg, midi.is_connected());
+        }
     } else {
         ui.label("No MIDI device", Info::None);
     }
 
+    surface_controls(ui, cfg, surface);
+
     if let Some(i) = ui.combo_box("render_format", "Render format",
         &cfg.render_format.to_string(), Info::RenderFormat,
         || RenderFormat::VARIANTS.map(|x| x.to_string()).to_vec()
@@ -115,8 +287,35 @@ fn io_controls(ui: &mut Ui, cfg: &mut Config, sample_rate: u32, midi: &mut Midi,
         cfg.render_format = RenderFormat::VARIANTS[i]
     }
 
+    if let RenderFormat::Wav16 = cfg.render_format {
+        ui.checkbox("Dither", &mut cfg.dither, true, Info::Dither);
+        ui.checkbox("Noise shaping", &mut cfg.noise_shaping, cfg.dither,
+            Info::NoiseShaping);
+    }
+
+    let quality = cfg.render_quality.map_or("Per patch", |x| x.name());
+    if let Some(i) = ui.combo_box("render_quality", "Render quality", quality,
+        Info::RenderQuality, || {
+            let mut v = vec![String::from("Per patch")];
+            v.extend(Oversampling::VARIANTS.map(|x| x.name().to_owned()));
+            v
+        }
+    ) {
+        cfg.render_quality = i.checked_sub(1).map(|i| Oversampling::VARIANTS[i]);
+    }
+
+    ui.checkbox("Normalize loudness", &mut cfg.normalize_loudness, true,
+        Info::NormalizeLoudness);
+    ui.formatted_slider("loudness_target", "Target loudness", &mut cfg.loudness_target,
+        -36.0..=-6.0, 1, cfg.normalize_loudness, Info::NormalizeLoudness,
+        |f| format!("{f:.0} LUFS"), |f| f.round());
+
     ui.checkbox("Autosave", &mut cfg.autosave, true, Info::Autosave);
     ui.checkbox("Trim samples", &mut cfg.trim_samples, true, Info::TrimSamples);
+    ui.checkbox("Detect sample pitch", &mut cfg.detect_sample_pitch, true,
+        Info::DetectSamplePitch);
+    ui.checkbox("Reload edited samples", &mut cfg.watch_samples, true,
+        Info::WatchSamples);
 }
 
 fn appearance_controls(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell) {
@@ -140,10 +339,10 @@ fn appearance_controls(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell)
 
     ui.start_group();
     if ui.button("Reset (light)", true, Info::ResetTheme("light")) {
-        ui.style.theme = Theme::light(ui.style.theme.gamma);
+        ui.style.theme = ui.style.theme.with_colors_of(Theme::light(ui.style.theme.gamma));
     }
     if ui.button("Reset (dark)", true, Info::ResetTheme("dark")) {
-        ui.style.theme = Theme::dark(ui.style.theme.gamma);
+        ui.style.theme = ui.style.theme.with_colors_of(Theme::dark(ui.style.theme.gamma));
     }
     if ui.button("Save", true, Info::SaveTheme) {
         save_theme(ui, cfg, player);
@@ -151,16 +350,96 @@ fn appearance_controls(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell)
     if ui.button("Load", true, Info::LoadTheme) {
         load_theme(ui, cfg, player);
     }
+    if let Some(i) = ui.combo_box("theme_file", "", "Themes folder", Info::ThemesFolder,
+        || theme_files(cfg).iter()
+            .map(|p| p.file_stem().unwrap_or_default().to_string_lossy().into_owned())
+            .collect()
+    ) {
+        if let Some(path) = theme_files(cfg).get(i) {
+            apply_theme_file(ui, path.clone());
+        }
+    }
+    ui.end_group();
+
+    ui.start_group();
+    role_controls(ui, "Note", |t| &mut t.roles.note, Theme::note_fg);
+    role_controls(ui, "Pressure", |t| &mut t.roles.pressure, Theme::pressure_fg);
+    role_controls(ui, "Modulation", |t| &mut t.roles.modulation, Theme::modulation_fg);
+    role_controls(ui, "Interpolation", |t| &mut t.roles.interpolation,
+        |t| t.interpolation_fg(0));
+    ui.end_group();
+
+    ui.start_group();
+    let mut high_contrast = ui.style.theme.high_contrast;
+    if ui.checkbox("High contrast", &mut high_contrast, true, Info::HighContrast) {
+        ui.style.theme.high_contrast = high_contrast;
+    }
+    let low_contrast = ui.style.theme.low_contrast_colors();
+    if !low_contrast.is_empty() {
+        ui.colored_label(&format!("Low contrast: {}", low_contrast.join(", ")),
+            Info::LowContrast, ui.style.theme.accent2_fg());
+    }
+    ui.end_group();
+
+    ui.start_group();
+    let mut scale = cfg.ui_scale.unwrap_or_else(super::auto_scale);
+    if ui.formatted_slider("ui_scale", "UI scale", &mut scale, super::SCALE_RANGE,
+        1, cfg.ui_scale.is_some(), Info::UiScale,
+        |x| format!("{x:.2}x"), |x| (x * 4.0).round() / 4.0
+    ) {
+        cfg.ui_scale = Some(scale);
+    }
+    let mut auto = cfg.ui_scale.is_none();
+    if ui.checkbox("Auto", &mut auto, true, Info::AutoScale) {
+        cfg.ui_scale = if auto { None } else { Some(scale) };
+    }
     ui.end_group();
 
     ui.start_group();
     ui.offset_label("Font size", Info::Font);
-    if ui.button("-", cfg.font_size > 0, Info::FontSize("Increase")) {
-        set_font(cfg, ui, cfg.font_size - 1);
+    if cfg.font_path.is_some() {
+        if ui.button("-", cfg.ttf_size > text::MIN_TTF_SIZE, Info::FontSize("Decrease")) {
+            cfg.ttf_size -= 1.0;
+            load_font(cfg, ui);
+        }
+        if ui.button("+", cfg.ttf_size < text::MAX_TTF_SIZE, Info::FontSize("Increase")) {
+            cfg.ttf_size += 1.0;
+            load_font(cfg, ui);
+        }
+    } else {
+        if ui.button("-", cfg.font_size > 0, Info::FontSize("Decrease")) {
+            set_font(cfg, ui, cfg.font_size - 1);
+        }
+        if ui.button("+", cfg.font_size < text::FONT_BYTES.len() - 1,
+            Info::FontSize("Increase")) {
+            set_font(cfg, ui, cfg.font_size + 1);
+        }
+    }
+    if ui.button("Load font", true, Info::LoadFont) {
+        browse_font(ui, cfg, player);
     }
-    if ui.button("+", cfg.font_size < text::FONT_BYTES.len() - 1,
-        Info::FontSize("Decrease")) {
-        set_font(cfg, ui, cfg.font_size + 1);
+    if ui.button("Built-in font", cfg.font_path.is_some(), Info::BuiltinFont) {
+        cfg.font_path = None;
+        load_font(cfg, ui);
+    }
+    ui.end_group();
+
+    ui.start_group();
+    ui.offset_label("Pattern font size", Info::PatternFont);
+    let size = cfg.pattern_font_size;
+    if ui.button("-", size.is_some_and(|i| i > 0), Info::FontSize("Decrease pattern")) {
+        cfg.pattern_font_size = size.map(|i| i - 1);
+        load_font(cfg, ui);
+    }
+    if ui.button("+", size.is_some_and(|i| i < text::FONT_BYTES.len() - 1),
+        Info::FontSize("Increase pattern")) {
+        cfg.pattern_font_size = size.map(|i| i + 1);
+        load_font(cfg, ui);
+    }
+    let mut same = size.is_none();
+    if ui.checkbox("Same as interface", &mut same, true, Info::PatternFont) {
+        cfg.pattern_font_size = (!same).then_some(cfg.font_size);
+        load_font(cfg, ui);
     }
     ui.end_group();
 }
@@ -169,11 +448,49 @@ fn color_controls(ui: &mut Ui, label: &str, accent: bool,
     get_lchuv: impl Fn(&mut Theme) -> &mut Lchuv) {
     ui.start_group();
     ui.label(label, Info::None);
+    seed_controls(ui, label, accent, get_lchuv);
+    ui.end_group();
+}
 
-    let lchuv = get_lchuv(&mut ui.style.theme);
+/// Controls for a pattern color role, which can be unset to use the color
+/// derived from the theme.
+fn role_controls(ui: &mut Ui, label: &str,
+    get_role: impl Fn(&mut Theme) -> &mut Option<Lchuv>,
+    derived: impl Fn(&Theme) -> Color) {
+    ui.start_group();
+    let mut enabled = get_role(&mut ui.style.theme).is_some();
+    if ui.checkbox(label, &mut enabled, true, Info::ColorRole) {
+        let seed = ui.style.theme.lchuv_from_color(derived(&ui.style.theme));
+        *get_role(&mut ui.style.theme) = enabled.then_some(seed);
+    }
+    if enabled {
+        seed_controls(ui, label, false, |t| get_role(t).get_or_insert(Lchuv::new(50.0, 0.0, 0.0)));
+    }
+    ui.end_group();
+}
+
+/// Color table, hex entry, and sliders for a seed color.
+fn seed_controls(ui: &mut Ui, label: &str, accent: bool,
+    get_lchuv: impl Fn(&mut Theme) -> &mut Lchuv) {
+    let lchuv = *get_lchuv(&mut ui.style.theme);
     let (mut l, mut chroma, _) = lchuv.into_components();
     let mut hue = lchuv.hue.into_degrees();
 
+    ui.start_group();
+    ui.color_table(vec![ui.style.theme.color_from_lchuv(lchuv)]);
+    if let Some(s) = ui.edit_box(&format!("{label} RGB"), 7,
+        ui.style.theme.hex_string(lchuv), Info::HexColor
+    ) {
+        match ui.style.theme.parse_hex(&s) {
+            Some(c) => {
+                let seed = get_lchuv(&mut ui.style.theme);
+                *seed = Lchuv { l: if accent { seed.l } else { c.l }, ..c };
+            }
+            None => ui.report("Could not parse color"),
+        }
+    }
+    ui.end_group();
+
     if !accent {
         if ui.formatted_slider(&format!("{}_l", label), "Lightness", &mut l,
             0.0..=100.0, 1, true, Info::None, |f| format!("{f:.1}"), |f| f) {
@@ -188,15 +505,45 @@ fn color_controls(ui: &mut Ui, label: &str, accent: bool,
         -180.0..=180.0, 1, true, Info::None, |f| format!("{f:.1} degrees"), |f| f) {
         get_lchuv(&mut ui.style.theme).hue = hue.into();
     }
+}
 
-    ui.end_group();
+fn mouse_controls(ui: &mut Ui, cfg: &mut Config) {
+    ui.header("MOUSE", Info::None);
+
+    let mouse = &mut cfg.mouse;
+    ui.checkbox("Middle-drag pans pattern", &mut mouse.middle_drag_pan, true,
+        Info::MiddleDragPan);
+    ui.checkbox("Wheel adjusts sliders", &mut mouse.wheel_adjusts_sliders, true,
+        Info::WheelAdjustsSliders);
+    if let Some(i) = ui.combo_box("double_click", "Double-click in pattern",
+        &mouse.double_click.to_string(), Info::DoubleClick,
+        || DoubleClickAction::VARIANTS.map(|x| x.to_string()).to_vec()
+    ) {
+        mouse.double_click = DoubleClickAction::VARIANTS[i];
+    }
+    ui.formatted_slider("scroll_lines", "Scroll speed", &mut mouse.scroll_lines,
+        0.5..=10.0, 1, true, Info::ScrollSpeed,
+        |f| format!("{f:.1} lines"), |f| f);
 }
 
 fn hotkey_controls(ui: &mut Ui, cfg: &mut Config) -> usize {
     ui.header("KEY COMMANDS", Info::None);
-    ui.start_group();
+
+    if let Some(i) = ui.combo_box("key_profile", "Profile", cfg.key_profile.name(),
+        Info::KeyProfile,
+        || KeyProfile::VARIANTS.map(|x| x.name().to_owned()).to_vec()
+    ) {
+        cfg.set_key_profile(KeyProfile::VARIANTS[i]);
+    }
 
     let mut id = 0;
+    ui.start_group();
+    ui.hotkey_input(id, &mut cfg.layer_key, false, Info::LayerKey);
+    id += 1;
+    ui.offset_label("Layer key", Info::LayerKey);
+    ui.end_group();
+
+    ui.start_group();
     let mut keymap: Vec<&mut _> = cfg.iter_keymap().collect();
 
     // column heuristric
@@ -207,7 +554,7 @@ fn hotkey_controls(ui: &mut Ui, cfg: &mut Config) -> usize {
         ui.start_group();
         for (hotkey, action) in chunk.iter_mut() {
             ui.start_group();
-            ui.hotkey_input(id, hotkey, Info::Action(*action));
+            ui.hotkey_input(id, hotkey, true, Info::Action(*action));
             id += 1;
             ui.offset_label(action.name(), Info::Action(*action));
             ui.end_group();
@@ -227,7 +574,7 @@ fn note_key_controls(ui: &mut Ui, cfg: &mut Config, hotkey_input_id: usize) {
     for range in [17..cfg.note_keys.len(), 0..17] {
         ui.start_group();
         for (hotkey, note) in &mut cfg.note_keys[range] {
-            ui.hotkey_input(hotkey_input_id, hotkey, Info::None);
+            ui.hotkey_input(hotkey_input_id, hotkey, false, Info::None);
             hotkey_input_id += 1;
             ui.offset_label(&note.to_string(), Info::None);
         }
@@ -246,11 +593,49 @@ fn entries_per_col(ui: &Ui, max_chars: usize, len: usize) -> usize {
 
 /// Change the current font size.
 fn set_font(cfg: &mut Config, ui: &mut Ui, size: usize) {
-    if let Some(bytes) = text::FONT_BYTES.get(size) {
-        let atlas = GlyphAtlas::from_bdf_bytes(bytes).unwrap();
-        ui.style.margin = atlas.max_height() - atlas.cap_height();
-        ui.style.atlas = atlas;
+    if size < text::FONT_BYTES.len() {
         cfg.font_size = size;
+        load_font(cfg, ui);
+    }
+}
+
+/// Rebuild the glyph atlas from font settings. The built-in font is used as
+/// a fallback for characters missing from a loaded font.
+pub fn load_font(cfg: &mut Config, ui: &mut Ui) {
+    let bytes = text::FONT_BYTES.get(cfg.font_size).unwrap_or(&text::FONT_BYTES[0]);
+    let mut atlas = GlyphAtlas::from_bdf_bytes(bytes)
+        .expect("included font should be loadable");
+
+    if let Some(path) = &cfg.font_path {
+        match std::fs::read(path).map_err(|e| e.into())
+            .and_then(|bytes| GlyphAtlas::from_ttf_bytes(&bytes, cfg.ttf_size, &atlas)) {
+            Ok(ttf) => atlas = ttf,
+            Err(e) => {
+                ui.report(format!("Error loading font: {e}"));
+                cfg.font_path = None;
+            }
+        }
+    }
+
+    ui.style.margin = atlas.max_height() - atlas.cap_height();
+    ui.style.atlas = atlas;
+    ui.style.pattern_atlas = cfg.pattern_font_size
+        .and_then(|i| text::FONT_BYTES.get(i))
+        .map(|bytes| GlyphAtlas::from_bdf_bytes(bytes)
+            .expect("included font should be loadable"));
+}
+
+/// Browse for and load a TrueType or OpenType font.
+fn browse_font(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell) {
+    let dir = cfg.font_path.as_deref()
+        .and_then(|p| config::dir_as_string(Path::new(p)))
+        .unwrap_or(String::from("."));
+    if let Some(path) = super::new_file_dialog(player)
+        .add_filter("Font", &["ttf", "otf"])
+        .set_directory(dir)
+        .pick_file() {
+        cfg.font_path = path.to_str().map(|s| s.to_owned());
+        load_font(cfg, ui);
     }
 }
 
@@ -278,16 +663,103 @@ fn load_theme(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell) {
         .set_directory(cfg.theme_folder.clone().unwrap_or(String::from(".")))
         .pick_file() {
         cfg.theme_folder = config::dir_as_string(&path);
-        match Theme::load(path) {
-            Ok(t) => ui.style.theme = Theme {
-                gamma: ui.style.theme.gamma,
-                ..t
-            },
-            Err(e) => ui.report(format!("Error loading theme: {e}")),
+        apply_theme_file(ui, path);
+    }
+}
+
+/// Load a theme from disk, keeping the current gamma and accessibility
+/// settings.
+fn apply_theme_file(ui: &mut Ui, path: PathBuf) {
+    match Theme::load(path) {
+        Ok(t) => ui.style.theme = ui.style.theme.with_colors_of(t),
+        Err(e) => ui.report(format!("Error loading theme: {e}")),
+    }
+}
+
+/// Returns the theme files in the last-used theme folder, or in the "themes"
+/// folder next to the executable.
+fn theme_files(cfg: &Config) -> Vec<PathBuf> {
+    let dir = cfg.theme_folder.as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| exe_relative_path("themes"));
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .map(|entries| entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == THEME_FILTER_EXT))
+            .collect())
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
+
+const SETTINGS_FILTER_NAME: &str = "Osctet settings";
+const SETTINGS_FILTER_EXT: &str = "toml";
+
+/// Browse and export settings to disk.
+fn export_settings(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell) {
+    if let Some(mut path) = super::new_file_dialog(player)
+        .add_filter(SETTINGS_FILTER_NAME, &[SETTINGS_FILTER_EXT])
+        .set_directory(cfg.settings_folder.clone().unwrap_or(String::from(".")))
+        .save_file() {
+        path.set_extension(SETTINGS_FILTER_EXT);
+        cfg.settings_folder = config::dir_as_string(&path);
+        if let Err(e) = cfg.export(ui.style.theme.clone(), &path) {
+            ui.report(format!("Error exporting settings: {e}"));
         }
     }
 }
 
+/// Browse and import settings from disk.
+fn import_settings(ui: &mut Ui, cfg: &mut Config, player: &mut PlayerShell) {
+    if let Some(path) = super::new_file_dialog(player)
+        .add_filter(SETTINGS_FILTER_NAME, &[SETTINGS_FILTER_EXT])
+        .set_directory(cfg.settings_folder.clone().unwrap_or(String::from(".")))
+        .pick_file() {
+        cfg.settings_folder = config::dir_as_string(&path);
+        match cfg.import(&path) {
+            Ok(()) => {
+                if let Some(theme) = &cfg.theme {
+                    ui.style.theme = theme.clone();
+                }
+                load_font(cfg, ui);
+                player.set_voice_settings(cfg.voices);
+            }
+            Err(e) => ui.report(format!("Error importing settings: {e}")),
+        }
+    }
+}
+
+fn surface_controls(ui: &mut Ui, cfg: &mut Config, surface: &mut ControlSurface) {
+    ui.start_group();
+
+    if let Some(i) = ui.combo_box("surface_layout", "Control surface",
+        &surface.layout().to_string(), Info::SurfaceLayout,
+        || SurfaceLayout::VARIANTS.map(|x| x.to_string()).to_vec()
+    ) {
+        surface.set_layout(SurfaceLayout::VARIANTS[i]);
+        cfg.surface_layout = surface.layout();
+    }
+
+    if surface.has_output() && surface.layout() != SurfaceLayout::None {
+        let s = surface.port_name().unwrap_or("(none)").to_owned();
+        if let Some(i) = ui.combo_box("surface_output", "LED output", &s,
+            Info::SurfaceOutput, || surface.output_names()) {
+            let name = if i == 0 {
+                None
+            } else {
+                surface.output_names().get(i).cloned()
+            };
+            cfg.surface_output = None;
+            match surface.connect(name) {
+                Ok(()) => cfg.surface_output = surface.port_name().map(String::from),
+                Err(e) => ui.report(format!("MIDI connection failed: {e}")),
+            }
+        }
+    }
+
+    ui.end_group();
+}
+
 /// Return the names of MIDI input options.
 fn input_names(input: &midir::MidiInput) -> Vec<String> {
     let mut v = vec![String::from("(none)")];
@@ -1,9 +1,11 @@
 //! Code for drawing text using bitmap fonts.
 
-use std::{collections::HashMap, io::BufReader};
+use std::{collections::HashMap, error::Error, io::BufReader};
 
 use bdf_reader::{Bitmap, Font};
-use macroquad::{color::Color, math::Rect, texture::{build_textures_atlas, draw_texture, Texture2D}};
+use fontdue::FontSettings;
+use unicode_segmentation::UnicodeSegmentation;
+use macroquad::{color::Color, math::Rect, texture::{build_textures_atlas, draw_texture, FilterMode, Texture2D}};
 
 // character codes -- these are invalid as character literals,
 // so we use u32 and convert.
@@ -37,6 +39,16 @@ pub const FONT_BYTES: [&[u8]; 4] = [
     include_bytes!("../../font/DinaMedium-13.bdf"),
 ];
 
+/// Minimum size of a TrueType/OpenType font, in pixels.
+pub const MIN_TTF_SIZE: f32 = 6.0;
+
+/// Maximum size of a TrueType/OpenType font, in pixels.
+pub const MAX_TTF_SIZE: f32 = 48.0;
+
+/// Upper bound on glyphs rasterized from a TrueType/OpenType font, to keep
+/// load times reasonable for fonts with large CJK sets.
+const MAX_TTF_GLYPHS: usize = 8192;
+
 /// Returns the character code for a superscript digit.
 pub fn digit_superscript(digit: u8) -> char {
     char::from_u32(match digit {
@@ -51,14 +63,22 @@ pub fn digit_superscript(digit: u8) -> char {
     }).expect("code point constants should be valid")
 }
 
+/// A character's texture and its position relative to the pen.
+#[derive(Clone)]
+struct Glyph {
+    texture: Texture2D,
+    left: f32,
+    /// Offset of the top of the texture from the baseline.
+    top: f32,
+}
+
 /// Maps characters to GPU textures.
 pub struct GlyphAtlas {
-    map: HashMap<char, Texture2D>,
+    map: HashMap<char, Glyph>,
     width: f32,
     height: f32,
     cap_height: f32,
     offset_y: f32,
-    font: Font,
 }
 
 impl GlyphAtlas {
@@ -79,8 +99,13 @@ impl GlyphAtlas {
             let texture = texture_from_bitmap(glyph.bitmap());
             height = height.max(texture.height());
             width = width.max(texture.width());
+            let bbox = glyph.bounding_box();
             match char::from_u32(glyph.encoding()) {
-                Some(c) => { map.insert(c, texture); }
+                Some(c) => { map.insert(c, Glyph {
+                    texture,
+                    left: bbox.offset_x as f32,
+                    top: -(bbox.offset_y as f32 + bbox.height as f32),
+                }); }
                 None => eprintln!("invalid char encoding: {}", glyph.encoding())
             }
         }
@@ -95,32 +120,78 @@ impl GlyphAtlas {
             (height, 0.0)
         };
 
-        Self { map, width, height, cap_height, offset_y, font }
+        Self { map, width, height, cap_height, offset_y }
     }
 
-    /// Draws `text` horizontally without wrapping. Returns the drawn area.
+    /// Creates a new atlas from the bytes of a TrueType or OpenType font,
+    /// rasterized at `size` pixels. Characters the font lacks, such as the
+    /// accidental codes, are taken from `fallback`.
+    pub fn from_ttf_bytes(bytes: &[u8], size: f32, fallback: &GlyphAtlas
+    ) -> Result<Self, Box<dyn Error>> {
+        let font = fontdue::Font::from_bytes(bytes, FontSettings::default())?;
+        let mut map = HashMap::new();
+        let mut height = 0.0_f32;
+
+        let mut chars: Vec<char> = font.chars().keys().copied().collect();
+        chars.sort();
+        for c in chars.into_iter().take(MAX_TTF_GLYPHS) {
+            let (metrics, coverage) = font.rasterize(c, size);
+            let texture = if metrics.width == 0 || metrics.height == 0 {
+                Texture2D::from_rgba8(1, 1, &[0, 0, 0, 0])
+            } else {
+                let rgba: Vec<u8> = coverage.into_iter()
+                    .flat_map(|a| [255, 255, 255, a])
+                    .collect();
+                Texture2D::from_rgba8(metrics.width as u16, metrics.height as u16, &rgba)
+            };
+            texture.set_filter(FilterMode::Nearest);
+            if c.is_ascii_graphic() {
+                height = height.max(metrics.height as f32);
+            }
+            map.insert(c, Glyph {
+                texture,
+                left: metrics.xmin as f32,
+                top: -(metrics.ymin as f32 + metrics.height as f32),
+            });
+        }
+
+        for (c, glyph) in &fallback.map {
+            map.entry(*c).or_insert_with(|| glyph.clone());
+        }
+
+        build_textures_atlas();
+
+        let cap_height = font.metrics('X', size).height as f32;
+        let width = font.metrics('0', size).advance_width.ceil();
+
+        Ok(Self { map, width, height: height.max(cap_height), cap_height, offset_y: 0.0 })
+    }
+
+    /// Draws `text` horizontally without wrapping. Each grapheme cluster
+    /// occupies one cell. Returns the drawn area.
     pub fn draw_text(&self, x: f32, y: f32, text: &str, color: Color) -> Rect {
         // round coordinates; bitmap fonts should be pixel-aligned
         let initial_x = x.round();
         let y = y.round() + self.offset_y;
 
+        let baseline = y + self.cap_height;
+
         let mut x = initial_x;
 
-        for char in text.chars() {
-            let char = if self.map.contains_key(&char) {
-                char
-            } else {
-                '?'
-            };
-            if let Some(texture) = self.map.get(&char) {
-                if let Some(glyph) = self.font.glyph(char) {
-                    let bbox = glyph.bounding_box();
-                    draw_texture(texture, x + bbox.offset_x as f32,
-                        y - bbox.offset_y as f32 + self.cap_height - bbox.height as f32,
-                        color);
-                    x += self.width;
+        for cluster in text.graphemes(true) {
+            for (i, char) in cluster.chars().enumerate() {
+                // only substitute for the base character; unknown combining
+                // marks are dropped
+                let glyph = match self.map.get(&char) {
+                    Some(glyph) => Some(glyph),
+                    None if i == 0 => self.map.get(&'?'),
+                    None => None,
+                };
+                if let Some(glyph) = glyph {
+                    draw_texture(&glyph.texture, x + glyph.left, baseline + glyph.top, color);
                 }
             }
+            x += self.width;
         }
 
         Rect {
@@ -148,7 +219,7 @@ impl GlyphAtlas {
 
     /// Returns the width of a string.
     pub fn text_width(&self, text: &str) -> f32 {
-        self.width * text.chars().count() as f32
+        self.width * text.graphemes(true).count() as f32
     }
 }
 
@@ -167,7 +238,10 @@ fn texture_from_bitmap(bitmap: Bitmap) -> Texture2D {
         }
     }
 
-    Texture2D::from_rgba8(bitmap.width() as u16, bitmap.height() as u16, &rgba)
+    let texture = Texture2D::from_rgba8(bitmap.width() as u16, bitmap.height() as u16, &rgba);
+    // keep glyphs sharp when the UI is scaled
+    texture.set_filter(FilterMode::Nearest);
+    texture
 }
 
 /// Returns the number of non-blank rows in a bitmap.
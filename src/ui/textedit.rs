@@ -1,6 +1,8 @@
-use macroquad::input::*;
+use macroquad::{input::*, miniquad::window::{clipboard_get, clipboard_set}};
+use unicode_segmentation::UnicodeSegmentation;
 
-/// UI state of a text field.
+/// UI state of a text field. Positions are in grapheme clusters, which
+/// correspond to character cells on screen.
 pub struct TextEditState {
     /// Allows determining which control this state belongs to.
     pub id: String,
@@ -16,7 +18,7 @@ impl TextEditState {
         Self {
             id,
             anchor: 0,
-            cursor: text.chars().count(), // start with entire text selected
+            cursor: text.graphemes(true).count(), // start with entire text selected
             text,
         }
     }
@@ -37,7 +39,7 @@ impl TextEditState {
         }
 
         while let Some(c) = get_char_pressed() {
-            if !c.is_ascii_control() {
+            if !c.is_control() {
                 self.insert(&c.to_string(), max_width);
             }
         }
@@ -46,12 +48,17 @@ impl TextEditState {
             if is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl) {
                 match key {
                     KeyCode::X => {
-                        *clipboard = Some(self.selected_text().to_owned());
+                        self.copy(clipboard);
                         self.delete(0);
                     }
-                    KeyCode::C => *clipboard = Some(self.selected_text().to_owned()),
-                    KeyCode::V => if let Some(s) = clipboard {
-                        self.insert(s, max_width)
+                    KeyCode::C => self.copy(clipboard),
+                    KeyCode::V => {
+                        // prefer the system clipboard, which may hold text
+                        // from other programs
+                        if let Some(s) = clipboard_get().or_else(|| clipboard.clone()) {
+                            let s: String = s.chars().filter(|c| !c.is_control()).collect();
+                            self.insert(&s, max_width);
+                        }
                     }
                     _ => (),
                 }
@@ -79,9 +86,24 @@ impl TextEditState {
         self.anchor = self.anchor.min(self.len());
     }
 
-    /// Returns the number of characters in the text buffer.
+    /// Returns the number of grapheme clusters in the text buffer.
     pub fn len(&self) -> usize {
-        self.text.chars().count()
+        self.text.graphemes(true).count()
+    }
+
+    /// Returns the byte index of a grapheme position.
+    fn byte_index(&self, pos: usize) -> usize {
+        self.text.grapheme_indices(true)
+            .nth(pos)
+            .map(|(i, _)| i)
+            .unwrap_or(self.text.len())
+    }
+
+    /// Copy selected text to the internal and system clipboards.
+    fn copy(&self, clipboard: &mut Option<String>) {
+        let s = self.selected_text().to_owned();
+        clipboard_set(&s);
+        *clipboard = Some(s);
     }
 
     /// Sets the mouse cursor to the given position, updating anchor as needed.
@@ -100,18 +122,17 @@ impl TextEditState {
         }
         let s = {
             let n = self.len();
-            if n + s.chars().count() > max_width {
-                &s.chars().take(max_width - n).collect::<String>()
+            if n + s.graphemes(true).count() > max_width {
+                &s.graphemes(true).take(max_width.saturating_sub(n)).collect::<String>()
             } else {
                 s
             }
         };
-        let index = self.text.char_indices()
-            .nth(self.cursor)
-            .map(|(i, _)| i)
-            .unwrap_or(self.text.len());
+        let index = self.byte_index(self.cursor);
         self.text.insert_str(index, s);
-        self.cursor += s.chars().count();
+        // inserted combining marks may merge with existing clusters, so count
+        // from the start
+        self.cursor = self.text[..index + s.len()].graphemes(true).count();
         self.anchor = self.cursor;
     }
 
@@ -123,16 +144,8 @@ impl TextEditState {
         }
 
         let (start, end) = self.selection_bounds();
-
-        self.text = self.text.chars()
-            .enumerate()
-            .filter_map(|(i, c)| {
-                if i < start || i >= end {
-                    Some(c)
-                } else {
-                    None
-                }
-            }).collect();
+        let range = self.byte_index(start)..self.byte_index(end);
+        self.text.replace_range(range, "");
 
         self.cursor = start;
         self.anchor = start;
@@ -141,16 +154,7 @@ impl TextEditState {
     /// Returns the selected text.
     fn selected_text(&self) -> &str {
         let (start, end) = self.selection_bounds();
-
-        if let Some((start, _)) = self.text.char_indices().nth(start) {
-            if let Some((end, _)) = self.text.char_indices().nth(end) {
-                &self.text[start..end]
-            } else {
-                &self.text[start..]
-            }
-        } else {
-            ""
-        }
+        &self.text[self.byte_index(start)..self.byte_index(end)]
     }
 
     /// Returns the start and end of the selection.
@@ -176,4 +180,19 @@ mod tests {
         state.anchor = 1;
         assert_eq!(state.selected_text(), "ello");
     }
+
+    #[test]
+    fn test_graphemes() {
+        // "e" followed by a combining acute accent is one cell
+        let mut state = TextEditState::new(String::from(""), String::from("ce\u{301}ß"));
+        assert_eq!(state.len(), 3);
+        state.anchor = 1;
+        state.cursor = 2;
+        assert_eq!(state.selected_text(), "e\u{301}");
+        state.delete(0);
+        assert_eq!(state.text, "cß");
+        state.insert("日本", 3);
+        assert_eq!(state.text, "c日ß");
+        assert_eq!(state.cursor, 2);
+    }
 }
@@ -20,6 +20,25 @@ const ACCENT_L_OFFSET: f32 = 15.0;
 
 const ACCENT_BG_CHROMA_MULTIPLIER: f32 = 1.0/3.0;
 
+/// Minimum contrast ratio for text, per WCAG AA.
+const MIN_CONTRAST: f32 = 4.5;
+
+/// Returns the WCAG contrast ratio of two colors, from 1 to 21.
+pub fn contrast_ratio(a: Color, b: Color) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Returns the WCAG relative luminance of a color.
+fn relative_luminance(c: Color) -> f32 {
+    let linear = |x: f32| if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    };
+    0.2126 * linear(c.r) + 0.7152 * linear(c.g) + 0.0722 * linear(c.b)
+}
+
 /// Color theme using four seed colors. Seed colors use the CIE L*C*uv h°uv
 /// color space, which is a cylindrical version of the "perceptually uniform"
 /// CIE L*u*v* color space. Lightness values for `accent1` and `accent2` are
@@ -31,6 +50,23 @@ pub struct Theme {
     pub accent1: Lchuv,
     pub accent2: Lchuv,
     pub gamma: f32,
+    /// Colors for pattern data, kept when the theme is reset or loaded.
+    #[serde(default)]
+    pub roles: ColorRoles,
+    /// Use black and white for foreground and background, kept when the
+    /// theme is reset or loaded.
+    #[serde(default)]
+    pub high_contrast: bool,
+}
+
+/// Colors for kinds of pattern data. Roles that are None use colors derived
+/// from the theme.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ColorRoles {
+    pub note: Option<Lchuv>,
+    pub pressure: Option<Lchuv>,
+    pub modulation: Option<Lchuv>,
+    pub interpolation: Option<Lchuv>,
 }
 
 impl Theme {
@@ -42,6 +78,8 @@ impl Theme {
             accent1: Lchuv::new(50.0, DEFAULT_ACCENT_CHROMA, DEFAULT_ACCENT1_HUE),
             accent2: Lchuv::new(50.0, DEFAULT_ACCENT_CHROMA, DEFAULT_ACCENT2_HUE),
             gamma,
+            roles: Default::default(),
+            high_contrast: false,
         }
     }
 
@@ -53,6 +91,18 @@ impl Theme {
             accent1: Lchuv::new(50.0, DEFAULT_ACCENT_CHROMA, DEFAULT_ACCENT1_HUE),
             accent2: Lchuv::new(50.0, DEFAULT_ACCENT_CHROMA, DEFAULT_ACCENT2_HUE),
             gamma,
+            roles: Default::default(),
+            high_contrast: false,
+        }
+    }
+
+    /// Returns `self` with the seed colors of `other`.
+    pub fn with_colors_of(&self, other: Theme) -> Theme {
+        Theme {
+            gamma: self.gamma,
+            roles: self.roles.clone(),
+            high_contrast: self.high_contrast,
+            ..other
         }
     }
 
@@ -73,34 +123,97 @@ impl Theme {
         self.bg.l >= 50.0
     }
 
+    /// Returns the foreground seed, at full contrast in high-contrast mode.
+    fn fg_seed(&self) -> Lchuv {
+        match (self.high_contrast, self.is_light()) {
+            (false, _) => self.fg,
+            (true, light) => Lchuv::new(if light { 0.0 } else { 100.0 }, 0.0, 0.0),
+        }
+    }
+
+    /// Returns the background seed, at full contrast in high-contrast mode.
+    fn bg_seed(&self) -> Lchuv {
+        match (self.high_contrast, self.is_light()) {
+            (false, _) => self.bg,
+            (true, light) => Lchuv::new(if light { 100.0 } else { 0.0 }, 0.0, 0.0),
+        }
+    }
+
+    /// Returns the color of a role, or the fallback if the role is unset.
+    fn role_color(&self, role: Option<Lchuv>, fallback: Color) -> Color {
+        role.map(|c| self.color_from_lchuv(c)).unwrap_or(fallback)
+    }
+
+    pub fn note_fg(&self) -> Color {
+        self.role_color(self.roles.note, self.fg())
+    }
+
+    pub fn pressure_fg(&self) -> Color {
+        self.role_color(self.roles.pressure, self.accent1_fg())
+    }
+
+    pub fn modulation_fg(&self) -> Color {
+        self.role_color(self.roles.modulation, self.accent2_fg())
+    }
+
+    /// Returns the color of interpolation lines in a column. Unless the role
+    /// is set, this matches the column's data.
+    pub fn interpolation_fg(&self, column: usize) -> Color {
+        let fallback = match column {
+            0 => self.note_fg(),
+            1 => self.pressure_fg(),
+            _ => self.modulation_fg(),
+        };
+        self.role_color(self.roles.interpolation, fallback)
+    }
+
+    /// Returns the names of text colors with less than `MIN_CONTRAST`
+    /// against the pattern background.
+    pub fn low_contrast_colors(&self) -> Vec<&'static str> {
+        let bg = self.content_bg();
+        let roles = &self.roles;
+        [
+            ("Foreground", Some(self.fg())),
+            ("Accent 1", Some(self.accent1_fg())),
+            ("Accent 2", Some(self.accent2_fg())),
+            ("Note", roles.note.map(|_| self.note_fg())),
+            ("Pressure", roles.pressure.map(|_| self.pressure_fg())),
+            ("Modulation", roles.modulation.map(|_| self.modulation_fg())),
+            ("Interpolation", roles.interpolation.map(|_| self.interpolation_fg(0))),
+        ].into_iter()
+            .filter(|(_, c)| c.is_some_and(|c| contrast_ratio(c, bg) < MIN_CONTRAST))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
     pub fn fg(&self) -> Color {
-        self.color_from_lchuv(self.fg)
+        self.color_from_lchuv(self.fg_seed())
     }
 
     pub fn accent1_bg(&self) -> Color {
         let sign = if self.is_light() { -1.0 } else { 1.0 };
-        let c = Lchuv::new(self.bg.l + sign * ACCENT_L_OFFSET,
+        let c = Lchuv::new(self.bg_seed().l + sign * ACCENT_L_OFFSET,
             self.accent1.chroma * ACCENT_BG_CHROMA_MULTIPLIER, self.accent1.hue);
         self.color_from_lchuv(c)
     }
 
     pub fn accent1_fg(&self) -> Color {
         let sign = if self.is_light() { -1.0 } else { 1.0 };
-        let c = Lchuv::new(self.fg.l - sign * ACCENT_L_OFFSET,
+        let c = Lchuv::new(self.fg_seed().l - sign * ACCENT_L_OFFSET,
             self.accent1.chroma, self.accent1.hue);
         self.color_from_lchuv(c)
     }
 
     pub fn accent2_bg(&self) -> Color {
         let sign = if self.is_light() { -1.0 } else { 1.0 };
-        let c = Lchuv::new(self.bg.l + sign * ACCENT_L_OFFSET,
+        let c = Lchuv::new(self.bg_seed().l + sign * ACCENT_L_OFFSET,
             self.accent2.chroma * ACCENT_BG_CHROMA_MULTIPLIER, self.accent2.hue);
         self.color_from_lchuv(c)
     }
 
     pub fn accent2_fg(&self) -> Color {
         let sign = if self.is_light() { -1.0 } else { 1.0 };
-        let c = Lchuv::new(self.fg.l - sign * ACCENT_L_OFFSET,
+        let c = Lchuv::new(self.fg_seed().l - sign * ACCENT_L_OFFSET,
             self.accent2.chroma, self.accent2.hue);
         self.color_from_lchuv(c)
     }
@@ -108,12 +221,12 @@ impl Theme {
     /// Returns background color plus a lightness offset (magnitude only).
     fn bg_plus(&self, offset: f32) -> Color {
         let sign = if self.is_light() { -1.0 } else { 1.0 };
-        let bg = Lchuv::new(self.bg.l + sign * offset, self.bg.chroma, self.bg.hue);
-        self.color_from_lchuv(bg)
+        let bg = self.bg_seed();
+        self.color_from_lchuv(Lchuv { l: bg.l + sign * offset, ..bg })
     }
 
     pub fn content_bg(&self) -> Color {
-        self.color_from_lchuv(self.bg)
+        self.color_from_lchuv(self.bg_seed())
     }
 
     pub fn content_bg_hover(&self) -> Color {
@@ -150,10 +263,8 @@ impl Theme {
 
     pub fn border_unfocused(&self) -> Color {
         // take average of foreground and background
-        let c = Lchuv::new(
-            (self.bg.l + self.fg.l) * 0.5,
-            (self.bg.chroma + self.fg.chroma) * 0.5,
-            self.bg.hue);
+        let (fg, bg) = (self.fg_seed(), self.bg_seed());
+        let c = Lchuv::new((bg.l + fg.l) * 0.5, (bg.chroma + fg.chroma) * 0.5, bg.hue);
         self.color_from_lchuv(c)
     }
 
@@ -166,13 +277,47 @@ impl Theme {
     }
 
     /// Convert LCH to RGB.
-    fn color_from_lchuv(&self, lchuv: Lchuv) -> Color {
-        let lchuv = Lchuv {
+    pub fn color_from_lchuv(&self, lchuv: Lchuv) -> Color {
+        let rgb = Srgb::from_color(self.gamma_corrected(lchuv));
+        Color::new(rgb.red, rgb.green, rgb.blue, 1.0)
+    }
+
+    /// Apply gamma to the lightness of a color.
+    fn gamma_corrected(&self, lchuv: Lchuv) -> Lchuv {
+        Lchuv {
             l: (lchuv.l * 0.01).powf(1.0/self.gamma) * 100.0,
             ..lchuv
-        };
-        let rgb = Srgb::from_color(lchuv);
-        Color::new(rgb.red, rgb.green, rgb.blue, 1.0)
+        }
+    }
+
+    /// Returns a seed color as an RGB hex string.
+    pub fn hex_string(&self, lchuv: Lchuv) -> String {
+        let rgb: Srgb<u8> = Srgb::from_color(self.gamma_corrected(lchuv)).into_format();
+        format!("{:02x}{:02x}{:02x}", rgb.red, rgb.green, rgb.blue)
+    }
+
+    /// Parse an RGB hex string into a seed color. A leading '#' is optional.
+    pub fn parse_hex(&self, s: &str) -> Option<Lchuv> {
+        let s = s.trim().trim_start_matches('#');
+        if s.len() != 6 {
+            return None
+        }
+        let n = u32::from_str_radix(s, 16).ok()?;
+        let rgb = Srgb::new((n >> 16) as u8, (n >> 8) as u8, n as u8).into_format::<f32>();
+        Some(self.lchuv_from_srgb(rgb))
+    }
+
+    /// Convert RGB to a seed color. Inverse of `color_from_lchuv`.
+    pub fn lchuv_from_color(&self, color: Color) -> Lchuv {
+        self.lchuv_from_srgb(Srgb::new(color.r, color.g, color.b))
+    }
+
+    fn lchuv_from_srgb(&self, rgb: Srgb) -> Lchuv {
+        let lchuv = Lchuv::from_color(rgb);
+        Lchuv {
+            l: (lchuv.l * 0.01).max(0.0).powf(self.gamma) * 100.0,
+            ..lchuv
+        }
     }
 
     /// Return a table representing the colors of the theme. Does not contain
@@ -195,4 +340,18 @@ impl Default for Theme {
     fn default() -> Self {
         Self::light(1.8)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::color::{BLACK, WHITE};
+
+    #[test]
+    fn test_contrast_ratio() {
+        assert!((contrast_ratio(BLACK, WHITE) - 21.0).abs() < 0.01);
+        assert_eq!(contrast_ratio(WHITE, WHITE), 1.0);
+        assert!(Theme { high_contrast: true, ..Theme::dark(1.8) }
+            .low_contrast_colors().is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! Touch gestures. Single touches already act as the left mouse button, so
+//! this only handles gestures that the mouse can't express.
+
+use macroquad::prelude::*;
+
+/// Seconds a touch must be held in place to count as a long press.
+const LONG_PRESS_TIME: f64 = 0.5;
+
+/// Distance a touch can move and still count as held in place, in pixels.
+const TOUCH_SLOP: f32 = 10.0;
+
+/// Ratio of finger spread that counts as one zoom step.
+const ZOOM_STEP_RATIO: f32 = 1.5;
+
+/// A single touch that may become a long press.
+struct Press {
+    id: u64,
+    position: Vec2,
+    time: f64,
+    /// True once the press has been held long enough.
+    long: bool,
+}
+
+/// Tracks touch gestures between frames.
+#[derive(Default)]
+pub struct TouchState {
+    press: Option<Press>,
+    /// True on the frame a long press is recognized.
+    long_press: bool,
+    /// True on the frame a long press is released.
+    long_release: bool,
+    /// Midpoint and spread of two touches as of the last frame.
+    pinch: Option<(Vec2, f32)>,
+    /// Spread ratio accumulated since the last zoom step.
+    zoom: f32,
+    pan: Vec2,
+    zoom_step: i8,
+}
+
+impl TouchState {
+    /// Updates gesture state from this frame's touches.
+    pub fn update(&mut self) {
+        let touches = touches();
+        self.long_press = false;
+        self.long_release = false;
+        self.pan = Vec2::ZERO;
+        self.zoom_step = 0;
+
+        match touches.as_slice() {
+            [t] => match t.phase {
+                TouchPhase::Started => self.press = Some(Press {
+                    id: t.id,
+                    position: t.position,
+                    time: get_time(),
+                    long: false,
+                }),
+                TouchPhase::Moved | TouchPhase::Stationary => {
+                    if let Some(press) = self.press.as_mut().filter(|p| p.id == t.id) {
+                        if press.position.distance(t.position) > TOUCH_SLOP {
+                            self.press = None;
+                        } else if !press.long && get_time() - press.time > LONG_PRESS_TIME {
+                            press.long = true;
+                            self.long_press = true;
+                        }
+                    }
+                }
+                TouchPhase::Ended | TouchPhase::Cancelled => {
+                    self.long_release = self.press.take().is_some_and(|p| p.long);
+                }
+            },
+            _ => self.press = None,
+        }
+
+        let held: Vec<_> = touches.iter()
+            .filter(|t| !matches!(t.phase, TouchPhase::Ended | TouchPhase::Cancelled))
+            .collect();
+        if let [a, b] = held.as_slice() {
+            let mid = (a.position + b.position) * 0.5;
+            let spread = a.position.distance(b.position).max(1.0);
+            if let Some((last_mid, last_spread)) = self.pinch {
+                self.pan = mid - last_mid;
+                self.zoom *= spread / last_spread;
+                if self.zoom > ZOOM_STEP_RATIO {
+                    self.zoom_step = 1;
+                    self.zoom = 1.0;
+                } else if self.zoom < 1.0 / ZOOM_STEP_RATIO {
+                    self.zoom_step = -1;
+                    self.zoom = 1.0;
+                }
+            } else {
+                self.zoom = 1.0;
+            }
+            self.pinch = Some((mid, spread));
+        } else {
+            self.pinch = None;
+        }
+    }
+
+    /// Returns true on the frame a long press is recognized.
+    pub fn long_press(&self) -> bool {
+        self.long_press
+    }
+
+    /// Returns true on the frame a long press is released. The release
+    /// shouldn't count as a click.
+    pub fn long_release(&self) -> bool {
+        self.long_release
+    }
+
+    /// Returns true if a two-finger gesture is in progress.
+    pub fn pinching(&self) -> bool {
+        self.pinch.is_some()
+    }
+
+    /// Returns the two-finger pan this frame, in pixels.
+    pub fn pan(&self) -> Vec2 {
+        self.pan
+    }
+
+    /// Returns 1 if the fingers spread by a zoom step this frame, -1 if they
+    /// pinched by one, or 0.
+    pub fn zoom_step(&self) -> i8 {
+        self.zoom_step
+    }
+}
@@ -0,0 +1,173 @@
+//! Guided tour of the main parts of the UI, using an embedded demo song.
+
+use crate::{config::Config, input::Action, TAB_GENERAL, TAB_INSTRUMENTS, TAB_SETTINGS};
+
+use super::*;
+
+/// Contents of the song loaded by the tutorial.
+pub const DEMO_MODULE: &[u8] =
+    include_bytes!("../../demosongs/portrait of an imagined landscape.osctet");
+
+/// Part of the window highlighted by a tutorial step.
+#[derive(Clone, Copy)]
+enum Region {
+    Tabs,
+    Content,
+    BottomPanel,
+}
+
+struct Step {
+    tab: usize,
+    region: Region,
+    text: &'static str,
+    /// Key commands listed after the text.
+    actions: &'static [Action],
+}
+
+const STEPS: [Step; 7] = [
+    Step {
+        tab: TAB_GENERAL,
+        region: Region::Content,
+        text:
+"This is a demo song. The General tab holds song
+metadata, master effects, tuning, and rendering.",
+        actions: &[],
+    },
+    Step {
+        tab: TAB_PATTERN,
+        region: Region::Content,
+        text:
+"The pattern editor shows each track as columns of
+events, with time running downward. Listen to the
+song, then move the cursor around it.",
+        actions: &[Action::PlayFromStart, Action::PlayFromCursor, Action::NextRow,
+            Action::NextChannel],
+    },
+    Step {
+        tab: TAB_PATTERN,
+        region: Region::Content,
+        text:
+"Type notes with the letter keys, laid out like a
+piano keyboard. Each note continues until the next
+event in its channel.",
+        actions: &[Action::NoteOff, Action::Delete, Action::Undo,
+            Action::ToggleCheatSheet],
+    },
+    Step {
+        tab: TAB_PATTERN,
+        region: Region::BottomPanel,
+        text:
+"The status bar sets the octave of typed notes and
+how far the cursor moves after each one.",
+        actions: &[Action::IncrementOctave, Action::DecrementOctave],
+    },
+    Step {
+        tab: TAB_INSTRUMENTS,
+        region: Region::Content,
+        text:
+"Each track plays a patch. Patches combine
+generators, filters, envelopes, and LFOs, and can
+be auditioned by typing notes here.",
+        actions: &[],
+    },
+    Step {
+        tab: TAB_GENERAL,
+        region: Region::Content,
+        text:
+"When the song is done, choose a range in the
+RENDER section and render it to a WAV file.",
+        actions: &[Action::RenderSong, Action::RenderTracks],
+    },
+    Step {
+        tab: TAB_SETTINGS,
+        region: Region::Tabs,
+        text:
+"Key commands can be changed in the Settings tab.
+Hover over a control to see what it does.",
+        actions: &[Action::NextTab, Action::PrevTab],
+    },
+];
+
+/// State of the guided tour.
+#[derive(Default)]
+pub struct Tutorial {
+    step: usize,
+    /// Step whose text was last shown.
+    shown_step: Option<usize>,
+}
+
+impl Tutorial {
+    /// Switches to the tab of the first step.
+    pub fn start(ui: &mut Ui) -> Self {
+        ui.set_tab(MAIN_TAB_ID, STEPS[0].tab);
+        Self::default()
+    }
+
+    /// Draws step navigation in the bottom panel. Returns false if the tour
+    /// was ended.
+    pub fn controls(&mut self, ui: &mut Ui) -> bool {
+        ui.label(&format!("Tutorial {}/{}", self.step + 1, STEPS.len()), Info::None);
+        let mut step = self.step;
+        if ui.button("Back", step > 0, Info::None) {
+            step -= 1;
+        }
+        let last = step + 1 == STEPS.len();
+        if ui.button(if last { "Finish" } else { "Next" }, true, Info::None) {
+            if last {
+                ui.notify(String::from("Tutorial finished."));
+                return false
+            }
+            step += 1;
+        }
+        if step != self.step {
+            self.step = step;
+            ui.set_tab(MAIN_TAB_ID, STEPS[step].tab);
+        }
+        if ui.button("Exit", true, Info::None) {
+            ui.notify(String::from("Tutorial ended."));
+            return false
+        }
+        true
+    }
+
+    /// Outlines the region of the current step, and shows its text as a
+    /// notification when the step is entered.
+    pub fn draw(&mut self, ui: &mut Ui, cfg: &Config) {
+        let step = &STEPS[self.step];
+        let tab_h = ui.style.line_height() + ui.style.margin;
+        let panel_h = ui.bottom_panel_height();
+        let rect = match step.region {
+            Region::Tabs => Rect::new(0.0, 0.0, screen_width(), tab_h),
+            Region::Content =>
+                Rect::new(0.0, tab_h, screen_width(), screen_height() - tab_h - panel_h),
+            Region::BottomPanel =>
+                Rect::new(0.0, screen_height() - panel_h, screen_width(), panel_h),
+        };
+        ui.cursor_z += PANEL_Z_OFFSET * 2;
+        ui.push_rect(Rect::new(rect.x + 1.0, rect.y + 1.0, rect.w - 2.0, rect.h - 2.0),
+            BLANK, Some(ui.style.theme.accent1_fg()));
+        ui.cursor_z -= PANEL_Z_OFFSET * 2;
+
+        if self.shown_step != Some(self.step) {
+            self.shown_step = Some(self.step);
+            let mut text = step.text.to_owned();
+            for action in step.actions {
+                text.push('\n');
+                text.push_str(&cfg.hotkey_string(*action));
+            }
+            ui.notify(text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::module::Module;
+
+    #[test]
+    fn test_demo_module() {
+        assert!(Module::from_bytes(DEMO_MODULE, Path::new(".")).is_ok());
+    }
+}
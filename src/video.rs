@@ -0,0 +1,279 @@
+//! Offline video export, drawing an oscilloscope for each track in sync with
+//! the rendered song.
+
+use std::{error::Error, fs::File, io::{self, Write}, path::{Path, PathBuf}, process::{ChildStdin, Command, Stdio}, sync::{mpsc::Sender, Arc}, thread};
+
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use fundsp::wave::Wave;
+use macroquad::color::Color;
+
+use crate::{module::Module, playback::{render_to_wave, StatusUpdate}, synth::VoiceSettings};
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const FPS: usize = 30;
+
+/// Length of audio shown in each scope, in seconds.
+const SCOPE_TIME: f64 = 0.025;
+
+/// Space between scopes, in pixels.
+const SCOPE_MARGIN: usize = 8;
+
+/// Share of progress spent rendering audio, with the rest spent on frames.
+const AUDIO_PROGRESS: f64 = 0.5;
+
+/// Where video frames are written.
+pub enum VideoOutput {
+    /// Numbered PNG files next to the path, with the song as a WAV file.
+    PngSequence(PathBuf),
+    /// A video file encoded by an `ffmpeg` executable on the PATH.
+    Ffmpeg(PathBuf),
+}
+
+impl VideoOutput {
+    /// Returns the output type for a path chosen in a file dialog.
+    pub fn from_path(path: PathBuf) -> Self {
+        if path.extension().is_some_and(|s| s == "png") {
+            Self::PngSequence(path)
+        } else {
+            Self::Ffmpeg(path)
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Self::PngSequence(path) | Self::Ffmpeg(path) => path,
+        }
+    }
+}
+
+/// Colors of a video frame.
+#[derive(Clone, Copy)]
+pub struct VideoColors {
+    pub bg: Color,
+    pub fg: Color,
+    pub line: Color,
+}
+
+/// Renders a video of the module in a new thread. The module must have an End
+/// event.
+pub fn export(module: Arc<Module>, output: VideoOutput, voice_settings: VoiceSettings,
+    colors: VideoColors, tx: Sender<StatusUpdate>
+) {
+    thread::spawn(move || {
+        let update = match write_video(&module, &output, voice_settings, colors, &tx) {
+            Ok(()) => StatusUpdate::VideoDone(output.path().to_path_buf()),
+            Err(e) => StatusUpdate::VideoError(e.to_string()),
+        };
+        if let Err(e) = tx.send(update) {
+            eprintln!("{e}");
+        }
+    });
+}
+
+fn write_video(module: &Module, output: &VideoOutput, voice_settings: VoiceSettings,
+    colors: VideoColors, tx: &Sender<StatusUpdate>
+) -> Result<(), Box<dyn Error>> {
+    let send_progress = |f: f64| if let Err(e) = tx.send(StatusUpdate::Progress(f)) {
+        eprintln!("{e}");
+    };
+
+    let renders = module.tracks.len();
+    let mut waves = Vec::new();
+    for i in 0..renders {
+        let track = if i == 0 { None } else { Some(i) };
+        let wave = render_to_wave(module, track, voice_settings, |f| {
+            send_progress((i as f64 + f) / renders as f64 * AUDIO_PROGRESS)
+        });
+        waves.push(wave);
+    }
+    let mix = waves.remove(0);
+
+    let path = output.path();
+    let wav_path = path.with_extension("wav");
+    if let Err(e) = mix.save_wav16(&wav_path) {
+        if let VideoOutput::Ffmpeg(_) = output {
+            let _ = std::fs::remove_file(&wav_path);
+        }
+        return Err(e.into())
+    }
+
+    let num_frames = (mix.duration() * FPS as f64).ceil() as usize;
+    let mut frame = vec![0; WIDTH * HEIGHT * 3];
+    let mut prev_progress = 0.0;
+    let mut report = |i: usize| {
+        let progress = AUDIO_PROGRESS + i as f64 / num_frames as f64 * (1.0 - AUDIO_PROGRESS);
+        if progress - prev_progress >= 0.01 {
+            prev_progress = progress;
+            send_progress(progress);
+        }
+    };
+
+    match output {
+        VideoOutput::PngSequence(_) => {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            for i in 0..num_frames {
+                draw_frame(&mut frame, &waves, i as f64 / FPS as f64, colors);
+                let frame_path = path.with_file_name(format!("{stem}_{i:05}.png"));
+                write_png(&mut File::create(frame_path)?, &frame)?;
+                report(i);
+            }
+        }
+        VideoOutput::Ffmpeg(_) => {
+            let result = run_ffmpeg(&wav_path, path, |stdin| {
+                for i in 0..num_frames {
+                    draw_frame(&mut frame, &waves, i as f64 / FPS as f64, colors);
+                    stdin.write_all(&frame)?;
+                    report(i);
+                }
+                Ok(())
+            });
+            // the WAV is only an intermediate file here, so remove it even if
+            // encoding failed
+            let removed = std::fs::remove_file(&wav_path);
+            result?;
+            removed?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encodes frames written by `write_frames` to `path` with ffmpeg, using the
+/// audio at `wav_path`. If writing fails, ffmpeg is stopped.
+fn run_ffmpeg(wav_path: &Path, path: &Path,
+    write_frames: impl FnOnce(&mut ChildStdin) -> io::Result<()>
+) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgb24",
+            "-s", &format!("{WIDTH}x{HEIGHT}"), "-r", &FPS.to_string(), "-i", "-",
+            "-i"])
+        .arg(wav_path)
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac",
+            "-shortest"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run ffmpeg: {e}"))?;
+    let mut stdin = child.stdin.take().expect("ffmpeg stdin should be piped");
+    if let Err(e) = write_frames(&mut stdin) {
+        drop(stdin);
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e.into())
+    }
+    drop(stdin);
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {status}").into())
+    }
+    Ok(())
+}
+
+/// Draws a scope for each wave, at `time` seconds, into an RGB frame.
+fn draw_frame(frame: &mut [u8], waves: &[Wave], time: f64, colors: VideoColors) {
+    let [bg, fg, line] = [colors.bg, colors.fg, colors.line].map(rgb);
+    for pixel in frame.chunks_exact_mut(3) {
+        pixel.copy_from_slice(&bg);
+    }
+
+    let cols = (waves.len() as f64).sqrt().ceil().max(1.0) as usize;
+    let rows = waves.len().div_ceil(cols).max(1);
+    let cell_w = ((WIDTH - SCOPE_MARGIN) / cols).saturating_sub(SCOPE_MARGIN);
+    let cell_h = ((HEIGHT - SCOPE_MARGIN) / rows).saturating_sub(SCOPE_MARGIN);
+
+    for (i, wave) in waves.iter().enumerate() {
+        let x0 = SCOPE_MARGIN + (i % cols) * (cell_w + SCOPE_MARGIN);
+        let y0 = SCOPE_MARGIN + (i / cols) * (cell_h + SCOPE_MARGIN);
+        let mid = y0 + cell_h / 2;
+        for x in x0..x0 + cell_w {
+            set_pixel(frame, x, mid, line);
+        }
+
+        let window = (SCOPE_TIME * wave.sample_rate()) as usize;
+        let start = trigger(wave, (time * wave.sample_rate()) as usize, window);
+        let mut prev_y = None;
+        for dx in 0..cell_w {
+            let j = start + dx * window / cell_w;
+            let v = if j < wave.len() {
+                ((wave.at(0, j) + wave.at(1, j)) * 0.5).clamp(-1.0, 1.0)
+            } else {
+                0.0
+            };
+            let y = (mid as f32 - v * (cell_h / 2) as f32).round() as usize;
+            let (top, bottom) = match prev_y {
+                Some(prev) => (y.min(prev), y.max(prev)),
+                None => (y, y),
+            };
+            for y in top..=bottom.min(y0 + cell_h - 1) {
+                set_pixel(frame, x0 + dx, y, fg);
+            }
+            prev_y = Some(y);
+        }
+    }
+}
+
+/// Returns the index of the last rising zero crossing in the window before
+/// `index`, so that periodic waves hold still. Returns `index` if there is no
+/// crossing.
+fn trigger(wave: &Wave, index: usize, window: usize) -> usize {
+    let mono = |i: usize| if i < wave.len() { wave.at(0, i) + wave.at(1, i) } else { 0.0 };
+    (index.saturating_sub(window) + 1..=index).rev()
+        .find(|&i| mono(i - 1) <= 0.0 && mono(i) > 0.0)
+        .unwrap_or(index)
+}
+
+fn set_pixel(frame: &mut [u8], x: usize, y: usize, color: [u8; 3]) {
+    let i = (y * WIDTH + x) * 3;
+    frame[i..i + 3].copy_from_slice(&color);
+}
+
+fn rgb(color: Color) -> [u8; 3] {
+    [color.r, color.g, color.b].map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Writes an RGB frame as a PNG image.
+fn write_png(w: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::new();
+    header.extend((WIDTH as u32).to_be_bytes());
+    header.extend((HEIGHT as u32).to_be_bytes());
+    header.extend([8, 2, 0, 0, 0]); // 8-bit RGB, no interlacing
+    write_png_chunk(w, b"IHDR", &header)?;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    for row in frame.chunks_exact(WIDTH * 3) {
+        encoder.write_all(&[0])?; // no filter
+        encoder.write_all(row)?;
+    }
+    write_png_chunk(w, b"IDAT", &encoder.finish()?)?;
+
+    write_png_chunk(w, b"IEND", &[])
+}
+
+fn write_png_chunk(w: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_all(&crc.sum().to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger() {
+        let mut wave = Wave::new(2, 100.0);
+        for i in 0..100 {
+            let v = if i % 10 < 5 { -1.0 } else { 1.0 };
+            wave.push((v, v));
+        }
+        assert_eq!(trigger(&wave, 52, 20), 45);
+        assert_eq!(trigger(&wave, 3, 20), 3);
+    }
+}
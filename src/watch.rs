@@ -0,0 +1,46 @@
+//! Polling of referenced sample files for edits made in other programs.
+
+use std::{collections::HashMap, fs, path::PathBuf, time::{Duration, Instant, SystemTime}};
+
+use crate::module::Module;
+
+/// Time between checks of sample files.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks the modification times of referenced sample files.
+pub struct SampleWatch {
+    last_poll: Instant,
+    /// Modification time of each file as of the last poll.
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+impl SampleWatch {
+    pub fn new() -> Self {
+        Self {
+            last_poll: Instant::now(),
+            modified: HashMap::new(),
+        }
+    }
+
+    /// Returns the paths of referenced samples in `module` whose files have
+    /// been modified since the last poll. Files seen for the first time
+    /// don't count as modified.
+    pub fn poll(&mut self, module: &Module) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new()
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for path in module.referenced_sample_paths() {
+            let Ok(time) = fs::metadata(path).and_then(|m| m.modified()) else {
+                continue
+            };
+            if self.modified.insert(path.clone(), time).is_some_and(|t| t != time)
+                && !changed.contains(path) {
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}